@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use chrono::{TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::{StreamExt, TryStreamExt};
+use rdkafka::{consumer::StreamConsumer as RdStreamConsumer, util::Timeout};
+use rskafka::{
+    client::{
+        consumer::{StartOffset, StreamConsumerBuilder as RsStreamConsumerBuilder},
+        partition::{Compression, PartitionClient},
+    },
+    record::{Headers, Record},
+};
+
+mod common;
+use common::{
+    benchark_group, exec_parallel, exec_sequential, maybe_start_logging, runtime, setup_rskafka,
+    FutureTimeItExt, RdKafka, RecordExt,
+};
+
+/// Number of records per produce request when preparing compressed data.
+#[cfg(feature = "compression-zstd")]
+const RECORDS_PER_BATCH: usize = 100;
+
+/// Maximum size of a single fetch.
+const FETCH_MAX_BYTES: i32 = 1_000_000;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    maybe_start_logging();
+    let connection = maybe_skip_kafka_integration!();
+
+    let record = Record {
+        key: Some(vec![b'k'; 10].into()),
+        value: Some(vec![b'x'; 10_000].into()),
+        headers: Headers::default(),
+        timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+    };
+
+    {
+        let mut group = benchark_group(c, "read_sequential");
+
+        group.bench_function("rdkafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let rd = RdKafka::setup(connection, false).await;
+                    produce_rdkafka(&rd, &record, iters, false).await;
+
+                    async {
+                        let consumer = rd.consumer().await;
+                        for _ in 0..iters {
+                            consumer.recv().await.unwrap();
+                        }
+                    }
+                    .time_it()
+                    .await
+                }
+            });
+        });
+
+        group.bench_function("rskafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let client = setup_rskafka(connection).await;
+                    produce_rskafka(&client, &record, iters, 1, Compression::NoCompression).await;
+
+                    fetch_sequential(&client, iters).time_it().await
+                }
+            });
+        });
+    }
+
+    {
+        let mut group = benchark_group(c, "read_stream");
+
+        group.bench_function("rdkafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let rd = RdKafka::setup(connection, false).await;
+                    produce_rdkafka(&rd, &record, iters, false).await;
+
+                    // rdkafka fetches data in the background, so we need include the client setup into the
+                    // measurement
+                    async { consume_rdkafka(rd.consumer().await, iters).await }
+                        .time_it()
+                        .await
+                }
+            });
+        });
+
+        group.bench_function("rskafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let client = setup_rskafka(connection).await;
+                    produce_rskafka(&client, &record, iters, 1, Compression::NoCompression).await;
+
+                    consume_rskafka(client, iters).time_it().await
+                }
+            });
+        });
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    {
+        // compressible, but not trivially so
+        let compressible_record = Record {
+            value: Some(
+                (0..10_000)
+                    .map(|i| b"abcdefghijklmnop"[(i * 7 + i / 13) % 16])
+                    .collect::<Vec<u8>>()
+                    .into(),
+            ),
+            ..record.clone()
+        };
+
+        let mut group = benchark_group(c, "read_compressed");
+
+        group.bench_function("rdkafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = compressible_record.clone();
+
+                async move {
+                    let mut rd = RdKafka::setup(connection, true).await;
+                    rd.cfg.set("compression.codec", "zstd");
+                    produce_rdkafka(&rd, &record, iters, true).await;
+
+                    async { consume_rdkafka(rd.consumer().await, iters).await }
+                        .time_it()
+                        .await
+                }
+            });
+        });
+
+        group.bench_function("rskafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = compressible_record.clone();
+
+                async move {
+                    let client = setup_rskafka(connection).await;
+                    produce_rskafka(
+                        &client,
+                        &record,
+                        iters,
+                        RECORDS_PER_BATCH,
+                        Compression::Zstd,
+                    )
+                    .await;
+
+                    consume_rskafka(client, iters).time_it().await
+                }
+            });
+        });
+    }
+}
+
+/// Produce `n` copies of `record`, sequentially or in parallel so that rdkafka batches them.
+async fn produce_rdkafka(rd: &RdKafka, record: &Record, n: u64, parallel: bool) {
+    let producer = rd.producer(false).await;
+    let f = || async {
+        let f_record = record.to_rdkafka(&rd.topic_name);
+        producer.send(f_record, Timeout::Never).await.unwrap();
+    };
+
+    if parallel {
+        exec_parallel(f, n).await;
+    } else {
+        exec_sequential(f, n).await;
+    }
+}
+
+/// Produce `n` copies of `record` in batches of `batch_size` records.
+async fn produce_rskafka(
+    client: &PartitionClient,
+    record: &Record,
+    n: u64,
+    batch_size: usize,
+    compression: Compression,
+) {
+    let mut remaining = n as usize;
+    while remaining > 0 {
+        let batch = remaining.min(batch_size);
+        client
+            .produce(vec![record.clone(); batch], compression)
+            .await
+            .unwrap();
+        remaining -= batch;
+    }
+}
+
+async fn consume_rdkafka(consumer: RdStreamConsumer, n: u64) {
+    let messages = consumer
+        .stream()
+        .take(n as usize)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), n as usize);
+}
+
+async fn consume_rskafka(client: Arc<PartitionClient>, n: u64) {
+    let consumer = RsStreamConsumerBuilder::new(client, StartOffset::Earliest).build();
+    let messages = consumer
+        .take(n as usize)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), n as usize);
+}
+
+/// Fetch `n` records one fetch request after another.
+async fn fetch_sequential(client: &PartitionClient, n: u64) {
+    let mut offset = 0;
+    while offset < n as i64 {
+        let (records, _high_watermark) = client
+            .fetch_records(offset, 1..FETCH_MAX_BYTES, 1_000)
+            .await
+            .unwrap();
+        offset = records.last().expect("records were produced").offset + 1;
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
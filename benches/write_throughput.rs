@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rdkafka::util::Timeout;
+use rskafka::{
+    client::{
+        partition::Compression,
+        producer::{aggregator::RecordAggregator, BatchProducerBuilder},
+    },
+    record::{Headers, Record},
+};
+
+mod common;
+use common::{
+    benchark_group, exec_parallel, exec_sequential, maybe_start_logging, runtime, setup_rskafka,
+    FutureTimeItExt, RdKafka, RecordExt, PARALLEL_BATCH_SIZE, PARALLEL_LINGER_MS,
+};
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    maybe_start_logging();
+    let connection = maybe_skip_kafka_integration!();
+
+    let record = Record {
+        key: Some(vec![b'k'; 10].into()),
+        value: Some(vec![b'x'; 10_000].into()),
+        headers: Headers::default(),
+        timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+    };
+
+    {
+        let mut group = benchark_group(c, "write_sequential");
+
+        group.bench_function("rdkafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let rd = RdKafka::setup(connection, false).await;
+                    let producer = rd.producer(true).await;
+
+                    exec_sequential(
+                        || async {
+                            let f_record = record.to_rdkafka(&rd.topic_name);
+                            producer.send(f_record, Timeout::Never).await.unwrap();
+                        },
+                        iters,
+                    )
+                    .time_it()
+                    .await
+                }
+            });
+        });
+
+        group.bench_function("rskafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let client = setup_rskafka(connection).await;
+
+                    exec_sequential(
+                        || async {
+                            client
+                                .produce(vec![record.clone()], Compression::NoCompression)
+                                .await
+                                .unwrap();
+                        },
+                        iters,
+                    )
+                    .time_it()
+                    .await
+                }
+            });
+        });
+    }
+
+    {
+        let mut group = benchark_group(c, "write_parallel");
+
+        group.bench_function("rdkafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let rd = RdKafka::setup(connection, true).await;
+                    let producer = rd.producer(true).await;
+
+                    exec_parallel(
+                        || async {
+                            let f_record = record.to_rdkafka(&rd.topic_name);
+                            producer.send(f_record, Timeout::Never).await.unwrap();
+                        },
+                        iters,
+                    )
+                    .time_it()
+                    .await
+                }
+            });
+        });
+
+        group.bench_function("rskafka", |b| {
+            b.to_async(runtime()).iter_custom(|iters| {
+                let connection = connection.clone();
+                let record = record.clone();
+
+                async move {
+                    let client = setup_rskafka(connection).await;
+                    let producer = BatchProducerBuilder::new(client)
+                        .with_linger(Duration::from_millis(PARALLEL_LINGER_MS))
+                        .build(RecordAggregator::new(PARALLEL_BATCH_SIZE));
+
+                    exec_parallel(
+                        || async {
+                            producer.produce(record.clone()).await.unwrap();
+                        },
+                        iters,
+                    )
+                    .time_it()
+                    .await
+                }
+            });
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
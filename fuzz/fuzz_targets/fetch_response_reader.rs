@@ -0,0 +1,15 @@
+#![no_main]
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rskafka::protocol::{fuzzing::decode_fetch_response, primitives::Int16, traits::ReadType};
+
+fuzz_target!(|data: &[u8]| {
+    let mut cursor = Cursor::new(data);
+    let Ok(version) = Int16::read(&mut cursor) else {
+        return;
+    };
+    let pos = cursor.position() as usize;
+
+    decode_fetch_response(&data[pos..], version.0).ok();
+});
@@ -68,7 +68,12 @@ fn driver(data: &[u8]) -> Result<(), Error> {
                 min_bytes: Int32(0),
                 max_bytes: None,
                 isolation_level: None,
+                session_id: None,
+                session_epoch: None,
                 topics: vec![],
+                forgotten_topics_data: vec![],
+                rack_id: None,
+                tagged_fields: None,
             },
             cursor,
             api_key,
@@ -88,6 +93,9 @@ fn driver(data: &[u8]) -> Result<(), Error> {
             MetadataRequest {
                 topics: None,
                 allow_auto_topic_creation: None,
+                include_cluster_authorized_operations: None,
+                include_topic_authorized_operations: None,
+                tagged_fields: None,
             },
             cursor,
             api_key,
@@ -99,6 +107,7 @@ fn driver(data: &[u8]) -> Result<(), Error> {
                 acks: Int16(0),
                 timeout_ms: Int32(0),
                 topic_data: vec![],
+                tagged_fields: None,
             },
             cursor,
             api_key,
@@ -1,18 +1,38 @@
 use rand::prelude::*;
+use std::future::Future;
 use std::ops::ControlFlow;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::info;
 
+use crate::metrics::{Metrics, Retry, Throttle, ThrottleCallback};
+use crate::runtime;
+
 /// Exponential backoff with jitter
 ///
 /// See <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>
 #[allow(missing_copy_implementations)]
 #[derive(Debug, Clone)]
 pub struct BackoffConfig {
+    /// Backoff after the first error.
     pub init_backoff: Duration,
+
+    /// Upper limit for a single backoff.
     pub max_backoff: Duration,
+
+    /// Multiplier for consecutive backoffs.
     pub base: f64,
+
+    /// Overall time budget for retrying an operation, including the time spent on the failed attempts.
+    ///
+    /// The operation fails with [`BackoffError::DeadlineExceded`] once the next backoff would exceed the deadline.
+    /// `None` means the operation is retried forever.
     pub deadline: Option<Duration>,
+
+    /// Randomize backoffs, so that clients that failed at the same time do not retry at the same time.
+    ///
+    /// If disabled, backoffs grow by exactly `base` every time.
+    pub jitter: bool,
 }
 
 impl Default for BackoffConfig {
@@ -22,6 +42,7 @@ impl Default for BackoffConfig {
             max_backoff: Duration::from_secs(500),
             base: 3.,
             deadline: None,
+            jitter: true,
         }
     }
 }
@@ -39,6 +60,27 @@ pub enum BackoffError {
 }
 pub type BackoffResult<T> = Result<T, BackoffError>;
 
+tokio::task_local! {
+    /// Deadline set by [`with_timeout`].
+    static DEADLINE: Instant;
+}
+
+/// Run `f` with a deadline of `timeout` for all client operations that it performs.
+///
+/// This overrides the [operation timeout](crate::client::ClientBuilder::operation_timeout) of the client. Operations
+/// that do not finish before the deadline fail with [`BackoffError::DeadlineExceded`], including requests that are
+/// still in flight. Nested calls cannot extend the deadline of the outer call.
+pub async fn with_timeout<F>(timeout: Duration, f: F) -> F::Output
+where
+    F: Future + Send,
+{
+    let deadline = Instant::now() + timeout;
+    let deadline = DEADLINE
+        .try_with(|outer| deadline.min(*outer))
+        .unwrap_or(deadline);
+    DEADLINE.scope(deadline, f).await
+}
+
 /// Error (which should increase backoff) or throttle for a specific duration (as asked for by the broker).
 #[derive(Debug)]
 pub enum ErrorOrThrottle<E>
@@ -60,7 +102,12 @@ pub struct Backoff {
     base: f64,
     total: f64,
     deadline: Option<f64>,
+    timeout_at: Option<Instant>,
+    jitter: bool,
+    start: Instant,
     rng: Option<Box<dyn RngCore + Sync + Send>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    throttle_callback: Option<ThrottleCallback>,
 }
 
 impl std::fmt::Debug for Backoff {
@@ -70,6 +117,7 @@ impl std::fmt::Debug for Backoff {
             .field("next_backoff_secs", &self.next_backoff_secs)
             .field("max_backoff_secs", &self.max_backoff_secs)
             .field("base", &self.base)
+            .field("jitter", &self.jitter)
             .finish()
     }
 }
@@ -96,6 +144,49 @@ impl Backoff {
             rng,
             total: 0.,
             deadline: config.deadline.map(|d| d.as_secs_f64()),
+            timeout_at: None,
+            jitter: config.jitter,
+            start: Instant::now(),
+            metrics: None,
+            throttle_callback: None,
+        }
+    }
+
+    /// Abort the operation once `timeout` elapsed, or at the deadline of the enclosing [`with_timeout`] call.
+    ///
+    /// Unlike [`BackoffConfig::deadline`] this also cancels an attempt that is still in progress.
+    pub(crate) fn with_operation_timeout(self, timeout: Option<Duration>) -> Self {
+        let timeout_at = DEADLINE
+            .try_with(|deadline| *deadline)
+            .ok()
+            .or_else(|| timeout.map(|timeout| self.start + timeout));
+        let Some(timeout_at) = timeout_at else {
+            return self;
+        };
+
+        let remaining = timeout_at
+            .saturating_duration_since(self.start)
+            .as_secs_f64();
+        Self {
+            deadline: Some(self.deadline.map_or(remaining, |d| d.min(remaining))),
+            timeout_at: Some(timeout_at),
+            ..self
+        }
+    }
+
+    /// Report retries to `metrics`.
+    pub(crate) fn with_metrics(self, metrics: Option<Arc<dyn Metrics>>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// Report throttling to `callback`.
+    pub(crate) fn with_throttle_callback(
+        self,
+        throttle_callback: Option<ThrottleCallback>,
+    ) -> Self {
+        Self {
+            throttle_callback,
+            ..self
         }
     }
 
@@ -110,13 +201,31 @@ impl Backoff {
         F1: std::future::Future<Output = ControlFlow<B, ErrorOrThrottle<E>>> + Send,
         E: std::error::Error + Send + Sync + 'static,
     {
+        let mut attempt = 0;
         loop {
-            // split match statement from `tokio::time::sleep`, because otherwise rustc requires `B: Send`
-            let fail = match do_stuff().await {
-                ControlFlow::Break(r) => break Ok(r),
-                ControlFlow::Continue(e) => e,
+            // split match statement from `runtime::sleep` and scope `res` to it, because otherwise rustc requires
+            // `B: Send`
+            let fail = {
+                let res = match self.timeout_at {
+                    Some(timeout_at) => {
+                        let remaining = timeout_at.saturating_duration_since(Instant::now());
+                        runtime::timeout(remaining, do_stuff()).await
+                    }
+                    None => Ok(do_stuff().await),
+                };
+                match res {
+                    Ok(ControlFlow::Break(r)) => break Ok(r),
+                    Ok(ControlFlow::Continue(e)) => e,
+                    Err(e) => {
+                        break Err(BackoffError::DeadlineExceded {
+                            deadline: Duration::from_secs_f64(self.deadline.unwrap()),
+                            source: Box::new(e),
+                        })
+                    }
+                }
             };
 
+            let throttled = matches!(fail, ErrorOrThrottle::Throttle(_));
             let sleep_time = match fail {
                 ErrorOrThrottle::Error(e) => match self.next() {
                     Some(backoff) => {
@@ -137,11 +246,35 @@ impl Backoff {
                 },
                 ErrorOrThrottle::Throttle(throttle) => {
                     info!(?throttle, request_name, "broker asked us to throttle",);
+                    self.report_throttle(request_name, throttle);
                     throttle
                 }
             };
 
-            tokio::time::sleep(sleep_time).await;
+            attempt += 1;
+            if let Some(metrics) = &self.metrics {
+                metrics.retry(&Retry {
+                    request_name,
+                    attempt,
+                    backoff: sleep_time,
+                    throttled,
+                });
+            }
+
+            runtime::sleep(sleep_time).await;
+        }
+    }
+
+    fn report_throttle(&self, request_name: &str, duration: Duration) {
+        let throttle = Throttle {
+            request_name,
+            duration,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.throttled(&throttle);
+        }
+        if let Some(callback) = &self.throttle_callback {
+            callback(&throttle);
         }
     }
 }
@@ -151,11 +284,15 @@ impl Iterator for Backoff {
 
     /// Returns the next backoff duration to wait for
     fn next(&mut self) -> Option<Duration> {
-        let range = self.init_backoff..(self.next_backoff_secs * self.base);
-
-        let rand_backoff = match self.rng.as_mut() {
-            Some(rng) => rng.gen_range(range),
-            None => thread_rng().gen_range(range),
+        let upper = self.next_backoff_secs * self.base;
+        let rand_backoff = if self.jitter {
+            let range = self.init_backoff..upper;
+            match self.rng.as_mut() {
+                Some(rng) => rng.gen_range(range),
+                None => thread_rng().gen_range(range),
+            }
+        } else {
+            upper
         };
 
         let next_backoff = self.max_backoff_secs.min(rand_backoff);
@@ -164,7 +301,9 @@ impl Iterator for Backoff {
             Duration::from_secs_f64(std::mem::replace(&mut self.next_backoff_secs, next_backoff));
 
         if let Some(deadline) = self.deadline {
-            if self.total >= deadline {
+            // the time spent on the attempts counts as well, e.g. connection timeouts
+            let elapsed = self.start.elapsed().as_secs_f64();
+            if self.total >= deadline || elapsed + backoff.as_secs_f64() >= deadline {
                 return None;
             }
         }
@@ -188,6 +327,7 @@ mod tests {
             max_backoff: Duration::from_secs_f64(max_backoff_secs),
             base,
             deadline: None,
+            jitter: true,
         };
 
         let assert_fuzzy_eq = |a: f64, b: f64| assert!((b - a).abs() < 0.0001, "{} != {}", a, b);
@@ -226,10 +366,121 @@ mod tests {
         let mut backoff = Backoff::new_with_rng(
             &BackoffConfig {
                 deadline: Some(deadline),
-                ..config
+                ..config.clone()
             },
             Some(rng),
         );
         assert_eq!(backoff.next(), None);
+
+        // no jitter
+        let mut backoff = Backoff::new(&BackoffConfig {
+            jitter: false,
+            ..config
+        });
+        for i in 0..20 {
+            let value = (base.powi(i) * init_backoff_secs).min(max_backoff_secs);
+            assert_fuzzy_eq(backoff.next().unwrap().as_secs_f64(), value);
+        }
+    }
+
+    #[test]
+    fn test_deadline_includes_elapsed_time() {
+        let mut backoff = Backoff::new(&BackoffConfig {
+            init_backoff: Duration::from_millis(10),
+            deadline: Some(Duration::from_millis(100)),
+            jitter: false,
+            ..Default::default()
+        });
+        assert!(backoff.next().is_some());
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(backoff.next(), None);
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout() {
+        let config = BackoffConfig {
+            init_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let pending = || async {
+            futures::future::pending::<ControlFlow<(), ErrorOrThrottle<std::io::Error>>>().await
+        };
+
+        // no timeout, no deadline
+        let backoff = Backoff::new(&config).with_operation_timeout(None);
+        assert!(backoff.deadline.is_none());
+        assert!(backoff.timeout_at.is_none());
+
+        // attempt in progress is cancelled
+        let mut backoff =
+            Backoff::new(&config).with_operation_timeout(Some(Duration::from_millis(10)));
+        let err = backoff
+            .retry_with_backoff("fetch", pending)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BackoffError::DeadlineExceded { .. }));
+
+        // retries stop at the deadline
+        let attempts = parking_lot::Mutex::new(0);
+        let mut backoff =
+            Backoff::new(&config).with_operation_timeout(Some(Duration::from_millis(50)));
+        backoff
+            .retry_with_backoff("fetch", || async {
+                *attempts.lock() += 1;
+                ControlFlow::<(), _>::Continue(ErrorOrThrottle::Error(std::io::Error::other("foo")))
+            })
+            .await
+            .unwrap_err();
+        assert!(*attempts.lock() >= 1);
+
+        // scope overrides the timeout, nested scopes cannot extend it
+        with_timeout(Duration::from_millis(10), async {
+            with_timeout(Duration::from_secs(3600), async {
+                let mut backoff =
+                    Backoff::new(&config).with_operation_timeout(Some(Duration::from_secs(3600)));
+                assert!(backoff.deadline.unwrap() <= 0.01);
+                backoff
+                    .retry_with_backoff("fetch", pending)
+                    .await
+                    .unwrap_err();
+            })
+            .await
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_throttle_callback() {
+        let throttles = Arc::new(parking_lot::Mutex::new(vec![]));
+        let throttles_captured = Arc::clone(&throttles);
+        let mut backoff = Backoff::new(&Default::default()).with_throttle_callback(Some(Arc::new(
+            move |throttle: &Throttle<'_>| {
+                throttles_captured
+                    .lock()
+                    .push((throttle.request_name.to_owned(), throttle.duration));
+            },
+        )));
+
+        let attempts = parking_lot::Mutex::new(0);
+        backoff
+            .retry_with_backoff("produce", || async {
+                let mut attempts = attempts.lock();
+                *attempts += 1;
+                if *attempts == 1 {
+                    ControlFlow::Continue(ErrorOrThrottle::<std::io::Error>::Throttle(
+                        Duration::from_millis(1),
+                    ))
+                } else {
+                    ControlFlow::Break(())
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *throttles.lock(),
+            [("produce".to_owned(), Duration::from_millis(1))]
+        );
     }
 }
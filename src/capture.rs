@@ -0,0 +1,342 @@
+//! Capture and replay of raw protocol frames.
+//!
+//! Captured frames allow to reproduce broker compatibility issues offline: register a [`FrameCapture`] via
+//! [`ClientBuilder::frame_capture`](crate::client::ClientBuilder::frame_capture), e.g. one that writes to a file using
+//! a [`FrameWriter`], read the frames back via a [`FrameReader`] later on and feed the responses into the decoder using
+//! [`decode_response`].
+//!
+//! Frames of the SASL exchange are never captured since they contain credentials.
+use std::{
+    fmt::Debug,
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::protocol::{
+    api_key::ApiKey,
+    api_version::ApiVersion,
+    messages::{
+        ApiVersionsRequest, CreateTopicsRequest, DeleteRecordsRequest, DeleteTopicsRequest,
+        FetchRequest, GetTelemetrySubscriptionsRequest, ListOffsetsRequest, MetadataRequest,
+        ProduceRequest, PushTelemetryRequest, ReadVersionedError, ReadVersionedType, RequestBody,
+        ResponseHeader,
+    },
+    primitives::{Int16, TaggedFields},
+    traits::{ReadError, ReadType},
+};
+
+/// Whether a [`Frame`] was sent to or received from the broker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    Request,
+    Response,
+}
+
+/// Raw protocol frame, i.e. a message without the length prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub direction: FrameDirection,
+
+    /// API key of the request that the frame belongs to.
+    pub api_key: i16,
+
+    /// API version of the request that the frame belongs to.
+    pub api_version: i16,
+
+    /// Message header and body.
+    pub data: Vec<u8>,
+}
+
+/// Callback that is invoked for every frame that is sent to or received from a broker.
+///
+/// The callback runs on the request path, so it should not block.
+pub type FrameCapture = Arc<dyn Fn(&Frame) + Send + Sync>;
+
+/// Writes [`Frame`]s in a simple binary format that can be read via [`FrameReader`].
+///
+/// Every frame is written as direction (`0` for requests, `1` for responses) as INT8, API key and version as INT16,
+/// followed by the data as BYTES.
+#[derive(Debug)]
+pub struct FrameWriter<W> {
+    inner: W,
+}
+
+impl<W> FrameWriter<W>
+where
+    W: Write,
+{
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub fn write(&mut self, frame: &Frame) -> std::io::Result<()> {
+        let direction: i8 = match frame.direction {
+            FrameDirection::Request => 0,
+            FrameDirection::Response => 1,
+        };
+        let len = i32::try_from(frame.data.len())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+        self.inner.write_all(&direction.to_be_bytes())?;
+        self.inner.write_all(&frame.api_key.to_be_bytes())?;
+        self.inner.write_all(&frame.api_version.to_be_bytes())?;
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&frame.data)?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> FrameWriter<W>
+where
+    W: Write + Send + 'static,
+{
+    /// Use this writer as a [`FrameCapture`].
+    ///
+    /// Every frame is flushed right away, so that the capture is complete even if the process crashes. Frames that
+    /// cannot be written are logged and dropped.
+    pub fn into_capture(self) -> FrameCapture {
+        let writer = Mutex::new(self);
+        Arc::new(move |frame| {
+            let mut writer = writer.lock();
+            if let Err(e) = writer.write(frame).and_then(|_| writer.inner.flush()) {
+                warn!(%e, "Cannot write captured frame");
+            }
+        })
+    }
+}
+
+/// Reads [`Frame`]s written by a [`FrameWriter`].
+#[derive(Debug)]
+pub struct FrameReader<R> {
+    inner: R,
+}
+
+impl<R> FrameReader<R>
+where
+    R: Read,
+{
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    fn read_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        let mut direction = [0u8; 1];
+        if self.inner.read(&mut direction)? == 0 {
+            return Ok(None);
+        }
+        let direction = match direction[0] {
+            0 => FrameDirection::Request,
+            1 => FrameDirection::Response,
+            other => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid frame direction: {other}"),
+                ));
+            }
+        };
+
+        let mut buf = [0u8; 8];
+        self.inner.read_exact(&mut buf)?;
+        let api_key = i16::from_be_bytes([buf[0], buf[1]]);
+        let api_version = i16::from_be_bytes([buf[2], buf[3]]);
+        let len = i32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let len = usize::try_from(len)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        let mut data = vec![];
+        (&mut self.inner).take(len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        }
+
+        Ok(Some(Frame {
+            direction,
+            api_key,
+            api_version,
+            data,
+        }))
+    }
+}
+
+impl<R> Iterator for FrameReader<R>
+where
+    R: Read,
+{
+    type Item = std::io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ReplayError {
+    #[error("Frame is not a response")]
+    NotAResponse,
+
+    #[error("Decoding responses is not supported for API key {0}")]
+    UnsupportedApiKey(i16),
+
+    #[error("Cannot read response header: {0}")]
+    Header(#[from] ReadVersionedError),
+
+    #[error("Cannot read tagged fields of response header: {0}")]
+    TaggedFields(#[from] ReadError),
+
+    #[error("Cannot read response body: {0}")]
+    Body(ReadVersionedError),
+
+    #[error("Data left after response body: read {read} of {message_size} bytes")]
+    TooMuchData { message_size: u64, read: u64 },
+}
+
+/// Decode a captured response frame in the same way as the client does.
+///
+/// Returns the decoded response, which can be inspected via its [`Debug`] implementation.
+pub fn decode_response(frame: &Frame) -> Result<Box<dyn Debug + Send>, ReplayError> {
+    if frame.direction != FrameDirection::Response {
+        return Err(ReplayError::NotAResponse);
+    }
+
+    let api_key = ApiKey::from(Int16(frame.api_key));
+    let version = ApiVersion(Int16(frame.api_version));
+    let data = &frame.data;
+
+    match api_key {
+        ApiKey::ApiVersions => decode::<ApiVersionsRequest>(data, version),
+        ApiKey::CreateTopics => decode::<CreateTopicsRequest>(data, version),
+        ApiKey::DeleteRecords => decode::<DeleteRecordsRequest>(data, version),
+        ApiKey::DeleteTopics => decode::<DeleteTopicsRequest>(data, version),
+        ApiKey::Fetch => decode::<FetchRequest>(data, version),
+        ApiKey::GetTelemetrySubscriptions => {
+            decode::<GetTelemetrySubscriptionsRequest>(data, version)
+        }
+        ApiKey::ListOffsets => decode::<ListOffsetsRequest>(data, version),
+        ApiKey::Metadata => decode::<MetadataRequest>(data, version),
+        ApiKey::Produce => decode::<ProduceRequest>(data, version),
+        ApiKey::PushTelemetry => decode::<PushTelemetryRequest>(data, version),
+        _ => Err(ReplayError::UnsupportedApiKey(frame.api_key)),
+    }
+}
+
+fn decode<R>(data: &[u8], version: ApiVersion) -> Result<Box<dyn Debug + Send>, ReplayError>
+where
+    R: RequestBody,
+    R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>> + Debug + Send + 'static,
+{
+    let mut cursor = Cursor::new(data.to_vec());
+
+    ResponseHeader::read_versioned(&mut cursor, ApiVersion(Int16(0)))?;
+    if version >= R::FIRST_TAGGED_FIELD_IN_RESPONSE_VERSION {
+        TaggedFields::read(&mut cursor)?;
+    }
+
+    let body = R::ResponseBody::read_versioned(&mut cursor, version).map_err(ReplayError::Body)?;
+
+    let read = cursor.position();
+    let message_size = data.len() as u64;
+    if read != message_size {
+        return Err(ReplayError::TooMuchData { message_size, read });
+    }
+
+    Ok(Box::new(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        messages::{ApiVersionsResponse, ApiVersionsResponseApiKey, WriteVersionedType},
+        primitives::Int32,
+    };
+
+    use super::*;
+
+    fn api_versions_response() -> Frame {
+        let version = ApiVersionsRequest::API_VERSION_RANGE.max();
+
+        let mut data = vec![];
+        ResponseHeader {
+            correlation_id: Int32(0),
+            tagged_fields: Default::default(),
+        }
+        .write_versioned(&mut data, ApiVersion(Int16(0)))
+        .unwrap();
+        ApiVersionsResponse {
+            error_code: None,
+            api_keys: vec![ApiVersionsResponseApiKey {
+                api_key: ApiKey::Produce,
+                min_version: ApiVersion(Int16(1)),
+                max_version: ApiVersion(Int16(5)),
+                tagged_fields: Default::default(),
+            }],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        }
+        .write_versioned(&mut data, version)
+        .unwrap();
+
+        Frame {
+            direction: FrameDirection::Response,
+            api_key: Int16::from(ApiKey::ApiVersions).0,
+            api_version: version.0 .0,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_writer_reader_roundtrip() {
+        let frames = vec![
+            Frame {
+                direction: FrameDirection::Request,
+                api_key: 3,
+                api_version: 9,
+                data: vec![1, 2, 3],
+            },
+            api_versions_response(),
+        ];
+
+        let mut writer = FrameWriter::new(vec![]);
+        for frame in &frames {
+            writer.write(frame).unwrap();
+        }
+        let buf = writer.into_inner();
+
+        let actual = FrameReader::new(buf.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(actual, frames);
+
+        let err = FrameReader::new(&buf[..buf.len() - 1])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let response = decode_response(&api_versions_response()).unwrap();
+        assert!(format!("{response:?}").contains("Produce"));
+
+        let mut frame = api_versions_response();
+        frame.data.push(0);
+        assert!(matches!(
+            decode_response(&frame),
+            Err(ReplayError::TooMuchData { .. })
+        ));
+
+        frame.direction = FrameDirection::Request;
+        assert!(matches!(
+            decode_response(&frame),
+            Err(ReplayError::NotAResponse)
+        ));
+    }
+}
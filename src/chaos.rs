@@ -0,0 +1,176 @@
+//! Fault injection to test how applications cope with misbehaving brokers.
+//!
+//! Register a [`FaultPolicy`] via [`ClientBuilder::fault_injection`](crate::client::ClientBuilder::fault_injection).
+//! The policy is asked for every request and can delay it, drop the connection or let the response report throttling
+//! or an error. This allows to trigger retries and metadata invalidation deterministically, which is hardly possible
+//! against a real broker.
+//!
+//! ```
+//! # fn f() {
+//! use std::{
+//!     sync::atomic::{AtomicBool, Ordering},
+//!     time::Duration,
+//! };
+//!
+//! use rskafka::{
+//!     chaos::Fault,
+//!     client::{error::ProtocolError, ClientBuilder},
+//! };
+//!
+//! // let the first produce request (API key 0) fail, delay all other requests
+//! let failed = AtomicBool::new(false);
+//! let builder = ClientBuilder::new(vec!["localhost:9092".to_owned()]).fault_injection(move |ctx| {
+//!     if ctx.api_key == 0 && !failed.swap(true, Ordering::SeqCst) {
+//!         Some(Fault::Error(ProtocolError::NotLeaderOrFollower))
+//!     } else {
+//!         Some(Fault::Latency(Duration::from_millis(10)))
+//!     }
+//! });
+//! # }
+//! ```
+//!
+//! Requests that set up a connection (API versions and SASL) are not subject to fault injection.
+use std::{sync::Arc, time::Duration};
+
+use crate::protocol::error::Error as ProtocolError;
+
+/// Request that a [`FaultPolicy`] decides about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FaultContext<'a> {
+    /// ID of the broker, if known.
+    pub broker_id: Option<i32>,
+
+    /// `host:port` of the broker, if known.
+    pub broker: Option<&'a str>,
+
+    /// API key, see <https://kafka.apache.org/protocol#protocol_api_keys>.
+    pub api_key: i16,
+
+    pub api_version: i16,
+}
+
+/// Fault to inject into a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// Delay the request before it is sent.
+    Latency(Duration),
+
+    /// Drop the connection instead of sending the request, like an IO error would.
+    ///
+    /// The request and all other requests in flight on the connection fail and the client reconnects.
+    Disconnect,
+
+    /// Let the response report that the broker throttled the request for the given time.
+    ///
+    /// This has no effect on APIs that do not report throttling.
+    Throttle(Duration),
+
+    /// Let the response report the given error for the whole request or, if errors are reported per topic or
+    /// partition, for all of them.
+    ///
+    /// This has no effect on APIs that do not report errors.
+    Error(ProtocolError),
+}
+
+/// Decides which fault, if any, to inject into a request.
+///
+/// The policy runs on the request path, so it should not block.
+pub type FaultPolicy = Arc<dyn Fn(&FaultContext<'_>) -> Option<Fault> + Send + Sync>;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::{
+        client::{
+            partition::{OffsetAt, UnknownTopicHandling},
+            ClientBuilder,
+        },
+        mock_broker::MockBroker,
+        protocol::{api_key::ApiKey, primitives::Int16},
+    };
+
+    const LIST_OFFSETS: i16 = 2;
+
+    /// Policy that injects `fault` into the first list offsets request.
+    fn fail_once(
+        fault: Fault,
+        calls: Arc<AtomicUsize>,
+    ) -> impl Fn(&FaultContext<'_>) -> Option<Fault> {
+        move |ctx| {
+            if ctx.api_key != LIST_OFFSETS {
+                return None;
+            }
+            assert_eq!(ctx.broker_id, Some(0));
+            (calls.fetch_add(1, Ordering::SeqCst) == 0).then_some(fault)
+        }
+    }
+
+    async fn get_offset(builder: ClientBuilder) -> i64 {
+        builder
+            .build()
+            .await
+            .unwrap()
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap()
+            .get_offset(OffsetAt::Latest)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_list_offsets_api_key() {
+        assert_eq!(Int16::from(ApiKey::ListOffsets).0, LIST_OFFSETS);
+    }
+
+    #[tokio::test]
+    async fn test_error_is_retried() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let builder = ClientBuilder::new(broker.bootstrap_brokers()).fault_injection(fail_once(
+            Fault::Error(ProtocolError::NotLeaderOrFollower),
+            Arc::clone(&calls),
+        ));
+        assert_eq!(get_offset(builder).await, 0);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_reconnects() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let builder = ClientBuilder::new(broker.bootstrap_brokers())
+            .fault_injection(fail_once(Fault::Disconnect, Arc::clone(&calls)));
+        assert_eq!(get_offset(builder).await, 0);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_throttle() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let throttles = Arc::new(Mutex::new(vec![]));
+        let throttles_captured = Arc::clone(&throttles);
+        let builder = ClientBuilder::new(broker.bootstrap_brokers())
+            .fault_injection(fail_once(
+                Fault::Throttle(Duration::from_millis(10)),
+                Arc::clone(&calls),
+            ))
+            .throttle_callback(move |throttle| throttles_captured.lock().push(throttle.duration));
+        assert_eq!(get_offset(builder).await, 0);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(*throttles.lock(), vec![Duration::from_millis(10)]);
+    }
+}
@@ -0,0 +1,92 @@
+//! API versions supported by brokers.
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use crate::protocol::{api_key::ApiKey, primitives::Int16};
+
+/// API versions that a broker advertised when the connection was established.
+///
+/// API keys and versions are the numeric values of the Kafka protocol, see
+/// <https://kafka.apache.org/protocol#protocol_api_keys>.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiVersions {
+    ranges: BTreeMap<i16, RangeInclusive<i16>>,
+}
+
+impl ApiVersions {
+    pub(crate) fn new(ranges: impl IntoIterator<Item = (ApiKey, RangeInclusive<i16>)>) -> Self {
+        Self {
+            ranges: ranges
+                .into_iter()
+                .map(|(api_key, range)| (Int16::from(api_key).0, range))
+                .collect(),
+        }
+    }
+
+    /// Supported versions of the given API, or `None` if the broker does not support it at all.
+    pub fn range(&self, api_key: i16) -> Option<RangeInclusive<i16>> {
+        self.ranges.get(&api_key).cloned()
+    }
+
+    /// Whether the broker supports the given version of the given API.
+    pub fn supports(&self, api_key: i16, version: i16) -> bool {
+        self.ranges
+            .get(&api_key)
+            .is_some_and(|range| range.contains(&version))
+    }
+
+    /// All supported APIs with their versions, ordered by API key.
+    pub fn iter(&self) -> impl Iterator<Item = (i16, RangeInclusive<i16>)> + '_ {
+        self.ranges
+            .iter()
+            .map(|(api_key, range)| (*api_key, range.clone()))
+    }
+
+    /// Whether the broker accepts and serves zstd-compressed records.
+    ///
+    /// This requires produce v7 and fetch v10.
+    pub fn supports_zstd(&self) -> bool {
+        self.supports_at_least(ApiKey::Produce, 7) && self.supports_at_least(ApiKey::Fetch, 10)
+    }
+
+    /// Whether the broker supports transactional producers.
+    pub fn supports_transactions(&self) -> bool {
+        [
+            ApiKey::InitProducerId,
+            ApiKey::AddPartitionsToTxn,
+            ApiKey::AddOffsetsToTxn,
+            ApiKey::EndTxn,
+            ApiKey::TxnOffsetCommit,
+        ]
+        .into_iter()
+        .all(|api_key| self.range(Int16::from(api_key).0).is_some())
+    }
+
+    fn supports_at_least(&self, api_key: ApiKey, version: i16) -> bool {
+        self.range(Int16::from(api_key).0)
+            .is_some_and(|range| *range.end() >= version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_versions() {
+        let versions = ApiVersions::new([(ApiKey::Produce, 3..=9), (ApiKey::Fetch, 4..=12)]);
+
+        assert_eq!(versions.range(0), Some(3..=9));
+        assert_eq!(versions.range(3), None);
+        assert!(versions.supports(1, 4));
+        assert!(!versions.supports(1, 13));
+        assert_eq!(
+            versions.iter().collect::<Vec<_>>(),
+            [(0, 3..=9), (1, 4..=12)]
+        );
+        assert!(versions.supports_zstd());
+        assert!(!versions.supports_transactions());
+
+        let versions = ApiVersions::new([(ApiKey::Produce, 3..=6), (ApiKey::Fetch, 4..=12)]);
+        assert!(!versions.supports_zstd());
+    }
+}
@@ -0,0 +1,100 @@
+//! Identity and brokers of the cluster.
+use crate::protocol::messages::MetadataResponse;
+
+/// Cluster that the client is connected to, as reported by the brokers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClusterMetadata {
+    /// The cluster ID, or `None` if the broker is too old to report it.
+    pub cluster_id: Option<String>,
+
+    /// ID of the controller broker, if known.
+    pub controller_id: Option<i32>,
+
+    /// Brokers of the cluster, ordered by ID.
+    pub brokers: Vec<BrokerMetadata>,
+}
+
+impl ClusterMetadata {
+    pub(crate) fn from_response(response: MetadataResponse) -> Self {
+        let mut brokers: Vec<_> = response
+            .brokers
+            .into_iter()
+            .map(|b| BrokerMetadata {
+                id: b.node_id.0,
+                host: b.host.0,
+                port: b.port.0,
+                rack: b.rack.and_then(|r| r.0),
+            })
+            .collect();
+        brokers.sort_by_key(|b| b.id);
+
+        Self {
+            cluster_id: response.cluster_id.and_then(|id| id.0),
+            controller_id: response.controller_id.map(|id| id.0).filter(|id| *id >= 0),
+            brokers,
+        }
+    }
+}
+
+/// Broker of the cluster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BrokerMetadata {
+    pub id: i32,
+    pub host: String,
+    pub port: i32,
+    pub rack: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        messages::MetadataResponseBroker,
+        primitives::{Int32, NullableString, String_},
+    };
+
+    fn broker(id: i32, rack: Option<&str>) -> MetadataResponseBroker {
+        MetadataResponseBroker {
+            node_id: Int32(id),
+            host: String_(format!("kafka-{id}")),
+            port: Int32(9092),
+            rack: Some(NullableString(rack.map(ToOwned::to_owned))),
+            tagged_fields: None,
+        }
+    }
+
+    #[test]
+    fn test_from_response() {
+        let cluster = ClusterMetadata::from_response(MetadataResponse {
+            throttle_time_ms: None,
+            brokers: vec![broker(2, None), broker(1, Some("a"))],
+            cluster_id: Some(NullableString(Some("cluster".to_owned()))),
+            controller_id: Some(Int32(-1)),
+            topics: vec![],
+            cluster_authorized_operations: None,
+            tagged_fields: None,
+        });
+
+        assert_eq!(cluster.cluster_id.as_deref(), Some("cluster"));
+        assert_eq!(cluster.controller_id, None);
+        assert_eq!(
+            cluster.brokers,
+            [
+                BrokerMetadata {
+                    id: 1,
+                    host: "kafka-1".to_owned(),
+                    port: 9092,
+                    rack: Some("a".to_owned()),
+                },
+                BrokerMetadata {
+                    id: 2,
+                    host: "kafka-2".to_owned(),
+                    port: 9092,
+                    rack: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Migration from the [rdkafka](https://docs.rs/rdkafka) crate.
+//!
+//! [`ClientConfig`], [`FutureProducer`] and [`FutureRecord`] mirror the rdkafka types of the same name, so that
+//! existing configuration and call sites can be ported with few changes:
+//!
+//! ```no_run
+//! # async fn test() {
+//! use rskafka::client::compat::{ClientConfig, FutureRecord};
+//!
+//! let producer = ClientConfig::new()
+//!     .set("bootstrap.servers", "localhost:9093")
+//!     .set("linger.ms", "10")
+//!     .create_producer()
+//!     .await
+//!     .unwrap();
+//!
+//! let (partition, offset) = producer
+//!     .send(FutureRecord::to("my_topic").partition(0).key("key").payload("value"))
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Client and producer settings are handled by [`Properties`]. In addition, the consumer settings
+//! `auto.offset.reset`, `fetch.wait.max.ms`, `fetch.min.bytes` and `fetch.max.bytes` are supported.
+//!
+//! The main differences to rdkafka are:
+//!
+//! - There is no partitioner, records must name their partition via [`FutureRecord::partition`].
+//! - There are no consumer groups. Consumers read a single partition, see [`ClientConfig::create_consumer`], and
+//!   offsets have to be tracked by the application.
+//! - Idempotent and transactional producers are not supported. Records are always acknowledged by all in-sync
+//!   replicas.
+//!
+//! Settings that rely on any of these are rejected with [`CompatError::Unsupported`].
+use std::{collections::BTreeMap, sync::Arc};
+
+use bytes::Bytes;
+use chrono::{TimeZone, Utc};
+use thiserror::Error;
+
+use super::{
+    consumer::{StartOffset, StreamConsumer, StreamConsumerBuilder},
+    error::Error as ClientError,
+    partition::UnknownTopicHandling,
+    producer::{
+        aggregator::RecordAggregator, Error as ProducerError, PartitionedBatchProducer,
+        PartitionedBatchProducerBuilder,
+    },
+    properties::{Properties, PropertiesError},
+    Client,
+};
+use crate::record::{Headers, Record};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CompatError {
+    #[error(transparent)]
+    Properties(#[from] PropertiesError),
+
+    #[error("Unsupported setting {key}={value}: {reason}")]
+    Unsupported {
+        key: String,
+        value: String,
+        reason: &'static str,
+    },
+
+    #[error("Client error: {0}")]
+    Client(#[from] ClientError),
+
+    #[error("Producer error: {0}")]
+    Producer(#[from] ProducerError),
+}
+
+/// Consumer settings.
+#[derive(Debug, Clone, Copy)]
+struct ConsumerConfig {
+    start_offset: StartOffset,
+    max_wait_ms: Option<i32>,
+    min_batch_size: Option<i32>,
+    max_batch_size: Option<i32>,
+}
+
+/// Configuration in the style of rdkafka's `ClientConfig`.
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    properties: BTreeMap<String, String>,
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a configuration property.
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    /// Get a configuration property.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// Remove a configuration property.
+    pub fn remove(&mut self, key: &str) -> &mut Self {
+        self.properties.remove(key);
+        self
+    }
+
+    /// Split the settings into the ones handled by [`Properties`] and the consumer settings.
+    fn parse(&self) -> Result<(Properties, ConsumerConfig), CompatError> {
+        let mut consumer = ConsumerConfig {
+            start_offset: StartOffset::Latest,
+            max_wait_ms: None,
+            min_batch_size: None,
+            max_batch_size: None,
+        };
+        let mut properties = vec![];
+
+        for (key, value) in &self.properties {
+            let unsupported = |reason| CompatError::Unsupported {
+                key: key.clone(),
+                value: value.clone(),
+                reason,
+            };
+            let parse_i32 = |value: &str| {
+                value.trim().parse().map_err(|e: std::num::ParseIntError| {
+                    PropertiesError::InvalidValue {
+                        key: key.clone(),
+                        value: value.to_owned(),
+                        reason: e.to_string(),
+                    }
+                })
+            };
+
+            match key.as_str() {
+                "auto.offset.reset" => {
+                    consumer.start_offset = match value.as_str() {
+                        "earliest" | "smallest" | "beginning" => StartOffset::Earliest,
+                        "latest" | "largest" | "end" => StartOffset::Latest,
+                        _ => return Err(unsupported("only earliest and latest are supported")),
+                    }
+                }
+                "fetch.wait.max.ms" => consumer.max_wait_ms = Some(parse_i32(value)?),
+                "fetch.min.bytes" => consumer.min_batch_size = Some(parse_i32(value)?),
+                "fetch.max.bytes" | "max.partition.fetch.bytes" => {
+                    consumer.max_batch_size = Some(parse_i32(value)?)
+                }
+                "acks" | "request.required.acks" => {
+                    if !matches!(value.as_str(), "all" | "-1") {
+                        return Err(unsupported(
+                            "records are always acknowledged by all in-sync replicas",
+                        ));
+                    }
+                }
+                "enable.idempotence" => {
+                    if value != "false" {
+                        return Err(unsupported("the idempotent producer is not supported"));
+                    }
+                }
+                "transactional.id" => {
+                    return Err(unsupported("transactions are not supported"));
+                }
+                "group.id"
+                | "group.instance.id"
+                | "enable.auto.commit"
+                | "auto.commit.interval.ms"
+                | "enable.auto.offset.store"
+                | "session.timeout.ms"
+                | "heartbeat.interval.ms"
+                | "partition.assignment.strategy" => {
+                    return Err(unsupported(
+                        "consumer groups are not supported, consume partitions via \
+                         ClientConfig::create_consumer and track offsets in the application",
+                    ));
+                }
+                "partitioner" => {
+                    return Err(unsupported(
+                        "there is no partitioner, set the partition of every FutureRecord",
+                    ));
+                }
+                _ => properties.push((key.clone(), value.clone())),
+            }
+        }
+
+        Ok((Properties::new(properties)?, consumer))
+    }
+
+    /// Create a [`Client`].
+    ///
+    /// TLS is not supported here, use [`Properties`] with a TLS config instead.
+    pub async fn create_client(&self) -> Result<Client, CompatError> {
+        let (properties, _) = self.parse()?;
+        Ok(properties.client_builder()?.build().await?)
+    }
+
+    /// Create a producer with its own [`Client`].
+    pub async fn create_producer(&self) -> Result<FutureProducer, CompatError> {
+        let (properties, _) = self.parse()?;
+        let client = Arc::new(properties.client_builder()?.build().await?);
+        let producer = properties
+            .configure_partitioned_producer(PartitionedBatchProducerBuilder::new(
+                client,
+                UnknownTopicHandling::Error,
+            ))
+            .build(move |_, _| properties.record_aggregator());
+        Ok(FutureProducer { producer })
+    }
+
+    /// Create a consumer for a single partition with its own [`Client`].
+    ///
+    /// The consumer starts at the position given by `auto.offset.reset`, which defaults to `latest`. Use
+    /// [`StreamConsumerBuilder`] directly to start at a stored offset.
+    pub async fn create_consumer(
+        &self,
+        topic: impl Into<String> + Send,
+        partition: i32,
+    ) -> Result<StreamConsumer, CompatError> {
+        let (properties, consumer) = self.parse()?;
+        let client = properties.client_builder()?.build().await?;
+        let partition_client = client
+            .partition_client(topic, partition, UnknownTopicHandling::Error)
+            .await?;
+
+        let mut builder = StreamConsumerBuilder::new(partition_client, consumer.start_offset);
+        if let Some(max_wait_ms) = consumer.max_wait_ms {
+            builder = builder.with_max_wait_ms(max_wait_ms);
+        }
+        if let Some(min_batch_size) = consumer.min_batch_size {
+            builder = builder.with_min_batch_size(min_batch_size);
+        }
+        if let Some(max_batch_size) = consumer.max_batch_size {
+            builder = builder.with_max_batch_size(max_batch_size);
+        }
+        Ok(builder.build())
+    }
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let properties: BTreeMap<_, _> = self
+            .properties
+            .iter()
+            .map(|(k, v)| {
+                let v = if k.contains("password") {
+                    "<redacted>"
+                } else {
+                    v.as_str()
+                };
+                (k.as_str(), v)
+            })
+            .collect();
+        f.debug_struct("ClientConfig")
+            .field("properties", &properties)
+            .finish()
+    }
+}
+
+/// Record to be sent by a [`FutureProducer`], in the style of rdkafka's `FutureRecord`.
+#[derive(Debug, Clone)]
+pub struct FutureRecord<'a> {
+    topic: &'a str,
+    partition: Option<i32>,
+    key: Option<&'a [u8]>,
+    payload: Option<&'a [u8]>,
+    timestamp: Option<i64>,
+    headers: Headers,
+}
+
+impl<'a> FutureRecord<'a> {
+    /// Create a record for the given topic.
+    pub fn to(topic: &'a str) -> Self {
+        Self {
+            topic,
+            partition: None,
+            key: None,
+            payload: None,
+            timestamp: None,
+            headers: Headers::new(),
+        }
+    }
+
+    /// Set the partition, which is required.
+    pub fn partition(self, partition: i32) -> Self {
+        Self {
+            partition: Some(partition),
+            ..self
+        }
+    }
+
+    pub fn key<K>(self, key: &'a K) -> Self
+    where
+        K: AsRef<[u8]> + ?Sized,
+    {
+        Self {
+            key: Some(key.as_ref()),
+            ..self
+        }
+    }
+
+    pub fn payload<P>(self, payload: &'a P) -> Self
+    where
+        P: AsRef<[u8]> + ?Sized,
+    {
+        Self {
+            payload: Some(payload.as_ref()),
+            ..self
+        }
+    }
+
+    /// Set the timestamp in milliseconds since the Unix epoch. Defaults to the current time.
+    pub fn timestamp(self, timestamp: i64) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    /// Add a header, replacing any previous header with the same key.
+    pub fn header(mut self, key: impl Into<String>, value: impl AsRef<[u8]>) -> Self {
+        self.headers
+            .insert(key, Bytes::copy_from_slice(value.as_ref()));
+        self
+    }
+
+    fn into_record(self) -> Result<(&'a str, i32, Record), CompatError> {
+        let unsupported = |key: &str, value: String, reason| CompatError::Unsupported {
+            key: key.to_owned(),
+            value,
+            reason,
+        };
+        let partition = self.partition.ok_or_else(|| {
+            unsupported(
+                "partition",
+                "<unset>".to_owned(),
+                "there is no partitioner, set the partition of every FutureRecord",
+            )
+        })?;
+
+        let mut builder = Record::builder();
+        if let Some(key) = self.key {
+            builder = builder.key(Bytes::copy_from_slice(key));
+        }
+        if let Some(payload) = self.payload {
+            builder = builder.value(Bytes::copy_from_slice(payload));
+        }
+        for (key, value) in self.headers {
+            builder = builder.append_header(key, value);
+        }
+        if let Some(timestamp) = self.timestamp {
+            let timestamp = Utc
+                .timestamp_millis_opt(timestamp)
+                .single()
+                .ok_or_else(|| {
+                    unsupported("timestamp", timestamp.to_string(), "timestamp out of range")
+                })?;
+            builder = builder.timestamp(timestamp);
+        }
+
+        Ok((self.topic, partition, builder.build()))
+    }
+}
+
+/// Producer in the style of rdkafka's `FutureProducer`.
+///
+/// Records are aggregated per partition and written in batches, see [`PartitionedBatchProducer`].
+#[derive(Debug)]
+pub struct FutureProducer {
+    producer: PartitionedBatchProducer<RecordAggregator>,
+}
+
+impl FutureProducer {
+    /// Send `record` and return its partition and offset once it was written.
+    pub async fn send(&self, record: FutureRecord<'_>) -> Result<(i32, i64), CompatError> {
+        let (topic, partition, record) = record.into_record()?;
+        let offset = self.producer.produce(topic, partition, record).await?;
+        Ok((partition, offset))
+    }
+
+    /// Wait until all pending records are written.
+    pub async fn flush(&self) -> Result<(), CompatError> {
+        Ok(self.producer.flush().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", "localhost:9093")
+            .set("linger.ms", "10")
+            .set("acks", "all")
+            .set("auto.offset.reset", "smallest")
+            .set("fetch.wait.max.ms", "100")
+            .set("sasl.password", "secret");
+        assert_eq!(config.get("linger.ms"), Some("10"));
+        assert!(!format!("{config:?}").contains("secret"));
+
+        config.remove("sasl.password");
+        let (_properties, consumer) = config.parse().unwrap();
+        assert_matches!(consumer.start_offset, StartOffset::Earliest);
+        assert_eq!(consumer.max_wait_ms, Some(100));
+
+        assert_matches!(
+            config.clone().set("group.id", "my-group").parse(),
+            Err(CompatError::Unsupported { key, .. }) if key == "group.id"
+        );
+        assert_matches!(
+            config.clone().set("acks", "1").parse(),
+            Err(CompatError::Unsupported { key, .. }) if key == "acks"
+        );
+        assert_matches!(
+            config.clone().set("foo", "bar").parse(),
+            Err(CompatError::Properties(PropertiesError::Unknown(key))) if key == "foo"
+        );
+        assert_matches!(
+            config.clone().set("retry.backoff.ms", "0").parse(),
+            Err(CompatError::Properties(PropertiesError::InvalidValue { key, .. }))
+                if key == "retry.backoff.ms"
+        );
+    }
+
+    #[test]
+    fn test_future_record() {
+        let (topic, partition, record) = FutureRecord::to("my_topic")
+            .partition(1)
+            .key("k")
+            .payload(&[1u8, 2][..])
+            .header("h", "v")
+            .timestamp(1337)
+            .into_record()
+            .unwrap();
+        assert_eq!(topic, "my_topic");
+        assert_eq!(partition, 1);
+        assert_eq!(
+            record,
+            Record {
+                key: Some(b"k".to_vec().into()),
+                value: Some(vec![1, 2].into()),
+                headers: Headers::from([("h", "v")]),
+                timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+            }
+        );
+
+        assert_matches!(
+            FutureRecord::to("my_topic").payload("v").into_record(),
+            Err(CompatError::Unsupported { key, .. }) if key == "partition"
+        );
+    }
+}
@@ -12,18 +12,15 @@
 //!     },
 //!     partition::UnknownTopicHandling,
 //! };
-//! use std::sync::Arc;
 //!
 //! // get partition client
 //! let connection = "localhost:9093".to_owned();
 //! let client = ClientBuilder::new(vec![connection]).build().await.unwrap();
-//! let partition_client = Arc::new(
-//!     client.partition_client(
-//!         "my_topic",
-//!         0,
-//!         UnknownTopicHandling::Retry,
-//!     ).await.unwrap()
-//! );
+//! let partition_client = client.partition_client(
+//!     "my_topic",
+//!     0,
+//!     UnknownTopicHandling::Retry,
+//! ).await.unwrap();
 //!
 //! // construct stream consumer
 //! let mut stream = StreamConsumerBuilder::new(
@@ -44,12 +41,15 @@
 use std::collections::VecDeque;
 use std::ops::Range;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
 use futures::future::{BoxFuture, Fuse, FusedFuture, FutureExt};
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::Notify;
 use tracing::{debug, trace, warn};
 
 use crate::{
@@ -57,7 +57,9 @@ use crate::{
         error::{Error, ProtocolError, Result},
         partition::PartitionClient,
     },
+    codec::{self, Codec, TypedRecordAndOffset},
     record::RecordAndOffset,
+    runtime,
 };
 
 use super::partition::OffsetAt;
@@ -82,6 +84,105 @@ pub enum StartOffset {
     At(i64),
 }
 
+/// Memory budget for records that [`StreamConsumer`]s fetched but did not yield yet.
+///
+/// Share a budget between the consumers of multiple partitions via [`StreamConsumerBuilder::with_fetch_budget`].
+/// While their buffered records exceed the budget, consumers that ran out of records do not fetch until the
+/// application drained the others. This keeps a slow handler of one partition from piling up the data of a
+/// high-volume topic in memory. Since consumers that are fetching already may finish, the budget can be exceeded by
+/// up to one fetch per consumer.
+///
+/// Sizes are the [approximate sizes](crate::record::Record::approximate_size) of the records.
+#[derive(Debug, Clone)]
+pub struct FetchBudget {
+    inner: Arc<FetchBudgetInner>,
+}
+
+#[derive(Debug)]
+struct FetchBudgetInner {
+    max_bytes: usize,
+    buffered_bytes: AtomicUsize,
+    drained: Notify,
+}
+
+impl FetchBudget {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            inner: Arc::new(FetchBudgetInner {
+                max_bytes,
+                buffered_bytes: AtomicUsize::new(0),
+                drained: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.inner.max_bytes
+    }
+
+    /// Bytes of the records that the consumers sharing this budget currently buffer.
+    pub fn buffered_bytes(&self) -> usize {
+        self.inner.buffered_bytes.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, bytes: usize) {
+        self.inner
+            .buffered_bytes
+            .fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        self.inner
+            .buffered_bytes
+            .fetch_sub(bytes, Ordering::Relaxed);
+        self.inner.drained.notify_waiters();
+    }
+
+    /// Wait until the buffered records do not exceed the budget.
+    async fn wait(&self) {
+        loop {
+            // registered before checking, so that releases in between are not missed
+            let drained = self.inner.drained.notified();
+            let buffered_bytes = self.buffered_bytes();
+            if buffered_bytes <= self.inner.max_bytes {
+                return;
+            }
+            debug!(
+                buffered_bytes,
+                max_bytes = self.inner.max_bytes,
+                "fetch budget exhausted, waiting for records to be consumed",
+            );
+            drained.await;
+        }
+    }
+}
+
+/// Offsets that are missing from the fetched data, e.g. because records were lost during a leader failover.
+///
+/// Record batches keep their offset range when log compaction removes some of their records, and transaction markers
+/// take up offsets as well, so neither leads to gaps. Compaction can remove whole batches though, so gaps are expected
+/// on compacted topics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OffsetGap {
+    /// Offset that the next record batch was expected to start at.
+    pub expected: i64,
+
+    /// Offset that the next record batch actually starts at.
+    pub actual: i64,
+}
+
+/// Receives the gaps that a [`StreamConsumer`] detects, see [`StreamConsumerBuilder::with_gap_detection`].
+///
+/// The handler is called from within the stream when the records are fetched, before the records after the gap are
+/// yielded. It should not block.
+pub trait OffsetGapHandler: std::fmt::Debug + Send + Sync {
+    fn handle(&self, gap: &OffsetGap);
+}
+
 #[derive(Debug)]
 pub struct StreamConsumerBuilder {
     client: Arc<dyn FetchClient>,
@@ -93,6 +194,12 @@ pub struct StreamConsumerBuilder {
     min_batch_size: i32,
 
     max_batch_size: i32,
+
+    max_poll_records: usize,
+
+    budget: Option<FetchBudget>,
+
+    gap_handler: Option<Arc<dyn OffsetGapHandler>>,
 }
 
 impl StreamConsumerBuilder {
@@ -110,6 +217,10 @@ impl StreamConsumerBuilder {
             max_wait_ms: 500,
             min_batch_size: 1,
             max_batch_size: 52428800,
+            // same default as the Java consumer
+            max_poll_records: 500,
+            budget: None,
+            gap_handler: None,
         }
     }
 
@@ -137,18 +248,52 @@ impl StreamConsumerBuilder {
         }
     }
 
+    /// The maximum number of records returned by a single [`next_batch`](StreamConsumer::next_batch) call
+    ///
+    /// Records beyond that are buffered and returned by the next call, independent of how many records the broker
+    /// returned in a single fetch.
+    ///
+    /// # Panics
+    /// Panics if `max_poll_records` is zero.
+    pub fn with_max_poll_records(self, max_poll_records: usize) -> Self {
+        assert!(max_poll_records > 0, "max_poll_records must be positive");
+        Self {
+            max_poll_records,
+            ..self
+        }
+    }
+
+    /// Account the buffered records against `budget` and do not fetch while it is exceeded, see [`FetchBudget`].
+    pub fn with_fetch_budget(self, budget: FetchBudget) -> Self {
+        Self {
+            budget: Some(budget),
+            ..self
+        }
+    }
+
+    /// Verify that the fetched offsets are contiguous and pass gaps to `handler`, see [`OffsetGap`].
+    pub fn with_gap_detection(self, handler: impl OffsetGapHandler + 'static) -> Self {
+        Self {
+            gap_handler: Some(Arc::new(handler)),
+            ..self
+        }
+    }
+
     pub fn build(self) -> StreamConsumer {
         StreamConsumer {
             client: self.client,
             max_wait_ms: self.max_wait_ms,
             min_batch_size: self.min_batch_size,
             max_batch_size: self.max_batch_size,
+            max_poll_records: self.max_poll_records,
             next_offset: None,
             next_backoff: None,
             start_offset: self.start_offset,
             terminated: false,
             last_high_watermark: -1,
             buffer: Default::default(),
+            budget: self.budget,
+            gap_handler: self.gap_handler,
             fetch_fut: Fuse::terminated(),
         }
     }
@@ -156,15 +301,19 @@ impl StreamConsumerBuilder {
 
 struct FetchResultOk {
     records_and_offsets: Vec<RecordAndOffset>,
+    gaps: Vec<OffsetGap>,
     watermark: i64,
     used_offset: i64,
 }
 
 type FetchResult = Result<FetchResultOk>;
 
+/// Fetched records, the gaps in their offsets and the high watermark.
+type FetchedRecords = (Vec<RecordAndOffset>, Vec<OffsetGap>, i64);
+
 /// A trait wrapper to allow mocking
 trait FetchClient: std::fmt::Debug + Send + Sync {
-    /// Fetch records.
+    /// Fetch records and the gaps in their offsets.
     ///
     /// Arguments are identical to [`PartitionClient::fetch_records`].
     fn fetch_records(
@@ -172,7 +321,7 @@ trait FetchClient: std::fmt::Debug + Send + Sync {
         offset: i64,
         bytes: Range<i32>,
         max_wait_ms: i32,
-    ) -> BoxFuture<'_, Result<(Vec<RecordAndOffset>, i64)>>;
+    ) -> BoxFuture<'_, Result<FetchedRecords>>;
 
     /// Get offset.
     ///
@@ -186,8 +335,8 @@ impl FetchClient for PartitionClient {
         offset: i64,
         bytes: Range<i32>,
         max_wait_ms: i32,
-    ) -> BoxFuture<'_, Result<(Vec<RecordAndOffset>, i64)>> {
-        Box::pin(self.fetch_records(offset, bytes, max_wait_ms))
+    ) -> BoxFuture<'_, Result<FetchedRecords>> {
+        Box::pin(self.fetch_records_and_gaps(offset, bytes, max_wait_ms))
     }
 
     fn get_offset(&self, at: OffsetAt) -> BoxFuture<'_, Result<i64>> {
@@ -209,6 +358,8 @@ pub struct StreamConsumer {
 
     max_wait_ms: i32,
 
+    max_poll_records: usize,
+
     start_offset: StartOffset,
 
     next_offset: Option<i64>,
@@ -221,9 +372,61 @@ pub struct StreamConsumer {
 
     buffer: VecDeque<RecordAndOffset>,
 
+    budget: Option<FetchBudget>,
+
+    gap_handler: Option<Arc<dyn OffsetGapHandler>>,
+
     fetch_fut: Fuse<BoxFuture<'static, FetchResult>>,
 }
 
+impl StreamConsumer {
+    /// Offset of the next record that the stream yields, e.g. to report progress or to restart consuming at.
+    ///
+    /// This is `None` while a [`StartOffset::Earliest`] or [`StartOffset::Latest`] start offset is not resolved yet,
+    /// i.e. before the first fetch and after records were deleted in between resolving the offset and fetching.
+    pub fn position(&self) -> Option<i64> {
+        match (self.buffer.front(), self.next_offset, self.start_offset) {
+            (Some(record), _, _) => Some(record.offset),
+            (None, Some(offset), _) | (None, None, StartOffset::At(offset)) => Some(offset),
+            (None, None, StartOffset::Earliest | StartOffset::Latest) => None,
+        }
+    }
+
+    /// Wait for the next records and return up to
+    /// [`max_poll_records`](StreamConsumerBuilder::with_max_poll_records) of them, together with the high watermark.
+    ///
+    /// Records from one fetch that exceed the limit are returned by subsequent calls before fetching again. Returns
+    /// `None` once the stream terminated, see [error handling](Self#error-handling).
+    pub async fn next_batch(&mut self) -> Option<Result<(Vec<RecordAndOffset>, i64)>> {
+        let (first, high_watermark) = match self.next().await? {
+            Ok(x) => x,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let n = self.buffer.len().min(self.max_poll_records - 1);
+        let mut records = Vec::with_capacity(n + 1);
+        records.push(first);
+        records.extend(self.buffer.drain(..n));
+        if let Some(budget) = &self.budget {
+            budget.release(
+                records[1..]
+                    .iter()
+                    .map(|x| x.record.approximate_size())
+                    .sum(),
+            );
+        }
+        Some(Ok((records, high_watermark)))
+    }
+
+    fn pop_buffered(&mut self) -> Option<RecordAndOffset> {
+        let x = self.buffer.pop_front()?;
+        if let Some(budget) = &self.budget {
+            budget.release(x.record.approximate_size());
+        }
+        Some(x)
+    }
+}
+
 impl Stream for StreamConsumer {
     type Item = Result<(RecordAndOffset, i64)>;
 
@@ -232,7 +435,7 @@ impl Stream for StreamConsumer {
             if self.terminated {
                 return Poll::Ready(None);
             }
-            if let Some(x) = self.buffer.pop_front() {
+            if let Some(x) = self.pop_buffered() {
                 return Poll::Ready(Some(Ok((x, self.last_high_watermark))));
             }
 
@@ -243,12 +446,16 @@ impl Stream for StreamConsumer {
                 let max_wait_ms = self.max_wait_ms;
                 let next_backoff = std::mem::take(&mut self.next_backoff);
                 let client = Arc::clone(&self.client);
+                let budget = self.budget.clone();
 
                 trace!(?start_offset, ?next_offset, "Fetching records at offset");
 
                 self.fetch_fut = FutureExt::fuse(Box::pin(async move {
                     if let Some(backoff) = next_backoff {
-                        tokio::time::sleep(backoff).await;
+                        runtime::sleep(backoff).await;
+                    }
+                    if let Some(budget) = budget {
+                        budget.wait().await;
                     }
 
                     let offset = match next_offset {
@@ -268,10 +475,11 @@ impl Stream for StreamConsumer {
                         },
                     };
 
-                    let (records_and_offsets, watermark) =
+                    let (records_and_offsets, gaps, watermark) =
                         client.fetch_records(offset, bytes, max_wait_ms).await?;
                     Ok(FetchResultOk {
                         records_and_offsets,
+                        gaps,
                         watermark,
                         used_offset: offset,
                     })
@@ -284,9 +492,20 @@ impl Stream for StreamConsumer {
                 (Ok(inner), _) => {
                     let FetchResultOk {
                         mut records_and_offsets,
+                        gaps,
                         watermark,
                         used_offset,
                     } = inner;
+                    if let Some(handler) = &self.gap_handler {
+                        for gap in &gaps {
+                            warn!(
+                                expected = gap.expected,
+                                actual = gap.actual,
+                                "Gap in fetched offsets",
+                            );
+                            handler.handle(gap);
+                        }
+                    }
                     trace!(
                         high_watermark = watermark,
                         n_records = records_and_offsets.len(),
@@ -303,6 +522,14 @@ impl Stream for StreamConsumer {
                     self.last_high_watermark = watermark;
                     if let Some(x) = records_and_offsets.last() {
                         self.next_offset = Some(x.offset + 1);
+                        if let Some(budget) = &self.budget {
+                            budget.add(
+                                records_and_offsets
+                                    .iter()
+                                    .map(|x| x.record.approximate_size())
+                                    .sum(),
+                            );
+                        }
                         self.buffer.extend(records_and_offsets)
                     }
                     continue;
@@ -351,14 +578,180 @@ impl std::fmt::Debug for StreamConsumer {
             .field("min_batch_size", &self.min_batch_size)
             .field("max_batch_size", &self.max_batch_size)
             .field("max_wait_ms", &self.max_wait_ms)
+            .field("max_poll_records", &self.max_poll_records)
             .field("next_offset", &self.next_offset)
             .field("terminated", &self.terminated)
             .field("last_high_watermark", &self.last_high_watermark)
             .field("buffer", &self.buffer)
+            .field("budget", &self.budget)
+            .field("gap_handler", &self.gap_handler)
             .finish_non_exhaustive()
     }
 }
 
+impl Drop for StreamConsumer {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(
+                self.buffer
+                    .iter()
+                    .map(|x| x.record.approximate_size())
+                    .sum(),
+            );
+        }
+    }
+}
+
+/// Error of [`DeserializingConsumer`].
+#[derive(Debug, Error)]
+pub enum DeserializeError {
+    #[error(transparent)]
+    Client(#[from] Error),
+
+    /// The record at `offset` cannot be decoded, `record` is the raw record as fetched.
+    #[error("Cannot decode record at offset {offset}: {source}")]
+    Decode {
+        offset: i64,
+        record: RecordAndOffset,
+        source: codec::Error,
+    },
+}
+
+/// Receives records that [`DeserializingConsumer`] cannot decode, e.g. to write them to a dead letter topic.
+///
+/// The handler is called synchronously from within the stream, so it should not block.
+pub trait DecodeErrorHandler: std::fmt::Debug + Send + Sync {
+    /// Handle the raw `record` that failed to decode with `error`.
+    fn handle(&self, record: &RecordAndOffset, error: &codec::Error);
+}
+
+/// What [`DeserializingConsumer`] does with records that cannot be decoded.
+#[derive(Debug, Clone, Default)]
+pub enum DecodeErrorPolicy {
+    /// Yield [`DeserializeError::Decode`] and continue with the next record.
+    #[default]
+    Report,
+
+    /// Log a warning and continue with the next record.
+    Skip,
+
+    /// Pass the record to the handler and continue with the next record.
+    Handle(Arc<dyn DecodeErrorHandler>),
+
+    /// Yield [`DeserializeError::Decode`] and terminate the stream.
+    Fail,
+}
+
+/// [`StreamConsumer`] that decodes keys and values via [`Codec`]s.
+///
+/// # Error Handling
+/// Records that cannot be decoded are handled according to the [`DecodeErrorPolicy`]. By default they are reported as
+/// [`DeserializeError::Decode`], after which the stream continues with the next record. Fetch errors terminate the
+/// stream, see [`StreamConsumer`].
+pub struct DeserializingConsumer<K, V> {
+    consumer: StreamConsumer,
+    key_codec: Arc<dyn Codec<K>>,
+    value_codec: Arc<dyn Codec<V>>,
+    decode_error_policy: DecodeErrorPolicy,
+    terminated: bool,
+}
+
+impl<K, V> DeserializingConsumer<K, V> {
+    pub fn new(
+        consumer: StreamConsumer,
+        key_codec: impl Codec<K> + 'static,
+        value_codec: impl Codec<V> + 'static,
+    ) -> Self {
+        Self {
+            consumer,
+            key_codec: Arc::new(key_codec),
+            value_codec: Arc::new(value_codec),
+            decode_error_policy: DecodeErrorPolicy::default(),
+            terminated: false,
+        }
+    }
+
+    /// How to handle records that cannot be decoded.
+    ///
+    /// Defaults to [`DecodeErrorPolicy::Report`].
+    pub fn with_decode_error_policy(self, decode_error_policy: DecodeErrorPolicy) -> Self {
+        Self {
+            decode_error_policy,
+            ..self
+        }
+    }
+
+    /// Offset of the next record that the stream yields, see [`StreamConsumer::position`].
+    pub fn position(&self) -> Option<i64> {
+        self.consumer.position()
+    }
+}
+
+impl<K, V> Stream for DeserializingConsumer<K, V> {
+    type Item = Result<(TypedRecordAndOffset<K, V>, i64), DeserializeError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.terminated {
+                return Poll::Ready(None);
+            }
+
+            let (record, high_watermark) = match futures::ready!(self.consumer.poll_next_unpin(cx))
+            {
+                Some(Ok(x)) => x,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+
+            let source = match TypedRecordAndOffset::decode(
+                &record,
+                self.key_codec.as_ref(),
+                self.value_codec.as_ref(),
+            ) {
+                Ok(decoded) => return Poll::Ready(Some(Ok((decoded, high_watermark)))),
+                Err(e) => e,
+            };
+
+            match &self.decode_error_policy {
+                DecodeErrorPolicy::Report => {}
+                DecodeErrorPolicy::Skip => {
+                    warn!(
+                        offset = record.offset,
+                        e = %source,
+                        "skipping record that cannot be decoded",
+                    );
+                    continue;
+                }
+                DecodeErrorPolicy::Handle(handler) => {
+                    handler.handle(&record, &source);
+                    continue;
+                }
+                DecodeErrorPolicy::Fail => {
+                    self.terminated = true;
+                }
+            }
+
+            return Poll::Ready(Some(Err(DeserializeError::Decode {
+                offset: record.offset,
+                record,
+                source,
+            })));
+        }
+    }
+}
+
+impl<K, V> std::fmt::Debug for DeserializingConsumer<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeserializingConsumer")
+            .field("consumer", &self.consumer)
+            .field("key_codec", &self.key_codec)
+            .field("value_codec", &self.value_codec)
+            .field("decode_error_policy", &self.decode_error_policy)
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -370,6 +763,7 @@ mod tests {
 
     use crate::{
         client::error::{Error, ProtocolError, RequestContext},
+        codec::StringCodec,
         record::Record,
     };
 
@@ -387,6 +781,7 @@ mod tests {
         next_err: Option<Error>,
         buffer: Vec<Record>,
         range: (i64, i64),
+        next_gaps: Vec<OffsetGap>,
     }
 
     impl MockFetch {
@@ -398,6 +793,7 @@ mod tests {
                     buffer: Default::default(),
                     next_err,
                     range,
+                    next_gaps: vec![],
                 })),
             }
         }
@@ -413,7 +809,7 @@ mod tests {
             start_offset: i64,
             bytes: Range<i32>,
             max_wait_ms: i32,
-        ) -> BoxFuture<'_, Result<(Vec<RecordAndOffset>, i64)>> {
+        ) -> BoxFuture<'_, Result<FetchedRecords>> {
             let inner = Arc::clone(&self.inner);
             Box::pin(async move {
                 if let Some(err) = inner.lock().await.next_err.take() {
@@ -491,7 +887,8 @@ mod tests {
 
                 inner.batch_sizes.push(buffer.len());
 
-                Ok((buffer, inner.buffer.len() as i64 - 1))
+                let gaps = std::mem::take(&mut inner.next_gaps);
+                Ok((buffer, gaps, inner.buffer.len() as i64 - 1))
             })
         }
 
@@ -510,8 +907,8 @@ mod tests {
     #[tokio::test]
     async fn test_consumer() {
         let record = Record {
-            key: Some(vec![0; 4]),
-            value: Some(vec![0; 6]),
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
@@ -574,11 +971,101 @@ mod tests {
         assert_eq!(&received, &[1, 3]);
     }
 
+    #[tokio::test]
+    async fn test_consumer_fetch_budget() {
+        // 10 bytes each
+        let record = Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let budget = FetchBudget::new(10);
+
+        let consumer = |n: usize| {
+            let (sender, receiver) = mpsc::channel(10);
+            for _ in 0..n {
+                sender.try_send(record.clone()).unwrap();
+            }
+            let client = Arc::new(MockFetch::new(receiver, None, (0, 1_000)));
+            let stream = StreamConsumerBuilder::new_with_client(
+                Arc::<MockFetch>::clone(&client) as _,
+                StartOffset::At(0),
+            )
+            .with_max_wait_ms(10)
+            .with_fetch_budget(budget.clone())
+            .build();
+            (client, stream, sender)
+        };
+        let (_client_a, mut stream_a, _sender_a) = consumer(3);
+        let (client_b, mut stream_b, _sender_b) = consumer(1);
+
+        assert_eq!(stream_a.next().await.unwrap().unwrap().0.offset, 0);
+        assert_eq!(budget.buffered_bytes(), 20);
+
+        // the other consumer does not even fetch while the budget is exceeded
+        assert_stream_pending(&mut stream_b).await;
+        assert!(client_b.batch_sizes().await.is_empty());
+
+        assert_eq!(stream_a.next().await.unwrap().unwrap().0.offset, 1);
+        assert_eq!(budget.buffered_bytes(), 10);
+        let (record_and_offset, _) = tokio::time::timeout(Duration::from_secs(1), stream_b.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(record_and_offset.offset, 0);
+        assert_eq!(client_b.batch_sizes().await, [1]);
+
+        // buffered records of dropped consumers are released
+        drop(stream_a);
+        assert_eq!(budget.buffered_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_consumer_gap_detection() {
+        #[derive(Debug, Default)]
+        struct Gaps(std::sync::Mutex<Vec<OffsetGap>>);
+
+        impl OffsetGapHandler for Arc<Gaps> {
+            fn handle(&self, gap: &OffsetGap) {
+                self.0.lock().unwrap().push(*gap);
+            }
+        }
+
+        let record = Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let (sender, receiver) = mpsc::channel(10);
+        sender.send(record).await.unwrap();
+        let client = Arc::new(MockFetch::new(receiver, None, (0, 1_000)));
+        let gap = OffsetGap {
+            expected: 0,
+            actual: 3,
+        };
+        client.inner.lock().await.next_gaps = vec![gap];
+
+        let gaps = Arc::new(Gaps::default());
+        let mut stream = StreamConsumerBuilder::new_with_client(
+            Arc::<MockFetch>::clone(&client) as _,
+            StartOffset::At(0),
+        )
+        .with_max_wait_ms(10)
+        .with_gap_detection(Arc::clone(&gaps))
+        .build();
+
+        stream.next().await.unwrap().unwrap();
+        assert_eq!(*gaps.0.lock().unwrap(), [gap]);
+    }
+
     #[tokio::test]
     async fn test_consumer_timeout() {
         let record = Record {
-            key: Some(vec![0; 4]),
-            value: Some(vec![0; 6]),
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
@@ -645,6 +1132,7 @@ mod tests {
             request: RequestContext::Partition("foo".into(), 1),
             response: None,
             is_virtual: true,
+            info: None,
         };
         let (_sender, receiver) = mpsc::channel(10);
         let consumer = Arc::new(MockFetch::new(receiver, Some(e), (0, 1_000)));
@@ -668,8 +1156,8 @@ mod tests {
     #[tokio::test]
     async fn test_consumer_earliest() {
         let record = Record {
-            key: Some(vec![0; 4]),
-            value: Some(vec![0; 6]),
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
@@ -681,6 +1169,7 @@ mod tests {
             request: RequestContext::Partition("foo".into(), 1),
             response: None,
             is_virtual: true,
+            info: None,
         };
 
         let (sender, receiver) = mpsc::channel(10);
@@ -715,8 +1204,8 @@ mod tests {
     #[tokio::test]
     async fn test_consumer_latest() {
         let record = Record {
-            key: Some(vec![0; 4]),
-            value: Some(vec![0; 6]),
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
@@ -728,6 +1217,7 @@ mod tests {
             request: RequestContext::Partition("foo".into(), 1),
             response: None,
             is_virtual: true,
+            info: None,
         };
 
         let (sender, receiver) = mpsc::channel(10);
@@ -759,6 +1249,177 @@ mod tests {
         assert_eq!(high_watermark, 2);
     }
 
+    #[tokio::test]
+    async fn test_deserializing_consumer() {
+        let record = |value: &[u8]| Record {
+            key: None,
+            value: Some(value.to_vec().into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        let (sender, receiver) = mpsc::channel(10);
+        let consumer = Arc::new(MockFetch::new(receiver, None, (0, 1_000)));
+        let mut stream = DeserializingConsumer::new(
+            StreamConsumerBuilder::new_with_client(consumer, StartOffset::At(0))
+                .with_max_wait_ms(10)
+                .build(),
+            StringCodec,
+            StringCodec,
+        );
+
+        sender.send(record(b"foo")).await.unwrap();
+        sender.send(record(&[0xff])).await.unwrap();
+        sender.send(record(b"bar")).await.unwrap();
+
+        let (record_and_offset, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(record_and_offset.offset, 0);
+        assert_eq!(record_and_offset.record.key, None);
+        assert_eq!(record_and_offset.record.value.as_deref(), Some("foo"));
+
+        // invalid records do not terminate the stream
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_matches!(err, DeserializeError::Decode { offset: 1, record, .. } => {
+            assert_eq!(record.record.value.as_deref(), Some([0xff].as_slice()));
+        });
+
+        let (record_and_offset, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(record_and_offset.offset, 2);
+        assert_eq!(record_and_offset.record.value.as_deref(), Some("bar"));
+    }
+
+    #[derive(Debug, Default)]
+    struct CollectingHandler(std::sync::Mutex<Vec<i64>>);
+
+    impl DecodeErrorHandler for CollectingHandler {
+        fn handle(&self, record: &RecordAndOffset, _error: &codec::Error) {
+            self.0.lock().unwrap().push(record.offset);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deserializing_consumer_decode_error_policy() {
+        let record = |value: &[u8]| Record {
+            key: None,
+            value: Some(value.to_vec().into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        // values of the records at offsets 0 to 3, offsets 1 and 2 cannot be decoded
+        let consumer = |policy: DecodeErrorPolicy| {
+            let (sender, receiver) = mpsc::channel(10);
+            for value in [b"foo".as_slice(), &[0xff], &[0xfe], b"bar"] {
+                sender.try_send(record(value)).unwrap();
+            }
+            let consumer = Arc::new(MockFetch::new(receiver, None, (0, 1_000)));
+            let stream = DeserializingConsumer::<String, String>::new(
+                StreamConsumerBuilder::new_with_client(consumer, StartOffset::At(0))
+                    .with_max_wait_ms(10)
+                    .build(),
+                StringCodec,
+                StringCodec,
+            )
+            .with_decode_error_policy(policy);
+            (stream, sender)
+        };
+        let offset = |item: Option<Result<(TypedRecordAndOffset<_, _>, i64), _>>| {
+            item.unwrap().unwrap().0.offset
+        };
+
+        let (mut stream, _sender) = consumer(DecodeErrorPolicy::Skip);
+        assert_eq!(offset(stream.next().await), 0);
+        assert_eq!(offset(stream.next().await), 3);
+
+        let handler = Arc::new(CollectingHandler::default());
+        let (mut stream, _sender) = consumer(DecodeErrorPolicy::Handle(
+            Arc::<CollectingHandler>::clone(&handler),
+        ));
+        assert_eq!(offset(stream.next().await), 0);
+        assert_eq!(offset(stream.next().await), 3);
+        assert_eq!(*handler.0.lock().unwrap(), [1, 2]);
+
+        let (mut stream, _sender) = consumer(DecodeErrorPolicy::Fail);
+        assert_eq!(offset(stream.next().await), 0);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_matches!(err, DeserializeError::Decode { offset: 1, .. });
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_consumer_position() {
+        let record = Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        let (sender, receiver) = mpsc::channel(10);
+        let consumer = Arc::new(MockFetch::new(receiver, None, (1, 1_000)));
+        let mut stream = StreamConsumerBuilder::new_with_client(
+            Arc::<MockFetch>::clone(&consumer),
+            StartOffset::Earliest,
+        )
+        .with_max_wait_ms(10)
+        .build();
+        assert_eq!(stream.position(), None);
+
+        for _ in 0..3 {
+            sender.send(record.clone()).await.unwrap();
+        }
+
+        let (record_and_offset, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(record_and_offset.offset, 1);
+        assert_eq!(stream.position(), Some(2));
+
+        let (record_and_offset, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(record_and_offset.offset, 2);
+        assert_eq!(stream.position(), Some(3));
+
+        // no new records, the position stays
+        assert_stream_pending(&mut stream).await;
+        assert_eq!(stream.position(), Some(3));
+
+        let stream = StreamConsumerBuilder::new_with_client(consumer, StartOffset::At(5)).build();
+        assert_eq!(stream.position(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_consumer_next_batch() {
+        let record = Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        let (sender, receiver) = mpsc::channel(10);
+        let consumer = Arc::new(MockFetch::new(receiver, None, (0, 1_000)));
+        let mut stream = StreamConsumerBuilder::new_with_client(
+            Arc::<MockFetch>::clone(&consumer),
+            StartOffset::At(0),
+        )
+        .with_max_wait_ms(10)
+        .with_max_poll_records(2)
+        .build();
+
+        for _ in 0..5 {
+            sender.send(record.clone()).await.unwrap();
+        }
+
+        let mut offsets = vec![];
+        for _ in 0..3 {
+            let (records, _) = stream.next_batch().await.unwrap().unwrap();
+            offsets.push(records.iter().map(|x| x.offset).collect::<Vec<_>>());
+        }
+        assert_eq!(offsets, vec![vec![0, 1], vec![2, 3], vec![4]]);
+
+        // all records were returned by a single fetch
+        assert_eq!(consumer.batch_sizes().await, vec![5]);
+        assert_eq!(stream.position(), Some(5));
+    }
+
     /// Assert that given stream is pending.
     ///
     /// This will will try to poll the stream for a bit to ensure that async IO has a chance to catch up.
@@ -1,43 +1,38 @@
-use std::ops::ControlFlow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, error, info};
+use tracing::{info_span, Instrument};
 
 use crate::{
-    backoff::{Backoff, BackoffConfig, ErrorOrThrottle},
+    backoff::{BackoffConfig, ErrorOrThrottle},
     client::{Error, Result},
-    connection::{
-        BrokerCache, BrokerCacheGeneration, BrokerConnection, BrokerConnector, MessengerTransport,
-        MetadataLookupMode,
-    },
-    messenger::RequestError,
+    connection::{BrokerConnector, MetadataLookupMode},
     protocol::{
         error::Error as ProtocolError,
-        messages::{CreateTopicRequest, CreateTopicsRequest, DeleteTopicsRequest},
-        primitives::{Array, Int16, Int32, String_},
+        messages::{
+            CreateTopicRequest, CreateTopicsRequest, DeleteTopicsRequest, DescribeConfigsRequest,
+            DescribeConfigsRequestResource, RESOURCE_TYPE_TOPIC,
+        },
+        primitives::{Array, Boolean, Int16, Int32, String_},
     },
     throttle::maybe_throttle,
+    topic::{ConfigEntry, ConfigSource},
     validation::ExactlyOne,
 };
 
-use super::error::RequestContext;
+use super::{
+    error::RequestContext,
+    routing::{is_connection_broken, BrokerLocator, ErrorAction, RoutedBroker},
+};
 
 #[derive(Debug)]
 pub struct ControllerClient {
-    brokers: Arc<BrokerConnector>,
-
-    backoff_config: Arc<BackoffConfig>,
-
-    /// Current broker connection if any
-    current_broker: Mutex<(Option<BrokerConnection>, BrokerCacheGeneration)>,
+    broker: RoutedBroker<Controller>,
 }
 
 impl ControllerClient {
     pub(super) fn new(brokers: Arc<BrokerConnector>, backoff_config: Arc<BackoffConfig>) -> Self {
         Self {
-            brokers,
-            backoff_config,
-            current_broker: Mutex::new((None, BrokerCacheGeneration::START)),
+            broker: RoutedBroker::new(brokers, backoff_config, Controller),
         }
     }
 
@@ -49,9 +44,15 @@ impl ControllerClient {
         replication_factor: i16,
         timeout_ms: i32,
     ) -> Result<()> {
+        let name = name.into();
+        let span = info_span!(
+            "controller_request",
+            request = "create_topic",
+            topic = name.as_str()
+        );
         let request = &CreateTopicsRequest {
             topics: vec![CreateTopicRequest {
-                name: String_(name.into()),
+                name: String_(name),
                 num_partitions: Int32(num_partitions),
                 replication_factor: Int16(replication_factor),
                 assignments: vec![],
@@ -63,41 +64,36 @@ impl ControllerClient {
             tagged_fields: None,
         };
 
-        maybe_retry(&self.backoff_config, self, "create_topic", || async move {
-            let (broker, gen) = self
-                .get()
-                .await
-                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-            let response = broker
-                .request(request)
-                .await
-                .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-
-            maybe_throttle(response.throttle_time_ms)?;
-
-            let topic = response
-                .topics
-                .exactly_one()
-                .map_err(|e| ErrorOrThrottle::Error((Error::exactly_one_topic(e), Some(gen))))?;
-
-            match topic.error {
-                None => Ok(()),
-                Some(protocol_error) => Err(ErrorOrThrottle::Error((
-                    Error::ServerError {
+        self.broker
+            .request("create_topic", |broker| async move {
+                let (response, info) = broker.request_with_info(request).await;
+                let response = response
+                    .map_err(|e| ErrorOrThrottle::Error(Error::from(e).with_info(&info)))?;
+
+                maybe_throttle(response.throttle_time_ms)?;
+
+                let topic = response
+                    .topics
+                    .exactly_one()
+                    .map_err(|e| ErrorOrThrottle::Error(Error::exactly_one_topic(e)))?;
+
+                match topic.error {
+                    None => Ok(()),
+                    Some(protocol_error) => Err(ErrorOrThrottle::Error(Error::ServerError {
                         protocol_error,
                         error_message: topic.error_message.and_then(|s| s.0),
                         request: RequestContext::Topic(topic.name.0),
                         response: None,
                         is_virtual: false,
-                    },
-                    Some(gen),
-                ))),
-            }
-        })
-        .await?;
+                        info: Some(Box::new(info)),
+                    })),
+                }
+            })
+            .instrument(span)
+            .await?;
 
         // Refresh the cache now there is definitely a new topic to observe.
-        let _ = self.brokers.refresh_metadata().await;
+        let _ = self.broker.brokers().refresh_metadata().await;
 
         Ok(())
     }
@@ -108,56 +104,130 @@ impl ControllerClient {
         name: impl Into<String> + Send,
         timeout_ms: i32,
     ) -> Result<()> {
+        let name = name.into();
+        let span = info_span!(
+            "controller_request",
+            request = "delete_topic",
+            topic = name.as_str()
+        );
         let request = &DeleteTopicsRequest {
-            topic_names: Array(Some(vec![String_(name.into())])),
+            topic_names: Array(Some(vec![String_(name)])),
             timeout_ms: Int32(timeout_ms),
             tagged_fields: None,
         };
 
-        maybe_retry(&self.backoff_config, self, "delete_topic", || async move {
-            let (broker, gen) = self
-                .get()
-                .await
-                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-            let response = broker
-                .request(request)
-                .await
-                .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-
-            maybe_throttle(response.throttle_time_ms)?;
-
-            let topic = response
-                .responses
-                .exactly_one()
-                .map_err(|e| ErrorOrThrottle::Error((Error::exactly_one_topic(e), Some(gen))))?;
-
-            match topic.error {
-                None => Ok(()),
-                Some(protocol_error) => Err(ErrorOrThrottle::Error((
-                    Error::ServerError {
+        self.broker
+            .request("delete_topic", |broker| async move {
+                let (response, info) = broker.request_with_info(request).await;
+                let response = response
+                    .map_err(|e| ErrorOrThrottle::Error(Error::from(e).with_info(&info)))?;
+
+                maybe_throttle(response.throttle_time_ms)?;
+
+                let topic = response
+                    .responses
+                    .exactly_one()
+                    .map_err(|e| ErrorOrThrottle::Error(Error::exactly_one_topic(e)))?;
+
+                match topic.error {
+                    None => Ok(()),
+                    Some(protocol_error) => Err(ErrorOrThrottle::Error(Error::ServerError {
                         protocol_error,
                         error_message: topic.error_message.and_then(|s| s.0),
                         request: RequestContext::Topic(topic.name.0),
                         response: None,
                         is_virtual: false,
-                    },
-                    Some(gen),
-                ))),
-            }
-        })
-        .await?;
+                        info: Some(Box::new(info)),
+                    })),
+                }
+            })
+            .instrument(span)
+            .await?;
 
         // Refresh the cache now there is definitely a new topic to observe.
-        let _ = self.brokers.refresh_metadata().await;
+        let _ = self.broker.brokers().refresh_metadata().await;
 
         Ok(())
     }
 
-    /// Retrieve the broker ID of the controller
-    async fn get_controller_id(&self) -> Result<i32> {
+    /// Describe the configuration of a topic, see [`Client::describe_topic`](super::Client::describe_topic).
+    pub(super) async fn describe_topic_configs(
+        &self,
+        name: &str,
+    ) -> Result<BTreeMap<String, ConfigEntry>> {
+        let span = info_span!(
+            "controller_request",
+            request = "describe_topic_configs",
+            topic = name
+        );
+        let request = &DescribeConfigsRequest {
+            resources: vec![DescribeConfigsRequestResource {
+                resource_type: RESOURCE_TYPE_TOPIC,
+                resource_name: String_(name.to_owned()),
+                configuration_keys: Array(None),
+            }],
+            include_synonyms: Some(Boolean(false)),
+            include_documentation: Some(Boolean(false)),
+        };
+
+        self.broker
+            .request("describe_topic_configs", |broker| async move {
+                let (response, info) = broker.request_with_info(request).await;
+                let response = response
+                    .map_err(|e| ErrorOrThrottle::Error(Error::from(e).with_info(&info)))?;
+
+                maybe_throttle(Some(response.throttle_time_ms))?;
+
+                let result = response
+                    .results
+                    .exactly_one()
+                    .map_err(|e| ErrorOrThrottle::Error(Error::exactly_one_topic(e)))?;
+
+                if let Some(protocol_error) = result.error {
+                    return Err(ErrorOrThrottle::Error(Error::ServerError {
+                        protocol_error,
+                        error_message: result.error_message.0,
+                        request: RequestContext::Topic(result.resource_name.0),
+                        response: None,
+                        is_virtual: false,
+                        info: Some(Box::new(info)),
+                    }));
+                }
+
+                Ok(result
+                    .configs
+                    .into_iter()
+                    .map(|config| {
+                        let source = match (config.config_source, config.is_default) {
+                            (Some(source), _) => ConfigSource::new(source.0),
+                            (None, Some(Boolean(true))) => ConfigSource::Default,
+                            (None, _) => ConfigSource::Unknown,
+                        };
+                        let entry = ConfigEntry {
+                            value: config.value.0,
+                            source,
+                            is_read_only: config.read_only.0,
+                            is_sensitive: config.is_sensitive.0,
+                        };
+                        (config.name.0, entry)
+                    })
+                    .collect())
+            })
+            .instrument(span)
+            .await
+    }
+}
+
+/// Locates the cluster controller.
+#[derive(Debug)]
+struct Controller;
+
+impl BrokerLocator for Controller {
+    const NAME: &'static str = "controller";
+
+    async fn broker_id(&self, brokers: &BrokerConnector) -> Result<i32> {
         // Request an uncached, fresh copy of the metadata.
-        let (metadata, _gen) = self
-            .brokers
+        let (metadata, _gen) = brokers
             .request_metadata(&MetadataLookupMode::ArbitraryBroker, Some(vec![]))
             .await?;
 
@@ -168,121 +238,20 @@ impl ControllerClient {
 
         Ok(controller_id)
     }
-}
-
-/// Caches the cluster controller broker.
-impl BrokerCache for &ControllerClient {
-    type R = MessengerTransport;
-    type E = Error;
-
-    async fn get(&self) -> Result<(Arc<Self::R>, BrokerCacheGeneration)> {
-        let mut current_broker = self.current_broker.lock().await;
-        if let Some(broker) = &current_broker.0 {
-            return Ok((Arc::clone(broker), current_broker.1));
-        }
-
-        info!("Creating new controller broker connection",);
-
-        let controller_id = self.get_controller_id().await?;
-        let broker = self.brokers.connect(controller_id).await?.ok_or_else(|| {
-            Error::InvalidResponse(format!(
-                "Controller {} not found in metadata response",
-                controller_id
-            ))
-        })?;
 
-        current_broker.0 = Some(Arc::clone(&broker));
-        current_broker.1.bump();
+    fn classify(&self, error: &Error) -> ErrorAction {
+        match error {
+            e if is_connection_broken(e) => {
+                ErrorAction::Invalidate("controller client: connection broken")
+            }
 
-        Ok((broker, current_broker.1))
-    }
+            // our broker is actually not the controller
+            Error::ServerError {
+                protocol_error: ProtocolError::NotController,
+                ..
+            } => ErrorAction::Invalidate("controller client: server error: not controller"),
 
-    async fn invalidate(&self, reason: &'static str, gen: BrokerCacheGeneration) {
-        let mut guard = self.current_broker.lock().await;
-
-        if guard.1 != gen {
-            // stale request
-            debug!(
-                reason,
-                current_gen = guard.1.get(),
-                request_gen = gen.get(),
-                "stale invalidation request for arbitrary broker cache",
-            );
-            return;
+            _ => ErrorAction::Fail,
         }
-
-        info!(reason, "Invalidating cached controller broker",);
-        guard.0.take();
     }
 }
-
-/// Takes a `request_name` and a function yielding a fallible future
-/// and handles certain classes of error
-async fn maybe_retry<B, R, F, T>(
-    backoff_config: &BackoffConfig,
-    broker_cache: B,
-    request_name: &str,
-    f: R,
-) -> Result<T>
-where
-    B: BrokerCache,
-    R: (Fn() -> F) + Send + Sync,
-    F: std::future::Future<
-            Output = Result<T, ErrorOrThrottle<(Error, Option<BrokerCacheGeneration>)>>,
-        > + Send,
-{
-    let mut backoff = Backoff::new(backoff_config);
-
-    backoff
-        .retry_with_backoff(request_name, || async {
-            let (error, cache_gen) = match f().await {
-                Ok(v) => {
-                    return ControlFlow::Break(Ok(v));
-                }
-                Err(ErrorOrThrottle::Throttle(t)) => {
-                    return ControlFlow::Continue(ErrorOrThrottle::Throttle(t));
-                }
-                Err(ErrorOrThrottle::Error(e)) => e,
-            };
-
-            match error {
-                // broken connection
-                Error::Request(RequestError::Poisoned(_) | RequestError::IO(_))
-                | Error::Connection(_) => {
-                    if let Some(cache_gen) = cache_gen {
-                        broker_cache
-                            .invalidate("controller client: connection broken", cache_gen)
-                            .await
-                    }
-                }
-
-                // our broker is actually not the controller
-                Error::ServerError {
-                    protocol_error: ProtocolError::NotController,
-                    ..
-                } => {
-                    if let Some(cache_gen) = cache_gen {
-                        broker_cache
-                            .invalidate(
-                                "controller client: server error: not controller",
-                                cache_gen,
-                            )
-                            .await;
-                    }
-                }
-
-                // fatal
-                _ => {
-                    error!(
-                        e=%error,
-                        request_name,
-                        "request encountered fatal error",
-                    );
-                    return ControlFlow::Break(Err(error));
-                }
-            }
-            ControlFlow::Continue(ErrorOrThrottle::Error(error))
-        })
-        .await
-        .map_err(Error::RetryFailed)?
-}
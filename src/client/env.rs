@@ -0,0 +1,229 @@
+//! Configuration via environment variables, see [`ClientBuilder::from_env`].
+use std::collections::HashMap;
+
+use super::{
+    properties::{Properties, PropertiesError},
+    ClientBuilder,
+};
+
+/// Environment variables and the [properties](super::properties) that they map to.
+const VARS: &[(&str, &str)] = &[
+    ("KAFKA_BROKERS", "bootstrap.servers"),
+    ("KAFKA_CLIENT_ID", "client.id"),
+    (
+        "KAFKA_CONNECT_TIMEOUT_MS",
+        "socket.connection.setup.timeout.ms",
+    ),
+    ("KAFKA_REQUEST_TIMEOUT_MS", "socket.timeout.ms"),
+    ("KAFKA_SECURITY_PROTOCOL", "security.protocol"),
+    ("KAFKA_SASL_MECHANISM", "sasl.mechanism"),
+    ("KAFKA_SASL_USERNAME", "sasl.username"),
+    ("KAFKA_SASL_PASSWORD", "sasl.password"),
+];
+
+const TLS_CA_FILE: &str = "KAFKA_TLS_CA_FILE";
+const TLS_CERT_FILE: &str = "KAFKA_TLS_CERT_FILE";
+const TLS_KEY_FILE: &str = "KAFKA_TLS_KEY_FILE";
+
+impl ClientBuilder {
+    /// Create a [`ClientBuilder`] from environment variables.
+    ///
+    /// | Variable                      | Setting                                                   |
+    /// | ----------------------------- | --------------------------------------------------------- |
+    /// | `KAFKA_BROKERS` (required)    | Comma-separated bootstrap brokers                         |
+    /// | `KAFKA_CLIENT_ID`             | [`client_id`](Self::client_id)                            |
+    /// | `KAFKA_CONNECT_TIMEOUT_MS`    | [`connect_timeout`](Self::connect_timeout)                |
+    /// | `KAFKA_REQUEST_TIMEOUT_MS`    | [`request_timeout`](Self::request_timeout)                |
+    /// | `KAFKA_SECURITY_PROTOCOL`     | `plaintext`, `ssl`, `sasl_plaintext` or `sasl_ssl`        |
+    /// | `KAFKA_SASL_MECHANISM`        | `PLAIN` (default), `SCRAM-SHA-256` or `SCRAM-SHA-512`     |
+    /// | `KAFKA_SASL_USERNAME`         | SASL user                                                 |
+    /// | `KAFKA_SASL_PASSWORD`         | SASL password                                             |
+    /// | `KAFKA_TLS_CA_FILE`           | PEM file with the CA certificates to trust                |
+    /// | `KAFKA_TLS_CERT_FILE`         | PEM file with the client certificate chain, if any        |
+    /// | `KAFKA_TLS_KEY_FILE`          | PEM file with the client private key, if any              |
+    ///
+    /// If `KAFKA_SECURITY_PROTOCOL` is not set, SASL is used if `KAFKA_SASL_USERNAME` is set and TLS is used if
+    /// `KAFKA_TLS_CA_FILE` is set. TLS requires the `transport-tls` feature.
+    pub fn from_env() -> Result<Self, PropertiesError> {
+        let vars = VARS
+            .iter()
+            .map(|(var, _)| *var)
+            .chain([TLS_CA_FILE, TLS_CERT_FILE, TLS_KEY_FILE])
+            .filter_map(|var| Some((var.to_owned(), std::env::var(var).ok()?)))
+            .collect();
+        Self::from_vars(vars)
+    }
+
+    fn from_vars(vars: HashMap<String, String>) -> Result<Self, PropertiesError> {
+        let mut properties: Vec<_> = VARS
+            .iter()
+            .filter_map(|(var, property)| Some((*property, vars.get(*var)?.clone())))
+            .collect();
+
+        let sasl = vars.contains_key("KAFKA_SASL_USERNAME");
+        if sasl && !vars.contains_key("KAFKA_SASL_MECHANISM") {
+            properties.push(("sasl.mechanism", "PLAIN".to_owned()));
+        }
+        if !vars.contains_key("KAFKA_SECURITY_PROTOCOL") {
+            let protocol = match (sasl, vars.contains_key(TLS_CA_FILE)) {
+                (false, false) => "plaintext",
+                (false, true) => "ssl",
+                (true, false) => "sasl_plaintext",
+                (true, true) => "sasl_ssl",
+            };
+            properties.push(("security.protocol", protocol.to_owned()));
+        }
+
+        let properties = Properties::new(properties).map_err(env_error)?;
+        if !properties.uses_tls() {
+            return properties.client_builder().map_err(env_error);
+        }
+
+        #[cfg(feature = "transport-tls")]
+        {
+            let tls_config = tls::config(&vars)?;
+            properties
+                .client_builder_with_tls(tls_config)
+                .map_err(env_error)
+        }
+        #[cfg(not(feature = "transport-tls"))]
+        {
+            properties.client_builder().map_err(env_error)
+        }
+    }
+}
+
+/// Refer to the environment variables instead of the properties in `e`.
+fn env_error(e: PropertiesError) -> PropertiesError {
+    let var = |property: &str| {
+        VARS.iter()
+            .find(|(_, p)| *p == property)
+            .map(|(var, _)| *var)
+    };
+    match e {
+        PropertiesError::Missing(property) => {
+            PropertiesError::Missing(var(property).unwrap_or(property))
+        }
+        PropertiesError::InvalidValue { key, value, reason } => PropertiesError::InvalidValue {
+            key: var(&key).map(ToOwned::to_owned).unwrap_or(key),
+            value,
+            reason,
+        },
+        e => e,
+    }
+}
+
+#[cfg(feature = "transport-tls")]
+mod tls {
+    use std::{collections::HashMap, sync::Arc};
+
+    use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+    use super::{PropertiesError, TLS_CA_FILE, TLS_CERT_FILE, TLS_KEY_FILE};
+
+    /// TLS config from the PEM files given by the environment variables.
+    pub(super) fn config(
+        vars: &HashMap<String, String>,
+    ) -> Result<Arc<rustls::ClientConfig>, PropertiesError> {
+        let ca_file = vars
+            .get(TLS_CA_FILE)
+            .ok_or(PropertiesError::Missing(TLS_CA_FILE))?;
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in certs(TLS_CA_FILE, ca_file)? {
+            roots
+                .add(cert)
+                .map_err(|e| invalid(TLS_CA_FILE, ca_file, e))?;
+        }
+        let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+
+        let config = match (vars.get(TLS_CERT_FILE), vars.get(TLS_KEY_FILE)) {
+            (None, None) => builder.with_no_client_auth(),
+            (Some(cert_file), Some(key_file)) => {
+                let key = PrivateKeyDer::from_pem_file(key_file)
+                    .map_err(|e| invalid(TLS_KEY_FILE, key_file, e))?;
+                builder
+                    .with_client_auth_cert(certs(TLS_CERT_FILE, cert_file)?, key)
+                    .map_err(|e| invalid(TLS_KEY_FILE, key_file, e))?
+            }
+            (Some(_), None) => return Err(PropertiesError::Missing(TLS_KEY_FILE)),
+            (None, Some(_)) => return Err(PropertiesError::Missing(TLS_CERT_FILE)),
+        };
+        Ok(Arc::new(config))
+    }
+
+    fn certs(var: &str, file: &str) -> Result<Vec<CertificateDer<'static>>, PropertiesError> {
+        CertificateDer::pem_file_iter(file)
+            .map_err(|e| invalid(var, file, e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| invalid(var, file, e))
+    }
+
+    fn invalid(var: &str, file: &str, e: impl std::fmt::Display) -> PropertiesError {
+        PropertiesError::InvalidValue {
+            key: var.to_owned(),
+            value: file.to_owned(),
+            reason: e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_vars() {
+        ClientBuilder::from_vars(vars(&[
+            ("KAFKA_BROKERS", "localhost:9092"),
+            ("KAFKA_SASL_USERNAME", "user"),
+            ("KAFKA_SASL_PASSWORD", "secret"),
+        ]))
+        .unwrap();
+
+        assert_matches!(
+            ClientBuilder::from_vars(vars(&[])),
+            Err(PropertiesError::Missing("KAFKA_BROKERS"))
+        );
+        assert_matches!(
+            ClientBuilder::from_vars(vars(&[
+                ("KAFKA_BROKERS", "localhost:9092"),
+                ("KAFKA_REQUEST_TIMEOUT_MS", "1s"),
+            ])),
+            Err(PropertiesError::InvalidValue { key, .. }) if key == "KAFKA_REQUEST_TIMEOUT_MS"
+        );
+        assert_matches!(
+            ClientBuilder::from_vars(vars(&[
+                ("KAFKA_BROKERS", "localhost:9092"),
+                ("KAFKA_SASL_USERNAME", "user"),
+            ])),
+            Err(PropertiesError::Missing("KAFKA_SASL_PASSWORD"))
+        );
+    }
+
+    #[cfg(feature = "transport-tls")]
+    #[test]
+    fn test_from_vars_tls() {
+        assert_matches!(
+            ClientBuilder::from_vars(vars(&[
+                ("KAFKA_BROKERS", "localhost:9093"),
+                (TLS_CA_FILE, "/does/not/exist.pem"),
+            ])),
+            Err(PropertiesError::InvalidValue { key, .. }) if key == TLS_CA_FILE
+        );
+        assert_matches!(
+            ClientBuilder::from_vars(vars(&[
+                ("KAFKA_BROKERS", "localhost:9093"),
+                ("KAFKA_SECURITY_PROTOCOL", "ssl"),
+            ])),
+            Err(PropertiesError::Missing(TLS_CA_FILE))
+        );
+    }
+}
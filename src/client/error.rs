@@ -1,6 +1,10 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
-pub use crate::messenger::RequestError;
+use crate::backoff::BackoffError;
+
+pub use crate::messenger::{RequestError, RequestInfo};
 pub use crate::protocol::error::Error as ProtocolError;
 
 /// Request context for [`Error::ServerError`].
@@ -52,25 +56,51 @@ pub enum ServerErrorResponse {
     },
 }
 
+/// Coarse classification of an [`Error`](enum@Error), see [`Error::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Authentication or authorization failed.
+    Auth,
+
+    /// A broker could not be reached or the connection failed, e.g. because of an IO error or a timeout.
+    Network,
+
+    /// The broker rejected the request with a transient error, e.g. because partition leadership moved.
+    BrokerRetriable,
+
+    /// The request is invalid and fails again if it is retried, e.g. because a message is too large.
+    InvalidInput,
+
+    /// Any other error, e.g. a non-retriable broker error, an unexpected response or a closed client.
+    Other,
+}
+
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum Error {
     #[error("Connection error: {0}")]
     Connection(#[from] crate::connection::Error),
 
-    #[error("Request error: {0}")]
-    Request(#[from] RequestError),
+    #[error("Request error{}: {source}", info_suffix(info))]
+    Request {
+        source: RequestError,
+
+        /// Broker and request that failed, if known.
+        info: Option<Box<RequestInfo>>,
+    },
 
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
 
     #[error(
-        "Server error {} with message \"{}\", request: {:?}, response: {:?}, virtual: {}",
+        "Server error {} with message \"{}\", request: {:?}, response: {:?}, virtual: {}{}",
         protocol_error,
         string_or_na(error_message),
         request,
         response,
-        is_virtual
+        is_virtual,
+        info_suffix(info)
     )]
     ServerError {
         /// Protocol-level error message.
@@ -89,16 +119,147 @@ pub enum Error {
         ///
         /// This is mostly for debugging and bug reporting.
         is_virtual: bool,
+
+        /// Broker and request that returned the error, if known.
+        info: Option<Box<RequestInfo>>,
+    },
+
+    #[error(
+        "Corrupt record batch at offset {offset} of partition {partition} of topic '{topic}', got CRC 0x{actual_crc:x}, \
+         expected 0x{expected_crc:x}"
+    )]
+    CorruptBatch {
+        topic: String,
+        partition: i32,
+
+        /// Base offset of the record batch, or offset of the message for legacy message sets.
+        offset: i64,
+
+        /// CRC stated in the record batch.
+        expected_crc: u32,
+
+        /// CRC of the data in the record batch.
+        actual_crc: u32,
+    },
+
+    /// A fetched record batch uses a compression that is not in
+    /// [`ClientBuilder::allowed_fetch_compressions`](super::ClientBuilder::allowed_fetch_compressions).
+    #[error(
+        "Record batch at offset {offset} of partition {partition} of topic '{topic}' uses {compression} compression, \
+         which is not allowed"
+    )]
+    CompressionNotAllowed {
+        topic: String,
+        partition: i32,
+
+        /// Base offset of the record batch, or offset of the message for legacy message sets.
+        offset: i64,
+
+        /// Name of the compression, e.g. `"gzip"`.
+        compression: &'static str,
+    },
+
+    #[error(
+        "{} of {size} bytes exceeds the limit of {limit} bytes",
+        too_large_input(index)
+    )]
+    RecordTooLarge {
+        /// Index of the offending record, or of the offending batch when producing raw batches.
+        ///
+        /// `None` if the records are within the limit individually but not once they are encoded as a batch.
+        index: Option<usize>,
+
+        /// Size of the offending input.
+        size: usize,
+
+        /// Configured limit, see
+        /// [`ClientBuilder::max_produce_batch_size`](super::ClientBuilder::max_produce_batch_size).
+        limit: usize,
+    },
+
+    /// The broker is too old for a feature of the request.
+    ///
+    /// Other requests to the broker might still work.
+    #[error(
+        "Broker does not support {feature}, which requires Kafka {min_kafka_version} or later"
+    )]
+    UnsupportedBroker {
+        /// Feature that the broker cannot handle.
+        feature: &'static str,
+
+        /// First Kafka version that supports the feature.
+        min_kafka_version: &'static str,
+    },
+
+    /// The partition leader did not receive the acknowledgements of the in-sync replicas within the
+    /// [produce ack timeout](super::ClientBuilder::produce_ack_timeout).
+    ///
+    /// The records might still be written, so retrying can duplicate them.
+    #[error(
+        "Replicas of partition {partition} of topic '{topic}' did not acknowledge the write within {timeout:?}"
+    )]
+    ProduceAckTimeout {
+        topic: String,
+        partition: i32,
+
+        /// Timeout that was sent to the broker.
+        timeout: Duration,
     },
 
     #[error("All retries failed: {0}")]
-    RetryFailed(#[from] crate::backoff::BackoffError),
+    RetryFailed(#[from] BackoffError),
 
     #[error("Timeout")]
     Timeout,
 }
 
+impl From<RequestError> for Error {
+    fn from(source: RequestError) -> Self {
+        Self::Request { source, info: None }
+    }
+}
+
 impl Error {
+    /// Classify the error.
+    ///
+    /// If all retries failed, this is the kind of the last error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Connection(e) => e.kind(),
+            Self::Request { source, .. } => request_kind(source),
+            Self::InvalidResponse(_)
+            | Self::CorruptBatch { .. }
+            | Self::CompressionNotAllowed { .. } => ErrorKind::Other,
+            Self::ServerError { protocol_error, .. } => protocol_kind(*protocol_error),
+            Self::RetryFailed(BackoffError::DeadlineExceded { source, .. }) => {
+                source_kind(source.as_ref())
+            }
+            Self::RecordTooLarge { .. } | Self::UnsupportedBroker { .. } => ErrorKind::InvalidInput,
+            Self::ProduceAckTimeout { .. } => ErrorKind::BrokerRetriable,
+            Self::Timeout => ErrorKind::Network,
+        }
+    }
+
+    /// Whether retrying the operation later may succeed.
+    pub fn is_retriable(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Network | ErrorKind::BrokerRetriable)
+    }
+
+    /// Whether the operation cannot succeed without changing the request or the configuration, or because the client
+    /// was closed.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self.kind(), ErrorKind::Auth | ErrorKind::InvalidInput)
+            || matches!(self, Self::Connection(crate::connection::Error::Closed))
+    }
+
+    /// Attach `info` to a request or server error that does not have it yet.
+    pub(crate) fn with_info(mut self, info: &RequestInfo) -> Self {
+        if let Self::Request { info: i, .. } | Self::ServerError { info: i, .. } = &mut self {
+            i.get_or_insert_with(|| Box::new(info.clone()));
+        }
+        self
+    }
+
     pub(crate) fn exactly_one_topic(len: usize) -> Self {
         Self::InvalidResponse(format!("Expected a single topic in response, got {len}"))
     }
@@ -112,6 +273,79 @@ impl Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+pub(crate) fn request_kind(e: &RequestError) -> ErrorKind {
+    match e {
+        RequestError::IO(_) | RequestError::Timeout { .. } | RequestError::Closed => {
+            ErrorKind::Network
+        }
+        RequestError::Poisoned(e) | RequestError::Shared(e) => request_kind(e),
+        _ => ErrorKind::Other,
+    }
+}
+
+fn protocol_kind(e: ProtocolError) -> ErrorKind {
+    match e {
+        ProtocolError::TopicAuthorizationFailed
+        | ProtocolError::GroupAuthorizationFailed
+        | ProtocolError::ClusterAuthorizationFailed
+        | ProtocolError::TransactionalIdAuthorizationFailed
+        | ProtocolError::DelegationTokenAuthorizationFailed
+        | ProtocolError::UnsupportedSaslMechanism
+        | ProtocolError::IllegalSaslState
+        | ProtocolError::SaslAuthenticationFailed
+        | ProtocolError::UnacceptableCredential => ErrorKind::Auth,
+        ProtocolError::OffsetOutOfRange
+        | ProtocolError::InvalidFetchSize
+        | ProtocolError::MessageTooLarge
+        | ProtocolError::OffsetMetadataTooLarge
+        | ProtocolError::InvalidTopicException
+        | ProtocolError::RecordListTooLarge
+        | ProtocolError::InvalidRequiredAcks
+        | ProtocolError::InvalidTimestamp
+        | ProtocolError::TopicAlreadyExists
+        | ProtocolError::InvalidPartitions
+        | ProtocolError::InvalidReplicationFactor
+        | ProtocolError::InvalidReplicaAssignment
+        | ProtocolError::InvalidConfig
+        | ProtocolError::InvalidRequest
+        | ProtocolError::PolicyViolation
+        | ProtocolError::UnsupportedCompressionType
+        | ProtocolError::InvalidRecord => ErrorKind::InvalidInput,
+        e if e.is_retriable() => ErrorKind::BrokerRetriable,
+        _ => ErrorKind::Other,
+    }
+}
+
+/// Kind of the last error of failed retries.
+pub(crate) fn source_kind(e: &(dyn std::error::Error + 'static)) -> ErrorKind {
+    if let Some(e) = e.downcast_ref::<Error>() {
+        e.kind()
+    } else if let Some(e) = e.downcast_ref::<crate::connection::Error>() {
+        e.kind()
+    } else if let Some(e) = e.downcast_ref::<RequestError>() {
+        request_kind(e)
+    } else {
+        // attempts that time out fail with `Elapsed`
+        ErrorKind::Network
+    }
+}
+
+/// Formats the request info, if any, for appending it to an error message.
+fn info_suffix(info: &Option<Box<RequestInfo>>) -> String {
+    match info {
+        Some(info) => format!(" ({info})"),
+        None => String::new(),
+    }
+}
+
+/// Names the offending input of [`Error::RecordTooLarge`].
+fn too_large_input(index: &Option<usize>) -> String {
+    match index {
+        Some(index) => format!("Input {index}"),
+        None => String::from("Record batch"),
+    }
+}
+
 /// Simple formatting function the replaces `None` with `"n/a"`.
 fn string_or_na(s: &Option<String>) -> &str {
     match s {
@@ -119,3 +353,93 @@ fn string_or_na(s: &Option<String>) -> &str {
         None => "n/a",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn server_error(protocol_error: ProtocolError) -> Error {
+        Error::ServerError {
+            protocol_error,
+            error_message: None,
+            request: RequestContext::Topic("foo".to_owned()),
+            response: None,
+            is_virtual: false,
+            info: None,
+        }
+    }
+
+    #[test]
+    fn test_kind() {
+        let e = server_error(ProtocolError::NotLeaderOrFollower);
+        assert_eq!(e.kind(), ErrorKind::BrokerRetriable);
+        assert!(e.is_retriable());
+        assert!(!e.is_fatal());
+
+        let e = server_error(ProtocolError::TopicAuthorizationFailed);
+        assert_eq!(e.kind(), ErrorKind::Auth);
+        assert!(!e.is_retriable());
+        assert!(e.is_fatal());
+
+        let e = server_error(ProtocolError::MessageTooLarge);
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.is_fatal());
+
+        let e = server_error(ProtocolError::UnknownServerError);
+        assert_eq!(e.kind(), ErrorKind::Other);
+        assert!(!e.is_retriable());
+        assert!(!e.is_fatal());
+
+        let e = Error::from(RequestError::Shared(std::sync::Arc::new(RequestError::IO(
+            std::io::ErrorKind::ConnectionReset.into(),
+        ))));
+        assert_eq!(e.kind(), ErrorKind::Network);
+        assert!(e.is_retriable());
+
+        let e = Error::Connection(crate::connection::Error::SaslFailed(
+            crate::messenger::SaslError::UnsupportedSaslMechanism,
+        ));
+        assert_eq!(e.kind(), ErrorKind::Auth);
+
+        let e = Error::Connection(crate::connection::Error::Closed);
+        assert_eq!(e.kind(), ErrorKind::Other);
+        assert!(!e.is_retriable());
+        assert!(e.is_fatal());
+
+        let e = Error::RetryFailed(BackoffError::DeadlineExceded {
+            deadline: Duration::from_secs(1),
+            source: Box::new(server_error(ProtocolError::LeaderNotAvailable)),
+        });
+        assert_eq!(e.kind(), ErrorKind::BrokerRetriable);
+    }
+
+    #[test]
+    fn test_with_info() {
+        let info = |correlation_id| RequestInfo {
+            broker_id: Some(1),
+            broker_address: None,
+            api_key: crate::protocol::api_key::ApiKey::CreateTopics,
+            api_version: Some(5),
+            correlation_id: Some(correlation_id),
+        };
+
+        let e = Error::from(RequestError::Closed).with_info(&info(1));
+        assert_eq!(
+            e.to_string(),
+            "Request error (broker 1, CreateTopics v5, correlation ID 1): Connection is closed"
+        );
+
+        // existing info is kept
+        let e = server_error(ProtocolError::TopicAlreadyExists)
+            .with_info(&info(1))
+            .with_info(&info(2));
+        assert!(e
+            .to_string()
+            .ends_with("(broker 1, CreateTopics v5, correlation ID 1)"));
+
+        let e = Error::Timeout.with_info(&info(1));
+        assert_eq!(e.to_string(), "Timeout");
+    }
+}
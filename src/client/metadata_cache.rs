@@ -1,6 +1,7 @@
-use std::ops::Deref;
+use std::{collections::BTreeMap, ops::Deref};
 
 use parking_lot::Mutex;
+use tokio::sync::watch;
 use tracing::{debug, info};
 
 use crate::protocol::messages::MetadataResponse;
@@ -16,12 +17,16 @@ pub struct MetadataCacheGeneration(usize);
 #[derive(Debug)]
 pub(crate) struct MetadataCache {
     cache: Mutex<(Option<MetadataResponse>, MetadataCacheGeneration)>,
+
+    /// Notified whenever the cached entry is [updated](Self::update).
+    updates: watch::Sender<()>,
 }
 
 impl Default for MetadataCache {
     fn default() -> Self {
         Self {
             cache: Mutex::new((None, MetadataCacheGeneration(0))),
+            updates: watch::Sender::new(()),
         }
     }
 }
@@ -69,6 +74,54 @@ impl MetadataCache {
         Some((m, gen))
     }
 
+    /// Leader of the given partition according to the cached metadata.
+    ///
+    /// Unlike [`get`](Self::get) this does not copy the cached entry.
+    pub(crate) fn leader(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> Option<(i32, MetadataCacheGeneration)> {
+        let guard = self.cache.lock();
+        let (Some(m), gen) = guard.deref() else {
+            return None;
+        };
+
+        m.topics
+            .iter()
+            .find(|t| t.name.0 == topic)?
+            .partitions
+            .iter()
+            .find(|p| p.partition_index.0 == partition)
+            .map(|p| (p.leader_id.0, *gen))
+    }
+
+    /// Leaders of all partitions of `topic` according to the cached metadata.
+    ///
+    /// Returns `None` if there is no cached entry and an empty map if the cached entry does not contain `topic`.
+    pub(crate) fn partition_leaders(&self, topic: &str) -> Option<BTreeMap<i32, i32>> {
+        let guard = self.cache.lock();
+        let m = guard.0.as_ref()?;
+
+        Some(
+            m.topics
+                .iter()
+                .find(|t| t.name.0 == topic)
+                .map(|t| {
+                    t.partitions
+                        .iter()
+                        .map(|p| (p.partition_index.0, p.leader_id.0))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Receiver that is notified whenever the cached entry is updated.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.updates.subscribe()
+    }
+
     pub(crate) fn invalidate(&self, reason: &'static str, gen: MetadataCacheGeneration) {
         let mut guard = self.cache.lock();
         if guard.1 != gen {
@@ -90,6 +143,8 @@ impl MetadataCache {
         let mut guard = self.cache.lock();
         guard.0 = Some(m);
         guard.1 .0 += 1;
+        drop(guard);
+        self.updates.send_replace(());
         debug!("updated metadata cache");
     }
 }
@@ -97,8 +152,8 @@ impl MetadataCache {
 #[cfg(test)]
 mod tests {
     use crate::protocol::{
-        messages::MetadataResponseTopic,
-        primitives::{Int32, String_},
+        messages::{MetadataResponsePartition, MetadataResponseTopic},
+        primitives::{Array, Int32, String_},
     };
 
     use super::*;
@@ -113,6 +168,8 @@ mod tests {
                 error: Default::default(),
                 is_internal: Default::default(),
                 partitions: Default::default(),
+                topic_authorized_operations: Default::default(),
+                tagged_fields: Default::default(),
             })
             .collect();
 
@@ -122,6 +179,8 @@ mod tests {
             cluster_id: Default::default(),
             controller_id: Default::default(),
             topics,
+            cluster_authorized_operations: Default::default(),
+            tagged_fields: Default::default(),
         }
     }
 
@@ -170,6 +229,66 @@ mod tests {
         assert!(cache.get(&Some(vec!["bananas".to_string()])).is_none());
     }
 
+    #[test]
+    fn test_leader() {
+        let cache = MetadataCache::default();
+        assert!(cache.leader("bananas", 0).is_none());
+
+        let mut m = response_with_topics(Some(&["bananas"]));
+        m.topics[0].partitions = vec![MetadataResponsePartition {
+            error: None,
+            partition_index: Int32(1),
+            leader_id: Int32(42),
+            leader_epoch: None,
+            replica_nodes: Array(None),
+            isr_nodes: Array(None),
+            offline_replicas: None,
+            tagged_fields: None,
+        }];
+        cache.update(m);
+
+        let (leader, gen) = cache.leader("bananas", 1).unwrap();
+        assert_eq!(leader, 42);
+        assert_eq!(gen, cache.get(&None).unwrap().1);
+
+        assert!(cache.leader("bananas", 0).is_none());
+        assert!(cache.leader("platanos", 1).is_none());
+    }
+
+    #[test]
+    fn test_partition_leaders() {
+        let cache = MetadataCache::default();
+        let mut updates = cache.subscribe();
+        assert!(cache.partition_leaders("bananas").is_none());
+        assert!(!updates.has_changed().unwrap());
+
+        let mut m = response_with_topics(Some(&["bananas"]));
+        m.topics[0].partitions = vec![MetadataResponsePartition {
+            error: None,
+            partition_index: Int32(1),
+            leader_id: Int32(42),
+            leader_epoch: None,
+            replica_nodes: Array(None),
+            isr_nodes: Array(None),
+            offline_replicas: None,
+            tagged_fields: None,
+        }];
+        cache.update(m);
+        assert!(updates.has_changed().unwrap());
+        updates.mark_unchanged();
+
+        assert_eq!(
+            cache.partition_leaders("bananas").unwrap(),
+            BTreeMap::from([(1, 42)])
+        );
+        assert!(cache.partition_leaders("platanos").unwrap().is_empty());
+
+        let (_m, gen) = cache.get(&None).unwrap();
+        cache.invalidate("test", gen);
+        assert!(cache.partition_leaders("bananas").is_none());
+        assert!(!updates.has_changed().unwrap());
+    }
+
     #[test]
     fn test_explicit_invalidate() {
         let cache = MetadataCache::default();
@@ -179,6 +298,8 @@ mod tests {
             cluster_id: Default::default(),
             controller_id: Default::default(),
             topics: Default::default(),
+            cluster_authorized_operations: Default::default(),
+            tagged_fields: Default::default(),
         });
 
         let (_data, gen1) = cache.get(&None).unwrap();
@@ -191,6 +312,8 @@ mod tests {
             cluster_id: Default::default(),
             controller_id: Default::default(),
             topics: Default::default(),
+            cluster_authorized_operations: Default::default(),
+            tagged_fields: Default::default(),
         });
 
         let (_data, gen2) = cache.get(&None).unwrap();
@@ -0,0 +1,402 @@
+//! Copy a partition from one cluster to another.
+//!
+//! [`PartitionMirror`] fetches record batches from a source partition and produces them to a destination partition
+//! without decoding them, so keys, values, headers, timestamps and compression stay exactly as they were written. The
+//! position in the source partition is persisted via an [`OffsetCheckpoint`], so that mirroring resumes where it
+//! stopped after a restart.
+//!
+//! # Usage
+//! ```no_run
+//! # async fn test() {
+//! use std::sync::Arc;
+//!
+//! use rskafka::client::{
+//!     mirror::{MemoryCheckpoint, PartitionMirror},
+//!     partition::UnknownTopicHandling,
+//!     ClientBuilder,
+//! };
+//!
+//! let source = ClientBuilder::new(vec!["source:9093".to_owned()]).build().await.unwrap();
+//! let destination = ClientBuilder::new(vec!["destination:9093".to_owned()]).build().await.unwrap();
+//!
+//! let mut mirror = PartitionMirror::new(
+//!     source.partition_client("my_topic", 0, UnknownTopicHandling::Retry).await.unwrap(),
+//!     destination.partition_client("my_topic", 0, UnknownTopicHandling::Retry).await.unwrap(),
+//!     Arc::new(MemoryCheckpoint::default()),
+//! );
+//!
+//! loop {
+//!     let progress = mirror.mirror_once().await.unwrap();
+//!     println!("mirrored {} batches, lag: {}", progress.batches, progress.lag());
+//! }
+//! # }
+//! ```
+//!
+//! # Delivery Guarantees
+//! Batches are produced before the checkpoint is updated, so every record is delivered at least once. Records are
+//! duplicated if the mirror stops between producing and storing the checkpoint. Since the broker does not split record
+//! batches, the same happens if the checkpoint points into the middle of a batch.
+//!
+//! Transaction markers are not mirrored. Batches written by idempotent or transactional producers keep their producer
+//! ID and sequence numbers, which the destination broker might reject. If the destination topic uses
+//! `message.timestamp.type=LogAppendTime`, the broker overrides the timestamps.
+use std::{convert::Infallible, fmt::Debug, sync::Arc};
+
+use futures::future::{BoxFuture, FutureExt};
+use parking_lot::Mutex;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::client::{
+    consumer::StartOffset,
+    error::Error as ClientError,
+    partition::{OffsetAt, PartitionClient},
+};
+
+/// Error that occurs while loading or storing an offset via an [`OffsetCheckpoint`].
+pub type CheckpointError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum MirrorError {
+    #[error("Source error: {0}")]
+    Source(#[source] ClientError),
+
+    #[error("Destination error: {0}")]
+    Destination(#[source] ClientError),
+
+    #[error("Checkpoint error: {0}")]
+    Checkpoint(#[source] CheckpointError),
+}
+
+/// Persists the offset in the source partition that mirroring continues at.
+pub trait OffsetCheckpoint: Debug + Send + Sync {
+    /// Load the stored offset, or `None` if mirroring did not start yet.
+    fn load(&self) -> BoxFuture<'_, Result<Option<i64>, CheckpointError>>;
+
+    /// Store the offset of the next record to mirror.
+    fn store(&self, offset: i64) -> BoxFuture<'_, Result<(), CheckpointError>>;
+}
+
+/// [`OffsetCheckpoint`] that keeps the offset in memory.
+///
+/// This does not survive a restart of the process, but allows to resume mirroring with a new [`PartitionMirror`].
+#[derive(Debug, Default)]
+pub struct MemoryCheckpoint {
+    offset: Mutex<Option<i64>>,
+}
+
+impl MemoryCheckpoint {
+    /// Currently stored offset.
+    pub fn offset(&self) -> Option<i64> {
+        *self.offset.lock()
+    }
+}
+
+impl OffsetCheckpoint for MemoryCheckpoint {
+    fn load(&self) -> BoxFuture<'_, Result<Option<i64>, CheckpointError>> {
+        let offset = self.offset();
+        async move { Ok(offset) }.boxed()
+    }
+
+    fn store(&self, offset: i64) -> BoxFuture<'_, Result<(), CheckpointError>> {
+        *self.offset.lock() = Some(offset);
+        async move { Ok(()) }.boxed()
+    }
+}
+
+/// Result of [`PartitionMirror::mirror_once`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MirrorProgress {
+    /// Number of record batches that were produced to the destination.
+    pub batches: usize,
+
+    /// Offset in the source partition that mirroring continues at.
+    pub next_offset: i64,
+
+    /// High watermark of the source partition.
+    pub high_watermark: i64,
+}
+
+impl MirrorProgress {
+    /// Number of offsets in the source partition that were not mirrored yet.
+    pub fn lag(&self) -> i64 {
+        (self.high_watermark - self.next_offset).max(0)
+    }
+}
+
+/// Mirrors a partition to another one, see the [module docs](self).
+#[derive(Debug)]
+pub struct PartitionMirror {
+    source: Arc<PartitionClient>,
+    destination: Arc<PartitionClient>,
+    checkpoint: Arc<dyn OffsetCheckpoint>,
+    start_offset: StartOffset,
+    max_batch_size: i32,
+    max_wait_ms: i32,
+    next_offset: Option<i64>,
+}
+
+impl PartitionMirror {
+    /// Create a mirror from `source` to `destination` that keeps track of its position via `checkpoint`.
+    pub fn new(
+        source: Arc<PartitionClient>,
+        destination: Arc<PartitionClient>,
+        checkpoint: Arc<dyn OffsetCheckpoint>,
+    ) -> Self {
+        Self {
+            source,
+            destination,
+            checkpoint,
+            start_offset: StartOffset::Earliest,
+            max_batch_size: 52428800,
+            max_wait_ms: 500,
+            next_offset: None,
+        }
+    }
+
+    /// Where to start mirroring if the checkpoint does not contain an offset yet.
+    ///
+    /// Defaults to [`StartOffset::Earliest`].
+    pub fn with_start_offset(self, start_offset: StartOffset) -> Self {
+        Self {
+            start_offset,
+            ..self
+        }
+    }
+
+    /// Will wait for at most `max_wait_ms` before returning an empty batch.
+    ///
+    /// Defaults to 500ms.
+    pub fn with_max_wait_ms(self, max_wait_ms: i32) -> Self {
+        Self {
+            max_wait_ms,
+            ..self
+        }
+    }
+
+    /// The maximum amount of data to fetch in a single batch.
+    ///
+    /// Defaults to 52428800 (50 MB).
+    pub fn with_max_batch_size(self, max_batch_size: i32) -> Self {
+        Self {
+            max_batch_size,
+            ..self
+        }
+    }
+
+    /// Fetch the next record batches from the source, produce them to the destination and store the new offset in the
+    /// checkpoint.
+    ///
+    /// Errors can be retried by calling this method again, which continues at the last stored offset.
+    pub async fn mirror_once(&mut self) -> Result<MirrorProgress, MirrorError> {
+        let offset = match self.next_offset {
+            Some(offset) => offset,
+            None => self.initial_offset().await?,
+        };
+
+        let (mut batches, next_offset, high_watermark) = self
+            .source
+            .fetch_raw_batches_and_next_offset(offset, 1..self.max_batch_size, self.max_wait_ms)
+            .await
+            .map_err(MirrorError::Source)?;
+
+        // batches that end before `offset` were mirrored already
+        batches.retain(|batch| batch.last_offset >= offset);
+        let n_batches = batches.len();
+        self.destination
+            .produce_raw_batches(batches)
+            .await
+            .map_err(MirrorError::Destination)?;
+
+        let next_offset = next_offset.map_or(offset, |next_offset| next_offset.max(offset));
+        if next_offset != offset {
+            self.checkpoint
+                .store(next_offset)
+                .await
+                .map_err(MirrorError::Checkpoint)?;
+        }
+        self.next_offset = Some(next_offset);
+
+        debug!(
+            topic = self.source.topic(),
+            partition = self.source.partition(),
+            n_batches,
+            next_offset,
+            high_watermark,
+            "mirrored record batches",
+        );
+        Ok(MirrorProgress {
+            batches: n_batches,
+            next_offset,
+            high_watermark,
+        })
+    }
+
+    /// Mirror until an error occurs.
+    pub async fn run(mut self) -> Result<Infallible, MirrorError> {
+        loop {
+            self.mirror_once().await?;
+        }
+    }
+
+    async fn initial_offset(&self) -> Result<i64, MirrorError> {
+        if let Some(offset) = self
+            .checkpoint
+            .load()
+            .await
+            .map_err(MirrorError::Checkpoint)?
+        {
+            return Ok(offset);
+        }
+
+        let at = match self.start_offset {
+            StartOffset::Earliest => OffsetAt::Earliest,
+            StartOffset::Latest => OffsetAt::Latest,
+            StartOffset::At(offset) => return Ok(offset),
+        };
+        self.source
+            .get_offset(at)
+            .await
+            .map_err(MirrorError::Source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        client::{
+            partition::{Compression, UnknownTopicHandling},
+            ClientBuilder,
+        },
+        mock_broker::MockBroker,
+        record::{Headers, Record},
+    };
+
+    async fn partition_client(broker: &MockBroker) -> Arc<PartitionClient> {
+        broker.create_topic("foo", 1);
+        let client = ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+        client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap()
+    }
+
+    fn record(i: i64) -> Record {
+        Record {
+            key: Some(format!("k{i}").into_bytes().into()),
+            value: Some(format!("v{i}").into_bytes().into()),
+            headers: Headers::from([("h", i.to_be_bytes().to_vec())]),
+            timestamp: Utc.timestamp_millis_opt(1_000 + i).unwrap(),
+        }
+    }
+
+    async fn records(client: &PartitionClient) -> Vec<Record> {
+        let (records, _) = client.fetch_records(0, 1..1_000_000, 0).await.unwrap();
+        records.into_iter().map(|r| r.record).collect()
+    }
+
+    #[tokio::test]
+    async fn test_mirror() {
+        let source_broker = MockBroker::start().await.unwrap();
+        let destination_broker = MockBroker::start().await.unwrap();
+        let source = partition_client(&source_broker).await;
+        let destination = partition_client(&destination_broker).await;
+        let checkpoint = Arc::new(MemoryCheckpoint::default());
+
+        source
+            .produce(vec![record(0), record(1)], Compression::NoCompression)
+            .await
+            .unwrap();
+        source
+            .produce(vec![record(2)], Compression::NoCompression)
+            .await
+            .unwrap();
+
+        let mut mirror = PartitionMirror::new(
+            Arc::clone(&source),
+            Arc::clone(&destination),
+            Arc::clone(&checkpoint) as _,
+        )
+        .with_max_wait_ms(0);
+        let progress = mirror.mirror_once().await.unwrap();
+        assert_eq!(
+            progress,
+            MirrorProgress {
+                batches: 2,
+                next_offset: 3,
+                high_watermark: 3,
+            }
+        );
+        assert_eq!(progress.lag(), 0);
+        assert_eq!(checkpoint.offset(), Some(3));
+        assert_eq!(
+            records(&destination).await,
+            vec![record(0), record(1), record(2)]
+        );
+
+        // nothing new to mirror
+        let progress = mirror.mirror_once().await.unwrap();
+        assert_eq!(progress.batches, 0);
+        assert_eq!(progress.next_offset, 3);
+
+        // a new mirror resumes at the checkpoint
+        source
+            .produce(vec![record(3)], Compression::NoCompression)
+            .await
+            .unwrap();
+        let mut mirror = PartitionMirror::new(
+            Arc::clone(&source),
+            Arc::clone(&destination),
+            Arc::clone(&checkpoint) as _,
+        )
+        .with_max_wait_ms(0);
+        let progress = mirror.mirror_once().await.unwrap();
+        assert_eq!(progress.batches, 1);
+        assert_eq!(progress.next_offset, 4);
+        assert_eq!(checkpoint.offset(), Some(4));
+        assert_eq!(
+            records(&destination).await,
+            vec![record(0), record(1), record(2), record(3)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_start_offset() {
+        let source_broker = MockBroker::start().await.unwrap();
+        let destination_broker = MockBroker::start().await.unwrap();
+        let source = partition_client(&source_broker).await;
+        let destination = partition_client(&destination_broker).await;
+
+        source
+            .produce(vec![record(0)], Compression::NoCompression)
+            .await
+            .unwrap();
+
+        let mut mirror = PartitionMirror::new(
+            Arc::clone(&source),
+            Arc::clone(&destination),
+            Arc::new(MemoryCheckpoint::default()),
+        )
+        .with_start_offset(StartOffset::Latest)
+        .with_max_wait_ms(0);
+        let progress = mirror.mirror_once().await.unwrap();
+        assert_eq!(progress.batches, 0);
+        assert_eq!(progress.next_offset, 1);
+
+        source
+            .produce(vec![record(1)], Compression::NoCompression)
+            .await
+            .unwrap();
+        let progress = mirror.mirror_once().await.unwrap();
+        assert_eq!(progress.batches, 1);
+        assert_eq!(records(&destination).await, vec![record(1)]);
+    }
+}
@@ -2,10 +2,11 @@ use crate::{
     backoff::{Backoff, BackoffConfig, ErrorOrThrottle},
     client::error::{Error, RequestContext, Result},
     connection::{
-        BrokerCache, BrokerCacheGeneration, BrokerConnection, BrokerConnector, MessengerTransport,
-        MetadataLookupMode,
+        drop_poisoned, BrokerCache, BrokerCacheGeneration, BrokerConnection, BrokerConnector,
+        ConnectionEvent, MessengerTransport, MetadataLookupMode,
     },
     messenger::RequestError,
+    metrics::{FetchBatch, Metrics, ProduceBatch},
     protocol::{
         error::Error as ProtocolError,
         messages::{
@@ -13,25 +14,92 @@ use crate::{
             DeleteRequestTopic, DeleteResponsePartition, FetchRequest, FetchRequestPartition,
             FetchRequestTopic, FetchResponse, FetchResponsePartition, IsolationLevel,
             ListOffsetsRequest, ListOffsetsRequestPartition, ListOffsetsRequestTopic,
-            ListOffsetsResponse, ListOffsetsResponsePartition, ProduceRequest,
-            ProduceRequestPartitionData, ProduceRequestTopicData, ProduceResponse, NORMAL_CONSUMER,
+            ListOffsetsResponse, ListOffsetsResponsePartition, ProduceRecords, ProduceRequest,
+            ProduceRequestPartitionData, ProduceRequestTopicData, ProduceResponse,
+            WriteVersionedError, NORMAL_CONSUMER,
         },
         primitives::*,
         record::{Record as ProtocolRecord, *},
+        traits::{ReadError, WriteError},
     },
-    record::{Record, RecordAndOffset},
+    record::{
+        ControlRecord, ControlRecordType, RawRecordBatch, Record, RecordAndOffset, RecordOrControl,
+    },
+    runtime,
     throttle::maybe_throttle,
+    topic::TopicPartition,
     validation::ExactlyOne,
 };
-use chrono::{LocalResult, TimeZone, Utc};
+use bytes::BytesMut;
+use chrono::{DateTime, LocalResult, TimeZone, Utc};
 use std::{
-    ops::{ControlFlow, Deref, Range},
-    sync::Arc,
+    collections::BTreeMap,
+    ops::{Deref, Range},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use tokio::sync::Mutex;
-use tracing::{debug, error, info};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tracing::{debug, info, info_span, Instrument, Span};
+
+use super::{
+    consumer::OffsetGap,
+    error::ServerErrorResponse,
+    metadata_cache::MetadataCacheGeneration,
+    produce_router::ProduceRouter,
+    routing::{is_connection_broken, retry_routed, ErrorAction, Route},
+};
+
+/// First version of produce requests that may contain zstd compressed batches, see [KIP-110].
+///
+/// [KIP-110]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-110%3A+Add+Codec+for+ZStandard+Compression
+const PRODUCE_ZSTD_VERSION: i16 = 7;
+
+/// Default of [`ClientBuilder::produce_ack_timeout`](super::ClientBuilder::produce_ack_timeout), same as
+/// `request.timeout.ms` of the Java producer.
+pub(super) const DEFAULT_PRODUCE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Produce settings that a [`Client`](super::Client) passes on to its [`PartitionClient`]s.
+#[derive(Debug, Clone, Default)]
+pub(super) struct ProduceConfig {
+    /// Minimum record size for which produce requests are encoded on the blocking thread pool.
+    pub(super) blocking_encode_threshold: Option<usize>,
+
+    /// Router that coalesces produce requests to the same broker, if enabled.
+    pub(super) router: Option<Arc<ProduceRouter>>,
+
+    /// Maximum number of concurrent produce requests per partition.
+    pub(super) max_in_flight: Option<usize>,
+
+    /// Minimum record size for which produce requests are compressed.
+    pub(super) min_compression_size: Option<usize>,
+
+    /// Send record batches uncompressed if compression does not make them smaller.
+    pub(super) uncompressed_fallback: bool,
+
+    /// Maximum size of an encoded record batch.
+    pub(super) max_batch_size: Option<usize>,
+
+    /// How long the partition leader waits for the acknowledgements of the replicas, defaults to
+    /// [`DEFAULT_PRODUCE_ACK_TIMEOUT`].
+    pub(super) ack_timeout: Option<Duration>,
+
+    /// Store the trace context of the current span in the headers of produced records.
+    #[cfg(feature = "otel")]
+    pub(super) inject_trace_context: bool,
+}
+
+/// Fetch settings that a [`Client`](super::Client) passes on to its [`PartitionClient`]s.
+#[derive(Debug, Clone, Default)]
+pub(super) struct FetchConfig {
+    pub(super) crc_validation: CrcValidation,
 
-use super::{error::ServerErrorResponse, metadata_cache::MetadataCacheGeneration};
+    /// Compressions of fetched record batches that are decompressed, see
+    /// [`ClientBuilder::allowed_fetch_compressions`](super::ClientBuilder::allowed_fetch_compressions).
+    pub(super) allowed_compressions: AllowedCompressions,
+}
 
 /// How strongly a [`PartitionClient`] is bound to a partition.
 ///
@@ -45,7 +113,9 @@ use super::{error::ServerErrorResponse, metadata_cache::MetadataCacheGeneration}
 /// - Use a [`Error`](Self::Error). All other methods (including the creation of a [`PartitionClient`]) may produce
 ///   sporadic [`ProtocolError::UnknownTopicOrPartition`] errors.
 /// - Use a [`Retry`](Self::Error) which assumes a partition exists and retries [`ProtocolError::UnknownTopicOrPartition`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// - Use a [`RetryFor`](Self::RetryFor) which retries for a limited time after the [`PartitionClient`] was created, e.g.
+///   while a topic that was just created propagates to all brokers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnknownTopicHandling {
     /// When a [`ProtocolError::UnknownTopicOrPartition`] is returned by Kafka,
     /// it is passed up to the user to handle.
@@ -59,6 +129,17 @@ pub enum UnknownTopicHandling {
     ///
     /// This may unpredictably increase operation latency.
     Retry,
+
+    /// Retry operations that return a [`ProtocolError::UnknownTopicOrPartition`] like [`Retry`](Self::Retry) during
+    /// the given time after the [`PartitionClient`] was created, and pass them up like [`Error`](Self::Error)
+    /// afterwards.
+    ///
+    /// This covers the window in which a new topic is not known to all brokers yet, without retrying forever when the
+    /// topic really does not exist. Since partition clients are shared, see [`Client::partition_client`], the window
+    /// starts with the first request for the partition.
+    ///
+    /// [`Client::partition_client`]: super::Client::partition_client
+    RetryFor(Duration),
 }
 
 /// Compression of records.
@@ -79,6 +160,67 @@ pub enum Compression {
     Snappy,
     #[cfg(feature = "compression-zstd")]
     Zstd,
+
+    /// zstd with the given compression level.
+    ///
+    /// Higher levels compress better but are slower, negative levels are faster than the default. `0` selects the
+    /// default level, which is also used by [`Zstd`](Self::Zstd).
+    #[cfg(feature = "compression-zstd")]
+    ZstdWithLevel(i32),
+}
+
+impl Compression {
+    fn record_batch_compression(self) -> RecordBatchCompression {
+        match self {
+            Self::NoCompression => RecordBatchCompression::NoCompression,
+            #[cfg(feature = "compression-gzip")]
+            Self::Gzip => RecordBatchCompression::Gzip,
+            #[cfg(feature = "compression-lz4")]
+            Self::Lz4 => RecordBatchCompression::Lz4,
+            #[cfg(feature = "compression-snappy")]
+            Self::Snappy => RecordBatchCompression::Snappy,
+            #[cfg(feature = "compression-zstd")]
+            Self::Zstd | Self::ZstdWithLevel(_) => RecordBatchCompression::Zstd,
+        }
+    }
+
+    /// Set of compressions that fetched record batches may use, uncompressed batches are always allowed.
+    pub(super) fn allowed(compressions: impl IntoIterator<Item = Self>) -> AllowedCompressions {
+        AllowedCompressions::new(compressions.into_iter().map(Self::record_batch_compression))
+    }
+
+    /// Whether batches are compressed with zstd, which brokers older than Kafka 2.1 reject.
+    fn is_zstd(&self) -> bool {
+        #[cfg(feature = "compression-zstd")]
+        if matches!(self, Self::Zstd | Self::ZstdWithLevel(_)) {
+            return true;
+        }
+        false
+    }
+}
+
+/// How the CRCs of fetched record batches are validated, see
+/// [`ClientBuilder::crc_validation`](super::ClientBuilder::crc_validation).
+///
+/// Skipping the validation saves CPU time when fetching large amounts of data from trusted brokers, e.g. within a
+/// datacenter. Corrupted data then goes unnoticed unless it cannot be decoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CrcValidation {
+    /// Validate every record batch and fail the fetch with a generic error on a mismatch.
+    #[default]
+    Enabled,
+
+    /// Validate every record batch and fail the fetch with [`Error::CorruptBatch`] on a mismatch.
+    Strict,
+
+    /// Validate one in the given number of record batches, counted per [`PartitionClient`].
+    ///
+    /// `0` and `1` validate every batch.
+    Sampled(u32),
+
+    /// Do not validate record batches.
+    Disabled,
 }
 
 /// Which type of offset should be requested by [`PartitionClient::get_offset`].
@@ -99,12 +241,43 @@ pub enum OffsetAt {
     Latest,
 }
 
+/// Result of a successful [produce request](PartitionClient::produce_with_metadata).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProduceResult {
+    /// Offsets assigned to the produced records, in the order they were passed to the client.
+    pub offsets: Vec<i64>,
+
+    /// Timestamp assigned by the broker when the records were appended to the log.
+    ///
+    /// This is only set if the topic uses `LogAppendTime`. For `CreateTime` topics the broker keeps the timestamps
+    /// provided by the client and this is `None`.
+    pub log_append_time: Option<DateTime<Utc>>,
+
+    /// Start offset of the partition log at the time the records were appended.
+    ///
+    /// `None` if the broker did not report it.
+    pub log_start_offset: Option<i64>,
+}
+
+impl ProduceResult {
+    /// Offset of the first produced record, if any.
+    pub fn base_offset(&self) -> Option<i64> {
+        self.offsets.first().copied()
+    }
+}
+
 #[derive(Debug)]
 struct CurrentBroker {
     broker: Option<BrokerConnection>,
     gen_broker: BrokerCacheGeneration,
     gen_leader_from_arbitrary: Option<MetadataCacheGeneration>,
     gen_leader_from_self: Option<MetadataCacheGeneration>,
+
+    /// Leader that the current broker connection belongs to.
+    leader: i32,
+
+    /// Last cached metadata that was checked for a leader change.
+    gen_leader_checked: Option<MetadataCacheGeneration>,
 }
 
 /// Many operations must be performed on the leader for a partition
@@ -128,6 +301,19 @@ pub struct PartitionClient {
     current_broker: Mutex<CurrentBroker>,
 
     unknown_topic_handling: UnknownTopicHandling,
+
+    /// Start of the [`UnknownTopicHandling::RetryFor`] window.
+    created_at: Instant,
+
+    produce_config: ProduceConfig,
+
+    /// Limits the number of concurrent produce requests, if configured.
+    produce_in_flight: Option<Semaphore>,
+
+    fetch_config: FetchConfig,
+
+    /// Number of fetched record batches, used to sample CRC validations.
+    fetched_batches: AtomicU64,
 }
 
 impl std::fmt::Debug for PartitionClient {
@@ -143,6 +329,8 @@ impl PartitionClient {
         brokers: Arc<BrokerConnector>,
         unknown_topic_handling: UnknownTopicHandling,
         backoff_config: Arc<BackoffConfig>,
+        produce_config: ProduceConfig,
+        fetch_config: FetchConfig,
     ) -> Result<Self> {
         let p = Self {
             topic,
@@ -154,25 +342,27 @@ impl PartitionClient {
                 gen_broker: BrokerCacheGeneration::START,
                 gen_leader_from_arbitrary: None,
                 gen_leader_from_self: None,
+                leader: -1,
+                gen_leader_checked: None,
             }),
             unknown_topic_handling,
+            created_at: Instant::now(),
+            produce_in_flight: produce_config.max_in_flight.map(Semaphore::new),
+            produce_config,
+            fetch_config,
+            fetched_batches: AtomicU64::new(0),
         };
 
         // Force discover and establish a cached connection to the leader
         let scope = &p;
-        maybe_retry(
-            &p.backoff_config,
-            p.unknown_topic_handling,
-            &*brokers,
-            "leader_detection",
-            || async move {
-                scope
-                    .get()
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-                Ok(())
-            },
-        )
+        retry_routed(p.backoff(), scope, "leader_detection", || async move {
+            scope
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            Ok(())
+        })
+        .instrument(p.span("leader_detection"))
         .await?;
 
         Ok(p)
@@ -188,39 +378,204 @@ impl PartitionClient {
         self.partition
     }
 
+    /// Topic and partition
+    pub fn topic_partition(&self) -> TopicPartition {
+        TopicPartition::new(self.topic.clone(), self.partition)
+    }
+
     /// Produce a batch of records to the partition
     pub async fn produce(
         &self,
         records: Vec<Record>,
         compression: Compression,
     ) -> Result<Vec<i64>> {
+        self.produce_with_metadata(records, compression)
+            .await
+            .map(|res| res.offsets)
+    }
+
+    /// Produce a batch of records to the partition, returning the full broker response metadata.
+    ///
+    /// In contrast to [`produce`](Self::produce) this also reports the log append time and the log start offset.
+    pub async fn produce_with_metadata(
+        &self,
+        records: Vec<Record>,
+        compression: Compression,
+    ) -> Result<ProduceResult> {
         // skip request entirely if `records` is empty
         if records.is_empty() {
-            return Ok(vec![]);
+            return Ok(ProduceResult::default());
         }
 
+        #[cfg(feature = "otel")]
+        let records = self.inject_trace_context(records);
+
+        let max_batch_size = self.produce_config.max_batch_size;
+        check_input_sizes(records.iter().map(Record::approximate_size), max_batch_size)?;
+
+        let _permit = self.acquire_produce_permit().await;
+
         let n = records.len() as i64;
-        let request = &build_produce_request(self.partition, &self.topic, records, compression);
-
-        maybe_retry(
-            &self.backoff_config,
-            self.unknown_topic_handling,
-            self,
-            "produce",
-            || async move {
-                let (broker, gen) = self
-                    .get()
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-                let response = broker
-                    .request(&request)
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-                maybe_throttle(response.throttle_time_ms)?;
-                process_produce_response(self.partition, &self.topic, n, response)
-                    .map_err(|e| ErrorOrThrottle::Error((e, Some(gen))))
-            },
+        let size = records.iter().map(Record::approximate_size).sum::<usize>();
+        if let Some(metrics) = self.brokers.metrics() {
+            metrics.produce_batch(&ProduceBatch {
+                topic: &self.topic,
+                partition: self.partition,
+                records: records.len(),
+                bytes: size,
+            });
+        }
+        let encode_blocking = self
+            .produce_config
+            .blocking_encode_threshold
+            .is_some_and(|threshold| size >= threshold);
+        let compression = if self
+            .produce_config
+            .min_compression_size
+            .is_some_and(|min_size| size < min_size)
+        {
+            Compression::NoCompression
+        } else {
+            compression
+        };
+        let zstd = compression.is_zstd();
+        let settings = CompressionSettings {
+            uncompressed_fallback: self.produce_config.uncompressed_fallback,
+            ..Default::default()
+        };
+        let mut request =
+            build_produce_request(self.partition, &self.topic, records, compression, settings);
+        request.timeout_ms = Int32(self.ack_timeout_ms());
+        if encode_blocking {
+            request = encode_produce_request_blocking(request, max_batch_size).await?;
+        } else if max_batch_size.is_some() {
+            // the size is only known once the batch is encoded, retries reuse the encoded batch
+            request = encode_produce_request(request, max_batch_size)?;
+        }
+
+        self.send_produce_request(&request, n, zstd).await
+    }
+
+    #[cfg(feature = "otel")]
+    fn inject_trace_context(&self, mut records: Vec<Record>) -> Vec<Record> {
+        if self.produce_config.inject_trace_context {
+            for record in &mut records {
+                crate::trace_context::inject(record);
+            }
+        }
+        records
+    }
+
+    /// Produce record batches that are already encoded, e.g. ones returned by
+    /// [`fetch_raw_batches`](Self::fetch_raw_batches).
+    ///
+    /// The batches are sent as they are, without decoding or re-compressing them. The broker assigns new offsets, so
+    /// the returned offsets cover the entire offset ranges of the batches, including offsets that were removed from the
+    /// original batches by log compaction.
+    ///
+    /// Batches written by idempotent or transactional producers keep their producer ID and sequence numbers, which the
+    /// broker might reject.
+    pub async fn produce_raw_batches(&self, batches: Vec<RawRecordBatch>) -> Result<ProduceResult> {
+        // skip request entirely if `batches` is empty
+        if batches.is_empty() {
+            return Ok(ProduceResult::default());
+        }
+
+        check_input_sizes(
+            batches.iter().map(|batch| batch.data.len()),
+            self.produce_config.max_batch_size,
+        )?;
+
+        let _permit = self.acquire_produce_permit().await;
+
+        let n = batches
+            .iter()
+            .map(|batch| batch.last_offset - batch.base_offset + 1)
+            .sum();
+        let mut request = build_raw_produce_request(self.partition, &self.topic, batches);
+        request.timeout_ms = Int32(self.ack_timeout_ms());
+
+        self.send_produce_request(&request, n, false).await
+    }
+
+    fn ack_timeout(&self) -> Duration {
+        self.produce_config
+            .ack_timeout
+            .unwrap_or(DEFAULT_PRODUCE_ACK_TIMEOUT)
+    }
+
+    fn ack_timeout_ms(&self) -> i32 {
+        i32::try_from(self.ack_timeout().as_millis()).unwrap_or(i32::MAX)
+    }
+
+    /// Span for the operation `request_name` on this partition, the spans of the requests to the brokers are nested
+    /// within.
+    fn span(&self, request_name: &'static str) -> Span {
+        info_span!(
+            "partition_request",
+            request = request_name,
+            topic = self.topic.as_str(),
+            partition = self.partition,
         )
+    }
+
+    fn backoff(&self) -> Backoff {
+        self.brokers.backoff(&self.backoff_config)
+    }
+
+    pub(crate) fn metrics(&self) -> Option<Arc<dyn Metrics>> {
+        self.brokers.metrics()
+    }
+
+    /// Wait until another produce request may be issued, if the number of concurrent requests is limited.
+    async fn acquire_produce_permit(&self) -> Option<SemaphorePermit<'_>> {
+        // permits are handed out in FIFO order, so concurrent requests are still issued in call order
+        match &self.produce_in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed"),
+            ),
+            None => None,
+        }
+    }
+
+    /// Send a produce request that contains `n` records for this partition.
+    ///
+    /// `zstd` flags zstd compressed batches, which need a newer broker than the request itself.
+    async fn send_produce_request(
+        &self,
+        request: &ProduceRequest,
+        n: i64,
+        zstd: bool,
+    ) -> Result<ProduceResult> {
+        retry_routed(self.backoff(), self, "produce", || async move {
+            let (broker, gen) = self
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            check_produce_support(&broker, zstd)
+                .map_err(|e| ErrorOrThrottle::Error((e, Some(gen))))?;
+            // requests coalesced by the router are not attributed to a single request
+            let (response, info) = match &self.produce_config.router {
+                Some(router) => (router.produce(&broker, request).await, None),
+                None => {
+                    let (response, info) = broker.request_with_info(request).await;
+                    (response, Some(info))
+                }
+            };
+            let with_info = |e: Error| match &info {
+                Some(info) => e.with_info(info),
+                None => e,
+            };
+            let response =
+                response.map_err(|e| ErrorOrThrottle::Error((with_info(e.into()), Some(gen))))?;
+            maybe_throttle(response.throttle_time_ms)?;
+            process_produce_response(self.partition, &self.topic, n, self.ack_timeout(), response)
+                .map_err(|e| ErrorOrThrottle::Error((with_info(e), Some(gen))))
+        })
+        .instrument(self.span("produce"))
         .await
     }
 
@@ -239,34 +594,151 @@ impl PartitionClient {
         bytes: Range<i32>,
         max_wait_ms: i32,
     ) -> Result<(Vec<RecordAndOffset>, i64)> {
-        let request = &build_fetch_request(offset, bytes, max_wait_ms, self.partition, &self.topic);
+        let (records, _gaps, high_watermark) = self
+            .fetch_records_and_gaps(offset, bytes, max_wait_ms)
+            .await?;
+        Ok((records, high_watermark))
+    }
 
-        let partition = maybe_retry(
-            &self.backoff_config,
-            self.unknown_topic_handling,
-            self,
-            "fetch_records",
-            || async move {
-                let (broker, gen) = self
-                    .get()
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-                let response = broker
-                    .request(&request)
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-                maybe_throttle(response.throttle_time_ms)?;
-                process_fetch_response(self.partition, &self.topic, response, offset)
-                    .map_err(|e| ErrorOrThrottle::Error((e, Some(gen))))
-            },
-        )
-        .await?;
+    /// Same as [`fetch_records`](Self::fetch_records) but also returns the gaps between the fetched record batches, see
+    /// [`find_offset_gaps`].
+    pub(crate) async fn fetch_records_and_gaps(
+        &self,
+        offset: i64,
+        bytes: Range<i32>,
+        max_wait_ms: i32,
+    ) -> Result<(Vec<RecordAndOffset>, Vec<OffsetGap>, i64)> {
+        let partition = self.fetch(offset, bytes, max_wait_ms).await?;
+
+        let batches = self.decode_batches(&partition.records)?;
+        let gaps = find_offset_gaps(&batches.0, offset);
+        let records = extract_records(batches.0, offset)?;
+        self.report_fetch(&partition, records.len());
+        let records = records
+            .into_iter()
+            .filter_map(|record| match record {
+                RecordOrControl::Record(record) => Some(record),
+                RecordOrControl::Control { .. } => None,
+            })
+            .collect();
+
+        Ok((records, gaps, partition.high_watermark.0))
+    }
+
+    /// Same as [`fetch_records`](Self::fetch_records) but also returns the transaction control records (commit and
+    /// abort markers) instead of skipping them.
+    pub async fn fetch_records_with_control(
+        &self,
+        offset: i64,
+        bytes: Range<i32>,
+        max_wait_ms: i32,
+    ) -> Result<(Vec<RecordOrControl>, i64)> {
+        let partition = self.fetch(offset, bytes, max_wait_ms).await?;
 
-        let records = extract_records(partition.records.0, offset)?;
+        let batches = self.decode_batches(&partition.records)?;
+        let records = extract_records(batches.0, offset)?;
+        self.report_fetch(&partition, records.len());
 
         Ok((records, partition.high_watermark.0))
     }
 
+    /// Fetch `bytes` bytes of record data starting at sequence number `offset`, without decoding the record batches.
+    ///
+    /// Returns the record batches, and the current high watermark. Since the broker does not split record batches, the
+    /// first batch might start before `offset`. Control batches are skipped.
+    ///
+    /// See [`fetch_records`](Self::fetch_records) for the error handling. Record data in a legacy message format (message
+    /// versions 0 and 1) cannot be returned as record batches and leads to an error.
+    pub async fn fetch_raw_batches(
+        &self,
+        offset: i64,
+        bytes: Range<i32>,
+        max_wait_ms: i32,
+    ) -> Result<(Vec<RawRecordBatch>, i64)> {
+        let (batches, _next_offset, high_watermark) = self
+            .fetch_raw_batches_and_next_offset(offset, bytes, max_wait_ms)
+            .await?;
+        Ok((batches, high_watermark))
+    }
+
+    /// Same as [`fetch_raw_batches`](Self::fetch_raw_batches) but also returns the offset after the last fetched
+    /// batch, if any. Since control batches are taken into account, this is the offset to continue fetching at even if
+    /// no record batches are returned.
+    pub(crate) async fn fetch_raw_batches_and_next_offset(
+        &self,
+        offset: i64,
+        bytes: Range<i32>,
+        max_wait_ms: i32,
+    ) -> Result<(Vec<RawRecordBatch>, Option<i64>, i64)> {
+        let partition = self.fetch(offset, bytes, max_wait_ms).await?;
+
+        let mut next_offset = None;
+        let batches = partition
+            .records
+            .batches()
+            .map_err(RequestError::from)?
+            .into_iter()
+            .filter_map(|batch| {
+                let last_offset = batch.base_offset + i64::from(batch.last_offset_delta);
+                next_offset = next_offset.max(Some(last_offset + 1));
+                (!batch.is_control).then(|| RawRecordBatch {
+                    base_offset: batch.base_offset,
+                    last_offset,
+                    data: batch.data,
+                })
+            })
+            .collect();
+
+        Ok((batches, next_offset, partition.high_watermark.0))
+    }
+
+    async fn fetch(
+        &self,
+        offset: i64,
+        bytes: Range<i32>,
+        max_wait_ms: i32,
+    ) -> Result<FetchResponsePartition> {
+        let request = &build_fetch_request(offset, bytes, max_wait_ms, self.partition, &self.topic);
+
+        retry_routed(self.backoff(), self, "fetch_records", || async move {
+            let (broker, gen) = self
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            let (response, info) = broker.request_with_info(&request).await;
+            let response = response.map_err(|e| {
+                ErrorOrThrottle::Error((Error::from(e).with_info(&info), Some(gen)))
+            })?;
+            maybe_throttle(response.throttle_time_ms)?;
+            process_fetch_response(self.partition, &self.topic, response, offset)
+                .map_err(|e| ErrorOrThrottle::Error((e.with_info(&info), Some(gen))))
+        })
+        .instrument(self.span("fetch_records"))
+        .await
+    }
+
+    fn decode_batches(&self, records: &RawRecords) -> Result<Records> {
+        decode_batches(
+            records,
+            self.fetch_config.crc_validation,
+            self.fetch_config.allowed_compressions,
+            &self.fetched_batches,
+            &self.topic,
+            self.partition,
+        )
+    }
+
+    fn report_fetch(&self, partition: &FetchResponsePartition, records: usize) {
+        if let Some(metrics) = self.brokers.metrics() {
+            metrics.fetch_batch(&FetchBatch {
+                topic: &self.topic,
+                partition: self.partition,
+                records,
+                bytes: partition.records.0.len(),
+            });
+        }
+    }
+
     /// Get offset for this partition.
     ///
     /// Note that the value returned by this method should be considered stale data, since:
@@ -277,30 +749,38 @@ impl PartitionClient {
     pub async fn get_offset(&self, at: OffsetAt) -> Result<i64> {
         let request = &build_list_offsets_request(self.partition, &self.topic, at);
 
-        let partition = maybe_retry(
-            &self.backoff_config,
-            self.unknown_topic_handling,
-            self,
-            "get_offset",
-            || async move {
-                let (broker, gen) = self
-                    .get()
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-                let response = broker
-                    .request(&request)
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-                maybe_throttle(response.throttle_time_ms)?;
-                process_list_offsets_response(self.partition, &self.topic, response)
-                    .map_err(|e| ErrorOrThrottle::Error((e, Some(gen))))
-            },
-        )
+        let partition = retry_routed(self.backoff(), self, "get_offset", || async move {
+            let (broker, gen) = self
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            let (response, info) = broker.request_with_info(&request).await;
+            let response = response.map_err(|e| {
+                ErrorOrThrottle::Error((Error::from(e).with_info(&info), Some(gen)))
+            })?;
+            maybe_throttle(response.throttle_time_ms)?;
+            process_list_offsets_response(self.partition, &self.topic, response)
+                .map_err(|e| ErrorOrThrottle::Error((e.with_info(&info), Some(gen))))
+        })
+        .instrument(self.span("get_offset"))
         .await?;
 
         extract_offset(partition)
     }
 
+    /// Get the earliest and the latest offset of this partition, e.g. to compute the lag of a consumer.
+    ///
+    /// See [`get_offset`](Self::get_offset) on why the returned values should be considered stale.
+    pub async fn watermarks(&self) -> Result<(i64, i64)> {
+        // brokers reject list offsets requests that contain a partition twice, so issue two requests that are
+        // pipelined on the leader connection
+        futures::future::try_join(
+            self.get_offset(OffsetAt::Earliest),
+            self.get_offset(OffsetAt::Latest),
+        )
+        .await
+    }
+
     /// Delete records whose offset is smaller than the given offset.
     ///
     /// # Supported Brokers
@@ -310,25 +790,20 @@ impl PartitionClient {
         let request =
             &build_delete_records_request(offset, timeout_ms, &self.topic, self.partition);
 
-        maybe_retry(
-            &self.backoff_config,
-            self.unknown_topic_handling,
-            self,
-            "delete_records",
-            || async move {
-                let (broker, gen) = self
-                    .get()
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
-                let response = broker
-                    .request(&request)
-                    .await
-                    .map_err(|e| ErrorOrThrottle::Error((e.into(), Some(gen))))?;
-                maybe_throttle(Some(response.throttle_time_ms))?;
-                process_delete_records_response(&self.topic, self.partition, response)
-                    .map_err(|e| ErrorOrThrottle::Error((e, Some(gen))))
-            },
-        )
+        retry_routed(self.backoff(), self, "delete_records", || async move {
+            let (broker, gen) = self
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            let (response, info) = broker.request_with_info(&request).await;
+            let response = response.map_err(|e| {
+                ErrorOrThrottle::Error((Error::from(e).with_info(&info), Some(gen)))
+            })?;
+            maybe_throttle(Some(response.throttle_time_ms))?;
+            process_delete_records_response(&self.topic, self.partition, response)
+                .map_err(|e| ErrorOrThrottle::Error((e.with_info(&info), Some(gen))))
+        })
+        .instrument(self.span("delete_records"))
         .await?;
 
         Ok(())
@@ -364,6 +839,7 @@ impl PartitionClient {
                 request: RequestContext::Topic(self.topic.clone()),
                 response: None,
                 is_virtual: false,
+                info: None,
             });
         }
 
@@ -386,6 +862,7 @@ impl PartitionClient {
                 request: RequestContext::Partition(self.topic.clone(), self.partition),
                 response: None,
                 is_virtual: false,
+                info: None,
             });
         }
 
@@ -396,6 +873,7 @@ impl PartitionClient {
                 request: RequestContext::Partition(self.topic.clone(), self.partition),
                 response: None,
                 is_virtual: true,
+                info: None,
             });
         }
 
@@ -417,8 +895,28 @@ impl BrokerCache for &PartitionClient {
 
     async fn get(&self) -> Result<(Arc<Self::R>, BrokerCacheGeneration)> {
         let mut current_broker = self.current_broker.lock().await;
-        if let Some(broker) = &current_broker.broker {
-            return Ok((Arc::clone(broker), current_broker.gen_broker));
+        drop_poisoned(&mut current_broker.broker);
+        if let Some(broker) = current_broker.broker.clone() {
+            // The cached metadata might have been refreshed in the background since we connected. Every refresh is
+            // checked once, so a stale entry cannot cause reconnects over and over again.
+            match self.brokers.cached_leader(&self.topic, self.partition) {
+                Some((leader, gen)) if Some(gen) != current_broker.gen_leader_checked => {
+                    current_broker.gen_leader_checked = Some(gen);
+                    if leader == current_broker.leader {
+                        return Ok((broker, current_broker.gen_broker));
+                    }
+
+                    info!(
+                        topic=%self.topic,
+                        partition=%self.partition,
+                        old_leader=current_broker.leader,
+                        new_leader=leader,
+                        "Cached metadata reports new leader",
+                    );
+                    current_broker.broker = None;
+                }
+                _ => return Ok((broker, current_broker.gen_broker)),
+            }
         }
 
         info!(
@@ -497,6 +995,7 @@ impl BrokerCache for &PartitionClient {
                     new_leader: leader_self,
                 }),
                 is_virtual: true,
+                info: None,
             });
         }
 
@@ -505,6 +1004,8 @@ impl BrokerCache for &PartitionClient {
             gen_broker: current_broker.gen_broker.bump(),
             gen_leader_from_arbitrary,
             gen_leader_from_self,
+            leader,
+            gen_leader_checked: gen_leader_from_arbitrary,
         };
 
         info!(
@@ -544,101 +1045,68 @@ impl BrokerCache for &PartitionClient {
             self.brokers.invalidate_metadata_cache(reason, gen);
         }
 
-        current_broker.broker = None
+        if let Some(broker) = current_broker.broker.take() {
+            broker.report_event(&ConnectionEvent::Invalidated { reason });
+        }
     }
 }
 
-/// Takes a `request_name` and a function yielding a fallible future
-/// and handles certain classes of error
-async fn maybe_retry<B, R, F, T>(
-    backoff_config: &BackoffConfig,
-    unknown_topic_handling: UnknownTopicHandling,
-    broker_cache: B,
-    request_name: &str,
-    f: R,
-) -> Result<T>
-where
-    B: BrokerCache,
-    R: (Fn() -> F) + Send + Sync,
-    F: std::future::Future<
-            Output = Result<T, ErrorOrThrottle<(Error, Option<BrokerCacheGeneration>)>>,
-        > + Send,
-{
-    let mut backoff = Backoff::new(backoff_config);
-
-    backoff
-        .retry_with_backoff(request_name, || async {
-            let (error, cache_gen) = match f().await {
-                Ok(v) => {
-                    return ControlFlow::Break(Ok(v));
-                }
-                Err(ErrorOrThrottle::Throttle(throttle)) => {
-                    return ControlFlow::Continue(ErrorOrThrottle::Throttle(throttle));
-                }
-                Err(ErrorOrThrottle::Error(e)) => e,
-            };
-
-            let retry = match error {
-                Error::Request(RequestError::Poisoned(_) | RequestError::IO(_))
-                | Error::Connection(_) => {
-                    if let Some(cache_gen) = cache_gen {
-                        broker_cache
-                            .invalidate("partition client: connection broken", cache_gen)
-                            .await;
-                    }
-                    true
-                }
-                Error::ServerError {
-                    protocol_error:
-                        ProtocolError::InvalidReplicationFactor
-                        | ProtocolError::LeaderNotAvailable
-                        | ProtocolError::OffsetNotAvailable,
-                    ..
-                } => true,
-                Error::ServerError {
-                    protocol_error: ProtocolError::NotLeaderOrFollower,
-                    ..
-                } => {
-                    if let Some(cache_gen) = cache_gen {
-                        broker_cache
-                            .invalidate(
-                                "partition client: server error: not leader or follower",
-                                cache_gen,
-                            )
-                            .await;
+impl Route for &PartitionClient {
+    fn classify(&self, error: &Error) -> ErrorAction {
+        match error {
+            e if is_connection_broken(e) => {
+                ErrorAction::Invalidate("partition client: connection broken")
+            }
+            Error::ServerError {
+                protocol_error:
+                    ProtocolError::InvalidReplicationFactor
+                    | ProtocolError::LeaderNotAvailable
+                    | ProtocolError::OffsetNotAvailable,
+                ..
+            } => ErrorAction::Retry,
+            Error::ServerError {
+                protocol_error: ProtocolError::NotLeaderOrFollower,
+                ..
+            } => ErrorAction::Invalidate("partition client: server error: not leader or follower"),
+            Error::ServerError {
+                protocol_error: ProtocolError::UnknownTopicOrPartition,
+                ..
+            } => {
+                let reason = "partition client: server error: unknown topic or partition";
+                match self.unknown_topic_handling {
+                    UnknownTopicHandling::Retry => ErrorAction::Invalidate(reason),
+                    UnknownTopicHandling::RetryFor(window)
+                        if self.created_at.elapsed() < window =>
+                    {
+                        ErrorAction::Invalidate(reason)
                     }
-                    true
-                }
-                Error::ServerError {
-                    protocol_error: ProtocolError::UnknownTopicOrPartition,
-                    ..
-                } => {
-                    if let Some(cache_gen) = cache_gen {
-                        broker_cache
-                            .invalidate(
-                                "partition client: server error: unknown topic or partition",
-                                cache_gen,
-                            )
-                            .await;
+                    UnknownTopicHandling::RetryFor(_) | UnknownTopicHandling::Error => {
+                        ErrorAction::InvalidateAndFail(reason)
                     }
-                    unknown_topic_handling == UnknownTopicHandling::Retry
                 }
-                _ => false,
-            };
-
-            if retry {
-                ControlFlow::Continue(ErrorOrThrottle::Error(error))
-            } else {
-                error!(
-                    e=%error,
-                    request_name,
-                    "request encountered fatal error",
-                );
-                ControlFlow::Break(Err(error))
             }
-        })
-        .await
-        .map_err(Error::RetryFailed)?
+            _ => ErrorAction::Fail,
+        }
+    }
+}
+
+/// Check that `broker` accepts produce requests, and zstd compressed batches if `zstd` is set.
+///
+/// Record headers need no check, they are part of message format v2 which every produce request uses.
+fn check_produce_support(broker: &MessengerTransport, zstd: bool) -> Result<()> {
+    match broker.api_version::<ProduceRequest>() {
+        None => Err(Error::UnsupportedBroker {
+            feature: "message format v2",
+            min_kafka_version: "0.11",
+        }),
+        Some(version) if zstd && version.0 .0 < PRODUCE_ZSTD_VERSION => {
+            Err(Error::UnsupportedBroker {
+                feature: "zstd compression",
+                min_kafka_version: "2.1",
+            })
+        }
+        Some(_) => Ok(()),
+    }
 }
 
 fn build_produce_request(
@@ -646,6 +1114,7 @@ fn build_produce_request(
     topic: &str,
     records: Vec<Record>,
     compression: Compression,
+    settings: CompressionSettings,
 ) -> ProduceRequest {
     let n = records.len() as i32;
 
@@ -674,25 +1143,34 @@ fn build_produce_request(
         })
         .collect();
 
-    let record_batch = ProduceRequestPartitionData {
-        index: Int32(partition),
-        records: Records(vec![RecordBatch {
+    let (batch_compression, settings) = match compression {
+        Compression::NoCompression => (RecordBatchCompression::NoCompression, settings),
+        #[cfg(feature = "compression-gzip")]
+        Compression::Gzip => (RecordBatchCompression::Gzip, settings),
+        #[cfg(feature = "compression-lz4")]
+        Compression::Lz4 => (RecordBatchCompression::Lz4, settings),
+        #[cfg(feature = "compression-snappy")]
+        Compression::Snappy => (RecordBatchCompression::Snappy, settings),
+        #[cfg(feature = "compression-zstd")]
+        Compression::Zstd => (RecordBatchCompression::Zstd, settings),
+        #[cfg(feature = "compression-zstd")]
+        Compression::ZstdWithLevel(level) => (
+            RecordBatchCompression::Zstd,
+            CompressionSettings {
+                zstd_level: level,
+                ..settings
+            },
+        ),
+    };
+
+    let records = ProduceRecords::Batches(
+        Records(vec![RecordBatch {
             base_offset: 0,
             partition_leader_epoch: 0,
             last_offset_delta: n - 1,
             is_transactional: false,
             base_sequence: -1,
-            compression: match compression {
-                Compression::NoCompression => RecordBatchCompression::NoCompression,
-                #[cfg(feature = "compression-gzip")]
-                Compression::Gzip => RecordBatchCompression::Gzip,
-                #[cfg(feature = "compression-lz4")]
-                Compression::Lz4 => RecordBatchCompression::Lz4,
-                #[cfg(feature = "compression-snappy")]
-                Compression::Snappy => RecordBatchCompression::Snappy,
-                #[cfg(feature = "compression-zstd")]
-                Compression::Zstd => RecordBatchCompression::Zstd,
-            },
+            compression: batch_compression,
             timestamp_type: RecordBatchTimestampType::CreateTime,
             producer_id: -1,
             producer_epoch: -1,
@@ -700,6 +1178,40 @@ fn build_produce_request(
             max_timestamp: max_timestamp.timestamp_millis(),
             records: ControlBatchOrRecords::Records(records),
         }]),
+        settings,
+    );
+
+    build_produce_request_for_records(partition, topic, records)
+}
+
+fn build_raw_produce_request(
+    partition: i32,
+    topic: &str,
+    batches: Vec<RawRecordBatch>,
+) -> ProduceRequest {
+    let records = match batches.as_slice() {
+        [batch] => batch.data.clone(),
+        batches => {
+            let mut buf = BytesMut::with_capacity(batches.iter().map(|b| b.data.len()).sum());
+            for batch in batches {
+                buf.extend_from_slice(&batch.data);
+            }
+            buf.freeze()
+        }
+    };
+
+    build_produce_request_for_records(partition, topic, ProduceRecords::Encoded(records))
+}
+
+fn build_produce_request_for_records(
+    partition: i32,
+    topic: &str,
+    records: ProduceRecords,
+) -> ProduceRequest {
+    let record_batch = ProduceRequestPartitionData {
+        index: Int32(partition),
+        records,
+        tagged_fields: None,
     };
 
     ProduceRequest {
@@ -709,16 +1221,132 @@ fn build_produce_request(
         topic_data: vec![ProduceRequestTopicData {
             name: String_(topic.to_string()),
             partition_data: vec![record_batch],
+            tagged_fields: None,
         }],
+        tagged_fields: None,
+    }
+}
+
+/// Encode the record batches of `request`, failing if they exceed `max_batch_size` bytes.
+fn encode_produce_request(
+    mut request: ProduceRequest,
+    max_batch_size: Option<usize>,
+) -> Result<ProduceRequest> {
+    for partition_data in request
+        .topic_data
+        .iter_mut()
+        .flat_map(|topic_data| topic_data.partition_data.iter_mut())
+    {
+        let records = std::mem::replace(
+            &mut partition_data.records,
+            ProduceRecords::Encoded(Default::default()),
+        );
+        let records = records.encode().map_err(write_error)?;
+        if let (ProduceRecords::Encoded(data), Some(limit)) = (&records, max_batch_size) {
+            if data.len() > limit {
+                return Err(Error::RecordTooLarge {
+                    index: None,
+                    size: data.len(),
+                    limit,
+                });
+            }
+        }
+        partition_data.records = records;
+    }
+    Ok(request)
+}
+
+/// Same as [`encode_produce_request`], but on the blocking thread pool.
+async fn encode_produce_request_blocking(
+    request: ProduceRequest,
+    max_batch_size: Option<usize>,
+) -> Result<ProduceRequest> {
+    runtime::spawn_blocking(move || encode_produce_request(request, max_batch_size))
+        .await
+        .unwrap_or_else(|e| Err(write_error(WriteError::Malformed(Box::new(e)))))
+}
+
+fn write_error(e: WriteError) -> Error {
+    RequestError::from(WriteVersionedError::from(e)).into()
+}
+
+/// Fail if one of the `sizes` of the inputs to a produce request exceeds `max_batch_size`.
+fn check_input_sizes(
+    sizes: impl IntoIterator<Item = usize>,
+    max_batch_size: Option<usize>,
+) -> Result<()> {
+    let Some(limit) = max_batch_size else {
+        return Ok(());
+    };
+    match sizes
+        .into_iter()
+        .enumerate()
+        .find(|(_, size)| *size > limit)
+    {
+        Some((index, size)) => Err(Error::RecordTooLarge {
+            index: Some(index),
+            size,
+            limit,
+        }),
+        None => Ok(()),
     }
 }
 
+/// Decode fetched record batches, validating CRCs according to `crc_validation` and only decompressing
+/// `allowed_compressions`.
+///
+/// `fetched_batches` counts the batches for sampling.
+fn decode_batches(
+    records: &RawRecords,
+    crc_validation: CrcValidation,
+    allowed_compressions: AllowedCompressions,
+    fetched_batches: &AtomicU64,
+    topic: &str,
+    partition: i32,
+) -> Result<Records> {
+    let check_crc = || match crc_validation {
+        CrcValidation::Enabled | CrcValidation::Strict => true,
+        CrcValidation::Sampled(n) => {
+            fetched_batches.fetch_add(1, Ordering::Relaxed) % u64::from(n.max(1)) == 0
+        }
+        CrcValidation::Disabled => false,
+    };
+
+    records
+        .decode_checked(check_crc, allowed_compressions)
+        .map_err(|e| {
+            if let ReadError::Malformed(source) = &e {
+                if let Some(not_allowed) = source.downcast_ref::<CompressionNotAllowed>() {
+                    return Error::CompressionNotAllowed {
+                        topic: topic.to_owned(),
+                        partition,
+                        offset: not_allowed.offset,
+                        compression: not_allowed.compression.name(),
+                    };
+                }
+                if crc_validation == CrcValidation::Strict {
+                    if let Some(mismatch) = source.downcast_ref::<CrcMismatch>() {
+                        return Error::CorruptBatch {
+                            topic: topic.to_owned(),
+                            partition,
+                            offset: mismatch.offset,
+                            expected_crc: mismatch.expected,
+                            actual_crc: mismatch.actual,
+                        };
+                    }
+                }
+            }
+            RequestError::from(e).into()
+        })
+}
+
 fn process_produce_response(
     partition: i32,
     topic: &str,
     num_records: i64,
+    ack_timeout: Duration,
     response: ProduceResponse,
-) -> Result<Vec<i64>> {
+) -> Result<ProduceResult> {
     let response = response
         .responses
         .exactly_one()
@@ -744,16 +1372,42 @@ fn process_produce_response(
     }
 
     match response.error {
+        // with `acks=-1`, this means that the in-sync replicas did not catch up in time
+        Some(ProtocolError::RequestTimedOut) => Err(Error::ProduceAckTimeout {
+            topic: topic.to_owned(),
+            partition,
+            timeout: ack_timeout,
+        }),
         Some(e) => Err(Error::ServerError {
             protocol_error: e,
-            error_message: None,
+            error_message: response.error_message.and_then(|m| m.0),
             request: RequestContext::Partition(topic.to_owned(), partition),
             response: None,
             is_virtual: false,
+            info: None,
         }),
-        None => Ok((0..num_records)
-            .map(|x| x + response.base_offset.0)
-            .collect()),
+        None => {
+            // `-1` signals that the topic uses `CreateTime`
+            let log_append_time = match response.log_append_time_ms {
+                Some(Int64(-1)) | None => None,
+                Some(Int64(ms)) => match Utc.timestamp_millis_opt(ms) {
+                    LocalResult::Single(ts) => Some(ts),
+                    _ => {
+                        return Err(Error::InvalidResponse(format!(
+                            "Not a valid log append time ({ms})"
+                        )))
+                    }
+                },
+            };
+
+            Ok(ProduceResult {
+                offsets: (0..num_records)
+                    .map(|x| x + response.base_offset.0)
+                    .collect(),
+                log_append_time,
+                log_start_offset: response.log_start_offset.map(|o| o.0).filter(|o| *o >= 0),
+            })
+        }
     }
 }
 
@@ -770,14 +1424,24 @@ fn build_fetch_request(
         min_bytes: Int32(bytes.start),
         max_bytes: Some(Int32(bytes.end.saturating_sub(1))),
         isolation_level: Some(IsolationLevel::ReadCommitted),
+        session_id: None,
+        session_epoch: None,
         topics: vec![FetchRequestTopic {
             topic: String_(topic.to_string()),
             partitions: vec![FetchRequestPartition {
                 partition: Int32(partition),
+                current_leader_epoch: None,
                 fetch_offset: Int64(offset),
+                last_fetched_epoch: None,
+                log_start_offset: None,
                 partition_max_bytes: Int32(bytes.end.saturating_sub(1)),
+                tagged_fields: None,
             }],
+            tagged_fields: None,
         }],
+        forgotten_topics_data: vec![],
+        rack_id: None,
+        tagged_fields: None,
     }
 }
 
@@ -787,6 +1451,21 @@ fn process_fetch_response(
     response: FetchResponse,
     request_offset: i64,
 ) -> Result<FetchResponsePartition> {
+    if let Some(err) = response.error_code {
+        return Err(Error::ServerError {
+            protocol_error: err,
+            error_message: None,
+            request: RequestContext::Fetch {
+                topic_name: topic.to_owned(),
+                partition_id: partition,
+                offset: request_offset,
+            },
+            response: None,
+            is_virtual: false,
+            info: None,
+        });
+    }
+
     let response_topic = response
         .responses
         .exactly_one()
@@ -825,22 +1504,59 @@ fn process_fetch_response(
                 last_stable_offset: response_partition.last_stable_offset.map(|x| x.0),
             }),
             is_virtual: false,
+            info: None,
         });
     }
 
     Ok(response_partition)
 }
 
+/// Offsets that are not covered by the record batches fetched at `request_offset`.
+///
+/// Batches keep their offset range when log compaction removes some of their records and control batches take up an
+/// offset as well, so the batches of a partition are contiguous. Compaction can remove whole batches though.
+fn find_offset_gaps(batches: &[RecordBatch], request_offset: i64) -> Vec<OffsetGap> {
+    let mut expected = request_offset;
+    let mut gaps = vec![];
+    for batch in batches {
+        if batch.base_offset > expected {
+            gaps.push(OffsetGap {
+                expected,
+                actual: batch.base_offset,
+            });
+        }
+        expected = expected.max(batch.base_offset + i64::from(batch.last_offset_delta) + 1);
+    }
+    gaps
+}
+
 fn extract_records(
     partition_records: Vec<RecordBatch>,
     request_offset: i64,
-) -> Result<Vec<RecordAndOffset>> {
+) -> Result<Vec<RecordOrControl>> {
     let mut records = vec![];
 
     for batch in partition_records {
         match batch.records {
-            ControlBatchOrRecords::ControlBatch(_) => {
-                // ignore
+            ControlBatchOrRecords::ControlBatch(control_batch) => {
+                // control batches contain a single record
+                let offset = batch.base_offset;
+                if offset < request_offset {
+                    continue;
+                }
+
+                records.push(RecordOrControl::Control {
+                    record: ControlRecord {
+                        control_type: match control_batch {
+                            ControlBatchRecord::Abort => ControlRecordType::Abort,
+                            ControlBatchRecord::Commit => ControlRecordType::Commit,
+                        },
+                        producer_id: batch.producer_id,
+                        producer_epoch: batch.producer_epoch,
+                        timestamp: parse_timestamp(batch.first_timestamp, 0)?,
+                    },
+                    offset,
+                });
             }
             ControlBatchOrRecords::Records(protocol_records) => {
                 records.reserve(protocol_records.len());
@@ -853,31 +1569,9 @@ fn extract_records(
                         continue;
                     }
 
-                    let timestamp_millis =
-                        match batch.first_timestamp.checked_add(record.timestamp_delta) {
-                            Some(ts) => ts,
-                            None => {
-                                return Err(Error::InvalidResponse(format!(
-                                    "Timestamp overflow (first_timestamp={}, delta={}",
-                                    batch.first_timestamp, record.timestamp_delta
-                                )));
-                            }
-                        };
-                    let timestamp = match Utc.timestamp_millis_opt(timestamp_millis) {
-                        LocalResult::None => {
-                            return Err(Error::InvalidResponse(format!(
-                                "Not a valid timestamp ({timestamp_millis})"
-                            )));
-                        }
-                        LocalResult::Single(ts) => ts,
-                        LocalResult::Ambiguous(a, b) => {
-                            return Err(Error::InvalidResponse(format!(
-                                "Ambiguous timestamp ({timestamp_millis}): {a} or {b}"
-                            )));
-                        }
-                    };
+                    let timestamp = parse_timestamp(batch.first_timestamp, record.timestamp_delta)?;
 
-                    records.push(RecordAndOffset {
+                    records.push(RecordOrControl::Record(RecordAndOffset {
                         record: Record {
                             key: record.key,
                             value: record.value,
@@ -889,7 +1583,7 @@ fn extract_records(
                             timestamp,
                         },
                         offset,
-                    })
+                    }))
                 }
             }
         }
@@ -898,26 +1592,102 @@ fn extract_records(
     Ok(records)
 }
 
-fn build_list_offsets_request(partition: i32, topic: &str, at: OffsetAt) -> ListOffsetsRequest {
-    let timestamp = match at {
-        OffsetAt::Earliest => -2,
-        OffsetAt::Latest => -1,
+/// Get the timestamp of a record within a batch.
+fn parse_timestamp(first_timestamp: i64, timestamp_delta: i64) -> Result<DateTime<Utc>> {
+    let timestamp_millis = match first_timestamp.checked_add(timestamp_delta) {
+        Some(ts) => ts,
+        None => {
+            return Err(Error::InvalidResponse(format!(
+                "Timestamp overflow (first_timestamp={}, delta={}",
+                first_timestamp, timestamp_delta
+            )));
+        }
     };
+    match Utc.timestamp_millis_opt(timestamp_millis) {
+        LocalResult::None => Err(Error::InvalidResponse(format!(
+            "Not a valid timestamp ({timestamp_millis})"
+        ))),
+        LocalResult::Single(ts) => Ok(ts),
+        LocalResult::Ambiguous(a, b) => Err(Error::InvalidResponse(format!(
+            "Ambiguous timestamp ({timestamp_millis}): {a} or {b}"
+        ))),
+    }
+}
 
+fn build_list_offsets_request(partition: i32, topic: &str, at: OffsetAt) -> ListOffsetsRequest {
     ListOffsetsRequest {
         replica_id: NORMAL_CONSUMER,
         isolation_level: Some(IsolationLevel::ReadCommitted),
         topics: vec![ListOffsetsRequestTopic {
             name: String_(topic.to_owned()),
-            partitions: vec![ListOffsetsRequestPartition {
-                partition_index: Int32(partition),
-                timestamp: Int64(timestamp),
-                max_num_offsets: Some(Int32(1)),
-            }],
+            partitions: vec![list_offsets_request_partition(partition, at)],
         }],
     }
 }
 
+/// Build a request for the offsets of multiple partitions, which must be distinct.
+pub(super) fn build_multi_list_offsets_request(
+    partitions: &[(TopicPartition, OffsetAt)],
+) -> ListOffsetsRequest {
+    let mut topics: BTreeMap<&str, Vec<ListOffsetsRequestPartition>> = BTreeMap::new();
+    for (tp, at) in partitions {
+        topics
+            .entry(&tp.topic)
+            .or_default()
+            .push(list_offsets_request_partition(tp.partition, *at));
+    }
+
+    ListOffsetsRequest {
+        replica_id: NORMAL_CONSUMER,
+        isolation_level: Some(IsolationLevel::ReadCommitted),
+        topics: topics
+            .into_iter()
+            .map(|(topic, partitions)| ListOffsetsRequestTopic {
+                name: String_(topic.to_owned()),
+                partitions,
+            })
+            .collect(),
+    }
+}
+
+fn list_offsets_request_partition(partition: i32, at: OffsetAt) -> ListOffsetsRequestPartition {
+    let timestamp = match at {
+        OffsetAt::Earliest => -2,
+        OffsetAt::Latest => -1,
+    };
+
+    ListOffsetsRequestPartition {
+        partition_index: Int32(partition),
+        timestamp: Int64(timestamp),
+        max_num_offsets: Some(Int32(1)),
+    }
+}
+
+/// Offsets, or the errors reported instead, of all partitions in `response`.
+pub(super) fn process_multi_list_offsets_response(
+    response: ListOffsetsResponse,
+) -> BTreeMap<TopicPartition, Result<i64>> {
+    let mut offsets = BTreeMap::new();
+    for topic in response.topics {
+        for partition in topic.partitions {
+            let tp = TopicPartition::new(topic.name.0.clone(), partition.partition_index.0);
+            let offset = match partition.error_code {
+                Some(err) => Err(Error::ServerError {
+                    protocol_error: err,
+                    error_message: None,
+                    request: RequestContext::Partition(tp.topic.clone(), tp.partition),
+                    response: None,
+                    is_virtual: false,
+                    info: None,
+                }),
+                None => extract_offset(partition),
+            };
+            offsets.insert(tp, offset);
+        }
+    }
+    offsets
+}
+
 fn process_list_offsets_response(
     partition: i32,
     topic: &str,
@@ -954,6 +1724,7 @@ fn process_list_offsets_response(
             request: RequestContext::Partition(topic.to_owned(), partition),
             response: None,
             is_virtual: false,
+            info: None,
         }),
         None => Ok(response_partition),
     }
@@ -1040,7 +1811,611 @@ fn process_delete_records_response(
             request: RequestContext::Partition(topic.to_owned(), partition),
             response: None,
             is_virtual: false,
+            info: None,
         }),
         None => Ok(response_partition),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use crate::client::error::ErrorKind;
+
+    use crate::{
+        protocol::{
+            messages::{ProduceResponsePartitionResponse, ProduceResponseResponse},
+            traits::WriteType,
+        },
+        record::Headers,
+    };
+
+    use super::*;
+
+    fn produce_response(log_append_time_ms: i64, log_start_offset: i64) -> ProduceResponse {
+        ProduceResponse {
+            responses: vec![ProduceResponseResponse {
+                name: String_("foo".to_owned()),
+                partition_responses: vec![ProduceResponsePartitionResponse {
+                    index: Int32(1),
+                    error: None,
+                    base_offset: Int64(10),
+                    log_append_time_ms: Some(Int64(log_append_time_ms)),
+                    log_start_offset: Some(Int64(log_start_offset)),
+                    record_errors: vec![],
+                    error_message: None,
+                    tagged_fields: None,
+                }],
+                tagged_fields: None,
+            }],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        }
+    }
+
+    #[test]
+    fn test_process_produce_response_create_time() {
+        let res =
+            process_produce_response(1, "foo", 3, Duration::ZERO, produce_response(-1, 2)).unwrap();
+        assert_eq!(
+            res,
+            ProduceResult {
+                offsets: vec![10, 11, 12],
+                log_append_time: None,
+                log_start_offset: Some(2),
+            }
+        );
+        assert_eq!(res.base_offset(), Some(10));
+    }
+
+    #[test]
+    fn test_process_produce_response_log_append_time() {
+        let res = process_produce_response(1, "foo", 1, Duration::ZERO, produce_response(1337, -1))
+            .unwrap();
+        assert_eq!(
+            res.log_append_time,
+            Some(Utc.timestamp_millis_opt(1337).unwrap())
+        );
+        assert_eq!(res.log_start_offset, None);
+    }
+
+    #[test]
+    fn test_process_produce_response_ack_timeout() {
+        let mut response = produce_response(-1, -1);
+        response.responses[0].partition_responses[0].error = Some(ProtocolError::RequestTimedOut);
+        let err =
+            process_produce_response(1, "foo", 1, Duration::from_secs(5), response).unwrap_err();
+        assert_matches!(
+            err,
+            Error::ProduceAckTimeout { ref topic, partition: 1, timeout } => {
+                assert_eq!(topic, "foo");
+                assert_eq!(timeout, Duration::from_secs(5));
+            }
+        );
+        assert_eq!(err.kind(), ErrorKind::BrokerRetriable);
+    }
+
+    #[test]
+    fn test_extract_records_control() {
+        let batch = |base_offset: i64, records: ControlBatchOrRecords| RecordBatch {
+            base_offset,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            first_timestamp: 1337,
+            max_timestamp: 1337,
+            producer_id: 5,
+            producer_epoch: 1,
+            base_sequence: 0,
+            records,
+            compression: RecordBatchCompression::NoCompression,
+            is_transactional: true,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+        let batches = vec![
+            batch(
+                10,
+                ControlBatchOrRecords::Records(vec![ProtocolRecord {
+                    key: None,
+                    value: Some(b"foo".to_vec().into()),
+                    timestamp_delta: 0,
+                    offset_delta: 0,
+                    headers: vec![],
+                }]),
+            ),
+            batch(
+                11,
+                ControlBatchOrRecords::ControlBatch(ControlBatchRecord::Commit),
+            ),
+        ];
+
+        let records = extract_records(batches, 10).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_matches!(
+            &records[0],
+            RecordOrControl::Record(RecordAndOffset { offset: 10, .. })
+        );
+        assert_eq!(
+            records[1],
+            RecordOrControl::Control {
+                record: ControlRecord {
+                    control_type: ControlRecordType::Commit,
+                    producer_id: 5,
+                    producer_epoch: 1,
+                    timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+                },
+                offset: 11,
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_batch_size() {
+        check_input_sizes([10, 20], None).unwrap();
+        check_input_sizes([10, 20], Some(20)).unwrap();
+        assert_matches!(
+            check_input_sizes([10, 30, 40], Some(20)),
+            Err(Error::RecordTooLarge {
+                index: Some(1),
+                size: 30,
+                limit: 20
+            })
+        );
+
+        let record = Record {
+            key: None,
+            value: Some(vec![b'x'; 100].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let request = || {
+            build_produce_request(
+                0,
+                "foo",
+                vec![record.clone(); 2],
+                Compression::NoCompression,
+                CompressionSettings::default(),
+            )
+        };
+        let encoded = encode_produce_request(request(), Some(1_000)).unwrap();
+        let ProduceRecords::Encoded(data) = &encoded.topic_data[0].partition_data[0].records else {
+            panic!("expected encoded records");
+        };
+        let size = data.len();
+
+        let err = encode_produce_request(request(), Some(size - 1)).unwrap_err();
+        assert_matches!(
+            err,
+            Error::RecordTooLarge { index: None, size: s, limit } if s == size && limit == size - 1
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Record batch of {size} bytes exceeds the limit of {} bytes",
+                size - 1
+            )
+        );
+    }
+
+    #[test]
+    fn test_decode_batches_crc_validation() {
+        let mut data = vec![];
+        let mut starts = vec![];
+        for base_offset in 0..3 {
+            starts.push(data.len());
+            RecordBatch {
+                base_offset,
+                partition_leader_epoch: 0,
+                last_offset_delta: 0,
+                first_timestamp: 0,
+                max_timestamp: 0,
+                producer_id: -1,
+                producer_epoch: -1,
+                base_sequence: -1,
+                records: ControlBatchOrRecords::Records(vec![ProtocolRecord {
+                    key: None,
+                    value: Some(b"foo".to_vec().into()),
+                    timestamp_delta: 0,
+                    offset_delta: 0,
+                    headers: vec![],
+                }]),
+                compression: RecordBatchCompression::NoCompression,
+                is_transactional: false,
+                timestamp_type: RecordBatchTimestampType::CreateTime,
+            }
+            .write(&mut data)
+            .unwrap();
+        }
+        // corrupt the CRC of the second batch, which follows base offset, length, leader epoch and magic
+        data[starts[1] + 17] ^= 0xff;
+        let records = RawRecords(data.into());
+
+        let decode = |crc_validation, fetched_batches: &AtomicU64| {
+            decode_batches(
+                &records,
+                crc_validation,
+                AllowedCompressions::ALL,
+                fetched_batches,
+                "foo",
+                1,
+            )
+        };
+        let fetched_batches = AtomicU64::new(0);
+
+        assert_matches!(
+            decode(CrcValidation::Enabled, &fetched_batches),
+            Err(Error::Request { .. })
+        );
+        assert_matches!(
+            decode(CrcValidation::Strict, &fetched_batches),
+            Err(Error::CorruptBatch {
+                topic,
+                partition: 1,
+                offset: 1,
+                ..
+            }) if topic == "foo"
+        );
+        assert_eq!(
+            decode(CrcValidation::Disabled, &fetched_batches)
+                .unwrap()
+                .0
+                .len(),
+            3
+        );
+
+        // validates the first and the third batch
+        assert_eq!(
+            decode(CrcValidation::Sampled(2), &fetched_batches)
+                .unwrap()
+                .0
+                .len(),
+            3
+        );
+        // validates the second batch
+        assert_matches!(
+            decode(CrcValidation::Sampled(2), &fetched_batches),
+            Err(Error::Request { .. })
+        );
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_decode_batches_allowed_compressions() {
+        let mut data = vec![];
+        for (base_offset, compression) in [
+            (0, RecordBatchCompression::NoCompression),
+            (1, RecordBatchCompression::Gzip),
+        ] {
+            RecordBatch {
+                base_offset,
+                partition_leader_epoch: 0,
+                last_offset_delta: 0,
+                first_timestamp: 0,
+                max_timestamp: 0,
+                producer_id: -1,
+                producer_epoch: -1,
+                base_sequence: -1,
+                records: ControlBatchOrRecords::Records(vec![ProtocolRecord {
+                    key: None,
+                    value: Some(b"foo".to_vec().into()),
+                    timestamp_delta: 0,
+                    offset_delta: 0,
+                    headers: vec![],
+                }]),
+                compression,
+                is_transactional: false,
+                timestamp_type: RecordBatchTimestampType::CreateTime,
+            }
+            .write(&mut data)
+            .unwrap();
+        }
+        let records = RawRecords(data.into());
+
+        let decode = |allowed| {
+            decode_batches(
+                &records,
+                CrcValidation::Enabled,
+                Compression::allowed(allowed),
+                &AtomicU64::new(0),
+                "foo",
+                1,
+            )
+        };
+
+        assert_eq!(decode(vec![Compression::Gzip]).unwrap().0.len(), 2);
+        let err = decode(vec![]).unwrap_err();
+        assert_matches!(
+            &err,
+            Error::CompressionNotAllowed {
+                topic,
+                partition: 1,
+                offset: 1,
+                compression: "gzip",
+            } if topic == "foo"
+        );
+        assert_eq!(
+            err.to_string(),
+            "Record batch at offset 1 of partition 1 of topic 'foo' uses gzip compression, which is not allowed"
+        );
+    }
+
+    #[test]
+    fn test_find_offset_gaps() {
+        let batch = |base_offset, last_offset_delta, records| RecordBatch {
+            base_offset,
+            partition_leader_epoch: 0,
+            last_offset_delta,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records,
+            compression: RecordBatchCompression::NoCompression,
+            is_transactional: false,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+        let record = |offset_delta| ProtocolRecord {
+            key: None,
+            value: None,
+            timestamp_delta: 0,
+            offset_delta,
+            headers: vec![],
+        };
+
+        let batches = [
+            // starts before the requested offset
+            batch(
+                0,
+                2,
+                ControlBatchOrRecords::Records(vec![record(0), record(2)]),
+            ),
+            // compacted, only the last record is left
+            batch(3, 2, ControlBatchOrRecords::Records(vec![record(2)])),
+            batch(
+                6,
+                0,
+                ControlBatchOrRecords::ControlBatch(ControlBatchRecord::Commit),
+            ),
+            batch(
+                9,
+                1,
+                ControlBatchOrRecords::Records(vec![record(0), record(1)]),
+            ),
+            batch(11, 0, ControlBatchOrRecords::Records(vec![record(0)])),
+        ];
+        assert_eq!(
+            find_offset_gaps(&batches, 1),
+            [OffsetGap {
+                expected: 7,
+                actual: 9
+            }]
+        );
+        assert_eq!(
+            find_offset_gaps(&batches[1..], 1),
+            [
+                OffsetGap {
+                    expected: 1,
+                    actual: 3
+                },
+                OffsetGap {
+                    expected: 7,
+                    actual: 9
+                }
+            ]
+        );
+        assert!(find_offset_gaps(&batches[3..], 9).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_topic_retry_for() {
+        let broker = crate::mock_broker::MockBroker::start().await.unwrap();
+        let client = crate::client::ClientBuilder::new(broker.bootstrap_brokers())
+            .backoff_config(BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+                ..Default::default()
+            })
+            .build()
+            .await
+            .unwrap();
+
+        // the topic shows up within the window
+        let (partition_client, _) = tokio::join!(
+            client.partition_client(
+                "foo",
+                0,
+                UnknownTopicHandling::RetryFor(Duration::from_secs(10))
+            ),
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                broker.create_topic("foo", 1);
+            },
+        );
+        partition_client.unwrap();
+
+        // the topic does not exist
+        let err = client
+            .partition_client(
+                "bar",
+                0,
+                UnknownTopicHandling::RetryFor(Duration::from_millis(20)),
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            Error::ServerError {
+                protocol_error: ProtocolError::UnknownTopicOrPartition,
+                ..
+            }
+        );
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    #[tokio::test]
+    async fn test_produce_zstd_unsupported() {
+        // the mock broker only implements produce requests version 3
+        let broker = crate::mock_broker::MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+        let client = crate::client::ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        let record = Record {
+            key: None,
+            value: Some(b"foo".to_vec().into()),
+            headers: Headers::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let err = partition_client
+            .produce(vec![record.clone()], Compression::Zstd)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            Error::UnsupportedBroker {
+                feature: "zstd compression",
+                min_kafka_version: "2.1",
+            }
+        );
+        assert!(err.is_fatal());
+
+        partition_client
+            .produce(vec![record], Compression::NoCompression)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_produce_reconnects() {
+        let broker = crate::mock_broker::MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+        let client = crate::client::ClientBuilder::new(broker.bootstrap_brokers())
+            .backoff_config(BackoffConfig {
+                init_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+                ..Default::default()
+            })
+            .coalesce_produce_requests(true)
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        let record = Record {
+            key: None,
+            value: Some(b"foo".to_vec().into()),
+            headers: Headers::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let offsets = partition_client
+            .produce(vec![record.clone()], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(offsets, [0]);
+
+        // the error of the coalesced request reaches the partition client wrapped as a shared error, which still has to
+        // invalidate the connection
+        broker.close_connections();
+        let offsets = partition_client
+            .produce(vec![record], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(offsets, [1]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_health_check_reconnects() {
+        let broker = crate::mock_broker::MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+        let client = crate::client::ClientBuilder::new(broker.bootstrap_brokers())
+            // a retry would exceed the timeout below
+            .backoff_config(BackoffConfig {
+                init_backoff: Duration::from_secs(10),
+                max_backoff: Duration::from_secs(10),
+                ..Default::default()
+            })
+            .connection_health_check_interval(Some(Duration::from_millis(20)))
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        let record = Record {
+            key: None,
+            value: Some(b"foo".to_vec().into()),
+            headers: Headers::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        let offsets = partition_client
+            .produce(vec![record.clone()], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(offsets, [0]);
+
+        // let the pings of the half-open connections time out
+        broker.stall_connections();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let offsets = tokio::time::timeout(
+            Duration::from_secs(5),
+            partition_client.produce(vec![record], Compression::NoCompression),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(offsets, [1]);
+        let (records, _high_watermark) = tokio::time::timeout(
+            Duration::from_secs(5),
+            partition_client.fetch_records(0, 1..1_000_000, 0),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_headers_roundtrip() {
+        let broker = crate::mock_broker::MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+        let client = crate::client::ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        let record = Record {
+            key: None,
+            value: None,
+            headers: Headers::from([("b", "1"), ("a", "2"), ("b", "3")]),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        partition_client
+            .produce(vec![record.clone()], Compression::NoCompression)
+            .await
+            .unwrap();
+
+        let (records, _) = partition_client
+            .fetch_records(0, 1..1_000_000, 1_000)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].record, record);
+    }
+}
@@ -0,0 +1,274 @@
+//! Coalescing of produce requests that go to the same broker.
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+use tracing::debug;
+
+use crate::{
+    connection::BrokerConnection,
+    messenger::RequestError,
+    protocol::{
+        messages::{
+            ProduceRecords, ProduceRequest, ProduceRequestPartitionData, ProduceRequestTopicData,
+            ProduceResponse, ProduceResponseResponse, WriteVersionedError,
+        },
+        primitives::{Int16, Int32, NullableString, String_},
+    },
+    runtime,
+};
+
+/// Requests are only coalesced if they go to the same broker connection and use the same acks and timeout settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct QueueKey {
+    broker: usize,
+    acks: i16,
+    timeout_ms: i32,
+}
+
+type PartitionResult = Result<ProduceResponse, Arc<RequestError>>;
+
+#[derive(Debug)]
+struct PendingPartition {
+    topic: String,
+    partition: i32,
+    records: Bytes,
+    tx: oneshot::Sender<PartitionResult>,
+}
+
+/// Groups produce requests for partitions led by the same broker into a single [`ProduceRequest`].
+///
+/// There is at most one request in flight per broker. Everything that is submitted while that request is in flight is
+/// sent as one request as soon as it returns, so coalescing adds no latency when a broker is idle.
+#[derive(Debug, Default)]
+pub(crate) struct ProduceRouter {
+    /// Pending partition data per broker.
+    ///
+    /// A queue is present iff a background task is draining it.
+    queues: Mutex<HashMap<QueueKey, Vec<PendingPartition>>>,
+}
+
+impl ProduceRouter {
+    /// Send `request` to `broker`, possibly as part of a larger request.
+    ///
+    /// The response only contains the results for the partitions of `request`.
+    pub(crate) async fn produce(
+        self: &Arc<Self>,
+        broker: &BrokerConnection,
+        request: &ProduceRequest,
+    ) -> Result<ProduceResponse, RequestError> {
+        assert!(
+            request.transactional_id.0.is_none(),
+            "transactional produce requests cannot be coalesced"
+        );
+
+        let key = QueueKey {
+            broker: Arc::as_ptr(broker) as usize,
+            acks: request.acks.0,
+            timeout_ms: request.timeout_ms.0,
+        };
+
+        let mut pending = vec![];
+        let mut receivers = vec![];
+        for topic_data in &request.topic_data {
+            for partition_data in &topic_data.partition_data {
+                let records = partition_data
+                    .records
+                    .encoded()
+                    .map_err(WriteVersionedError::from)?;
+                let (tx, rx) = oneshot::channel();
+                pending.push(PendingPartition {
+                    topic: topic_data.name.0.clone(),
+                    partition: partition_data.index.0,
+                    records,
+                    tx,
+                });
+                receivers.push(rx);
+            }
+        }
+
+        let spawn = match self.queues.lock().entry(key) {
+            Entry::Occupied(mut o) => {
+                o.get_mut().extend(pending);
+                false
+            }
+            Entry::Vacant(v) => {
+                v.insert(pending);
+                true
+            }
+        };
+        if spawn {
+            runtime::spawn(Arc::clone(self).drain(key, Arc::clone(broker)));
+        }
+
+        let mut response = ProduceResponse {
+            responses: vec![],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        };
+        for rx in receivers {
+            // the drain task only drops requests if it is cancelled, e.g. because the runtime shuts down
+            let partition_response = rx
+                .await
+                .map_err(|_| RequestError::Closed)?
+                .map_err(RequestError::Shared)?;
+            response.responses.extend(partition_response.responses);
+            response.throttle_time_ms = response
+                .throttle_time_ms
+                .max(partition_response.throttle_time_ms);
+        }
+        Ok(response)
+    }
+
+    /// Send the pending partition data of the given queue until it is empty.
+    async fn drain(self: Arc<Self>, key: QueueKey, broker: BrokerConnection) {
+        loop {
+            let batch = {
+                let mut queues = self.queues.lock();
+                let Entry::Occupied(mut o) = queues.entry(key) else {
+                    unreachable!("queue is only removed by its drain task");
+                };
+                if o.get().is_empty() {
+                    o.remove();
+                    return;
+                }
+                take_batch(o.get_mut())
+            };
+
+            send(&broker, key, batch).await;
+        }
+    }
+}
+
+/// Take partition data from `queue` so that every topic-partition is contained at most once.
+///
+/// A produce request cannot contain the same topic-partition twice, so duplicates stay in the queue for the next
+/// request. This also preserves the order of the writes for every partition.
+fn take_batch(queue: &mut Vec<PendingPartition>) -> Vec<PendingPartition> {
+    let mut seen = HashSet::new();
+    let (batch, rest) = std::mem::take(queue)
+        .into_iter()
+        .partition(|p| seen.insert((p.topic.clone(), p.partition)));
+    *queue = rest;
+    batch
+}
+
+async fn send(broker: &BrokerConnection, key: QueueKey, batch: Vec<PendingPartition>) {
+    let mut topic_data: Vec<ProduceRequestTopicData> = vec![];
+    for p in &batch {
+        let partition_data = ProduceRequestPartitionData {
+            index: Int32(p.partition),
+            records: ProduceRecords::Encoded(p.records.clone()),
+            tagged_fields: None,
+        };
+        match topic_data.iter_mut().find(|t| t.name.0 == p.topic) {
+            Some(t) => t.partition_data.push(partition_data),
+            None => topic_data.push(ProduceRequestTopicData {
+                name: String_(p.topic.clone()),
+                partition_data: vec![partition_data],
+                tagged_fields: None,
+            }),
+        }
+    }
+
+    debug!(
+        n_topics = topic_data.len(),
+        n_partitions = batch.len(),
+        "sending coalesced produce request",
+    );
+    let request = ProduceRequest {
+        transactional_id: NullableString(None),
+        acks: Int16(key.acks),
+        timeout_ms: Int32(key.timeout_ms),
+        topic_data,
+        tagged_fields: None,
+    };
+
+    match broker.request(&request).await {
+        Ok(response) => {
+            let throttle_time_ms = response.throttle_time_ms;
+            let mut partition_responses: HashMap<_, _> = response
+                .responses
+                .into_iter()
+                .flat_map(|r| {
+                    let name = r.name.0;
+                    r.partition_responses
+                        .into_iter()
+                        .map(move |p| ((name.clone(), p.index.0), p))
+                })
+                .collect();
+
+            for p in batch {
+                // a missing response is reported as an empty one and rejected by the caller
+                let responses = partition_responses
+                    .remove(&(p.topic.clone(), p.partition))
+                    .map(|partition_response| ProduceResponseResponse {
+                        name: String_(p.topic),
+                        partition_responses: vec![partition_response],
+                        tagged_fields: None,
+                    })
+                    .into_iter()
+                    .collect();
+
+                // receiver might be gone if the caller was cancelled
+                p.tx.send(Ok(ProduceResponse {
+                    responses,
+                    throttle_time_ms,
+                    tagged_fields: None,
+                }))
+                .ok();
+            }
+        }
+        Err(e) => {
+            let e = Arc::new(e);
+            for p in batch {
+                p.tx.send(Err(Arc::clone(&e))).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(topic: &str, partition: i32) -> PendingPartition {
+        let (tx, _rx) = oneshot::channel();
+        PendingPartition {
+            topic: topic.to_owned(),
+            partition,
+            records: Bytes::new(),
+            tx,
+        }
+    }
+
+    fn ids(queue: &[PendingPartition]) -> Vec<(&str, i32)> {
+        queue
+            .iter()
+            .map(|p| (p.topic.as_str(), p.partition))
+            .collect()
+    }
+
+    #[test]
+    fn test_take_batch() {
+        let mut queue = vec![
+            pending("a", 0),
+            pending("a", 1),
+            pending("a", 0),
+            pending("b", 0),
+            pending("a", 0),
+        ];
+
+        let batch = take_batch(&mut queue);
+        assert_eq!(ids(&batch), [("a", 0), ("a", 1), ("b", 0)]);
+        assert_eq!(ids(&queue), [("a", 0), ("a", 0)]);
+
+        let batch = take_batch(&mut queue);
+        assert_eq!(ids(&batch), [("a", 0)]);
+        assert_eq!(ids(&queue), [("a", 0)]);
+    }
+}
@@ -5,6 +5,7 @@
 //! - **lingering:** Control how long your data should wait until being submitted.
 //! - **aggregation:** Control how much data should be accumulated on the client side.
 //! - **transformation:** Map your own data types to [`Record`]s after they have been aggregated.
+//! - **validation:** Reject data that violates your policies before it is aggregated, see [`InputValidator`].
 //!
 //! # Data Flow
 //!
@@ -48,25 +49,19 @@
 //!             BatchProducerBuilder,
 //!         },
 //!     },
-//!     record::Record,
+//!     record::{Headers, Record},
 //! };
 //! use chrono::{TimeZone, Utc};
-//! use std::{
-//!     collections::BTreeMap,
-//!     sync::Arc,
-//!     time::Duration,
-//! };
+//! use std::time::Duration;
 //!
 //! // get partition client
 //! let connection = "localhost:9093".to_owned();
 //! let client = ClientBuilder::new(vec![connection]).build().await.unwrap();
-//! let partition_client = Arc::new(
-//!     client.partition_client(
-//!         "my_topic",
-//!         0,
-//!         UnknownTopicHandling::Retry,
-//!     ).await.unwrap()
-//! );
+//! let partition_client = client.partition_client(
+//!     "my_topic",
+//!     0,
+//!     UnknownTopicHandling::Retry,
+//! ).await.unwrap();
 //!
 //! // construct batch producer
 //! let producer = BatchProducerBuilder::new(partition_client)
@@ -78,10 +73,8 @@
 //! // produce data
 //! let record = Record {
 //!     key: None,
-//!     value: Some(b"hello kafka".to_vec()),
-//!     headers: BTreeMap::from([
-//!         ("foo".to_owned(), b"bar".to_vec()),
-//!     ]),
+//!     value: Some(b"hello kafka".to_vec().into()),
+//!     headers: Headers::from([("foo", "bar")]),
 //!     timestamp: Utc.timestamp_millis(42),
 //! };
 //! producer.produce(record.clone()).await.unwrap();
@@ -96,7 +89,7 @@
 //! use rskafka::{
 //!     client::{
 //!         ClientBuilder,
-//!         partition::UnknownTopicHandling,
+//!         partition::{ProduceResult, UnknownTopicHandling},
 //!         producer::{
 //!             aggregator::{
 //!                 Aggregator,
@@ -107,14 +100,10 @@
 //!             BatchProducerBuilder,
 //!         },
 //!     },
-//!     record::Record,
+//!     record::{Headers, Record},
 //! };
 //! use chrono::{TimeZone, Utc};
-//! use std::{
-//!     collections::BTreeMap,
-//!     sync::Arc,
-//!     time::Duration,
-//! };
+//! use std::time::Duration;
 //!
 //! // This is the custom data type that we want to aggregate
 //! struct Payload {
@@ -152,10 +141,8 @@
 //!         let records = vec![
 //!             Record {
 //!                 key: None,
-//!                 value: Some(data),
-//!                 headers: BTreeMap::from([
-//!                     ("foo".to_owned(), b"bar".to_vec()),
-//!                 ]),
+//!                 value: Some(data.into()),
+//!                 headers: Headers::from([("foo", "bar")]),
 //!                 timestamp: Utc.timestamp_millis(42),
 //!             },
 //!         ];
@@ -173,7 +160,11 @@
 //!     type Status = ();
 //!     type Tag = ();
 //!
-//!     fn deaggregate(&self, _input: &[i64], _tag: Self::Tag) -> Result<Self::Status, AggError> {
+//!     fn deaggregate(
+//!         &self,
+//!         _input: &ProduceResult,
+//!         _tag: Self::Tag,
+//!     ) -> Result<Self::Status, AggError> {
 //!         // don't care about the offsets
 //!         Ok(())
 //!     }
@@ -182,13 +173,11 @@
 //! // get partition client
 //! let connection = "localhost:9093".to_owned();
 //! let client = ClientBuilder::new(vec![connection]).build().await.unwrap();
-//! let partition_client = Arc::new(
-//!     client.partition_client(
-//!         "my_topic",
-//!         0,
-//!         UnknownTopicHandling::Retry,
-//!     ).await.unwrap()
-//! );
+//! let partition_client = client.partition_client(
+//!     "my_topic",
+//!     0,
+//!     UnknownTopicHandling::Retry,
+//! ).await.unwrap();
 //!
 //! // construct batch producer
 //! let producer = BatchProducerBuilder::new(partition_client)
@@ -209,25 +198,47 @@ use std::time::Duration;
 
 use futures::future::BoxFuture;
 use thiserror::Error;
-use tokio::task::JoinHandle;
 use tracing::*;
 
 use self::{
     aggregator::Aggregator,
-    batch::{BatchBuilder, FlushResult, ResultHandle},
+    batch::{BatchBuilder, FlushResult, FlushSequencer, ResultHandle},
+    rate_limit::RateLimiter,
 };
 use crate::{
     client::{
         error::Error as ClientError,
-        partition::{Compression, PartitionClient},
+        partition::{Compression, PartitionClient, ProduceResult},
         producer::aggregator::TryPush,
     },
+    metrics::{AggregatorFlush, FlushReason, Metrics},
     record::Record,
+    runtime::{self, JoinHandle},
 };
 
 pub mod aggregator;
 mod batch;
 pub(crate) mod broadcast;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
+mod partition_selector;
+mod partitioned;
+mod rate_limit;
+mod serializing;
+mod validation;
+
+pub use partition_selector::{
+    LeastBacklog, PartitionLoad, PartitionSelector, RoundRobin, Weighted,
+};
+pub use partitioned::{
+    PartitionedBatchProducer, PartitionedBatchProducerBuilder, ProducerClientFactory,
+};
+pub use rate_limit::RateLimit;
+pub use serializing::SerializingProducer;
+pub use validation::{InputValidator, RecordValidator, ValidationError};
+
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::MockProducerClient;
 
 #[derive(Debug, Error, Clone)]
 pub enum Error {
@@ -237,29 +248,121 @@ pub enum Error {
     #[error("Client error: {0}")]
     Client(#[from] Arc<ClientError>),
 
+    #[error("Codec error: {0}")]
+    Codec(Arc<dyn std::error::Error + Send + Sync>),
+
     #[error("Flush error: {0}")]
     FlushError(String),
 
     #[error("Input too large for aggregator")]
     TooLarge,
+
+    #[error("Invalid input: {0}")]
+    Invalid(#[from] ValidationError),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
-/// Builder for [`BatchProducer`].
+/// Data that [`BatchProducer`] failed to deliver to Kafka.
 #[derive(Debug)]
-pub struct BatchProducerBuilder {
+pub enum DeadLetter<I> {
+    /// The input does not fit into an empty [`Aggregator`].
+    ///
+    /// The caller of [`BatchProducer::produce`] receives [`Error::TooLarge`].
+    TooLarge(I),
+
+    /// Writing a batch failed permanently, i.e. after the client exhausted its retries.
+    ///
+    /// Since the input was already converted by the [`Aggregator`], the [`Record`]s of the entire batch are handed
+    /// over. All callers that contributed to this batch receive `error`.
+    WriteFailed { records: Vec<Record>, error: Error },
+}
+
+/// Receives data that [`BatchProducer`] could not deliver, e.g. to write it to a dead letter queue or spill it to
+/// disk.
+///
+/// The handler is called synchronously from within the producer, so it should not block.
+pub trait DeadLetterHandler<I>: std::fmt::Debug + Send + Sync {
+    /// Handle undeliverable data.
+    fn handle(&self, dead_letter: DeadLetter<I>);
+}
+
+/// [`DeadLetterHandler`] for the input of an [`Aggregator`].
+type AggregatorDeadLetterHandler<A> = Arc<dyn DeadLetterHandler<<A as Aggregator>::Input>>;
+
+/// Data buffered in the [`Aggregator`] of a [`BatchProducer`], see [`BatchProducer::occupancy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AggregatorOccupancy {
+    /// Number of inputs that the aggregator accepted since the last flush.
+    pub inputs: usize,
+
+    /// Approximate size of the buffered data, see [`Aggregator::buffered_bytes`].
+    pub bytes: Option<usize>,
+
+    /// Time since the aggregator accepted the first input, `None` if it is empty.
+    pub age: Option<Duration>,
+}
+
+/// [`Metrics`] receiver of a producer.
+#[derive(Clone)]
+struct ProducerMetrics(Arc<dyn Metrics>);
+
+impl std::fmt::Debug for ProducerMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProducerMetrics")
+    }
+}
+
+/// Builder for [`BatchProducer`]s that accept `I` as input.
+pub struct BatchProducerBuilder<I> {
     client: Arc<dyn ProducerClient>,
 
     linger: Duration,
 
     compression: Compression,
+
+    ordered_flushes: bool,
+
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    split_oversized_batches: bool,
+
+    metrics: Option<ProducerMetrics>,
+
+    dead_letter: Option<Arc<dyn DeadLetterHandler<I>>>,
+
+    validators: Vec<Arc<dyn InputValidator<I>>>,
 }
 
-impl BatchProducerBuilder {
+// manual impl, so that `I` does not need to implement `Debug`
+impl<I> std::fmt::Debug for BatchProducerBuilder<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchProducerBuilder")
+            .field("client", &self.client)
+            .field("linger", &self.linger)
+            .field("compression", &self.compression)
+            .field("ordered_flushes", &self.ordered_flushes)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("split_oversized_batches", &self.split_oversized_batches)
+            .field("metrics", &self.metrics)
+            .field("dead_letter", &self.dead_letter)
+            .field("validators", &self.validators)
+            .finish()
+    }
+}
+
+impl<I> BatchProducerBuilder<I> {
     /// Build a new `BatchProducer`.
+    ///
+    /// Flushes are reported to the [metrics](crate::client::ClientBuilder::metrics) of the client, if any.
     pub fn new(client: Arc<PartitionClient>) -> Self {
-        Self::new_with_client(client)
+        let metrics = client.metrics();
+        let builder = Self::new_with_client(client);
+        match metrics {
+            Some(metrics) => builder.with_metrics(metrics),
+            None => builder,
+        }
     }
 
     /// Construct a [`BatchProducer`] with a dynamically dispatched
@@ -269,6 +372,20 @@ impl BatchProducerBuilder {
             client,
             linger: Duration::from_millis(5),
             compression: Compression::default(),
+            ordered_flushes: false,
+            rate_limiter: None,
+            split_oversized_batches: false,
+            metrics: None,
+            dead_letter: None,
+            validators: vec![],
+        }
+    }
+
+    /// Report every flush of the aggregator to `metrics`, see [`Metrics::aggregator_flushed`].
+    pub fn with_metrics(self, metrics: Arc<dyn Metrics>) -> Self {
+        Self {
+            metrics: Some(ProducerMetrics(metrics)),
+            ..self
         }
     }
 
@@ -285,9 +402,83 @@ impl BatchProducerBuilder {
         }
     }
 
+    /// Only write one batch at a time.
+    ///
+    /// By default a full batch is flushed in the background while the next batch is already being aggregated, so
+    /// multiple batches may be in flight at the same time. Since every batch is retried independently, a later batch
+    /// might land in Kafka before an earlier one. When enabled, a batch is only written once the previous batch
+    /// succeeded or failed for good, so that records sharing a key are stored in the order of the `produce` calls.
+    ///
+    /// This is required for changelog-style topics but limits the throughput to one produce request per round trip.
+    pub fn with_ordered_flushes(self, ordered_flushes: bool) -> Self {
+        Self {
+            ordered_flushes,
+            ..self
+        }
+    }
+
+    /// Split batches that are too large for the broker instead of failing them.
+    ///
+    /// If a flushed batch is rejected with [`ProtocolError::MessageTooLarge`] or
+    /// [`ProtocolError::RecordListTooLarge`], or exceeds
+    /// [`ClientBuilder::max_produce_batch_size`], it is split in half and the halves are written one after another,
+    /// splitting further as required. The [`StatusDeaggregator`] receives the offsets as if the batch was written at
+    /// once. This is useful for [`Aggregator`]s that cannot predict the encoded size of their output exactly.
+    ///
+    /// The parts are not written atomically: if a later part fails, the earlier ones are already stored in Kafka.
+    /// Only the records that were not written are passed to the [`DeadLetterHandler`].
+    ///
+    /// Since the records have to be kept around while they are written, this comes with the cost of a copy per
+    /// request. Defaults to `false`.
+    ///
+    /// [`ClientBuilder::max_produce_batch_size`]: crate::client::ClientBuilder::max_produce_batch_size
+    /// [`ProtocolError::MessageTooLarge`]: crate::client::error::ProtocolError::MessageTooLarge
+    /// [`ProtocolError::RecordListTooLarge`]: crate::client::error::ProtocolError::RecordListTooLarge
+    /// [`StatusDeaggregator`]: aggregator::StatusDeaggregator
+    pub fn with_split_oversized_batches(self, split_oversized_batches: bool) -> Self {
+        Self {
+            split_oversized_batches,
+            ..self
+        }
+    }
+
+    /// Limit the rate at which data is written to Kafka.
+    ///
+    /// Once the budget is exhausted, flushes and hence [`BatchProducer::produce`] calls are delayed instead of failing.
+    pub fn with_rate_limit(self, rate_limit: RateLimit) -> Self {
+        self.with_rate_limiter(Arc::new(RateLimiter::new(rate_limit)))
+    }
+
+    /// Sets a handler for data that cannot be delivered to Kafka.
+    ///
+    /// Without a handler, undeliverable data is dropped and only the error is reported to the callers of
+    /// [`BatchProducer::produce`].
+    pub fn with_dead_letter_handler(self, handler: Arc<dyn DeadLetterHandler<I>>) -> Self {
+        Self {
+            dead_letter: Some(handler),
+            ..self
+        }
+    }
+
+    /// Check data with `validator` before passing it to the aggregator.
+    ///
+    /// Can be called multiple times, the validators are applied in order.
+    pub fn with_validator(mut self, validator: Arc<dyn InputValidator<I>>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Use a rate limiter that might be shared with other producers.
+    fn with_rate_limiter(self, rate_limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            rate_limiter: Some(rate_limiter),
+            ..self
+        }
+    }
+
     pub fn build<A>(self, aggregator: A) -> BatchProducer<A>
     where
-        A: aggregator::Aggregator,
+        A: aggregator::Aggregator<Input = I>,
     {
         BatchProducer {
             linger: self.linger,
@@ -295,7 +486,13 @@ impl BatchProducerBuilder {
                 aggregator,
                 self.client,
                 self.compression,
+                self.ordered_flushes,
+                self.rate_limiter,
+                self.split_oversized_batches,
+                self.metrics,
+                self.dead_letter,
             ))),
+            validators: self.validators,
         }
     }
 }
@@ -308,7 +505,8 @@ impl BatchProducerBuilder {
 /// produce call.
 ///
 /// Most users will want to use the [`BatchProducer`] implementation of this
-/// trait.
+/// trait. For tests, an in-memory implementation is available as
+/// `MockProducerClient` when the `test-util` feature is enabled.
 pub trait ProducerClient: std::fmt::Debug + Send + Sync {
     /// Write the set of `records` to the Kafka broker, using the specified
     /// `compression` algorithm.
@@ -316,7 +514,7 @@ pub trait ProducerClient: std::fmt::Debug + Send + Sync {
         &self,
         records: Vec<Record>,
         compression: Compression,
-    ) -> BoxFuture<'_, Result<Vec<i64>, ClientError>>;
+    ) -> BoxFuture<'_, Result<ProduceResult, ClientError>>;
 }
 
 impl ProducerClient for PartitionClient {
@@ -324,8 +522,8 @@ impl ProducerClient for PartitionClient {
         &self,
         records: Vec<Record>,
         compression: Compression,
-    ) -> BoxFuture<'_, Result<Vec<i64>, ClientError>> {
-        Box::pin(self.produce(records, compression))
+    ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
+        Box::pin(self.produce_with_metadata(records, compression))
     }
 }
 
@@ -370,6 +568,19 @@ where
     /// removed from this list when adding new flush tasks or manually flushing
     /// with a call to [`BatchProducer::flush()`].
     pending_flushes: Vec<JoinHandle<()>>,
+
+    /// Serialises flushes if ordered flushes are requested.
+    sequencer: Option<FlushSequencer>,
+
+    dead_letter: Option<AggregatorDeadLetterHandler<A>>,
+
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// Split batches that are rejected as too large, see
+    /// [`BatchProducerBuilder::with_split_oversized_batches()`].
+    split_oversized_batches: bool,
+
+    metrics: Option<ProducerMetrics>,
 }
 
 impl<A> Drop for ProducerInner<A>
@@ -387,7 +598,17 @@ impl<A> ProducerInner<A>
 where
     A: aggregator::Aggregator,
 {
-    fn new(aggregator: A, client: Arc<dyn ProducerClient>, compression: Compression) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        aggregator: A,
+        client: Arc<dyn ProducerClient>,
+        compression: Compression,
+        ordered_flushes: bool,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        split_oversized_batches: bool,
+        metrics: Option<ProducerMetrics>,
+        dead_letter: Option<AggregatorDeadLetterHandler<A>>,
+    ) -> Self {
         Self {
             batch_builder: Some(BatchBuilder::new(aggregator)),
             flush_clock: 0,
@@ -395,6 +616,11 @@ where
             client,
             compression,
             pending_flushes: Vec::new(),
+            sequencer: ordered_flushes.then(FlushSequencer::default),
+            dead_letter,
+            rate_limiter,
+            split_oversized_batches,
+            metrics,
         }
     }
 
@@ -432,12 +658,15 @@ where
                 // As a side effect, this invalidates any callers performing a
                 // linger wait + flush, preventing them from flushing this new
                 // batch.
-                self.flush(None)?;
+                self.flush(None, FlushReason::Full)?;
 
                 match self.batch_builder.as_mut().unwrap().try_push(data)? {
                     TryPush::Aggregated(handle) => handle,
-                    TryPush::NoCapacity(_) => {
+                    TryPush::NoCapacity(data) => {
                         error!(client=?self.client, "record too large for aggregator");
+                        if let Some(handler) = &self.dead_letter {
+                            handler.handle(DeadLetter::TooLarge(data));
+                        }
                         return Err(Error::TooLarge);
                     }
                 }
@@ -475,7 +704,9 @@ where
     /// If the caller provides a `flusher_token`, the batch flush is conditional
     /// on the token matching. If the token does not match, the batch the caller
     /// is attempting to flush has already been flushed, and this call is a NOP.
-    fn flush(&mut self, flusher_token: Option<usize>) -> Result<()> {
+    ///
+    /// Non-empty batches are reported to the metrics with the given `reason`.
+    fn flush(&mut self, flusher_token: Option<usize>, reason: FlushReason) -> Result<()> {
         // If this caller is is intending to conditionally flush a specific
         // batch, verify this BatchBuilder is the batch it is indenting to
         // flush.
@@ -486,7 +717,17 @@ where
             }
         }
 
-        debug!(client=?self.client, "flushing batch");
+        debug!(client=?self.client, ?reason, "flushing batch");
+
+        let occupancy = self.batch_builder.as_ref().unwrap().occupancy();
+        if let (Some(metrics), Some(age)) = (&self.metrics, occupancy.age) {
+            metrics.0.aggregator_flushed(&AggregatorFlush {
+                reason,
+                inputs: occupancy.inputs,
+                bytes: occupancy.bytes,
+                age,
+            });
+        }
 
         // Remove the batch, temporarily swapping it for a None until a new
         // batch is built.
@@ -495,14 +736,20 @@ where
         // immediately replaced with a new batch instance below.
         let batch = self.batch_builder.take().expect("no batch to flush");
 
-        let (new_builder, flush_task, maybe_err) =
-            match batch.background_flush(Arc::clone(&self.client), self.compression) {
-                FlushResult::Ok(b, flush_task) => (b, flush_task, None),
-                FlushResult::Error(b, e) => {
-                    error!(client=?self.client, error=%e, "failed to write record batch");
-                    (b, None, Some(e))
-                }
-            };
+        let (new_builder, flush_task, maybe_err) = match batch.background_flush(
+            Arc::clone(&self.client),
+            self.compression,
+            self.sequencer.as_mut(),
+            self.dead_letter.clone(),
+            self.rate_limiter.clone(),
+            self.split_oversized_batches,
+        ) {
+            FlushResult::Ok(b, flush_task) => (b, flush_task, None),
+            FlushResult::Error(b, e) => {
+                error!(client=?self.client, error=%e, "failed to write record batch");
+                (b, None, Some(e))
+            }
+        };
 
         // Replace the batch builder with the new instance.
         self.batch_builder = Some(new_builder);
@@ -580,16 +827,31 @@ where
 {
     linger: Duration,
     inner: Arc<parking_lot::Mutex<ProducerInner<A>>>,
+    validators: Vec<Arc<dyn InputValidator<A::Input>>>,
 }
 
 impl<A> BatchProducer<A>
 where
     A: aggregator::Aggregator,
 {
+    /// Data that is currently buffered in the aggregator, i.e. not flushed yet.
+    ///
+    /// This helps to tune the linger time and the batch size of the aggregator.
+    pub fn occupancy(&self) -> AggregatorOccupancy {
+        self.inner
+            .lock()
+            .batch_builder
+            .as_ref()
+            .unwrap()
+            .occupancy()
+    }
+
     /// Write `data` to this [`BatchProducer`]
     ///
     /// Returns when the data has been committed to Kafka or an unrecoverable
-    /// error has been encountered.
+    /// error has been encountered. Data that a
+    /// [validator](BatchProducerBuilder::with_validator)
+    /// rejects fails with [`Error::Invalid`] right away.
     ///
     /// # Cancellation
     ///
@@ -601,6 +863,10 @@ where
         &self,
         data: A::Input,
     ) -> Result<<A as aggregator::AggregatorStatus>::Status> {
+        for validator in &self.validators {
+            validator.validate(&data)?;
+        }
+
         let role = {
             // Try to add the record to the aggregator
             let mut inner = self.inner.lock();
@@ -622,16 +888,16 @@ where
                 // duration, and then attempt to flush the batch of writes.
                 //
                 // Spawn a task for the linger to ensure cancellation safety.
-                let linger: JoinHandle<Result<(), Error>> = tokio::spawn({
+                let linger: JoinHandle<Result<(), Error>> = runtime::spawn({
                     let linger = self.linger;
                     let inner = Arc::clone(&self.inner);
                     async move {
-                        tokio::time::sleep(linger).await;
+                        runtime::sleep(linger).await;
 
                         // The linger has expired, attempt to conditionally flush the
                         // batch using the provided token to ensure only the correct
                         // batch is flushed.
-                        inner.lock().flush(Some(flush_token))?;
+                        inner.lock().flush(Some(flush_token), FlushReason::Linger)?;
                         Ok(())
                     }
                 });
@@ -663,7 +929,7 @@ where
             let mut inner = self.inner.lock();
 
             debug!("Manual flush");
-            inner.flush(None)?;
+            inner.flush(None, FlushReason::Manual)?;
             std::mem::take(&mut inner.pending_flushes)
         };
 
@@ -686,6 +952,7 @@ mod tests {
     use crate::{
         client::producer::aggregator::RecordAggregator, protocol::error::Error as ProtocolError,
     };
+    use assert_matches::assert_matches;
     use chrono::{TimeZone, Utc};
     use futures::stream::{FuturesOrdered, FuturesUnordered};
     use futures::{pin_mut, FutureExt, StreamExt, TryStreamExt};
@@ -703,7 +970,7 @@ mod tests {
             &self,
             records: Vec<Record>,
             _compression: Compression,
-        ) -> BoxFuture<'_, Result<Vec<i64>, ClientError>> {
+        ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
             Box::pin(async move {
                 tokio::time::sleep(self.delay).await;
 
@@ -714,6 +981,7 @@ mod tests {
                         request: RequestContext::Partition("foo".into(), 1),
                         response: None,
                         is_virtual: false,
+                        info: None,
                     });
                 }
 
@@ -727,20 +995,125 @@ mod tests {
                     .map(|x| (x + offset_base) as i64)
                     .collect();
                 batch_sizes.push(records.len());
-                Ok(offsets)
+                Ok(ProduceResult {
+                    offsets,
+                    ..Default::default()
+                })
             })
         }
     }
 
     fn record() -> Record {
         Record {
-            key: Some(vec![0; 4]),
-            value: Some(vec![0; 6]),
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(320).unwrap(),
         }
     }
 
+    #[derive(Default)]
+    struct FlushRecorder(parking_lot::Mutex<Vec<AggregatorFlush>>);
+
+    impl Metrics for FlushRecorder {
+        fn aggregator_flushed(&self, flush: &AggregatorFlush) {
+            self.0.lock().push(*flush);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_occupancy_and_flush_metrics() {
+        let size = record().approximate_size();
+        let metrics = Arc::new(FlushRecorder::default());
+        let producer = BatchProducerBuilder::new_with_client(Arc::new(MockClient {
+            error: None,
+            panic: None,
+            delay: Duration::ZERO,
+            batch_sizes: Default::default(),
+        }))
+        .with_linger(Duration::from_secs(3600))
+        .with_metrics(Arc::<FlushRecorder>::clone(&metrics))
+        .build(RecordAggregator::new(size * 2));
+
+        let empty = AggregatorOccupancy {
+            inputs: 0,
+            bytes: Some(0),
+            age: None,
+        };
+        assert_eq!(producer.occupancy(), empty);
+
+        let mut futures = FuturesUnordered::new();
+        futures.push(producer.produce(record()));
+        tokio::time::timeout(Duration::from_millis(10), futures.next())
+            .await
+            .unwrap_err();
+        let occupancy = producer.occupancy();
+        assert_eq!(occupancy.inputs, 1);
+        assert_eq!(occupancy.bytes, Some(size));
+        assert!(occupancy.age.is_some());
+
+        // the third record does not fit and flushes the first two
+        futures.push(producer.produce(record()));
+        futures.push(producer.produce(record()));
+        futures.next().await.unwrap().unwrap();
+        futures.next().await.unwrap().unwrap();
+        assert_eq!(producer.occupancy().inputs, 1);
+
+        producer.flush().await.unwrap();
+        futures.next().await.unwrap().unwrap();
+        assert_eq!(producer.occupancy(), empty);
+
+        // empty batches are not reported
+        producer.flush().await.unwrap();
+
+        let flushes = metrics.0.lock();
+        assert_eq!(
+            flushes
+                .iter()
+                .map(|f| (f.reason, f.inputs, f.bytes))
+                .collect::<Vec<_>>(),
+            [
+                (FlushReason::Full, 2, Some(size * 2)),
+                (FlushReason::Manual, 1, Some(size)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validator() {
+        let producer = BatchProducerBuilder::new_with_client(Arc::new(MockClient {
+            error: None,
+            panic: None,
+            delay: Duration::ZERO,
+            batch_sizes: Default::default(),
+        }))
+        .with_linger(Duration::from_millis(1))
+        .with_validator(Arc::new(RecordValidator::new().with_max_key_size(4)))
+        .with_validator(Arc::new(
+            RecordValidator::new().with_required_header("trace-id"),
+        ))
+        .build(RecordAggregator::new(1024));
+
+        let mut record = record();
+        assert_matches!(
+            producer.produce(record.clone()).await,
+            Err(Error::Invalid(ValidationError::MissingHeader(key))) if key == "trace-id"
+        );
+        record.key = Some(vec![0; 5].into());
+        assert_matches!(
+            producer.produce(record.clone()).await,
+            Err(Error::Invalid(ValidationError::KeyTooLarge {
+                size: 5,
+                limit: 4
+            }))
+        );
+        assert_eq!(producer.occupancy().inputs, 0);
+
+        record.key = None;
+        record.headers.insert("trace-id", "1");
+        producer.produce(record).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_producer() {
         let record = record();
@@ -838,6 +1211,65 @@ mod tests {
         assert!(((offset_a == 0) && (offset_b == 1)) || ((offset_a == 1) && (offset_b == 0)));
     }
 
+    #[tokio::test]
+    async fn test_ordered_flushes() {
+        /// Client where earlier calls take longer than later ones.
+        #[derive(Debug, Default)]
+        struct SlowFirstClient {
+            calls: parking_lot::Mutex<i64>,
+            completed: parking_lot::Mutex<Vec<i64>>,
+        }
+
+        impl ProducerClient for SlowFirstClient {
+            fn produce(
+                &self,
+                _records: Vec<Record>,
+                _compression: Compression,
+            ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
+                let call = {
+                    let mut calls = self.calls.lock();
+                    *calls += 1;
+                    *calls - 1
+                };
+
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_millis(50) * (3 - call as u32)).await;
+                    self.completed.lock().push(call);
+                    Ok(ProduceResult {
+                        offsets: vec![call],
+                        ..Default::default()
+                    })
+                })
+            }
+        }
+
+        let record = record();
+
+        for (ordered, expected) in [(false, [2, 1, 0]), (true, [0, 1, 2])] {
+            let client = Arc::new(SlowFirstClient::default());
+
+            // one record per batch
+            let aggregator = RecordAggregator::new(record.approximate_size());
+            let producer =
+                BatchProducerBuilder::new_with_client(Arc::<SlowFirstClient>::clone(&client))
+                    .with_linger(Duration::from_millis(1))
+                    .with_ordered_flushes(ordered)
+                    .build(aggregator);
+
+            // Each push flushes the previous batch, the last one is flushed by the linger.
+            let (a, b, c) = tokio::join!(
+                producer.produce(record.clone()),
+                producer.produce(record.clone()),
+                producer.produce(record.clone()),
+            );
+            a.unwrap();
+            b.unwrap();
+            c.unwrap();
+
+            assert_eq!(client.completed.lock().as_slice(), &expected);
+        }
+    }
+
     #[tokio::test]
     async fn test_producer_empty_aggregator_with_linger() {
         // this setting used to result in a panic
@@ -884,7 +1316,7 @@ mod tests {
 
             fn deaggregate(
                 &self,
-                _input: &[i64],
+                _input: &ProduceResult,
                 _tag: Self::Tag,
             ) -> Result<Self::Status, aggregator::Error> {
                 Ok(())
@@ -927,6 +1359,128 @@ mod tests {
         futures.next().await.unwrap().unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_producer_dead_letter() {
+        #[derive(Debug, Default)]
+        struct MockDeadLetterHandler {
+            letters: parking_lot::Mutex<Vec<DeadLetter<Record>>>,
+        }
+
+        impl DeadLetterHandler<Record> for MockDeadLetterHandler {
+            fn handle(&self, dead_letter: DeadLetter<Record>) {
+                self.letters.lock().push(dead_letter);
+            }
+        }
+
+        let record = record();
+        let large_record = Record {
+            value: Some(vec![0; 100].into()),
+            ..record.clone()
+        };
+        let linger = Duration::from_millis(5);
+        let client = Arc::new(MockClient {
+            error: Some(ProtocolError::NetworkException),
+            panic: None,
+            delay: Duration::from_millis(1),
+            batch_sizes: Default::default(),
+        });
+        let handler = Arc::new(MockDeadLetterHandler::default());
+
+        let aggregator = RecordAggregator::new(record.approximate_size() * 2);
+        let producer = BatchProducerBuilder::new_with_client(Arc::<MockClient>::clone(&client))
+            .with_linger(linger)
+            .with_dead_letter_handler(Arc::<MockDeadLetterHandler>::clone(&handler) as _)
+            .build(aggregator);
+
+        assert_matches!(
+            producer.produce(large_record.clone()).await,
+            Err(Error::TooLarge)
+        );
+
+        let mut futures = FuturesUnordered::new();
+        futures.push(producer.produce(record.clone()));
+        futures.push(producer.produce(record.clone()));
+        futures.next().await.unwrap().unwrap_err();
+        futures.next().await.unwrap().unwrap_err();
+
+        let letters = std::mem::take(&mut *handler.letters.lock());
+        assert_eq!(letters.len(), 2);
+        assert_matches!(&letters[0], DeadLetter::TooLarge(r) if r == &large_record);
+        assert_matches!(
+            &letters[1],
+            DeadLetter::WriteFailed { records, error: Error::Client(_) } if records == &[record.clone(), record]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_producer_split_oversized_batches() {
+        /// Client that rejects requests with more than one record.
+        #[derive(Debug, Default)]
+        struct SingleRecordClient {
+            batch_sizes: parking_lot::Mutex<Vec<usize>>,
+        }
+
+        impl ProducerClient for SingleRecordClient {
+            fn produce(
+                &self,
+                records: Vec<Record>,
+                _compression: Compression,
+            ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
+                Box::pin(async move {
+                    let mut batch_sizes = self.batch_sizes.lock();
+                    batch_sizes.push(records.len());
+                    if records.len() > 1 {
+                        return Err(ClientError::ServerError {
+                            protocol_error: ProtocolError::MessageTooLarge,
+                            error_message: None,
+                            request: RequestContext::Partition("foo".into(), 1),
+                            response: None,
+                            is_virtual: false,
+                            info: None,
+                        });
+                    }
+
+                    let offset = batch_sizes.iter().filter(|n| **n == 1).count() as i64 - 1;
+                    Ok(ProduceResult {
+                        offsets: vec![offset * 10],
+                        ..Default::default()
+                    })
+                })
+            }
+        }
+
+        let record = record();
+
+        for split in [false, true] {
+            let client = Arc::new(SingleRecordClient::default());
+            let aggregator = RecordAggregator::new(record.approximate_size() * 3);
+            let producer =
+                BatchProducerBuilder::new_with_client(Arc::<SingleRecordClient>::clone(&client))
+                    .with_linger(Duration::from_millis(5))
+                    .with_split_oversized_batches(split)
+                    .build(aggregator);
+
+            let mut futures = FuturesOrdered::new();
+            for _ in 0..3 {
+                futures.push_back(producer.produce(record.clone()));
+            }
+
+            if split {
+                let offsets: Vec<_> = futures.try_collect().await.unwrap();
+                assert_eq!(offsets, vec![0, 10, 20]);
+                // [r0, r1, r2] -> [r0] + [r1, r2] -> [r1] + [r2]
+                assert_eq!(client.batch_sizes.lock().as_slice(), &[3, 1, 2, 1, 1]);
+            } else {
+                let err = futures.next().await.unwrap().unwrap_err();
+                assert_matches!(err, Error::Client(e) if matches!(
+                    *e,
+                    ClientError::ServerError { protocol_error: ProtocolError::MessageTooLarge, .. }
+                ));
+                assert_eq!(client.batch_sizes.lock().as_slice(), &[3]);
+            }
+        }
+    }
+
     #[tokio::test]
     async fn test_producer_aggregator_error_push() {
         let record = record();
@@ -1147,7 +1701,7 @@ mod tests {
 
         fn deaggregate(
             &self,
-            input: &[i64],
+            input: &ProduceResult,
             tag: Self::Tag,
         ) -> Result<Self::Status, aggregator::Error> {
             let mut errors = self.errors.lock().unwrap();
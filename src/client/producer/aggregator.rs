@@ -1,4 +1,4 @@
-use crate::record::Record;
+use crate::{client::partition::ProduceResult, record::Record};
 
 /// The error returned by [`Aggregator`] implementations
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -56,6 +56,14 @@ pub trait Aggregator: Send + 'static {
 
     /// Flush the contents of this aggregator to Kafka
     fn flush(&mut self) -> Result<(Vec<Record>, Self::StatusDeaggregator), Error>;
+
+    /// Approximate size of the buffered data in bytes, if the aggregator keeps track of it.
+    ///
+    /// This is reported via [`BatchProducer::occupancy`](super::BatchProducer::occupancy) and
+    /// [`Metrics::aggregator_flushed`](crate::metrics::Metrics::aggregator_flushed). Defaults to `None`.
+    fn buffered_bytes(&self) -> Option<usize> {
+        None
+    }
 }
 
 /// De-aggregate status for successful `produce` operations.
@@ -67,7 +75,10 @@ pub trait StatusDeaggregator: Send + Sync + std::fmt::Debug {
     type Tag: Send;
 
     /// De-aggregate status.
-    fn deaggregate(&self, input: &[i64], tag: Self::Tag) -> Result<Self::Status, Error>;
+    ///
+    /// `input` contains the offsets of the flushed records as well as the metadata that the broker returned for the
+    /// produce request, e.g. the log append time for topics that use `LogAppendTime`.
+    fn deaggregate(&self, input: &ProduceResult, tag: Self::Tag) -> Result<Self::Status, Error>;
 }
 
 /// Helper trait to access the status of an [`Aggregator`].
@@ -118,6 +129,10 @@ impl Aggregator for RecordAggregator {
         let state = std::mem::take(&mut self.state);
         Ok((state.records, RecordAggregatorStatusDeaggregator::default()))
     }
+
+    fn buffered_bytes(&self) -> Option<usize> {
+        Some(self.state.batch_size)
+    }
 }
 
 impl RecordAggregator {
@@ -136,8 +151,8 @@ impl StatusDeaggregator for RecordAggregatorStatusDeaggregator {
     type Status = i64;
     type Tag = usize;
 
-    fn deaggregate(&self, input: &[i64], tag: Self::Tag) -> Result<Self::Status, Error> {
-        Ok(input[tag])
+    fn deaggregate(&self, input: &ProduceResult, tag: Self::Tag) -> Result<Self::Status, Error> {
+        Ok(input.offsets[tag])
     }
 }
 
@@ -147,17 +162,24 @@ mod tests {
 
     use super::*;
 
+    fn offsets(offsets: &[i64]) -> ProduceResult {
+        ProduceResult {
+            offsets: offsets.to_vec(),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_record_aggregator() {
         let r1 = Record {
-            key: Some(vec![0; 45]),
-            value: Some(vec![0; 2]),
+            key: Some(vec![0; 45].into()),
+            value: Some(vec![0; 2].into()),
             headers: Default::default(),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
 
         let r2 = Record {
-            value: Some(vec![0; 34]),
+            value: Some(vec![0; 34].into()),
             ..r1.clone()
         };
 
@@ -175,14 +197,14 @@ mod tests {
         // flush two records
         let (records, deagg) = aggregator.flush().unwrap();
         assert_eq!(records.len(), 2);
-        assert_eq!(deagg.deaggregate(&[10, 20], t1).unwrap(), 10);
-        assert_eq!(deagg.deaggregate(&[10, 20], t2).unwrap(), 20);
+        assert_eq!(deagg.deaggregate(&offsets(&[10, 20]), t1).unwrap(), 10);
+        assert_eq!(deagg.deaggregate(&offsets(&[10, 20]), t2).unwrap(), 20);
 
         // Test early flush
         let t1 = aggregator.try_push(r1.clone()).unwrap().unwrap_tag();
         let (records, deagg) = aggregator.flush().unwrap();
         assert_eq!(records.len(), 1);
-        assert_eq!(deagg.deaggregate(&[10], t1).unwrap(), 10);
+        assert_eq!(deagg.deaggregate(&offsets(&[10]), t1).unwrap(), 10);
 
         // next flush has full capacity again
         let t1 = aggregator.try_push(r1.clone()).unwrap().unwrap_tag();
@@ -190,8 +212,8 @@ mod tests {
 
         let (records, deagg) = aggregator.flush().unwrap();
         assert_eq!(records.len(), 2);
-        assert_eq!(deagg.deaggregate(&[10, 20], t1).unwrap(), 10);
-        assert_eq!(deagg.deaggregate(&[10, 20], t2).unwrap(), 20);
+        assert_eq!(deagg.deaggregate(&offsets(&[10, 20]), t1).unwrap(), 10);
+        assert_eq!(deagg.deaggregate(&offsets(&[10, 20]), t2).unwrap(), 20);
 
         // Test empty flush
         let (records, _deagg) = aggregator.flush().unwrap();
@@ -1,14 +1,22 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use tokio::task::JoinHandle;
+use tokio::sync::oneshot;
 use tracing::*;
 
 use super::{
     aggregator::{self, Aggregator, StatusDeaggregator, TryPush},
     broadcast::{BroadcastOnce, BroadcastOnceReceiver},
-    Error, ProducerClient,
+    rate_limit::RateLimiter,
+    AggregatorDeadLetterHandler, AggregatorOccupancy, DeadLetter, Error, ProducerClient,
+};
+use crate::{
+    client::{
+        error::{Error as ClientError, ProtocolError},
+        partition::{Compression, ProduceResult},
+    },
+    record::Record,
+    runtime::{self, JoinHandle},
 };
-use crate::client::partition::Compression;
 
 pub(super) type BatchWriteResult<A> = Result<Arc<AggregatedStatus<A>>, Error>;
 
@@ -19,7 +27,7 @@ pub(super) struct AggregatedStatus<A>
 where
     A: Aggregator,
 {
-    aggregated_status: Vec<i64>,
+    aggregated_status: ProduceResult,
     status_deagg: <A as Aggregator>::StatusDeaggregator,
 }
 
@@ -67,6 +75,28 @@ where
     }
 }
 
+/// Chains background flushes so that each write to Kafka only starts once the
+/// previous one has completed (or failed).
+///
+/// Every flush task holds the sending half of a oneshot channel that is
+/// dropped when the task finishes. The next flush task waits on the
+/// corresponding receiver before issuing its own produce request.
+#[derive(Debug, Default)]
+pub(super) struct FlushSequencer {
+    tail: Option<oneshot::Receiver<()>>,
+}
+
+impl FlushSequencer {
+    /// Register a new flush.
+    ///
+    /// Returns the completion signal of the previous flush (if any) and the
+    /// handle that must be dropped once this flush has completed.
+    fn next(&mut self) -> (Option<oneshot::Receiver<()>>, oneshot::Sender<()>) {
+        let (tx, rx) = oneshot::channel();
+        (self.tail.replace(rx), tx)
+    }
+}
+
 /// A call to [`BatchBuilder::background_flush()`] can either succeed or fail,
 /// and a new [`BatchBuilder`] is always returned for the next set of writes.
 pub(crate) enum FlushResult<T> {
@@ -87,6 +117,12 @@ where
 {
     aggregator: A,
     results: BroadcastOnce<BatchWriteResult<A>>,
+
+    /// Number of inputs that the aggregator accepted.
+    inputs: usize,
+
+    /// When the aggregator accepted the first input.
+    first_input: Option<Instant>,
 }
 
 impl<A> BatchBuilder<A>
@@ -97,6 +133,16 @@ where
         Self {
             aggregator,
             results: Default::default(),
+            inputs: 0,
+            first_input: None,
+        }
+    }
+
+    pub(super) fn occupancy(&self) -> AggregatorOccupancy {
+        AggregatorOccupancy {
+            inputs: self.inputs,
+            bytes: self.aggregator.buffered_bytes(),
+            age: self.first_input.map(|t| t.elapsed()),
         }
     }
 
@@ -116,20 +162,40 @@ where
             .map_err(|e| Error::Aggregator(e.into()))?
         {
             TryPush::NoCapacity(data) => Ok(TryPush::NoCapacity(data)),
-            TryPush::Aggregated(tag) => Ok(TryPush::Aggregated(ResultHandle::new(
-                self.results.receiver(),
-                tag,
-            ))),
+            TryPush::Aggregated(tag) => {
+                self.inputs += 1;
+                self.first_input.get_or_insert_with(Instant::now);
+                Ok(TryPush::Aggregated(ResultHandle::new(
+                    self.results.receiver(),
+                    tag,
+                )))
+            }
         }
     }
 
     /// Perform an asynchronous flush of this buffer.
     ///
+    /// If a `sequencer` is provided, the write is only issued once all
+    /// previously sequenced flushes have completed.
+    ///
+    /// If the write fails and a `dead_letter` handler is provided, the records
+    /// of this batch are passed to it.
+    ///
+    /// If a `rate_limiter` is provided, the write is delayed until the limiter
+    /// grants enough budget for this batch.
+    ///
+    /// If `split_oversized` is set, a batch that is rejected as too large is
+    /// written in multiple parts, see [`produce_splitting()`].
+    ///
     /// Returns a handle to the async flush task if a flush was necessary.
     pub(super) fn background_flush(
         mut self,
         client: Arc<dyn ProducerClient>,
         compression: Compression,
+        sequencer: Option<&mut FlushSequencer>,
+        dead_letter: Option<AggregatorDeadLetterHandler<A>>,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        split_oversized: bool,
     ) -> FlushResult<Self> {
         let (batch, status_deagg) = match self.aggregator.flush() {
             Ok(v) => v,
@@ -147,23 +213,66 @@ where
             // Broadcast an empty result set to satisfy the aggregation
             // contract.
             self.results.broadcast(Ok(Arc::new(AggregatedStatus {
-                aggregated_status: vec![],
+                aggregated_status: ProduceResult::default(),
                 status_deagg,
             })));
             return FlushResult::Ok(Self::new(self.aggregator), None);
         }
 
-        let handle = tokio::spawn({
+        let order = sequencer.map(FlushSequencer::next);
+
+        let handle = runtime::spawn({
             let broadcast = self.results;
             async move {
-                let res = match client.produce(batch, compression).await {
+                // Keep the completion handle alive until the write finished.
+                let _done = match order {
+                    Some((previous, done)) => {
+                        if let Some(previous) = previous {
+                            // An error only means that the previous flush task
+                            // is gone, which is just as good.
+                            previous.await.ok();
+                        }
+                        Some(done)
+                    }
+                    None => None,
+                };
+
+                if let Some(rate_limiter) = rate_limiter {
+                    let bytes = batch.iter().map(|r| r.approximate_size()).sum();
+                    rate_limiter.acquire(batch.len(), bytes).await;
+                }
+
+                let res = if split_oversized {
+                    produce_splitting(client.as_ref(), batch, compression)
+                        .await
+                        .map_err(|(e, unwritten)| (e, Some(unwritten)))
+                } else {
+                    // The client consumes the records, so keep a copy around
+                    // for the dead letter handler.
+                    let records = dead_letter.is_some().then(|| batch.clone());
+                    client
+                        .produce(batch, compression)
+                        .await
+                        .map_err(|e| (e, records))
+                };
+
+                let res = match res {
                     Ok(status) => Ok(Arc::new(AggregatedStatus {
                         aggregated_status: status,
                         status_deagg,
                     })),
-                    Err(e) => {
+                    Err((e, records)) => {
                         error!(?client, error=?e, "Failed to produce records");
-                        Err(Error::Client(Arc::new(e)))
+                        let error = Error::Client(Arc::new(e));
+
+                        if let (Some(handler), Some(records)) = (dead_letter, records) {
+                            handler.handle(DeadLetter::WriteFailed {
+                                records,
+                                error: error.clone(),
+                            });
+                        }
+
+                        Err(error)
                     }
                 };
 
@@ -174,3 +283,65 @@ where
         FlushResult::Ok(Self::new(self.aggregator), Some(handle))
     }
 }
+
+/// Write `batch`, splitting it in half whenever it is rejected as too large.
+///
+/// The parts are written one after another and their offsets are concatenated,
+/// so that the result lines up with `batch` as if it was written by a single
+/// request and can be passed to the [`StatusDeaggregator`] as usual.
+///
+/// On error, the records that were not written yet are returned alongside the
+/// error. Parts that were written before stay in Kafka.
+async fn produce_splitting(
+    client: &dyn ProducerClient,
+    batch: Vec<Record>,
+    compression: Compression,
+) -> Result<ProduceResult, (ClientError, Vec<Record>)> {
+    let mut result = ProduceResult::default();
+
+    // Parts that still need to be written, the next one is at the end.
+    let mut pending = vec![batch];
+    while let Some(part) = pending.pop() {
+        match client.produce(part.clone(), compression).await {
+            Ok(status) => {
+                result.offsets.extend(status.offsets);
+                result.log_append_time = result.log_append_time.or(status.log_append_time);
+                result.log_start_offset = status.log_start_offset.or(result.log_start_offset);
+            }
+            Err(e) if part.len() > 1 && is_too_large(&e) => {
+                let mut first = part;
+                let second = first.split_off(first.len() / 2);
+                debug!(
+                    ?client,
+                    first = first.len(),
+                    second = second.len(),
+                    "Batch too large, splitting"
+                );
+                pending.push(second);
+                pending.push(first);
+            }
+            Err(e) => {
+                let unwritten = std::iter::once(part)
+                    .chain(pending.into_iter().rev())
+                    .flatten()
+                    .collect();
+                return Err((e, unwritten));
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether splitting the batch that caused `e` might help.
+fn is_too_large(e: &ClientError) -> bool {
+    matches!(
+        e,
+        // a single record that is too large is reported with its index
+        ClientError::RecordTooLarge { index: None, .. }
+            | ClientError::ServerError {
+                protocol_error: ProtocolError::MessageTooLarge | ProtocolError::RecordListTooLarge,
+                ..
+            }
+    )
+}
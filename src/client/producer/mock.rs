@@ -0,0 +1,129 @@
+//! In-memory [`ProducerClient`] for tests.
+use std::{collections::VecDeque, time::Duration};
+
+use futures::future::BoxFuture;
+use parking_lot::Mutex;
+
+use super::ProducerClient;
+use crate::{
+    client::{
+        error::Error as ClientError,
+        partition::{Compression, ProduceResult},
+    },
+    record::Record,
+    runtime,
+};
+
+/// A [`ProducerClient`] that keeps all written records in memory.
+///
+/// This allows testing [`BatchProducer`](super::BatchProducer)s and [aggregators](super::aggregator) without a
+/// running broker. Records get consecutive offsets starting at `0`, like they would in a fresh partition.
+#[derive(Debug, Default)]
+pub struct MockProducerClient {
+    delay: Duration,
+    errors: Mutex<VecDeque<ClientError>>,
+    batches: Mutex<Vec<(Vec<Record>, Compression)>>,
+}
+
+impl MockProducerClient {
+    /// Create a new, empty client.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay every write by the given duration.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    /// Fail the next write that has not been failed by an earlier call with `error`.
+    ///
+    /// Failed writes are not recorded.
+    pub fn fail_next(&self, error: ClientError) {
+        self.errors.lock().push_back(error);
+    }
+
+    /// All successfully written batches, in write order.
+    pub fn batches(&self) -> Vec<(Vec<Record>, Compression)> {
+        self.batches.lock().clone()
+    }
+
+    /// All successfully written records, in write order.
+    pub fn records(&self) -> Vec<Record> {
+        self.batches
+            .lock()
+            .iter()
+            .flat_map(|(records, _compression)| records.iter().cloned())
+            .collect()
+    }
+}
+
+impl ProducerClient for MockProducerClient {
+    fn produce(
+        &self,
+        records: Vec<Record>,
+        compression: Compression,
+    ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
+        Box::pin(async move {
+            if !self.delay.is_zero() {
+                runtime::sleep(self.delay).await;
+            }
+
+            if let Some(e) = self.errors.lock().pop_front() {
+                return Err(e);
+            }
+
+            let mut batches = self.batches.lock();
+            let offset_base = batches.iter().map(|(r, _)| r.len()).sum::<usize>() as i64;
+            let offsets = (0..records.len() as i64).map(|x| x + offset_base).collect();
+            batches.push((records, compression));
+
+            Ok(ProduceResult {
+                offsets,
+                ..Default::default()
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(320).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_producer_client() {
+        let client = MockProducerClient::new();
+
+        let res = client
+            .produce(vec![record(), record()], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(res.offsets, [0, 1]);
+
+        client.fail_next(ClientError::InvalidResponse("foo".to_owned()));
+        client
+            .produce(vec![record()], Compression::NoCompression)
+            .await
+            .unwrap_err();
+
+        let res = client
+            .produce(vec![record()], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(res.offsets, [2]);
+
+        assert_eq!(client.batches().len(), 2);
+        assert_eq!(client.records(), vec![record(); 3]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Partition selection for data that is not bound to a partition.
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A partition of a topic as seen by a [`PartitionSelector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PartitionLoad {
+    pub partition: i32,
+
+    /// Number of inputs that were passed to the producer of this partition and are not written yet.
+    pub backlog: usize,
+}
+
+impl PartitionLoad {
+    pub fn new(partition: i32, backlog: usize) -> Self {
+        Self { partition, backlog }
+    }
+}
+
+/// Picks the partition for data passed to
+/// [`PartitionedBatchProducer::produce_to_topic`](super::PartitionedBatchProducer::produce_to_topic).
+pub trait PartitionSelector: std::fmt::Debug + Send + Sync {
+    /// Select one of `partitions` of `topic`.
+    ///
+    /// `partitions` is never empty and ordered by partition index.
+    fn select(&self, topic: &str, partitions: &[PartitionLoad]) -> i32;
+}
+
+/// Cycles through all partitions.
+///
+/// This is the default [`PartitionSelector`].
+#[derive(Debug, Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl PartitionSelector for RoundRobin {
+    fn select(&self, _topic: &str, partitions: &[PartitionLoad]) -> i32 {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        partitions[n % partitions.len()].partition
+    }
+}
+
+/// Picks the partition with the smallest backlog, so that partitions whose brokers are slow to respond receive less
+/// data.
+///
+/// Ties are broken round-robin.
+#[derive(Debug, Default)]
+pub struct LeastBacklog {
+    next: AtomicUsize,
+}
+
+impl PartitionSelector for LeastBacklog {
+    fn select(&self, _topic: &str, partitions: &[PartitionLoad]) -> i32 {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % partitions.len();
+        let (head, tail) = partitions.split_at(start);
+        tail.iter()
+            .chain(head)
+            .min_by_key(|p| p.backlog)
+            .expect("partitions are not empty")
+            .partition
+    }
+}
+
+/// Distributes data proportional to user-supplied weights, e.g. to de-emphasize partitions led by a busy broker.
+///
+/// Partitions without a weight have a weight of 1. A weight of 0 excludes a partition unless all partitions have a
+/// weight of 0, in which case they are used round-robin. Weights apply to the partitions of all topics.
+#[derive(Debug, Default)]
+pub struct Weighted {
+    weights: HashMap<i32, u32>,
+    next: AtomicUsize,
+}
+
+impl Weighted {
+    pub fn new(weights: impl IntoIterator<Item = (i32, u32)>) -> Self {
+        Self {
+            weights: weights.into_iter().collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn weight(&self, partition: i32) -> usize {
+        self.weights.get(&partition).copied().unwrap_or(1) as usize
+    }
+}
+
+impl PartitionSelector for Weighted {
+    fn select(&self, _topic: &str, partitions: &[PartitionLoad]) -> i32 {
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+
+        let total: usize = partitions.iter().map(|p| self.weight(p.partition)).sum();
+        if total == 0 {
+            return partitions[n % partitions.len()].partition;
+        }
+
+        let mut n = n % total;
+        for p in partitions {
+            let weight = self.weight(p.partition);
+            if n < weight {
+                return p.partition;
+            }
+            n -= weight;
+        }
+        unreachable!("n is smaller than the sum of all weights")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loads(backlogs: &[usize]) -> Vec<PartitionLoad> {
+        backlogs
+            .iter()
+            .enumerate()
+            .map(|(partition, backlog)| PartitionLoad::new(partition as i32, *backlog))
+            .collect()
+    }
+
+    fn select_n(
+        selector: &dyn PartitionSelector,
+        partitions: &[PartitionLoad],
+        n: usize,
+    ) -> Vec<i32> {
+        (0..n).map(|_| selector.select("foo", partitions)).collect()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let selector = RoundRobin::default();
+        assert_eq!(select_n(&selector, &loads(&[0, 5, 0]), 4), [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_least_backlog() {
+        let selector = LeastBacklog::default();
+        assert_eq!(select_n(&selector, &loads(&[3, 1, 2]), 3), [1, 1, 1]);
+        assert_eq!(select_n(&selector, &loads(&[0, 1, 0]), 4), [0, 2, 2, 0]);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let selector = Weighted::new([(0, 2), (2, 0)]);
+        assert_eq!(
+            select_n(&selector, &loads(&[0, 0, 0, 0]), 8),
+            [0, 0, 1, 3, 0, 0, 1, 3]
+        );
+
+        let selector = Weighted::new([(0, 0), (1, 0)]);
+        assert_eq!(select_n(&selector, &loads(&[0, 0]), 3), [0, 1, 0]);
+    }
+}
@@ -0,0 +1,661 @@
+//! Aggregation across multiple partitions.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::sync::OnceCell;
+use tracing::*;
+
+use super::{
+    aggregator::{Aggregator, AggregatorStatus},
+    partition_selector::{PartitionLoad, PartitionSelector, RoundRobin},
+    rate_limit::{RateLimit, RateLimiter},
+    BatchProducer, BatchProducerBuilder, Error, InputValidator, ProducerClient, ProducerMetrics,
+    Result,
+};
+use crate::{
+    client::{
+        error::{Error as ClientError, ProtocolError, RequestContext},
+        partition::{Compression, UnknownTopicHandling},
+        Client,
+    },
+    metrics::Metrics,
+    topic::TopicPartition,
+};
+
+/// Creates [`ProducerClient`]s for the partitions that a [`PartitionedBatchProducer`] writes to.
+pub trait ProducerClientFactory: std::fmt::Debug + Send + Sync {
+    /// Create a client for the given topic and partition.
+    fn producer_client(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> BoxFuture<'_, Result<Arc<dyn ProducerClient>, ClientError>>;
+
+    /// List the partitions of the given topic in ascending order.
+    ///
+    /// This is used to [select a partition](PartitionedBatchProducer::produce_to_topic) if the caller does not name
+    /// one.
+    fn partitions(&self, topic: &str) -> BoxFuture<'_, Result<Vec<i32>, ClientError>>;
+}
+
+/// [`ProducerClientFactory`] that creates [`PartitionClient`](crate::client::partition::PartitionClient)s.
+#[derive(Debug)]
+struct PartitionClientFactory {
+    client: Arc<Client>,
+    unknown_topic_handling: UnknownTopicHandling,
+}
+
+impl ProducerClientFactory for PartitionClientFactory {
+    fn producer_client(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> BoxFuture<'_, Result<Arc<dyn ProducerClient>, ClientError>> {
+        let topic = topic.to_owned();
+        Box::pin(async move {
+            let client = self
+                .client
+                .partition_client(topic, partition, self.unknown_topic_handling)
+                .await?;
+            Ok(client as _)
+        })
+    }
+
+    fn partitions(&self, topic: &str) -> BoxFuture<'_, Result<Vec<i32>, ClientError>> {
+        let topic = topic.to_owned();
+        Box::pin(async move { self.client.partition_ids(&topic).await })
+    }
+}
+
+/// Builder for [`PartitionedBatchProducer`]s that accept `I` as input.
+pub struct PartitionedBatchProducerBuilder<I> {
+    factory: Arc<dyn ProducerClientFactory>,
+
+    linger: Duration,
+
+    compression: Compression,
+
+    ordered_flushes: bool,
+
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    split_oversized_batches: bool,
+
+    metrics: Option<ProducerMetrics>,
+
+    partition_selector: Arc<dyn PartitionSelector>,
+
+    validators: Vec<Arc<dyn InputValidator<I>>>,
+}
+
+// manual impl, so that `I` does not need to implement `Debug`
+impl<I> std::fmt::Debug for PartitionedBatchProducerBuilder<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartitionedBatchProducerBuilder")
+            .field("factory", &self.factory)
+            .field("linger", &self.linger)
+            .field("compression", &self.compression)
+            .field("ordered_flushes", &self.ordered_flushes)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("split_oversized_batches", &self.split_oversized_batches)
+            .field("metrics", &self.metrics)
+            .field("partition_selector", &self.partition_selector)
+            .field("validators", &self.validators)
+            .finish()
+    }
+}
+
+impl<I> PartitionedBatchProducerBuilder<I> {
+    /// Build a new `PartitionedBatchProducer` that writes via [`PartitionClient`]s created by `client`.
+    ///
+    /// Flushes are reported to the [metrics](crate::client::ClientBuilder::metrics) of the client, if any.
+    ///
+    /// [`PartitionClient`]: crate::client::partition::PartitionClient
+    pub fn new(client: Arc<Client>, unknown_topic_handling: UnknownTopicHandling) -> Self {
+        let metrics = client.metrics().map(ProducerMetrics);
+        Self {
+            metrics,
+            ..Self::new_with_factory(Arc::new(PartitionClientFactory {
+                client,
+                unknown_topic_handling,
+            }))
+        }
+    }
+
+    /// Construct a [`PartitionedBatchProducer`] with a dynamically dispatched [`ProducerClientFactory`]
+    /// implementation.
+    pub fn new_with_factory(factory: Arc<dyn ProducerClientFactory>) -> Self {
+        Self {
+            factory,
+            linger: Duration::from_millis(5),
+            compression: Compression::default(),
+            ordered_flushes: false,
+            rate_limiter: None,
+            split_oversized_batches: false,
+            metrics: None,
+            partition_selector: Arc::new(RoundRobin::default()),
+            validators: vec![],
+        }
+    }
+
+    /// Report every flush of the partition aggregators to `metrics`.
+    ///
+    /// See [`BatchProducerBuilder::with_metrics`].
+    pub fn with_metrics(self, metrics: Arc<dyn Metrics>) -> Self {
+        Self {
+            metrics: Some(ProducerMetrics(metrics)),
+            ..self
+        }
+    }
+
+    /// Sets the minimum amount of time to wait for new data before flushing the batch of a partition.
+    ///
+    /// See [`BatchProducerBuilder::with_linger`].
+    pub fn with_linger(self, linger: Duration) -> Self {
+        Self { linger, ..self }
+    }
+
+    /// Sets compression.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// Only write one batch at a time per partition.
+    ///
+    /// See [`BatchProducerBuilder::with_ordered_flushes`].
+    pub fn with_ordered_flushes(self, ordered_flushes: bool) -> Self {
+        Self {
+            ordered_flushes,
+            ..self
+        }
+    }
+
+    /// Split batches that are too large for the broker instead of failing them.
+    ///
+    /// See [`BatchProducerBuilder::with_split_oversized_batches`].
+    pub fn with_split_oversized_batches(self, split_oversized_batches: bool) -> Self {
+        Self {
+            split_oversized_batches,
+            ..self
+        }
+    }
+
+    /// Limit the rate at which data is written to Kafka.
+    ///
+    /// The budget is shared by all partitions. See [`BatchProducerBuilder::with_rate_limit`].
+    pub fn with_rate_limit(self, rate_limit: RateLimit) -> Self {
+        Self {
+            rate_limiter: Some(Arc::new(RateLimiter::new(rate_limit))),
+            ..self
+        }
+    }
+
+    /// Sets how [`produce_to_topic`](PartitionedBatchProducer::produce_to_topic) picks partitions.
+    ///
+    /// Defaults to [`RoundRobin`].
+    pub fn with_partition_selector(self, partition_selector: Arc<dyn PartitionSelector>) -> Self {
+        Self {
+            partition_selector,
+            ..self
+        }
+    }
+
+    /// Check data with `validator` before passing it to the aggregator of a partition.
+    ///
+    /// See [`BatchProducerBuilder::with_validator`].
+    pub fn with_validator(mut self, validator: Arc<dyn InputValidator<I>>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Build the producer.
+    ///
+    /// `aggregator` is called once for every topic-partition that is written to.
+    pub fn build<A, F>(self, aggregator: F) -> PartitionedBatchProducer<A>
+    where
+        A: Aggregator<Input = I>,
+        F: Fn(&str, i32) -> A + Send + Sync + 'static,
+    {
+        PartitionedBatchProducer {
+            builder: self,
+            aggregator: Box::new(aggregator),
+            producers: Default::default(),
+            topics: Default::default(),
+        }
+    }
+}
+
+/// Creates the [`Aggregator`] for a topic-partition.
+type AggregatorFactory<A> = Box<dyn Fn(&str, i32) -> A + Send + Sync>;
+
+/// Lazily initialized producer of a single topic-partition.
+struct PartitionProducer<A>
+where
+    A: Aggregator,
+{
+    producer: OnceCell<Arc<BatchProducer<A>>>,
+
+    /// Number of inputs passed to `producer` that are not written yet.
+    backlog: AtomicUsize,
+}
+
+impl<A> Default for PartitionProducer<A>
+where
+    A: Aggregator,
+{
+    fn default() -> Self {
+        Self {
+            producer: OnceCell::new(),
+            backlog: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Lazily looked up partitions of a topic.
+type TopicPartitions = Arc<OnceCell<Arc<[i32]>>>;
+
+/// Counts an input towards the backlog of a partition until it is dropped.
+struct BacklogGuard<'a>(&'a AtomicUsize);
+
+impl<'a> BacklogGuard<'a> {
+    fn new(backlog: &'a AtomicUsize) -> Self {
+        backlog.fetch_add(1, Ordering::Relaxed);
+        Self(backlog)
+    }
+}
+
+impl Drop for BacklogGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A producer that maintains a separate [`BatchProducer`] per topic-partition.
+///
+/// The producers (including their [`ProducerClient`]s) are created on first use and every partition is aggregated and
+/// flushed independently.
+pub struct PartitionedBatchProducer<A>
+where
+    A: Aggregator,
+{
+    builder: PartitionedBatchProducerBuilder<A::Input>,
+    aggregator: AggregatorFactory<A>,
+    producers: parking_lot::Mutex<HashMap<TopicPartition, Arc<PartitionProducer<A>>>>,
+
+    /// Partitions of the topics written via [`produce_to_topic`](Self::produce_to_topic).
+    topics: parking_lot::Mutex<HashMap<String, TopicPartitions>>,
+}
+
+impl<A> std::fmt::Debug for PartitionedBatchProducer<A>
+where
+    A: Aggregator,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PartitionedBatchProducer")
+            .field("builder", &self.builder)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> PartitionedBatchProducer<A>
+where
+    A: Aggregator,
+{
+    /// Write `data` to the given topic-partition.
+    ///
+    /// Returns when the data has been committed to Kafka or an unrecoverable error has been encountered.
+    ///
+    /// # Cancellation
+    ///
+    /// See [`BatchProducer::produce`].
+    pub async fn produce(
+        &self,
+        topic: &str,
+        partition: i32,
+        data: A::Input,
+    ) -> Result<<A as AggregatorStatus>::Status> {
+        self.validate(&data)?;
+        self.produce_validated(topic, partition, data).await
+    }
+
+    /// Write `data` to a partition of `topic` that is picked by the
+    /// [`PartitionSelector`](PartitionedBatchProducerBuilder::with_partition_selector).
+    ///
+    /// Returns the partition together with the status. The partitions of a topic are looked up on first use, so
+    /// partitions that are added to the topic afterwards are not picked.
+    ///
+    /// # Cancellation
+    ///
+    /// See [`BatchProducer::produce`].
+    pub async fn produce_to_topic(
+        &self,
+        topic: &str,
+        data: A::Input,
+    ) -> Result<(i32, <A as AggregatorStatus>::Status)> {
+        self.validate(&data)?;
+
+        let partitions = self.partitions(topic).await?;
+        let loads: Vec<_> = {
+            let producers = self.producers.lock();
+            partitions
+                .iter()
+                .map(|partition| {
+                    let backlog = producers
+                        .get(&TopicPartition::new(topic, *partition))
+                        .map(|p| p.backlog.load(Ordering::Relaxed))
+                        .unwrap_or_default();
+                    PartitionLoad::new(*partition, backlog)
+                })
+                .collect()
+        };
+
+        let partition = self.builder.partition_selector.select(topic, &loads);
+        trace!(topic, partition, "selected partition");
+
+        let status = self.produce_validated(topic, partition, data).await?;
+        Ok((partition, status))
+    }
+
+    /// Flush all partitions.
+    ///
+    /// Blocks until all pending writes to Kafka complete (or fail).
+    ///
+    /// If this function returns an error, the flush may be incomplete.
+    pub async fn flush(&self) -> Result<()> {
+        let producers: Vec<_> = self
+            .producers
+            .lock()
+            .values()
+            .filter_map(|p| p.producer.get().map(Arc::clone))
+            .collect();
+
+        for producer in producers {
+            producer.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self, data: &A::Input) -> Result<()> {
+        for validator in &self.builder.validators {
+            validator.validate(data)?;
+        }
+        Ok(())
+    }
+
+    async fn produce_validated(
+        &self,
+        topic: &str,
+        partition: i32,
+        data: A::Input,
+    ) -> Result<<A as AggregatorStatus>::Status> {
+        let state = self.partition_producer(topic, partition);
+        let producer = self.producer(topic, partition, &state).await?;
+
+        let _backlog = BacklogGuard::new(&state.backlog);
+        producer.produce(data).await
+    }
+
+    /// Get the state of the given topic-partition, creating it if necessary.
+    fn partition_producer(&self, topic: &str, partition: i32) -> Arc<PartitionProducer<A>> {
+        Arc::clone(
+            self.producers
+                .lock()
+                .entry(TopicPartition::new(topic, partition))
+                .or_default(),
+        )
+    }
+
+    /// Get the producer of the given topic-partition, creating it if necessary.
+    async fn producer(
+        &self,
+        topic: &str,
+        partition: i32,
+        state: &PartitionProducer<A>,
+    ) -> Result<Arc<BatchProducer<A>>> {
+        let producer = state
+            .producer
+            .get_or_try_init(|| async {
+                debug!(topic, partition, "creating partition producer");
+
+                let client = self
+                    .builder
+                    .factory
+                    .producer_client(topic, partition)
+                    .await
+                    .map_err(|e| Error::Client(Arc::new(e)))?;
+
+                let mut builder = BatchProducerBuilder::new_with_client(client)
+                    .with_linger(self.builder.linger)
+                    .with_compression(self.builder.compression)
+                    .with_ordered_flushes(self.builder.ordered_flushes)
+                    .with_split_oversized_batches(self.builder.split_oversized_batches);
+                if let Some(rate_limiter) = &self.builder.rate_limiter {
+                    builder = builder.with_rate_limiter(Arc::clone(rate_limiter));
+                }
+                if let Some(metrics) = &self.builder.metrics {
+                    builder = builder.with_metrics(Arc::clone(&metrics.0));
+                }
+                let producer = builder.build((self.aggregator)(topic, partition));
+
+                Ok::<_, Error>(Arc::new(producer))
+            })
+            .await?;
+
+        Ok(Arc::clone(producer))
+    }
+
+    /// Get the partitions of the given topic, looking them up if necessary.
+    async fn partitions(&self, topic: &str) -> Result<Arc<[i32]>> {
+        let cell = Arc::clone(self.topics.lock().entry(topic.to_owned()).or_default());
+
+        let partitions = cell
+            .get_or_try_init(|| async {
+                let partitions = self
+                    .builder
+                    .factory
+                    .partitions(topic)
+                    .await
+                    .map_err(|e| Error::Client(Arc::new(e)))?;
+                if partitions.is_empty() {
+                    return Err(Error::Client(Arc::new(ClientError::ServerError {
+                        protocol_error: ProtocolError::UnknownTopicOrPartition,
+                        error_message: None,
+                        request: RequestContext::Topic(topic.to_owned()),
+                        response: None,
+                        is_virtual: false,
+                        info: None,
+                    })));
+                }
+                debug!(
+                    topic,
+                    n_partitions = partitions.len(),
+                    "looked up partitions"
+                );
+                Ok::<_, Error>(partitions.into())
+            })
+            .await?;
+
+        Ok(Arc::clone(partitions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        client::{
+            partition::ProduceResult,
+            producer::{aggregator::RecordAggregator, LeastBacklog},
+        },
+        record::Record,
+    };
+
+    #[derive(Debug, Default)]
+    struct MockClient {
+        batch_sizes: parking_lot::Mutex<Vec<usize>>,
+    }
+
+    impl ProducerClient for MockClient {
+        fn produce(
+            &self,
+            records: Vec<Record>,
+            _compression: Compression,
+        ) -> BoxFuture<'_, Result<ProduceResult, ClientError>> {
+            Box::pin(async move {
+                let mut batch_sizes = self.batch_sizes.lock();
+                let offset_base = batch_sizes.iter().sum::<usize>() as i64;
+                batch_sizes.push(records.len());
+                Ok(ProduceResult {
+                    offsets: (0..records.len() as i64).map(|x| x + offset_base).collect(),
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct MockFactory {
+        clients: parking_lot::Mutex<HashMap<(String, i32), Arc<MockClient>>>,
+    }
+
+    impl ProducerClientFactory for MockFactory {
+        fn producer_client(
+            &self,
+            topic: &str,
+            partition: i32,
+        ) -> BoxFuture<'_, Result<Arc<dyn ProducerClient>, ClientError>> {
+            let client = Arc::clone(
+                self.clients
+                    .lock()
+                    .entry((topic.to_owned(), partition))
+                    .or_default(),
+            );
+            Box::pin(async move { Ok(client as _) })
+        }
+
+        fn partitions(&self, _topic: &str) -> BoxFuture<'_, Result<Vec<i32>, ClientError>> {
+            Box::pin(async move { Ok(vec![0, 1, 2]) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_producer() {
+        let record = Record {
+            key: Some(vec![0; 4].into()),
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(320).unwrap(),
+        };
+
+        let factory = Arc::new(MockFactory::default());
+        let producer = PartitionedBatchProducerBuilder::new_with_factory(
+            Arc::<MockFactory>::clone(&factory) as _,
+        )
+        .with_linger(Duration::from_secs(3600))
+        .build({
+            let size = record.approximate_size();
+            move |_topic, _partition| RecordAggregator::new(size * 10)
+        });
+
+        let (a, b, c, flush) = tokio::join!(
+            producer.produce("foo", 0, record.clone()),
+            producer.produce("foo", 0, record.clone()),
+            producer.produce("foo", 1, record.clone()),
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                producer.flush().await
+            },
+        );
+        flush.unwrap();
+
+        let mut offsets = [a.unwrap(), b.unwrap()];
+        offsets.sort_unstable();
+        assert_eq!(offsets, [0, 1]);
+        assert_eq!(c.unwrap(), 0);
+
+        let clients = factory.clients.lock();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(
+            clients[&("foo".to_owned(), 0)]
+                .batch_sizes
+                .lock()
+                .as_slice(),
+            &[2]
+        );
+        assert_eq!(
+            clients[&("foo".to_owned(), 1)]
+                .batch_sizes
+                .lock()
+                .as_slice(),
+            &[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_produce_to_topic() {
+        let record = Record {
+            key: None,
+            value: Some(vec![0; 6].into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(320).unwrap(),
+        };
+
+        let factory = Arc::new(MockFactory::default());
+        let producer = PartitionedBatchProducerBuilder::new_with_factory(
+            Arc::<MockFactory>::clone(&factory) as _,
+        )
+        .with_linger(Duration::from_secs(3600))
+        .with_partition_selector(Arc::new(LeastBacklog::default()))
+        .build({
+            let size = record.approximate_size();
+            move |_topic, _partition| RecordAggregator::new(size * 10)
+        });
+
+        let (a, b, c, d, flush) = tokio::join!(
+            producer.produce("foo", 1, record.clone()),
+            producer.produce("foo", 1, record.clone()),
+            async {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                producer.produce_to_topic("foo", record.clone()).await
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                producer.produce_to_topic("foo", record.clone()).await
+            },
+            async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                producer.flush().await
+            },
+        );
+        flush.unwrap();
+        a.unwrap();
+        b.unwrap();
+
+        // partition 1 has a backlog of two records, so it is skipped
+        assert_eq!(c.unwrap(), (0, 0));
+        assert_eq!(d.unwrap(), (2, 0));
+
+        let clients = factory.clients.lock();
+        assert_eq!(clients.len(), 3);
+        assert_eq!(
+            clients[&("foo".to_owned(), 1)]
+                .batch_sizes
+                .lock()
+                .as_slice(),
+            &[2]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Client-side rate limiting of produce requests.
+use std::{num::NonZeroU64, time::Duration};
+
+use tokio::time::Instant;
+use tracing::*;
+
+use crate::runtime;
+
+/// Rate limit for [`BatchProducer`](super::BatchProducer) writes.
+///
+/// The limit is implemented as a token bucket per dimension that can hold up to one second worth of budget. A flush
+/// that exceeds the remaining budget is delayed until the budget is refilled, it does NOT fail. Batches larger than
+/// the budget of one second are still written, but delay subsequent flushes accordingly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Maximum number of records per second. `None` means unlimited.
+    pub records_per_second: Option<NonZeroU64>,
+
+    /// Maximum number of bytes per second. `None` means unlimited.
+    ///
+    /// This uses the [approximate size](crate::record::Record::approximate_size) of the records before compression.
+    pub bytes_per_second: Option<NonZeroU64>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl Bucket {
+    /// A non-zero rate keeps the wait time of [`take`](Self::take) finite.
+    fn new(rate: NonZeroU64) -> Self {
+        let rate = rate.get() as f64;
+        Self { rate, tokens: rate }
+    }
+
+    /// Take `cost` tokens and return how long the caller has to wait until the bucket is balanced again.
+    fn take(&mut self, elapsed: Duration, cost: usize) -> Duration {
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.tokens -= cost as f64;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            // saturate for absurdly large batches instead of panicking
+            Duration::try_from_secs_f64(-self.tokens / self.rate).unwrap_or(Duration::MAX)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    records: Option<Bucket>,
+    bytes: Option<Bucket>,
+    last_refill: Instant,
+}
+
+/// Token bucket implementation of a [`RateLimit`].
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    state: parking_lot::Mutex<State>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            state: parking_lot::Mutex::new(State {
+                records: limit.records_per_second.map(Bucket::new),
+                bytes: limit.bytes_per_second.map(Bucket::new),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Reserve budget for `records` records with a total size of `bytes` at `now`.
+    ///
+    /// Returns how long the caller must wait before using the budget.
+    fn reserve(&self, records: usize, bytes: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock();
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.last_refill = now;
+
+        let wait_records = state
+            .records
+            .as_mut()
+            .map(|b| b.take(elapsed, records))
+            .unwrap_or_default();
+        let wait_bytes = state
+            .bytes
+            .as_mut()
+            .map(|b| b.take(elapsed, bytes))
+            .unwrap_or_default();
+
+        wait_records.max(wait_bytes)
+    }
+
+    /// Wait until there is budget for `records` records with a total size of `bytes`.
+    pub(crate) async fn acquire(&self, records: usize, bytes: usize) {
+        let wait = self.reserve(records, bytes, Instant::now());
+        if !wait.is_zero() {
+            debug!(?wait, records, bytes, "rate limit exceeded, delaying write");
+            runtime::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = RateLimiter::new(RateLimit::default());
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(1_000_000, 1_000_000, now), Duration::ZERO);
+        assert_eq!(limiter.reserve(1_000_000, 1_000_000, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_records() {
+        let limiter = RateLimiter::new(RateLimit {
+            records_per_second: NonZeroU64::new(10),
+            bytes_per_second: None,
+        });
+        let now = Instant::now();
+
+        // initial burst
+        assert_eq!(limiter.reserve(10, 1_000, now), Duration::ZERO);
+
+        // budget exhausted
+        assert_eq!(limiter.reserve(5, 0, now), Duration::from_millis(500));
+
+        // refilled after waiting
+        let now = now + Duration::from_millis(500);
+        assert_eq!(limiter.reserve(1, 0, now), Duration::from_millis(100));
+
+        // burst is capped
+        let now = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(10, 0, now), Duration::ZERO);
+        assert_eq!(limiter.reserve(1, 0, now), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_bytes_and_records() {
+        let limiter = RateLimiter::new(RateLimit {
+            records_per_second: NonZeroU64::new(10),
+            bytes_per_second: NonZeroU64::new(100),
+        });
+        let now = Instant::now();
+
+        // bytes are the bottleneck
+        assert_eq!(limiter.reserve(1, 300, now), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_lowest_rate() {
+        let limiter = RateLimiter::new(RateLimit {
+            records_per_second: NonZeroU64::new(1),
+            bytes_per_second: NonZeroU64::new(1),
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve(1, 1, now), Duration::ZERO);
+        assert_eq!(limiter.reserve(1, 3, now), Duration::from_secs(3));
+        assert_eq!(limiter.reserve(usize::MAX, 0, now), Duration::MAX);
+    }
+}
@@ -0,0 +1,214 @@
+//! Producing typed keys and values.
+use std::{fmt::Debug, sync::Arc};
+
+use super::{aggregator::RecordAggregator, BatchProducer, Error, Result};
+use crate::codec::{Codec, RecordEncoder, TypedRecord};
+
+/// [`BatchProducer`] that encodes keys and values via [`Codec`]s.
+///
+/// # Example
+/// ```no_run
+/// # async fn test() {
+/// use rskafka::{
+///     client::{
+///         partition::UnknownTopicHandling,
+///         producer::{aggregator::RecordAggregator, BatchProducerBuilder, SerializingProducer},
+///         ClientBuilder,
+///     },
+///     codec::{BytesCodec, StringCodec, TypedRecord},
+/// };
+///
+/// let client = ClientBuilder::new(vec!["localhost:9093".to_owned()]).build().await.unwrap();
+/// let partition_client = client
+///     .partition_client("my_topic", 0, UnknownTopicHandling::Retry)
+///     .await
+///     .unwrap();
+/// let producer = SerializingProducer::new(
+///     BatchProducerBuilder::new(partition_client).build(RecordAggregator::new(1024)),
+///     StringCodec,
+///     BytesCodec,
+/// );
+///
+/// let offset = producer
+///     .produce(TypedRecord::new(Some("key".to_owned()), b"value".to_vec()))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct SerializingProducer<K, V> {
+    producer: BatchProducer<RecordAggregator>,
+    key_codec: Arc<dyn Codec<K>>,
+    value_codec: Arc<dyn Codec<V>>,
+    encoder: Option<Arc<dyn RecordEncoder>>,
+}
+
+impl<K, V> SerializingProducer<K, V> {
+    pub fn new(
+        producer: BatchProducer<RecordAggregator>,
+        key_codec: impl Codec<K> + 'static,
+        value_codec: impl Codec<V> + 'static,
+    ) -> Self {
+        Self {
+            producer,
+            key_codec: Arc::new(key_codec),
+            value_codec: Arc::new(value_codec),
+            encoder: None,
+        }
+    }
+
+    /// Pass every encoded record through `encoder` before it is written, e.g. a
+    /// [`SchemaRegistryEncoder`](crate::codec::schema_registry::SchemaRegistryEncoder).
+    pub fn with_encoder(self, encoder: impl RecordEncoder + 'static) -> Self {
+        Self {
+            encoder: Some(Arc::new(encoder)),
+            ..self
+        }
+    }
+
+    /// Encode and write `record`, see [`BatchProducer::produce`].
+    ///
+    /// Returns the offset of the record. Records that cannot be encoded, by the codecs or the
+    /// [encoder](Self::with_encoder), are rejected with [`Error::Codec`] before they reach the producer.
+    pub async fn produce(&self, record: TypedRecord<K, V>) -> Result<i64>
+    where
+        K: Send,
+        V: Send,
+    {
+        let mut record = record
+            .encode(self.key_codec.as_ref(), self.value_codec.as_ref())
+            .map_err(|e| Error::Codec(e.into()))?;
+        if let Some(encoder) = &self.encoder {
+            record = encoder
+                .encode(record)
+                .await
+                .map_err(|e| Error::Codec(e.into()))?;
+        }
+        self.producer.produce(record).await
+    }
+
+    /// See [`BatchProducer::flush`].
+    pub async fn flush(&self) -> Result<()> {
+        self.producer.flush().await
+    }
+}
+
+impl<K, V> Debug for SerializingProducer<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerializingProducer")
+            .field("producer", &self.producer)
+            .field("key_codec", &self.key_codec)
+            .field("value_codec", &self.value_codec)
+            .field("encoder", &self.encoder)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::{
+        client::producer::{BatchProducerBuilder, MockProducerClient},
+        codec::{BytesCodec, StringCodec},
+    };
+
+    /// Fails to encode empty strings.
+    #[derive(Debug)]
+    struct NonEmptyCodec;
+
+    impl Codec<String> for NonEmptyCodec {
+        fn encode(&self, value: &String) -> Result<Vec<u8>, crate::codec::Error> {
+            if value.is_empty() {
+                return Err("empty".into());
+            }
+            StringCodec.encode(value)
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<String, crate::codec::Error> {
+            StringCodec.decode(data)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_produce() {
+        let client = Arc::new(MockProducerClient::new());
+        let producer = SerializingProducer::new(
+            BatchProducerBuilder::new_with_client(Arc::<MockProducerClient>::clone(&client))
+                .with_linger(Duration::ZERO)
+                .build(RecordAggregator::new(1024)),
+            NonEmptyCodec,
+            BytesCodec,
+        );
+
+        let offset = producer
+            .produce(TypedRecord::new(Some("k".to_owned()), b"v".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(offset, 0);
+
+        let err = producer
+            .produce(TypedRecord::new(Some(String::new()), b"v".to_vec()))
+            .await
+            .unwrap_err();
+        assert_matches!(err, Error::Codec(_));
+
+        let records = client.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key.as_deref(), Some(b"k".as_slice()));
+        assert_eq!(records[0].value.as_deref(), Some(b"v".as_slice()));
+    }
+
+    /// Prefixes values with `x`, fails for empty values.
+    #[derive(Debug)]
+    struct PrefixEncoder;
+
+    impl RecordEncoder for PrefixEncoder {
+        fn encode(
+            &self,
+            mut record: crate::record::Record,
+        ) -> futures::future::BoxFuture<'_, Result<crate::record::Record, crate::codec::Error>>
+        {
+            Box::pin(async move {
+                match record.value.as_deref() {
+                    Some([]) => Err("empty".into()),
+                    Some(value) => {
+                        record.value = Some([b"x", value].concat().into());
+                        Ok(record)
+                    }
+                    None => Ok(record),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_produce_with_encoder() {
+        let client = Arc::new(MockProducerClient::new());
+        let producer = SerializingProducer::new(
+            BatchProducerBuilder::new_with_client(Arc::<MockProducerClient>::clone(&client))
+                .with_linger(Duration::ZERO)
+                .build(RecordAggregator::new(1024)),
+            StringCodec,
+            BytesCodec,
+        )
+        .with_encoder(PrefixEncoder);
+
+        producer
+            .produce(TypedRecord::new(None, b"v".to_vec()))
+            .await
+            .unwrap();
+
+        let err = producer
+            .produce(TypedRecord::new(None, vec![]))
+            .await
+            .unwrap_err();
+        assert_matches!(err, Error::Codec(_));
+
+        let records = client.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].value.as_deref(), Some(b"xv".as_slice()));
+    }
+}
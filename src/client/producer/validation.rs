@@ -0,0 +1,180 @@
+//! Checks that producers apply to data before aggregating it.
+use thiserror::Error;
+
+use crate::record::Record;
+
+/// Why an [`InputValidator`] rejected data.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    #[error("Key of {size} bytes exceeds the limit of {limit} bytes")]
+    KeyTooLarge { size: usize, limit: usize },
+
+    #[error("Value of {size} bytes exceeds the limit of {limit} bytes")]
+    ValueTooLarge { size: usize, limit: usize },
+
+    #[error("Required header '{0}' is missing")]
+    MissingHeader(String),
+
+    #[error("Value of header '{0}' is not valid UTF-8")]
+    NonUtf8Header(String),
+
+    /// Rejected by a custom validator.
+    #[error("{0}")]
+    Other(String),
+}
+
+/// Checks data before a [`BatchProducer`](super::BatchProducer) passes it to its [`Aggregator`], e.g. to enforce
+/// policies for all records that an application produces.
+///
+/// Rejected data is not produced, the caller receives [`Error::Invalid`](super::Error::Invalid). Validators are called
+/// synchronously from within the producer, so they should not block.
+///
+/// [`Aggregator`]: super::aggregator::Aggregator
+pub trait InputValidator<I>: std::fmt::Debug + Send + Sync {
+    fn validate(&self, input: &I) -> Result<(), ValidationError>;
+}
+
+/// [`InputValidator`] for [`Record`]s with common rules.
+///
+/// All rules are disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct RecordValidator {
+    max_key_size: Option<usize>,
+    max_value_size: Option<usize>,
+    required_headers: Vec<String>,
+    utf8_headers: bool,
+}
+
+impl RecordValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject records with keys larger than `max_key_size` bytes.
+    pub fn with_max_key_size(self, max_key_size: usize) -> Self {
+        Self {
+            max_key_size: Some(max_key_size),
+            ..self
+        }
+    }
+
+    /// Reject records with values larger than `max_value_size` bytes.
+    pub fn with_max_value_size(self, max_value_size: usize) -> Self {
+        Self {
+            max_value_size: Some(max_value_size),
+            ..self
+        }
+    }
+
+    /// Reject records without the header `key`.
+    ///
+    /// Can be called multiple times to require several headers.
+    pub fn with_required_header(mut self, key: impl Into<String>) -> Self {
+        self.required_headers.push(key.into());
+        self
+    }
+
+    /// Reject records with header values that are not valid UTF-8.
+    pub fn with_utf8_headers(self, utf8_headers: bool) -> Self {
+        Self {
+            utf8_headers,
+            ..self
+        }
+    }
+}
+
+impl InputValidator<Record> for RecordValidator {
+    fn validate(&self, record: &Record) -> Result<(), ValidationError> {
+        let size = |data: &Option<_>| data.as_ref().map(bytes::Bytes::len).unwrap_or_default();
+        if let Some(limit) = self.max_key_size {
+            let size = size(&record.key);
+            if size > limit {
+                return Err(ValidationError::KeyTooLarge { size, limit });
+            }
+        }
+        if let Some(limit) = self.max_value_size {
+            let size = size(&record.value);
+            if size > limit {
+                return Err(ValidationError::ValueTooLarge { size, limit });
+            }
+        }
+
+        if let Some(key) = self
+            .required_headers
+            .iter()
+            .find(|key| !record.headers.contains_key(key))
+        {
+            return Err(ValidationError::MissingHeader(key.clone()));
+        }
+
+        if self.utf8_headers {
+            if let Some((key, _)) = record
+                .headers
+                .iter()
+                .find(|(_, value)| std::str::from_utf8(value).is_err())
+            {
+                return Err(ValidationError::NonUtf8Header(key.to_owned()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn test_record_validator() {
+        let record = Record {
+            key: Some(b"key".to_vec().into()),
+            value: Some(b"value".to_vec().into()),
+            headers: [("trace-id", &b"\xff"[..])].into(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        RecordValidator::new().validate(&record).unwrap();
+        RecordValidator::new()
+            .with_max_key_size(3)
+            .with_max_value_size(5)
+            .with_required_header("trace-id")
+            .validate(&record)
+            .unwrap();
+
+        assert_eq!(
+            RecordValidator::new()
+                .with_max_key_size(2)
+                .validate(&record)
+                .unwrap_err(),
+            ValidationError::KeyTooLarge { size: 3, limit: 2 }
+        );
+        assert_eq!(
+            RecordValidator::new()
+                .with_max_value_size(4)
+                .validate(&record)
+                .unwrap_err(),
+            ValidationError::ValueTooLarge { size: 5, limit: 4 }
+        );
+        assert_eq!(
+            RecordValidator::new()
+                .with_required_header("trace-id")
+                .with_required_header("tenant")
+                .validate(&record)
+                .unwrap_err(),
+            ValidationError::MissingHeader("tenant".to_owned())
+        );
+        let err = RecordValidator::new()
+            .with_utf8_headers(true)
+            .validate(&record)
+            .unwrap_err();
+        assert_eq!(err, ValidationError::NonUtf8Header("trace-id".to_owned()));
+        assert_eq!(
+            err.to_string(),
+            "Value of header 'trace-id' is not valid UTF-8"
+        );
+    }
+}
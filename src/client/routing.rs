@@ -0,0 +1,354 @@
+//! Requests that have to be sent to a specific broker, e.g. the controller or a partition leader.
+//!
+//! The connection to that broker is cached in a [`BrokerCache`] and [`retry_routed`] retries requests with backoff.
+//! When a request fails because the connection broke or the broker no longer is the right one, the cached connection
+//! is invalidated and the retry locates the broker again.
+//!
+//! New kinds of brokers only need a [`BrokerLocator`] that finds them and classifies errors, [`RoutedBroker`] does the
+//! rest.
+use std::{future::Future, ops::ControlFlow, sync::Arc};
+
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::{
+    backoff::{Backoff, BackoffConfig, ErrorOrThrottle},
+    client::error::{Error, Result},
+    connection::{
+        drop_poisoned, BrokerCache, BrokerCacheGeneration, BrokerConnection, BrokerConnector,
+        ConnectionEvent, MessengerTransport,
+    },
+    messenger::RequestError,
+};
+
+/// How [`retry_routed`] handles an error of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorAction {
+    /// Retry with the cached broker.
+    Retry,
+
+    /// Invalidate the cached broker for the given reason and retry.
+    Invalidate(&'static str),
+
+    /// Invalidate the cached broker for the given reason but fail anyway, e.g. because the caller asked not to retry
+    /// this kind of error.
+    InvalidateAndFail(&'static str),
+
+    /// Fail without retrying.
+    Fail,
+}
+
+/// [`BrokerCache`] that knows how to handle errors of the requests sent to its broker.
+pub(crate) trait Route: BrokerCache {
+    fn classify(&self, error: &Error) -> ErrorAction;
+}
+
+/// Whether `error` means that the connection to the broker is unusable.
+pub(crate) fn is_connection_broken(error: &Error) -> bool {
+    match error {
+        Error::Request { source, .. } => is_request_broken(source),
+        Error::Connection(_) => true,
+        _ => false,
+    }
+}
+
+/// Whether `error` broke the connection it was sent on, looking through errors of requests shared by several callers.
+fn is_request_broken(error: &RequestError) -> bool {
+    match error {
+        RequestError::Poisoned(_) | RequestError::IO(_) => true,
+        RequestError::Shared(e) => is_request_broken(e),
+        _ => false,
+    }
+}
+
+/// Retry `f` with `backoff` until it succeeds or fails with an error that `route` does not retry.
+///
+/// `f` reports the generation of the broker it used along with errors, so that stale errors do not invalidate a newer
+/// connection.
+pub(crate) async fn retry_routed<B, R, F, T>(
+    mut backoff: Backoff,
+    route: B,
+    request_name: &str,
+    f: R,
+) -> Result<T>
+where
+    B: Route,
+    R: (Fn() -> F) + Send + Sync,
+    F: Future<Output = Result<T, ErrorOrThrottle<(Error, Option<BrokerCacheGeneration>)>>> + Send,
+    T: Send,
+{
+    backoff
+        .retry_with_backoff(request_name, || async {
+            let (error, cache_gen) = match f().await {
+                Ok(v) => {
+                    return ControlFlow::Break(Ok(v));
+                }
+                Err(ErrorOrThrottle::Throttle(throttle)) => {
+                    return ControlFlow::Continue(ErrorOrThrottle::Throttle(throttle));
+                }
+                Err(ErrorOrThrottle::Error(e)) => e,
+            };
+
+            let action = route.classify(&error);
+            if let (
+                ErrorAction::Invalidate(reason) | ErrorAction::InvalidateAndFail(reason),
+                Some(cache_gen),
+            ) = (action, cache_gen)
+            {
+                route.invalidate(reason, cache_gen).await;
+            }
+
+            match action {
+                ErrorAction::Retry | ErrorAction::Invalidate(_) => {
+                    ControlFlow::Continue(ErrorOrThrottle::Error(error))
+                }
+                ErrorAction::InvalidateAndFail(_) | ErrorAction::Fail => {
+                    error!(
+                        e=%error,
+                        request_name,
+                        "request encountered fatal error",
+                    );
+                    ControlFlow::Break(Err(error))
+                }
+            }
+        })
+        .await
+        .map_err(Error::RetryFailed)?
+}
+
+/// Strategy to find the broker of a [`RoutedBroker`].
+pub(crate) trait BrokerLocator: Send + Sync {
+    /// Name of the broker in logs and errors, e.g. `"controller"`.
+    const NAME: &'static str;
+
+    /// ID of the broker that requests have to be sent to.
+    fn broker_id(&self, brokers: &BrokerConnector) -> impl Future<Output = Result<i32>> + Send;
+
+    /// See [`Route::classify`].
+    fn classify(&self, error: &Error) -> ErrorAction;
+}
+
+/// Cached connection to the broker that `L` locates.
+#[derive(Debug)]
+pub(crate) struct RoutedBroker<L> {
+    brokers: Arc<BrokerConnector>,
+    backoff_config: Arc<BackoffConfig>,
+    locator: L,
+
+    /// Current broker connection if any
+    current_broker: Mutex<(Option<BrokerConnection>, BrokerCacheGeneration)>,
+}
+
+impl<L: BrokerLocator> RoutedBroker<L> {
+    pub(crate) fn new(
+        brokers: Arc<BrokerConnector>,
+        backoff_config: Arc<BackoffConfig>,
+        locator: L,
+    ) -> Self {
+        Self {
+            brokers,
+            backoff_config,
+            locator,
+            current_broker: Mutex::new((None, BrokerCacheGeneration::START)),
+        }
+    }
+
+    pub(crate) fn brokers(&self) -> &Arc<BrokerConnector> {
+        &self.brokers
+    }
+
+    /// Send requests via `f` to the broker until one succeeds or fails with an error that is not retried.
+    pub(crate) async fn request<R, F, T>(&self, request_name: &str, f: R) -> Result<T>
+    where
+        R: (Fn(BrokerConnection) -> F) + Send + Sync,
+        F: Future<Output = Result<T, ErrorOrThrottle<Error>>> + Send,
+        T: Send,
+    {
+        let backoff = self.brokers.backoff(&self.backoff_config);
+        retry_routed(backoff, self, request_name, || async {
+            let (broker, gen) = self
+                .get()
+                .await
+                .map_err(|e| ErrorOrThrottle::Error((e, None)))?;
+            f(broker).await.map_err(|e| match e {
+                ErrorOrThrottle::Error(e) => ErrorOrThrottle::Error((e, Some(gen))),
+                ErrorOrThrottle::Throttle(throttle) => ErrorOrThrottle::Throttle(throttle),
+            })
+        })
+        .await
+    }
+}
+
+impl<L: BrokerLocator> BrokerCache for &RoutedBroker<L> {
+    type R = MessengerTransport;
+    type E = Error;
+
+    async fn get(&self) -> Result<(Arc<Self::R>, BrokerCacheGeneration)> {
+        let mut current_broker = self.current_broker.lock().await;
+        drop_poisoned(&mut current_broker.0);
+        if let Some(broker) = &current_broker.0 {
+            return Ok((Arc::clone(broker), current_broker.1));
+        }
+
+        info!(broker = L::NAME, "Creating new broker connection");
+
+        let broker_id = self.locator.broker_id(&self.brokers).await?;
+        let broker = self.brokers.connect(broker_id).await?.ok_or_else(|| {
+            Error::InvalidResponse(format!(
+                "{} {} not found in metadata response",
+                L::NAME,
+                broker_id
+            ))
+        })?;
+
+        current_broker.0 = Some(Arc::clone(&broker));
+        current_broker.1.bump();
+
+        Ok((broker, current_broker.1))
+    }
+
+    async fn invalidate(&self, reason: &'static str, gen: BrokerCacheGeneration) {
+        let mut guard = self.current_broker.lock().await;
+
+        if guard.1 != gen {
+            // stale request
+            debug!(
+                reason,
+                broker = L::NAME,
+                current_gen = guard.1.get(),
+                request_gen = gen.get(),
+                "stale invalidation request for broker cache",
+            );
+            return;
+        }
+
+        info!(reason, broker = L::NAME, "Invalidating cached broker");
+        if let Some(broker) = guard.0.take() {
+            broker.report_event(&ConnectionEvent::Invalidated { reason });
+        }
+    }
+}
+
+impl<L: BrokerLocator> Route for &RoutedBroker<L> {
+    fn classify(&self, error: &Error) -> ErrorAction {
+        self.locator.classify(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, sync::Mutex as StdMutex, time::Duration};
+
+    use assert_matches::assert_matches;
+
+    use super::*;
+    use crate::protocol::error::Error as ProtocolError;
+
+    /// Route to a broker that is never connected, errors are classified by their protocol error.
+    #[derive(Default)]
+    struct FakeRoute {
+        invalidated: StdMutex<Vec<&'static str>>,
+    }
+
+    impl BrokerCache for &FakeRoute {
+        type R = MessengerTransport;
+        type E = Error;
+
+        async fn get(&self) -> Result<(Arc<Self::R>, BrokerCacheGeneration)> {
+            unreachable!("requests do not use the cache")
+        }
+
+        async fn invalidate(&self, reason: &'static str, _gen: BrokerCacheGeneration) {
+            self.invalidated.lock().unwrap().push(reason);
+        }
+    }
+
+    impl Route for &FakeRoute {
+        fn classify(&self, error: &Error) -> ErrorAction {
+            match error {
+                Error::ServerError {
+                    protocol_error: ProtocolError::LeaderNotAvailable,
+                    ..
+                } => ErrorAction::Retry,
+                Error::ServerError {
+                    protocol_error: ProtocolError::NotController,
+                    ..
+                } => ErrorAction::Invalidate("moved"),
+                Error::ServerError {
+                    protocol_error: ProtocolError::UnknownTopicOrPartition,
+                    ..
+                } => ErrorAction::InvalidateAndFail("unknown"),
+                _ => ErrorAction::Fail,
+            }
+        }
+    }
+
+    fn server_error(protocol_error: ProtocolError) -> Error {
+        Error::ServerError {
+            protocol_error,
+            error_message: None,
+            request: crate::client::error::RequestContext::Topic("foo".to_owned()),
+            response: None,
+            is_virtual: false,
+            info: None,
+        }
+    }
+
+    /// Run a request whose attempts fail with `errors` in turn and then succeed.
+    async fn run(route: &FakeRoute, errors: Vec<ProtocolError>) -> (Result<()>, usize) {
+        let backoff = Backoff::new(&BackoffConfig {
+            init_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            ..Default::default()
+        });
+        let errors = StdMutex::new(VecDeque::from(errors));
+        let attempts = StdMutex::new(0);
+        let res = retry_routed(backoff, route, "test", || async {
+            *attempts.lock().unwrap() += 1;
+            match errors.lock().unwrap().pop_front() {
+                Some(e) => Err(ErrorOrThrottle::Error((
+                    server_error(e),
+                    Some(BrokerCacheGeneration::START),
+                ))),
+                None => Ok(()),
+            }
+        })
+        .await;
+        let attempts = *attempts.lock().unwrap();
+        (res, attempts)
+    }
+
+    #[tokio::test]
+    async fn test_retry_routed() {
+        let route = FakeRoute::default();
+        let (res, attempts) = run(
+            &route,
+            vec![
+                ProtocolError::LeaderNotAvailable,
+                ProtocolError::NotController,
+            ],
+        )
+        .await;
+        res.unwrap();
+        assert_eq!(attempts, 3);
+        assert_eq!(*route.invalidated.lock().unwrap(), ["moved"]);
+
+        let route = FakeRoute::default();
+        let (res, attempts) = run(&route, vec![ProtocolError::UnknownTopicOrPartition]).await;
+        assert_matches!(
+            res,
+            Err(Error::ServerError {
+                protocol_error: ProtocolError::UnknownTopicOrPartition,
+                ..
+            })
+        );
+        assert_eq!(attempts, 1);
+        assert_eq!(*route.invalidated.lock().unwrap(), ["unknown"]);
+
+        let route = FakeRoute::default();
+        let (res, attempts) = run(&route, vec![ProtocolError::InvalidRequest]).await;
+        assert_matches!(res, Err(Error::ServerError { .. }));
+        assert_eq!(attempts, 1);
+        assert!(route.invalidated.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,661 @@
+//! Push of client metrics to brokers, see [KIP-714].
+//!
+//! Brokers that have a client metrics subscription configured tell the client which metrics to send and how often.
+//! The metrics are encoded as OpenTelemetry `MetricsData` protobuf, which is small enough to be written by hand here.
+//!
+//! [KIP-714]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-714%3A+Client+metrics+and+observability
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use rand::prelude::*;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::{
+    build_info::DEFAULT_CLIENT_ID,
+    connection::{BrokerCache, BrokerConnector},
+    messenger::RequestError,
+    metrics::{FetchBatch, Metrics, ProduceBatch, RequestEnd, Retry, Throttle},
+    protocol::{
+        error::Error as ProtocolError,
+        messages::{GetTelemetrySubscriptionsRequest, PushTelemetryRequest},
+        primitives::{Boolean, CompactBytes, Int32, Int8, Uuid},
+    },
+    runtime,
+};
+
+/// Push interval that is used if the broker does not provide a valid one and after failed requests.
+const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Prefix of all metric names.
+const METRIC_PREFIX: &str = "org.apache.kafka.";
+
+/// Aggregates client events into the metrics that are pushed to the brokers.
+#[derive(Debug)]
+pub(crate) struct TelemetryCollector {
+    start_time: SystemTime,
+    connections_created: AtomicU64,
+    connections_active: AtomicI64,
+    requests: AtomicU64,
+    request_errors: AtomicU64,
+    request_latency: Mutex<LatencyStats>,
+    retries: AtomicU64,
+    throttle_time_max_ms: AtomicU64,
+    records_produced: AtomicU64,
+    bytes_produced: AtomicU64,
+    records_fetched: AtomicU64,
+    bytes_fetched: AtomicU64,
+}
+
+/// Request latency since the last push.
+#[derive(Debug, Default, Clone, Copy)]
+struct LatencyStats {
+    sum: Duration,
+    count: u32,
+    max: Duration,
+}
+
+impl TelemetryCollector {
+    pub(crate) fn new() -> Self {
+        Self {
+            start_time: SystemTime::now(),
+            connections_created: AtomicU64::new(0),
+            connections_active: AtomicI64::new(0),
+            requests: AtomicU64::new(0),
+            request_errors: AtomicU64::new(0),
+            request_latency: Mutex::new(LatencyStats::default()),
+            retries: AtomicU64::new(0),
+            throttle_time_max_ms: AtomicU64::new(0),
+            records_produced: AtomicU64::new(0),
+            bytes_produced: AtomicU64::new(0),
+            records_fetched: AtomicU64::new(0),
+            bytes_fetched: AtomicU64::new(0),
+        }
+    }
+
+    /// Current values of all metrics.
+    ///
+    /// Sums are cumulative since the collector was created, gauges that describe an interval are reset.
+    fn snapshot(&self) -> Vec<Metric> {
+        let latency = std::mem::take(&mut *self.request_latency.lock());
+        let latency_avg = if latency.count == 0 {
+            Duration::ZERO
+        } else {
+            latency.sum / latency.count
+        };
+
+        let sum = |name, unit, value: &AtomicU64| Metric {
+            name,
+            unit,
+            value: MetricValue::Sum(value.load(Ordering::Relaxed)),
+        };
+        let gauge = |name, unit, value: f64| Metric {
+            name,
+            unit,
+            value: MetricValue::Gauge(value),
+        };
+
+        vec![
+            sum(
+                "client.connection.creation.total",
+                "",
+                &self.connections_created,
+            ),
+            gauge(
+                "client.connection.count",
+                "",
+                self.connections_active.load(Ordering::Relaxed) as f64,
+            ),
+            sum("client.request.total", "", &self.requests),
+            sum("client.request.error.total", "", &self.request_errors),
+            gauge(
+                "client.request.latency.avg",
+                "ms",
+                latency_avg.as_secs_f64() * 1000.0,
+            ),
+            gauge(
+                "client.request.latency.max",
+                "ms",
+                latency.max.as_secs_f64() * 1000.0,
+            ),
+            sum("client.retry.total", "", &self.retries),
+            gauge(
+                "client.throttle.time.max",
+                "ms",
+                self.throttle_time_max_ms.swap(0, Ordering::Relaxed) as f64,
+            ),
+            sum("producer.record.send.total", "", &self.records_produced),
+            sum("producer.byte.total", "By", &self.bytes_produced),
+            sum(
+                "consumer.fetch.manager.records.consumed.total",
+                "",
+                &self.records_fetched,
+            ),
+            sum(
+                "consumer.fetch.manager.bytes.consumed.total",
+                "By",
+                &self.bytes_fetched,
+            ),
+        ]
+    }
+}
+
+impl Metrics for TelemetryCollector {
+    fn request_completed(&self, request: &RequestEnd) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if request.error.is_some() {
+            self.request_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut latency = self.request_latency.lock();
+        latency.sum += request.duration;
+        latency.count += 1;
+        latency.max = latency.max.max(request.duration);
+    }
+
+    fn connection_opened(&self, _broker: &str) {
+        self.connections_created.fetch_add(1, Ordering::Relaxed);
+        self.connections_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn connection_closed(&self, _broker: &str) {
+        self.connections_active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn produce_batch(&self, batch: &ProduceBatch<'_>) {
+        self.records_produced
+            .fetch_add(batch.records as u64, Ordering::Relaxed);
+        self.bytes_produced
+            .fetch_add(batch.bytes as u64, Ordering::Relaxed);
+    }
+
+    fn fetch_batch(&self, batch: &FetchBatch<'_>) {
+        self.records_fetched
+            .fetch_add(batch.records as u64, Ordering::Relaxed);
+        self.bytes_fetched
+            .fetch_add(batch.bytes as u64, Ordering::Relaxed);
+    }
+
+    fn retry(&self, _retry: &Retry<'_>) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn throttled(&self, throttle: &Throttle<'_>) {
+        self.throttle_time_max_ms
+            .fetch_max(throttle.duration.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Metric {
+    /// Name without [`METRIC_PREFIX`].
+    name: &'static str,
+
+    /// Unit as defined by [UCUM](https://ucum.org/ucum), empty for counts.
+    unit: &'static str,
+
+    value: MetricValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MetricValue {
+    /// Monotonic sum, cumulative or delta depending on the subscription.
+    Sum(u64),
+
+    Gauge(f64),
+}
+
+/// Subscription that the broker assigned to this client.
+#[derive(Debug)]
+struct Subscription {
+    id: i32,
+    push_interval: Duration,
+    max_bytes: usize,
+    delta_temporality: bool,
+    requested_metrics: Vec<String>,
+}
+
+impl Subscription {
+    fn is_requested(&self, metric: &Metric) -> bool {
+        self.requested_metrics.iter().any(|prefix| {
+            prefix.is_empty()
+                || prefix == "*"
+                || format!("{METRIC_PREFIX}{}", metric.name).starts_with(prefix.as_str())
+        })
+    }
+}
+
+/// What the push loop should do next.
+#[derive(Debug, PartialEq)]
+enum Next {
+    Wait(Duration),
+    Stop,
+}
+
+/// State of the push loop.
+#[derive(Debug)]
+struct Telemetry {
+    collector: Arc<TelemetryCollector>,
+
+    /// Assigned by the broker on the first subscription request.
+    client_instance_id: Uuid,
+
+    subscription: Option<Subscription>,
+
+    /// Sums and time of the last push, for delta temporality.
+    last_push: Option<(HashMap<&'static str, u64>, SystemTime)>,
+}
+
+impl Telemetry {
+    fn new(collector: Arc<TelemetryCollector>) -> Self {
+        Self {
+            collector,
+            client_instance_id: Uuid::default(),
+            subscription: None,
+            last_push: None,
+        }
+    }
+
+    async fn step(&mut self, brokers: &BrokerConnector) -> Next {
+        let result = match self.subscription {
+            None => self.subscribe(brokers).await,
+            Some(_) => self.push(brokers).await,
+        };
+
+        match result {
+            Ok(next) => next,
+            Err(StepError::Request(RequestError::NoVersionMatch { .. })) => {
+                info!("Broker does not support client telemetry, stop pushing metrics");
+                Next::Stop
+            }
+            Err(e) => {
+                warn!(%e, "Client telemetry request failed");
+                Next::Wait(DEFAULT_PUSH_INTERVAL)
+            }
+        }
+    }
+
+    async fn subscribe(&mut self, brokers: &BrokerConnector) -> Result<Next, StepError> {
+        let (broker, gen) = BrokerCache::get(&brokers).await?;
+        let response = match broker
+            .request(GetTelemetrySubscriptionsRequest {
+                client_instance_id: self.client_instance_id,
+                tagged_fields: None,
+            })
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                BrokerCache::invalidate(&brokers, "client telemetry: request failed", gen).await;
+                return Err(e.into());
+            }
+        };
+        if let Some(e) = response.error_code {
+            return Err(StepError::Server(e));
+        }
+
+        self.client_instance_id = response.client_instance_id;
+        let push_interval = match response.push_interval_ms.0 {
+            ms if ms > 0 => Duration::from_millis(ms as u64),
+            _ => DEFAULT_PUSH_INTERVAL,
+        };
+        let requested_metrics: Vec<String> = response
+            .requested_metrics
+            .into_iter()
+            .map(|s| s.0)
+            .collect();
+
+        // subscriptions may change, so ask again later even if nothing was requested
+        if requested_metrics.is_empty() {
+            debug!("No client metrics requested by broker");
+            return Ok(Next::Wait(push_interval));
+        }
+
+        debug!(
+            subscription_id = response.subscription_id.0,
+            ?push_interval,
+            ?requested_metrics,
+            "Subscribed to client telemetry",
+        );
+        self.subscription = Some(Subscription {
+            id: response.subscription_id.0,
+            push_interval,
+            max_bytes: response.telemetry_max_bytes.0.max(0) as usize,
+            delta_temporality: response.delta_temporality.0,
+            requested_metrics,
+        });
+        self.last_push = None;
+
+        // spread the first push of many clients that started at the same time
+        Ok(Next::Wait(
+            push_interval.mul_f64(thread_rng().gen_range(0.5..1.5)),
+        ))
+    }
+
+    async fn push(&mut self, brokers: &BrokerConnector) -> Result<Next, StepError> {
+        let subscription = self.subscription.as_ref().expect("subscribed");
+        let delta_temporality = subscription.delta_temporality;
+        let now = SystemTime::now();
+        let snapshot = self.collector.snapshot();
+        let sums: HashMap<_, _> = snapshot
+            .iter()
+            .filter_map(|m| match m.value {
+                MetricValue::Sum(v) => Some((m.name, v)),
+                MetricValue::Gauge(_) => None,
+            })
+            .collect();
+
+        let last_push = self.last_push.as_ref().filter(|_| delta_temporality);
+        let start_time = last_push.map_or(self.collector.start_time, |(_, time)| *time);
+        let metrics: Vec<_> = snapshot
+            .into_iter()
+            .filter(|m| subscription.is_requested(m))
+            .map(|m| match (m.value, last_push) {
+                (MetricValue::Sum(v), Some((last, _))) => Metric {
+                    value: MetricValue::Sum(
+                        v.saturating_sub(last.get(m.name).copied().unwrap_or(0)),
+                    ),
+                    ..m
+                },
+                _ => m,
+            })
+            .collect();
+
+        let payload = encode_metrics_data(&metrics, start_time, now, delta_temporality);
+        if subscription.max_bytes > 0 && payload.len() > subscription.max_bytes {
+            warn!(
+                size = payload.len(),
+                max_size = subscription.max_bytes,
+                "Client metrics exceed the size that the broker accepts, skip push",
+            );
+            return Ok(Next::Wait(subscription.push_interval));
+        }
+
+        let (broker, gen) = BrokerCache::get(&brokers).await?;
+        let response = match broker
+            .request(PushTelemetryRequest {
+                client_instance_id: self.client_instance_id,
+                subscription_id: Int32(subscription.id),
+                terminating: Boolean(false),
+                // uncompressed is always accepted
+                compression_type: Int8(0),
+                metrics: CompactBytes(payload),
+                tagged_fields: None,
+            })
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                BrokerCache::invalidate(&brokers, "client telemetry: request failed", gen).await;
+                return Err(e.into());
+            }
+        };
+
+        match response.error_code {
+            None => {
+                let push_interval = subscription.push_interval;
+                self.last_push = Some((sums, now));
+                Ok(Next::Wait(push_interval))
+            }
+            Some(
+                e @ (ProtocolError::UnknownSubscriptionId
+                | ProtocolError::UnsupportedCompressionType),
+            ) => {
+                debug!(%e, "Client telemetry subscription changed, subscribe again");
+                self.subscription = None;
+                Ok(Next::Wait(Duration::ZERO))
+            }
+            Some(e) => Err(StepError::Server(e)),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum StepError {
+    #[error(transparent)]
+    Connection(#[from] crate::connection::Error),
+
+    #[error(transparent)]
+    Request(#[from] RequestError),
+
+    #[error("Server error: {0}")]
+    Server(ProtocolError),
+}
+
+/// Push client metrics to the brokers until the client is closed or dropped.
+pub(crate) async fn push_telemetry_periodically(
+    brokers: Weak<BrokerConnector>,
+    collector: Arc<TelemetryCollector>,
+) {
+    let Some(mut closed) = brokers.upgrade().map(|b| b.closed()) else {
+        return;
+    };
+
+    let mut telemetry = Telemetry::new(collector);
+    let mut wait = Duration::ZERO;
+    loop {
+        tokio::select! {
+            _ = runtime::sleep(wait) => {}
+            _ = closed.wait_for(|closed| *closed) => {
+                return;
+            }
+        }
+
+        let Some(brokers) = brokers.upgrade() else {
+            return;
+        };
+        match telemetry.step(&brokers).await {
+            Next::Wait(next) => wait = next,
+            Next::Stop => return,
+        }
+    }
+}
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_I64: u64 = 1;
+const WIRE_TYPE_LEN: u64 = 2;
+
+/// Encode OpenTelemetry `MetricsData`, see
+/// <https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/metrics/v1/metrics.proto>.
+fn encode_metrics_data(
+    metrics: &[Metric],
+    start_time: SystemTime,
+    time: SystemTime,
+    delta_temporality: bool,
+) -> Vec<u8> {
+    let start_time = unix_nanos(start_time);
+    let time = unix_nanos(time);
+
+    let mut scope = vec![];
+    put_len(&mut scope, 1, DEFAULT_CLIENT_ID.as_bytes());
+    put_len(&mut scope, 2, env!("CARGO_PKG_VERSION").as_bytes());
+
+    let mut scope_metrics = vec![];
+    put_len(&mut scope_metrics, 1, &scope);
+    for metric in metrics {
+        put_len(
+            &mut scope_metrics,
+            2,
+            &encode_metric(metric, start_time, time, delta_temporality),
+        );
+    }
+
+    let mut resource_metrics = vec![];
+    put_len(&mut resource_metrics, 2, &scope_metrics);
+
+    let mut metrics_data = vec![];
+    put_len(&mut metrics_data, 1, &resource_metrics);
+    metrics_data
+}
+
+fn encode_metric(metric: &Metric, start_time: u64, time: u64, delta_temporality: bool) -> Vec<u8> {
+    let mut data_point = vec![];
+    put_i64(&mut data_point, 2, start_time);
+    put_i64(&mut data_point, 3, time);
+
+    let mut buf = vec![];
+    put_len(
+        &mut buf,
+        1,
+        format!("{METRIC_PREFIX}{}", metric.name).as_bytes(),
+    );
+    if !metric.unit.is_empty() {
+        put_len(&mut buf, 3, metric.unit.as_bytes());
+    }
+
+    match metric.value {
+        MetricValue::Gauge(v) => {
+            put_i64(&mut data_point, 4, v.to_bits());
+
+            let mut gauge = vec![];
+            put_len(&mut gauge, 1, &data_point);
+            put_len(&mut buf, 5, &gauge);
+        }
+        MetricValue::Sum(v) => {
+            put_i64(&mut data_point, 6, v);
+
+            let mut sum = vec![];
+            put_len(&mut sum, 1, &data_point);
+            // AGGREGATION_TEMPORALITY_DELTA = 1, AGGREGATION_TEMPORALITY_CUMULATIVE = 2
+            put_varint_field(&mut sum, 2, if delta_temporality { 1 } else { 2 });
+            // is_monotonic
+            put_varint_field(&mut sum, 3, 1);
+            put_len(&mut buf, 7, &sum);
+        }
+    }
+
+    buf
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn put_key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    put_varint(buf, (field << 3) | wire_type);
+}
+
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, v: u64) {
+    put_key(buf, field, WIRE_TYPE_VARINT);
+    put_varint(buf, v);
+}
+
+fn put_i64(buf: &mut Vec<u8>, field: u64, v: u64) {
+    put_key(buf, field, WIRE_TYPE_I64);
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_len(buf: &mut Vec<u8>, field: u64, data: &[u8]) {
+    put_key(buf, field, WIRE_TYPE_LEN);
+    put_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_is_requested() {
+        let metric = Metric {
+            name: "producer.record.send.total",
+            unit: "",
+            value: MetricValue::Sum(1),
+        };
+        let subscription = |requested_metrics: &[&str]| Subscription {
+            id: 1,
+            push_interval: DEFAULT_PUSH_INTERVAL,
+            max_bytes: 0,
+            delta_temporality: false,
+            requested_metrics: requested_metrics.iter().map(|s| s.to_string()).collect(),
+        };
+
+        assert!(!subscription(&[]).is_requested(&metric));
+        assert!(subscription(&["*"]).is_requested(&metric));
+        assert!(subscription(&[""]).is_requested(&metric));
+        assert!(subscription(&["org.apache.kafka.producer."]).is_requested(&metric));
+        assert!(!subscription(&["org.apache.kafka.consumer."]).is_requested(&metric));
+    }
+
+    #[test]
+    fn test_encode_metric() {
+        let mut buf = vec![];
+        put_varint(&mut buf, 300);
+        assert_eq!(buf, [0xac, 0x02]);
+
+        let metric = Metric {
+            name: "a",
+            unit: "",
+            value: MetricValue::Sum(5),
+        };
+        let mut expected = vec![0x0a, 0x12];
+        expected.extend_from_slice(b"org.apache.kafka.a");
+        expected.extend_from_slice(&[0x3a, 0x21, 0x0a, 0x1b]);
+        expected.extend_from_slice(&[0x11, 1, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x19, 2, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x31, 5, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(&[0x10, 0x02, 0x18, 0x01]);
+        assert_eq!(encode_metric(&metric, 1, 2, false), expected);
+    }
+
+    #[test]
+    fn test_collector_snapshot() {
+        let collector = TelemetryCollector::new();
+        collector.connection_opened("b1");
+        collector.connection_opened("b2");
+        collector.connection_closed("b1");
+        collector.throttled(&Throttle {
+            request_name: "produce",
+            duration: Duration::from_millis(100),
+        });
+
+        let value = |metrics: &[Metric], name| {
+            metrics
+                .iter()
+                .find(|m| m.name == name)
+                .map(|m| m.value)
+                .unwrap()
+        };
+        let metrics = collector.snapshot();
+        assert_eq!(
+            value(&metrics, "client.connection.creation.total"),
+            MetricValue::Sum(2)
+        );
+        assert_eq!(
+            value(&metrics, "client.connection.count"),
+            MetricValue::Gauge(1.0)
+        );
+        assert_eq!(
+            value(&metrics, "client.throttle.time.max"),
+            MetricValue::Gauge(100.0)
+        );
+
+        // interval gauges are reset, sums are cumulative
+        let metrics = collector.snapshot();
+        assert_eq!(
+            value(&metrics, "client.connection.creation.total"),
+            MetricValue::Sum(2)
+        );
+        assert_eq!(
+            value(&metrics, "client.throttle.time.max"),
+            MetricValue::Gauge(0.0)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Watch topics for partition and leadership changes, see [`Client::watch_topic`](super::Client::watch_topic).
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::Pin,
+    sync::{Arc, Weak},
+    task::{Context, Poll},
+};
+
+use futures::{stream::BoxStream, Stream, StreamExt};
+use tokio::sync::watch;
+
+use crate::connection::BrokerConnector;
+
+/// Change of a watched topic, see [`TopicWatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TopicEvent {
+    /// The number of partitions changed.
+    ///
+    /// This is also emitted when the topic is created (`old` is 0) or deleted (`new` is 0).
+    PartitionCountChanged { old: usize, new: usize },
+
+    /// The leader of an existing partition changed.
+    ///
+    /// The leader is `None` while the partition has no leader, e.g. because the broker is unavailable.
+    LeaderChanged {
+        partition: i32,
+        old: Option<i32>,
+        new: Option<i32>,
+    },
+}
+
+/// Stream of [`TopicEvent`]s for a single topic.
+///
+/// Changes are detected whenever the client refreshes its cached metadata: periodically if
+/// [`metadata_refresh_interval`](super::ClientBuilder::metadata_refresh_interval) is set, after errors that indicate
+/// stale metadata and when listing topics. Changes between two refreshes are coalesced, e.g. a leader that moves away
+/// and back is not reported.
+///
+/// The stream ends when the client is closed or dropped.
+pub struct TopicWatcher {
+    inner: BoxStream<'static, TopicEvent>,
+}
+
+struct State {
+    brokers: Weak<BrokerConnector>,
+    topic: String,
+    updates: watch::Receiver<()>,
+    closed: watch::Receiver<bool>,
+    leaders: BTreeMap<i32, Option<i32>>,
+    pending: VecDeque<TopicEvent>,
+}
+
+impl TopicWatcher {
+    /// Watch `topic` for changes relative to the currently cached metadata.
+    pub(crate) fn new(
+        brokers: &Arc<BrokerConnector>,
+        topic: String,
+        mut updates: watch::Receiver<()>,
+    ) -> Self {
+        updates.mark_unchanged();
+        let state = State {
+            brokers: Arc::downgrade(brokers),
+            leaders: leaders(brokers, &topic).unwrap_or_default(),
+            topic,
+            updates,
+            closed: brokers.closed(),
+            pending: VecDeque::new(),
+        };
+
+        let inner = futures::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((event, state));
+                }
+
+                tokio::select! {
+                    res = state.updates.changed() => {
+                        if res.is_err() {
+                            return None;
+                        }
+                    }
+                    _ = state.closed.wait_for(|closed| *closed) => {
+                        return None;
+                    }
+                }
+
+                let brokers = state.brokers.upgrade()?;
+                let Some(leaders) = leaders(&brokers, &state.topic) else {
+                    continue;
+                };
+                state.pending.extend(diff(&state.leaders, &leaders));
+                state.leaders = leaders;
+            }
+        });
+
+        Self {
+            inner: inner.boxed(),
+        }
+    }
+}
+
+impl Stream for TopicWatcher {
+    type Item = TopicEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+impl std::fmt::Debug for TopicWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TopicWatcher").finish_non_exhaustive()
+    }
+}
+
+/// Leader of every partition of `topic` according to the cached metadata, if any.
+fn leaders(brokers: &BrokerConnector, topic: &str) -> Option<BTreeMap<i32, Option<i32>>> {
+    Some(
+        brokers
+            .cached_partition_leaders(topic)?
+            .into_iter()
+            .map(|(partition, leader)| (partition, (leader >= 0).then_some(leader)))
+            .collect(),
+    )
+}
+
+fn diff(old: &BTreeMap<i32, Option<i32>>, new: &BTreeMap<i32, Option<i32>>) -> Vec<TopicEvent> {
+    let mut events = vec![];
+    if old.len() != new.len() {
+        events.push(TopicEvent::PartitionCountChanged {
+            old: old.len(),
+            new: new.len(),
+        });
+    }
+
+    events.extend(new.iter().filter_map(|(partition, new)| {
+        let old = old.get(partition)?;
+        (old != new).then_some(TopicEvent::LeaderChanged {
+            partition: *partition,
+            old: *old,
+            new: *new,
+        })
+    }));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let empty = BTreeMap::new();
+        let one = BTreeMap::from([(0, Some(1))]);
+        let two = BTreeMap::from([(0, Some(2)), (1, None)]);
+
+        assert_eq!(diff(&one, &one), vec![]);
+        assert_eq!(
+            diff(&empty, &one),
+            vec![TopicEvent::PartitionCountChanged { old: 0, new: 1 }]
+        );
+        assert_eq!(
+            diff(&one, &two),
+            vec![
+                TopicEvent::PartitionCountChanged { old: 1, new: 2 },
+                TopicEvent::LeaderChanged {
+                    partition: 0,
+                    old: Some(1),
+                    new: Some(2),
+                },
+            ]
+        );
+        assert_eq!(
+            diff(&two, &empty),
+            vec![TopicEvent::PartitionCountChanged { old: 2, new: 0 }]
+        );
+    }
+}
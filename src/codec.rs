@@ -0,0 +1,259 @@
+//! Conversion between typed keys / values and the bytes of a [`Record`].
+//!
+//! A [`Codec`] encodes and decodes a single type. [`TypedRecord`] uses one codec for the key and one for the value and
+//! is what [`SerializingProducer`](crate::client::producer::SerializingProducer) and
+//! [`DeserializingConsumer`](crate::client::consumer::DeserializingConsumer) work with. Records that were fetched
+//! directly via [`PartitionClient`](crate::client::partition::PartitionClient) can be converted via
+//! [`TypedRecord::decode`].
+//!
+//! With the `serde-json` and `serde-bincode` features, [serde] types can be encoded as JSON and [bincode]
+//! respectively.
+//!
+//! Encodings that need I/O, like looking up schema IDs in a [schema registry](schema_registry), are implemented as
+//! [`RecordEncoder`]s, which transform records after the codecs.
+//!
+//! [bincode]: https://docs.rs/bincode
+//! [serde]: https://serde.rs
+use std::fmt::Debug;
+
+#[cfg(feature = "serde-bincode")]
+use bincode::Options;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+
+use crate::record::{utc_now, Headers, Record, RecordAndOffset};
+
+pub mod schema_registry;
+
+/// The error returned by [`Codec`] implementations.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Encodes values of type `T` to bytes and decodes them again.
+pub trait Codec<T>: Debug + Send + Sync {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error>;
+
+    fn decode(&self, data: &[u8]) -> Result<T, Error>;
+}
+
+/// Asynchronous, per-record transformation of encoded records, see
+/// [`SerializingProducer::with_encoder`](crate::client::producer::SerializingProducer::with_encoder).
+pub trait RecordEncoder: Debug + Send + Sync {
+    fn encode(&self, record: Record) -> BoxFuture<'_, Result<Record, Error>>;
+}
+
+/// [`Codec`] that passes bytes through unchanged.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BytesCodec;
+
+impl Codec<Vec<u8>> for BytesCodec {
+    fn encode(&self, value: &Vec<u8>) -> Result<Vec<u8>, Error> {
+        Ok(value.clone())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(data.to_vec())
+    }
+}
+
+/// [`Codec`] for UTF-8 strings.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StringCodec;
+
+impl Codec<String> for StringCodec {
+    fn encode(&self, value: &String) -> Result<Vec<u8>, Error> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<String, Error> {
+        Ok(std::str::from_utf8(data)?.to_owned())
+    }
+}
+
+/// [`Codec`] that encodes [serde](https://serde.rs) types as JSON.
+#[cfg(feature = "serde-json")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JsonCodec;
+
+#[cfg(feature = "serde-json")]
+impl<T> Codec<T> for JsonCodec
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T, Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+}
+
+/// [`Codec`] that encodes [serde](https://serde.rs) types with the default [bincode](https://docs.rs/bincode) options,
+/// i.e. variable-length integers in little endian.
+///
+/// Decoding fails if there are bytes left after the value.
+#[cfg(feature = "serde-bincode")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "serde-bincode")]
+impl<T> Codec<T> for BincodeCodec
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    fn encode(&self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(bincode::DefaultOptions::new().serialize(value)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<T, Error> {
+        Ok(bincode::DefaultOptions::new().deserialize(data)?)
+    }
+}
+
+/// [`Record`] with typed key and value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedRecord<K, V> {
+    pub key: Option<K>,
+    pub value: Option<V>,
+    pub headers: Headers,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl<K, V> TypedRecord<K, V> {
+    /// Create a record with the given key and value, no headers and the current time as timestamp.
+    pub fn new(key: Option<K>, value: V) -> Self {
+        Self {
+            key,
+            value: Some(value),
+            headers: Headers::new(),
+            timestamp: utc_now(),
+        }
+    }
+
+    /// Encode key and value.
+    pub fn encode(
+        &self,
+        key_codec: &(impl Codec<K> + ?Sized),
+        value_codec: &(impl Codec<V> + ?Sized),
+    ) -> Result<Record, Error> {
+        Ok(Record {
+            key: self
+                .key
+                .as_ref()
+                .map(|k| key_codec.encode(k).map(Bytes::from))
+                .transpose()?,
+            value: self
+                .value
+                .as_ref()
+                .map(|v| value_codec.encode(v).map(Bytes::from))
+                .transpose()?,
+            headers: self.headers.clone(),
+            timestamp: self.timestamp,
+        })
+    }
+
+    /// Decode key and value of `record`.
+    pub fn decode(
+        record: &Record,
+        key_codec: &(impl Codec<K> + ?Sized),
+        value_codec: &(impl Codec<V> + ?Sized),
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            key: record
+                .key
+                .as_ref()
+                .map(|k| key_codec.decode(k))
+                .transpose()?,
+            value: record
+                .value
+                .as_ref()
+                .map(|v| value_codec.decode(v))
+                .transpose()?,
+            headers: record.headers.clone(),
+            timestamp: record.timestamp,
+        })
+    }
+}
+
+/// [`TypedRecord`] that has offset information attached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedRecordAndOffset<K, V> {
+    pub record: TypedRecord<K, V>,
+    pub offset: i64,
+}
+
+impl<K, V> TypedRecordAndOffset<K, V> {
+    /// Decode key and value of `record`.
+    pub fn decode(
+        record: &RecordAndOffset,
+        key_codec: &(impl Codec<K> + ?Sized),
+        value_codec: &(impl Codec<V> + ?Sized),
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            record: TypedRecord::decode(&record.record, key_codec, value_codec)?,
+            offset: record.offset,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde-json")]
+    use std::collections::BTreeMap;
+
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_typed_record_roundtrip() {
+        let record = TypedRecord {
+            key: Some("k".to_owned()),
+            value: Some(b"v".to_vec()),
+            headers: Headers::from([("h", "x")]),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+
+        let encoded = record.encode(&StringCodec, &BytesCodec).unwrap();
+        assert_eq!(encoded.key.as_deref(), Some(b"k".as_slice()));
+        assert_eq!(encoded.value.as_deref(), Some(b"v".as_slice()));
+        assert_eq!(encoded.headers, record.headers);
+        assert_eq!(encoded.timestamp, record.timestamp);
+
+        let decoded = TypedRecord::decode(&encoded, &StringCodec, &BytesCodec).unwrap();
+        assert_eq!(decoded, record);
+    }
+
+    #[test]
+    fn test_string_codec_invalid_utf8() {
+        assert!(Codec::<String>::decode(&StringCodec, &[0xff]).is_err());
+    }
+
+    #[cfg(feature = "serde-json")]
+    #[test]
+    fn test_json_codec() {
+        let value = BTreeMap::from([("a".to_owned(), 1u32)]);
+        let data = JsonCodec.encode(&value).unwrap();
+        assert_eq!(data, br#"{"a":1}"#);
+        assert_eq!(
+            Codec::<BTreeMap<String, u32>>::decode(&JsonCodec, &data).unwrap(),
+            value
+        );
+    }
+
+    #[cfg(feature = "serde-bincode")]
+    #[test]
+    fn test_bincode_codec() {
+        let value = (1u32, "foo".to_owned());
+        let data = BincodeCodec.encode(&value).unwrap();
+        assert_eq!(
+            Codec::<(u32, String)>::decode(&BincodeCodec, &data).unwrap(),
+            value
+        );
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(Codec::<(u32, String)>::decode(&BincodeCodec, &trailing).is_err());
+    }
+}
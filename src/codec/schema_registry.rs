@@ -0,0 +1,168 @@
+//! Framing of keys and values for a Confluent-style schema registry.
+//!
+//! Framed data starts with a zero magic byte, followed by the ID of the schema as big-endian 32-bit integer and the
+//! data encoded with that schema, see the [wire format] of the Confluent serializers. [`SchemaRegistryEncoder`] frames
+//! the records of a [`SerializingProducer`](crate::client::producer::SerializingProducer), consumers can use
+//! [`unframe`] before decoding.
+//!
+//! [wire format]: https://docs.confluent.io/platform/current/schema-registry/fundamentals/serdes-develop/index.html#wire-format
+use std::fmt::Debug;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::future::BoxFuture;
+
+use super::{Error, RecordEncoder};
+use crate::record::Record;
+
+/// Magic byte of the wire format.
+const MAGIC_BYTE: u8 = 0;
+
+/// Length of magic byte and schema ID.
+const HEADER_LEN: usize = 5;
+
+/// Client of a schema registry, e.g. one that registers schemas via its REST API.
+pub trait SchemaRegistry: Debug + Send + Sync {
+    /// ID of the schema that data of `subject` is written with.
+    ///
+    /// This is called for every record, so implementations should cache the IDs.
+    fn schema_id<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<u32, Error>>;
+}
+
+/// [`RecordEncoder`] that frames values, and optionally keys, with the ID of their schema.
+///
+/// Null keys and values, e.g. tombstones, are left as they are.
+#[derive(Debug)]
+pub struct SchemaRegistryEncoder<R> {
+    registry: R,
+    key_subject: Option<String>,
+    value_subject: String,
+}
+
+impl<R: SchemaRegistry> SchemaRegistryEncoder<R> {
+    /// Frame values with the schema of `value_subject`, e.g. `"my_topic-value"` with the default subject name strategy
+    /// of the Confluent serializers.
+    pub fn new(registry: R, value_subject: impl Into<String>) -> Self {
+        Self {
+            registry,
+            key_subject: None,
+            value_subject: value_subject.into(),
+        }
+    }
+
+    /// Frame keys with the schema of `key_subject` as well.
+    pub fn with_key_subject(self, key_subject: impl Into<String>) -> Self {
+        Self {
+            key_subject: Some(key_subject.into()),
+            ..self
+        }
+    }
+}
+
+impl<R: SchemaRegistry> RecordEncoder for SchemaRegistryEncoder<R> {
+    fn encode(&self, mut record: Record) -> BoxFuture<'_, Result<Record, Error>> {
+        Box::pin(async move {
+            if let (Some(subject), Some(key)) = (&self.key_subject, &record.key) {
+                let schema_id = self.registry.schema_id(subject).await?;
+                record.key = Some(frame(schema_id, key));
+            }
+            if let Some(value) = &record.value {
+                let schema_id = self.registry.schema_id(&self.value_subject).await?;
+                record.value = Some(frame(schema_id, value));
+            }
+            Ok(record)
+        })
+    }
+}
+
+/// Prefix `data` with the magic byte and `schema_id`.
+pub fn frame(schema_id: u32, data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(HEADER_LEN + data.len());
+    buf.put_u8(MAGIC_BYTE);
+    buf.put_u32(schema_id);
+    buf.put_slice(data);
+    buf.freeze()
+}
+
+/// Split framed data into the schema ID and the data encoded with that schema.
+pub fn unframe(data: &[u8]) -> Result<(u32, &[u8]), Error> {
+    match data {
+        [MAGIC_BYTE, a, b, c, d, rest @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), rest)),
+        [magic, _, _, _, _, ..] => Err(format!("Unknown magic byte {magic}").into()),
+        _ => Err(format!(
+            "Expected at least {HEADER_LEN} bytes of framed data, got {}",
+            data.len()
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct FakeRegistry(HashMap<&'static str, u32>);
+
+    impl SchemaRegistry for FakeRegistry {
+        fn schema_id<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<u32, Error>> {
+            Box::pin(async move {
+                self.0
+                    .get(subject)
+                    .copied()
+                    .ok_or_else(|| format!("Unknown subject {subject}").into())
+            })
+        }
+    }
+
+    fn registry() -> FakeRegistry {
+        FakeRegistry(HashMap::from([("foo-key", 1), ("foo-value", 0x01020304)]))
+    }
+
+    #[tokio::test]
+    async fn test_encode() {
+        let record = Record::builder().key("k").value("v").build();
+
+        let encoder = SchemaRegistryEncoder::new(registry(), "foo-value");
+        let encoded = encoder.encode(record.clone()).await.unwrap();
+        assert_eq!(encoded.key, record.key);
+        assert_eq!(
+            encoded.value.as_deref(),
+            Some([0, 1, 2, 3, 4, b'v'].as_slice())
+        );
+        assert_eq!(encoded.headers, record.headers);
+        assert_eq!(encoded.timestamp, record.timestamp);
+
+        let encoder = encoder.with_key_subject("foo-key");
+        let encoded = encoder.encode(record.clone()).await.unwrap();
+        assert_eq!(
+            encoded.key.as_deref(),
+            Some([0, 0, 0, 0, 1, b'k'].as_slice())
+        );
+
+        // tombstones stay null
+        let tombstone = Record::builder().key("k").build();
+        let encoded = encoder.encode(tombstone).await.unwrap();
+        assert_eq!(encoded.value, None);
+
+        let encoder = SchemaRegistryEncoder::new(registry(), "bar-value");
+        let err = encoder.encode(record).await.unwrap_err();
+        assert_eq!(err.to_string(), "Unknown subject bar-value");
+    }
+
+    #[test]
+    fn test_unframe() {
+        let data = frame(42, b"foo");
+        assert_eq!(unframe(&data).unwrap(), (42, b"foo".as_slice()));
+        assert_eq!(unframe(&frame(7, b"")).unwrap(), (7, b"".as_slice()));
+
+        let err = unframe(&[1, 0, 0, 0, 42]).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown magic byte 1");
+        let err = unframe(&[0, 0, 0]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected at least 5 bytes of framed data, got 3"
+        );
+    }
+}
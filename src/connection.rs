@@ -1,29 +1,50 @@
+use futures::stream::{FuturesUnordered, StreamExt};
 use rand::prelude::*;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 use std::future::Future;
 use std::ops::ControlFlow;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use thiserror::Error;
-use tokio::{io::BufStream, sync::Mutex};
+use tokio::{
+    io::BufStream,
+    sync::{watch, Mutex},
+};
 use tracing::{debug, error, info, warn};
 
 use crate::backoff::ErrorOrThrottle;
+use crate::capture::FrameCapture;
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultPolicy;
+use crate::client::error::{request_kind, source_kind, ErrorKind};
 use crate::client::metadata_cache::MetadataCacheGeneration;
 use crate::connection::topology::{Broker, BrokerTopology};
 use crate::connection::transport::Transport;
 use crate::messenger::{Messenger, RequestError};
+use crate::metrics::{Metrics, ThrottleCallback};
 use crate::protocol::messages::{MetadataRequest, MetadataRequestTopic, MetadataResponse};
 use crate::protocol::primitives::String_;
+use crate::runtime;
 use crate::throttle::maybe_throttle;
 use crate::{
     backoff::{Backoff, BackoffConfig, BackoffError},
     client::metadata_cache::MetadataCache,
 };
 
+pub use self::events::{ConnectionEvent, ConnectionEventHandler};
 pub use self::transport::Credentials;
-pub use self::transport::SaslConfig;
 pub use self::transport::TlsConfig;
+pub use self::transport::{
+    GssapiClientContext, GssapiConfig, GssapiError, GssapiProvider, OauthBearerConfig,
+    OauthBearerToken, OauthBearerTokenError, OauthBearerTokenProvider,
+};
+#[cfg(feature = "transport-tls")]
+pub use self::transport::{ReloadableClientCert, SkipServerNameVerification};
+pub use self::transport::{SaslConfig, SaslProvider, SaslProviderError, SaslSession};
+pub use self::transport::{TcpConfig, TcpKeepalive};
 
+mod events;
 mod topology;
 mod transport;
 
@@ -51,10 +72,42 @@ pub enum Error {
 
     #[error("Sasl handshake failed: {0}")]
     SaslFailed(#[from] crate::messenger::SaslError),
+
+    #[error("client is closed")]
+    Closed,
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    /// See [`client::error::Error::kind`](crate::client::error::Error::kind).
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Metadata(e) => request_kind(e),
+            Self::Transport { error, .. } => match error {
+                transport::Error::IO(_) | transport::Error::Timeout(_) => ErrorKind::Network,
+                #[cfg(feature = "transport-socks5")]
+                transport::Error::Socks5(_) => ErrorKind::Network,
+                transport::Error::InvalidHostPort(_) | transport::Error::InvalidPort(_) => {
+                    ErrorKind::InvalidInput
+                }
+                #[cfg(feature = "transport-tls")]
+                transport::Error::BadHostname(_) => ErrorKind::InvalidInput,
+            },
+            Self::SyncVersions(crate::messenger::SyncVersionsError::RequestError(e)) => {
+                request_kind(e)
+            }
+            Self::SyncVersions(_) => ErrorKind::Other,
+            Self::RetryFailed(BackoffError::DeadlineExceded { source, .. }) => {
+                source_kind(source.as_ref())
+            }
+            Self::SaslFailed(crate::messenger::SaslError::RequestError(e)) => request_kind(e),
+            Self::SaslFailed(_) => ErrorKind::Auth,
+            Self::Closed => ErrorKind::Other,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub struct MultiError(Vec<Box<dyn std::error::Error + Send + Sync>>);
 
@@ -75,17 +128,133 @@ impl Display for MultiError {
     }
 }
 
+/// Maps the advertised `host:port` of a broker to the address that is actually dialed.
+pub type BrokerAddressRewrite = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Maps the `host:port` of a broker to the server name used for TLS.
+pub type TlsServerNameOverride = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Settings for new broker connections.
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    /// Client ID.
+    pub client_id: Arc<str>,
+
+    /// TCP socket options.
+    pub tcp_config: TcpConfig,
+
+    /// TLS configuration if any
+    pub tls_config: TlsConfig,
+
+    /// Override of the TLS server name, if any.
+    pub tls_server_name: Option<TlsServerNameOverride>,
+
+    /// SOCKS5 proxy.
+    pub socks5_proxy: Option<String>,
+
+    /// SASL Configuration
+    pub sasl_config: Option<SaslConfig>,
+
+    /// Maximum message size for framing protocol.
+    pub max_message_size: usize,
+
+    /// Timeout for establishing the TCP connection and the TLS handshake.
+    pub connect_timeout: Option<Duration>,
+
+    /// Delay before dialing the next broker while connecting to an arbitrary one, `None` to dial them sequentially.
+    pub connection_attempt_delay: Option<Duration>,
+
+    /// Timeout for a single request, see [`Messenger::set_request_timeout`].
+    pub request_timeout: Option<Duration>,
+
+    /// Timeout for operations including their retries, see [`Backoff::with_operation_timeout`].
+    pub operation_timeout: Option<Duration>,
+
+    /// Interval for pinging idle connections.
+    pub health_check_interval: Option<Duration>,
+
+    /// Maximum number of requests in flight per connection, see [`Messenger::set_max_in_flight`].
+    pub max_in_flight_requests: Option<usize>,
+
+    /// Rewrite of advertised broker addresses, if any.
+    pub broker_address_rewrite: Option<BrokerAddressRewrite>,
+
+    /// Capture of protocol frames, see [`Messenger::set_frame_capture`].
+    pub frame_capture: Option<FrameCapture>,
+
+    /// Receiver of client events.
+    pub metrics: Option<Arc<dyn Metrics>>,
+
+    /// Receiver of connection lifecycle events.
+    pub event_handler: Option<ConnectionEventHandler>,
+
+    /// Receiver of broker throttling.
+    pub throttle_callback: Option<ThrottleCallback>,
+
+    /// Faults to inject into requests, see [`Messenger::set_fault_policy`].
+    #[cfg(feature = "chaos")]
+    pub fault_policy: Option<FaultPolicy>,
+}
+
+impl ConnectionConfig {
+    /// Backoff that reports retries and throttling to the configured receivers.
+    fn backoff(&self, backoff_config: &BackoffConfig) -> Backoff {
+        Backoff::new(backoff_config)
+            .with_operation_timeout(self.operation_timeout)
+            .with_metrics(self.metrics.clone())
+            .with_throttle_callback(self.throttle_callback.clone())
+    }
+
+    /// Pass `event` for the connection to `broker` to the event handler, if any.
+    fn report_event(&self, broker: &str, event: &ConnectionEvent<'_>) {
+        if let Some(handler) = &self.event_handler {
+            handler(broker, event);
+        }
+    }
+}
+
+impl std::fmt::Debug for ConnectionConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("ConnectionConfig");
+        f.field("client_id", &self.client_id)
+            .field("tcp_config", &self.tcp_config)
+            .field("tls_config", &"...")
+            .field(
+                "tls_server_name",
+                &self.tls_server_name.as_ref().map(|_| "..."),
+            )
+            .field("socks5_proxy", &self.socks5_proxy)
+            .field("sasl_config", &self.sasl_config)
+            .field("max_message_size", &self.max_message_size)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("request_timeout", &self.request_timeout)
+            .field("operation_timeout", &self.operation_timeout)
+            .field("health_check_interval", &self.health_check_interval)
+            .field("max_in_flight_requests", &self.max_in_flight_requests)
+            .field(
+                "broker_address_rewrite",
+                &self.broker_address_rewrite.as_ref().map(|_| "..."),
+            )
+            .field("frame_capture", &self.frame_capture.as_ref().map(|_| "..."))
+            .field("metrics", &self.metrics.as_ref().map(|_| "..."))
+            .field("event_handler", &self.event_handler.as_ref().map(|_| "..."))
+            .field(
+                "throttle_callback",
+                &self.throttle_callback.as_ref().map(|_| "..."),
+            );
+        #[cfg(feature = "chaos")]
+        f.field("fault_policy", &self.fault_policy.as_ref().map(|_| "..."));
+        f.finish()
+    }
+}
+
 /// How to connect to a `Transport`
 trait ConnectionHandler {
     type R: RequestHandler + Send + Sync;
 
     fn connect(
         &self,
-        client_id: Arc<str>,
-        tls_config: TlsConfig,
-        socks5_proxy: Option<String>,
-        sasl_config: Option<SaslConfig>,
-        max_message_size: usize,
+        config: &ConnectionConfig,
     ) -> impl Future<Output = Result<Arc<Self::R>>> + Send;
 }
 
@@ -141,33 +310,197 @@ impl BrokerRepresentation {
 impl ConnectionHandler for BrokerRepresentation {
     type R = MessengerTransport;
 
-    async fn connect(
-        &self,
-        client_id: Arc<str>,
-        tls_config: TlsConfig,
-        socks5_proxy: Option<String>,
-        sasl_config: Option<SaslConfig>,
-        max_message_size: usize,
-    ) -> Result<Arc<Self::R>> {
+    async fn connect(&self, config: &ConnectionConfig) -> Result<Arc<Self::R>> {
         let url = self.url();
+        config.report_event(&url, &ConnectionEvent::Connecting);
+        let res = self.establish(url.clone(), config).await;
+        if let Err(e) = &res {
+            config.report_event(&url, &ConnectionEvent::ConnectFailed { error: e });
+        }
+        res
+    }
+}
+
+impl BrokerRepresentation {
+    /// Connect, negotiate API versions and authenticate.
+    async fn establish(
+        &self,
+        url: String,
+        config: &ConnectionConfig,
+    ) -> Result<Arc<MessengerTransport>> {
+        let dial_addr = match (self, &config.broker_address_rewrite) {
+            (Self::Topology(_), Some(rewrite)) => rewrite(&url),
+            _ => url.clone(),
+        };
         info!(
             broker = self.id(),
             url = url.as_str(),
+            dial_addr = dial_addr.as_str(),
             "Establishing new connection",
         );
-        let transport = Transport::connect(&url, tls_config, socks5_proxy)
-            .await
-            .map_err(|error| Error::Transport {
-                broker: url.to_string(),
-                error,
-            })?;
-
-        let mut messenger = Messenger::new(BufStream::new(transport), max_message_size, client_id);
+        let server_name = match &config.tls_server_name {
+            Some(f) => f(&url),
+            None => url.clone(),
+        };
+        let connect = Transport::connect(
+            &server_name,
+            &dial_addr,
+            &config.tcp_config,
+            config.tls_config.clone(),
+            config.socks5_proxy.clone(),
+        );
+        let transport = match config.connect_timeout {
+            Some(timeout) => runtime::timeout(timeout, connect)
+                .await
+                .unwrap_or(Err(transport::Error::Timeout(timeout))),
+            None => connect.await,
+        }
+        .map_err(|error| Error::Transport {
+            broker: url.to_string(),
+            error,
+        })?;
+
+        let mut messenger = Messenger::new(
+            BufStream::new(transport),
+            config.max_message_size,
+            Arc::clone(&config.client_id),
+        );
+        messenger.set_broker(self.id(), &url);
+        messenger.set_request_timeout(config.request_timeout);
+        messenger.set_max_in_flight(config.max_in_flight_requests);
+        messenger.set_frame_capture(config.frame_capture.clone());
+        #[cfg(feature = "chaos")]
+        messenger.set_fault_policy(config.fault_policy.clone());
+        if let Some(metrics) = &config.metrics {
+            messenger.set_metrics(Arc::clone(metrics), &url);
+        }
+        if let Some(handler) = &config.event_handler {
+            messenger.set_event_handler(Arc::clone(handler), &url);
+        }
         messenger.sync_versions().await?;
-        if let Some(sasl_config) = sasl_config {
-            messenger.do_sasl(sasl_config).await?;
+        messenger.report_event(&ConnectionEvent::Established);
+        let mut reauth = None;
+        if let Some(sasl_config) = config.sasl_config.clone() {
+            // Strip port if any
+            let host = url.split(':').next().unwrap_or_default().to_owned();
+            let lifetime = messenger.do_sasl(sasl_config.clone(), &host).await?;
+            messenger.report_event(&ConnectionEvent::Authenticated {
+                reauthentication: false,
+            });
+            if let Some(lifetime) = lifetime {
+                reauth = Some((sasl_config, host, lifetime));
+            }
+        }
+
+        let messenger = Arc::new(messenger);
+        if let Some(interval) = config.health_check_interval {
+            runtime::spawn(check_health_periodically(
+                Arc::downgrade(&messenger),
+                interval,
+            ));
+        }
+        if let Some((sasl_config, host, lifetime)) = reauth {
+            runtime::spawn(reauthenticate_periodically(
+                Arc::downgrade(&messenger),
+                sasl_config,
+                host,
+                lifetime,
+            ));
+        }
+        Ok(messenger)
+    }
+}
+
+/// Re-authenticate a connection before its SASL session expires.
+///
+/// Stops when the connection is dropped or the re-authentication fails, in which case the broker will close the
+/// connection once the session expired.
+async fn reauthenticate_periodically(
+    messenger: Weak<MessengerTransport>,
+    sasl_config: SaslConfig,
+    host: String,
+    mut lifetime: Duration,
+) {
+    loop {
+        // use some jitter so that connections that were established together do not re-authenticate at the same time
+        let factor = thread_rng().gen_range(0.85..0.95);
+        runtime::sleep(lifetime.mul_f64(factor)).await;
+
+        let Some(messenger) = messenger.upgrade() else {
+            return;
+        };
+        debug!(host = host.as_str(), "Re-authenticating SASL session");
+        match messenger.reauthenticate(sasl_config.clone(), &host).await {
+            Ok(Some(new_lifetime)) => {
+                messenger.report_event(&ConnectionEvent::Authenticated {
+                    reauthentication: true,
+                });
+                lifetime = new_lifetime;
+            }
+            Ok(None) => {
+                return;
+            }
+            Err(e) => {
+                warn!(%e, host = host.as_str(), "SASL re-authentication failed");
+                return;
+            }
+        }
+    }
+}
+
+/// Ping a connection whenever it was idle for `interval`.
+///
+/// A failed ping poisons the connection, so that the broker caches replace half-open connections before they are used
+/// for the next request. Stops when the connection is dropped or broken.
+async fn check_health_periodically(messenger: Weak<MessengerTransport>, interval: Duration) {
+    let mut wait = interval;
+    loop {
+        runtime::sleep(wait).await;
+
+        let Some(messenger) = messenger.upgrade() else {
+            return;
+        };
+        let idle_time = messenger.idle_time();
+        if idle_time < interval {
+            wait = interval - idle_time;
+            continue;
+        }
+
+        debug!(?idle_time, "Pinging idle connection");
+        if let Err(e) = messenger.ping(interval).await {
+            warn!(%e, "Idle connection health check failed");
+            return;
+        }
+        wait = interval;
+    }
+}
+
+/// Refresh the cached metadata in the given interval.
+///
+/// Stops when the connector is dropped.
+pub(crate) async fn refresh_metadata_periodically(
+    brokers: Weak<BrokerConnector>,
+    interval: Duration,
+) {
+    let Some(mut closed) = brokers.upgrade().map(|b| b.closed()) else {
+        return;
+    };
+
+    loop {
+        tokio::select! {
+            _ = runtime::sleep(interval) => {}
+            _ = closed.wait_for(|closed| *closed) => {
+                return;
+            }
+        }
+
+        let Some(brokers) = brokers.upgrade() else {
+            return;
+        };
+        debug!("Refreshing metadata in background");
+        if let Err(e) = brokers.refresh_metadata().await {
+            warn!(%e, "Background metadata refresh failed");
         }
-        Ok(Arc::new(messenger))
     }
 }
 
@@ -181,9 +514,6 @@ pub struct BrokerConnector {
     /// Broker URLs used to boostrap this pool
     bootstrap_brokers: Vec<String>,
 
-    /// Client ID.
-    client_id: Arc<str>,
-
     /// Discovered brokers in the cluster, including bootstrap brokers
     topology: BrokerTopology,
 
@@ -192,6 +522,9 @@ pub struct BrokerConnector {
     /// This one is used for metadata queries.
     cached_arbitrary_broker: Mutex<(Option<BrokerConnection>, BrokerCacheGeneration)>,
 
+    /// Connections to specific brokers by broker ID, see [`connect_cached`](Self::connect_cached).
+    cached_brokers: parking_lot::Mutex<HashMap<i32, Arc<Mutex<Option<BrokerConnection>>>>>,
+
     /// A cache of [`MetadataResponse`].
     ///
     /// Used during leader discovery.
@@ -200,43 +533,80 @@ pub struct BrokerConnector {
     /// The backoff configuration on error
     backoff_config: Arc<BackoffConfig>,
 
-    /// TLS configuration if any
-    tls_config: TlsConfig,
+    /// Settings for new connections.
+    connection_config: ConnectionConfig,
 
-    /// SOCKS5 proxy.
-    socks5_proxy: Option<String>,
-
-    /// SASL Configuration
-    sasl_config: Option<SaslConfig>,
+    /// All connections that were established so far, so they can be closed.
+    connections: parking_lot::Mutex<Vec<Weak<MessengerTransport>>>,
 
-    /// Maximum message size for framing protocol.
-    max_message_size: usize,
+    /// Set once [`close`](Self::close) was called.
+    closed: watch::Sender<bool>,
 }
 
 impl BrokerConnector {
     pub fn new(
         bootstrap_brokers: Vec<String>,
-        client_id: Arc<str>,
-        tls_config: TlsConfig,
-        socks5_proxy: Option<String>,
-        sasl_config: Option<SaslConfig>,
-        max_message_size: usize,
+        connection_config: ConnectionConfig,
         backoff_config: Arc<BackoffConfig>,
     ) -> Self {
         Self {
             bootstrap_brokers,
-            client_id,
             topology: Default::default(),
             cached_arbitrary_broker: Mutex::new((None, BrokerCacheGeneration::START)),
+            cached_brokers: Default::default(),
             cached_metadata: Default::default(),
             backoff_config,
-            tls_config,
-            socks5_proxy,
-            sasl_config,
-            max_message_size,
+            connection_config,
+            connections: Default::default(),
+            closed: watch::Sender::new(false),
+        }
+    }
+
+    /// Close all connections and stop background tasks.
+    ///
+    /// In-flight requests are finished first, all later requests fail.
+    pub async fn close(&self) {
+        let connections = {
+            let mut connections = self.connections.lock();
+            self.closed.send_replace(true);
+            std::mem::take(&mut *connections)
+        };
+        self.cached_arbitrary_broker.lock().await.0.take();
+        self.cached_brokers.lock().clear();
+
+        let connections: Vec<_> = connections
+            .into_iter()
+            .filter_map(|c| c.upgrade())
+            .collect();
+        info!(n_connections = connections.len(), "Closing connections");
+        futures::future::join_all(connections.iter().map(|c| c.close())).await;
+    }
+
+    /// Receiver that changes to `true` once the connector is [closed](Self::close).
+    pub(crate) fn closed(&self) -> watch::Receiver<bool> {
+        self.closed.subscribe()
+    }
+
+    fn check_closed(&self) -> Result<()> {
+        if *self.closed.borrow() {
+            Err(Error::Closed)
+        } else {
+            Ok(())
         }
     }
 
+    /// Remember `connection` so that it is closed by [`close`](Self::close).
+    ///
+    /// Fails if the connector was closed while the connection was established, the connection is closed when it is
+    /// dropped then.
+    fn track(&self, connection: &BrokerConnection) -> Result<()> {
+        let mut connections = self.connections.lock();
+        self.check_closed()?;
+        connections.retain(|c| c.strong_count() > 0);
+        connections.push(Arc::downgrade(connection));
+        Ok(())
+    }
+
     /// Fetch and cache metadata
     pub async fn refresh_metadata(&self) -> Result<()> {
         self.request_metadata(&MetadataLookupMode::ArbitraryBroker, None)
@@ -287,14 +657,20 @@ impl BrokerConnector {
             }
         }
 
-        let backoff = Backoff::new(&self.backoff_config);
+        let backoff = self.backoff(&self.backoff_config);
         let request = MetadataRequest {
             topics: topics.map(|t| {
                 t.into_iter()
-                    .map(|x| MetadataRequestTopic { name: String_(x) })
+                    .map(|x| MetadataRequestTopic {
+                        name: String_(x),
+                        tagged_fields: None,
+                    })
                     .collect()
             }),
             allow_auto_topic_creation: None,
+            include_cluster_authorized_operations: None,
+            include_topic_authorized_operations: None,
+            tagged_fields: None,
         };
 
         let response = metadata_request_with_retry(metadata_mode, &request, backoff, self).await?;
@@ -311,6 +687,25 @@ impl BrokerConnector {
         Ok((response, None))
     }
 
+    /// Leader of the given partition according to the cached metadata, if any.
+    pub(crate) fn cached_leader(
+        &self,
+        topic: &str,
+        partition: i32,
+    ) -> Option<(i32, MetadataCacheGeneration)> {
+        self.cached_metadata.leader(topic, partition)
+    }
+
+    /// Leaders of all partitions of `topic` according to the cached metadata, if any.
+    pub(crate) fn cached_partition_leaders(&self, topic: &str) -> Option<BTreeMap<i32, i32>> {
+        self.cached_metadata.partition_leaders(topic)
+    }
+
+    /// Receiver that is notified whenever the cached metadata is updated.
+    pub(crate) fn metadata_updates(&self) -> watch::Receiver<()> {
+        self.cached_metadata.subscribe()
+    }
+
     pub(crate) fn invalidate_metadata_cache(
         &self,
         reason: &'static str,
@@ -319,40 +714,100 @@ impl BrokerConnector {
         self.cached_metadata.invalidate(reason, gen)
     }
 
+    /// Receiver of client events, if configured.
+    pub(crate) fn metrics(&self) -> Option<Arc<dyn Metrics>> {
+        self.connection_config.metrics.clone()
+    }
+
+    /// Backoff that reports retries and throttling to the configured receivers.
+    pub(crate) fn backoff(&self, backoff_config: &BackoffConfig) -> Backoff {
+        self.connection_config.backoff(backoff_config)
+    }
+
     /// Returns a new connection to the broker with the provided id
     pub async fn connect(&self, broker_id: i32) -> Result<Option<BrokerConnection>> {
+        self.check_closed()?;
         match self.topology.get_broker(broker_id).await {
             Some(broker) => {
                 let connection = BrokerRepresentation::Topology(broker)
-                    .connect(
-                        Arc::clone(&self.client_id),
-                        self.tls_config.clone(),
-                        self.socks5_proxy.clone(),
-                        self.sasl_config.clone(),
-                        self.max_message_size,
-                    )
+                    .connect(&self.connection_config)
                     .await?;
+                self.track(&connection)?;
                 Ok(Some(connection))
             }
             None => Ok(None),
         }
     }
 
-    /// Either the topology or the bootstrap brokers to be used as a connection
-    fn brokers(&self) -> Vec<BrokerRepresentation> {
+    /// Returns a connection to the broker with the given ID that is shared with other callers, or `None` if the broker
+    /// is unknown.
+    ///
+    /// Unlike [`connect`](Self::connect), the connection is reused until it is poisoned or
+    /// [invalidated](Self::invalidate_cached).
+    pub async fn connect_cached(&self, broker_id: i32) -> Result<Option<BrokerConnection>> {
+        let cached = Arc::clone(self.cached_brokers.lock().entry(broker_id).or_default());
+        let mut cached = cached.lock().await;
+        drop_poisoned(&mut cached);
+        if let Some(connection) = cached.as_ref() {
+            return Ok(Some(Arc::clone(connection)));
+        }
+
+        let connection = self.connect(broker_id).await?;
+        cached.clone_from(&connection);
+        Ok(connection)
+    }
+
+    /// Stop sharing `connection` to the broker with the given ID, see [`connect_cached`](Self::connect_cached).
+    ///
+    /// Does nothing if the cache already holds a different connection.
+    pub async fn invalidate_cached(
+        &self,
+        reason: &'static str,
+        broker_id: i32,
+        connection: &BrokerConnection,
+    ) {
+        let Some(cached) = self.cached_brokers.lock().get(&broker_id).map(Arc::clone) else {
+            return;
+        };
+        let mut cached = cached.lock().await;
+        if !cached.as_ref().is_some_and(|c| Arc::ptr_eq(c, connection)) {
+            debug!(
+                reason,
+                broker_id, "stale invalidation request for broker connection"
+            );
+            return;
+        }
+
+        info!(reason, broker_id, "Invalidating cached broker connection");
+        if let Some(connection) = cached.take() {
+            connection.report_event(&ConnectionEvent::Invalidated { reason });
+        }
+    }
+
+    /// Brokers to be used as a connection and fallbacks if none of them can be reached.
+    ///
+    /// These are the topology brokers with the bootstrap brokers as fallback, or only the bootstrap brokers if the
+    /// topology is still unknown. Bootstrap brokers are resolved on every connection attempt, so they still work if
+    /// the IP addresses of all known brokers changed, e.g. when the cluster was rescheduled.
+    fn brokers(&self) -> (Vec<BrokerRepresentation>, Vec<BrokerRepresentation>) {
+        let bootstrap = self
+            .bootstrap_brokers
+            .iter()
+            .cloned()
+            .map(BrokerRepresentation::Bootstrap)
+            .collect();
+
         if self.topology.is_empty() {
-            self.bootstrap_brokers
-                .iter()
-                .cloned()
-                .map(BrokerRepresentation::Bootstrap)
-                .collect()
+            (bootstrap, vec![])
         } else {
-            self.topology
+            let topology = self
+                .topology
                 .get_brokers()
                 .iter()
                 .cloned()
                 .map(BrokerRepresentation::Topology)
-                .collect()
+                .collect();
+            (topology, bootstrap)
         }
     }
 }
@@ -363,9 +818,9 @@ impl std::fmt::Debug for BrokerConnector {
             .field("bootstrap_brokers", &self.bootstrap_brokers)
             .field("topology", &self.topology)
             .field("cached_arbitrary_broker", &self.cached_arbitrary_broker)
+            .field("cached_brokers", &self.cached_brokers)
             .field("backoff_config", &self.backoff_config)
-            .field("tls_config", &"...")
-            .field("max_message_size", &self.max_message_size)
+            .field("connection_config", &self.connection_config)
             .finish()
     }
 }
@@ -425,6 +880,20 @@ pub trait BrokerCache: Send + Sync {
     ) -> impl Future<Output = ()> + Send;
 }
 
+/// Drop a cached connection that is known to be broken, e.g. because a health check failed.
+///
+/// This way the next request uses a new connection instead of failing and invalidating the cache first.
+pub(crate) fn drop_poisoned(broker: &mut Option<Arc<MessengerTransport>>) {
+    if broker.as_ref().is_some_and(|b| b.is_poisoned()) {
+        info!("Dropping poisoned broker connection");
+        if let Some(broker) = broker.take() {
+            broker.report_event(&ConnectionEvent::Invalidated {
+                reason: "connection poisoned",
+            });
+        }
+    }
+}
+
 /// BrokerConnector caches an arbitrary broker that can successfully connect.
 impl BrokerCache for &BrokerConnector {
     type R = MessengerTransport;
@@ -432,20 +901,21 @@ impl BrokerCache for &BrokerConnector {
 
     async fn get(&self) -> Result<(Arc<Self::R>, BrokerCacheGeneration), Self::E> {
         let mut current_broker = self.cached_arbitrary_broker.lock().await;
+        drop_poisoned(&mut current_broker.0);
         if let Some(broker) = &current_broker.0 {
             return Ok((Arc::clone(broker), current_broker.1));
         }
 
+        self.check_closed()?;
+        let (brokers, fallback_brokers) = self.brokers();
         let connection = connect_to_a_broker_with_retry(
-            self.brokers(),
-            Arc::clone(&self.client_id),
+            brokers,
+            fallback_brokers,
             &self.backoff_config,
-            self.tls_config.clone(),
-            self.socks5_proxy.clone(),
-            self.sasl_config.clone(),
-            self.max_message_size,
+            &self.connection_config,
         )
         .await?;
+        self.track(&connection)?;
 
         current_broker.0 = Some(Arc::clone(&connection));
         current_broker.1.bump();
@@ -468,51 +938,42 @@ impl BrokerCache for &BrokerConnector {
         }
 
         info!(reason, "Invalidating cached arbitrary broker",);
-        guard.0.take();
+        if let Some(broker) = guard.0.take() {
+            broker.report_event(&ConnectionEvent::Invalidated { reason });
+        }
     }
 }
 
+/// Connect to any of `brokers`, or any of `fallback_brokers` if none of them can be reached.
 async fn connect_to_a_broker_with_retry<B>(
     mut brokers: Vec<B>,
-    client_id: Arc<str>,
+    mut fallback_brokers: Vec<B>,
     backoff_config: &BackoffConfig,
-    tls_config: TlsConfig,
-    socks5_proxy: Option<String>,
-    sasl_config: Option<SaslConfig>,
-    max_message_size: usize,
+    connection_config: &ConnectionConfig,
 ) -> Result<Arc<B::R>>
 where
     B: ConnectionHandler + Send + Sync,
 {
     // Randomise search order to encourage different clients to choose different brokers
     brokers.shuffle(&mut thread_rng());
+    fallback_brokers.shuffle(&mut thread_rng());
 
-    let mut backoff = Backoff::new(backoff_config);
+    let mut backoff = connection_config.backoff(backoff_config);
     backoff
         .retry_with_backoff("broker_connect", || async {
-            let mut errors = Vec::<Box<dyn std::error::Error + Send + Sync>>::new();
-            for broker in &brokers {
-                let conn = broker
-                    .connect(
-                        Arc::clone(&client_id),
-                        tls_config.clone(),
-                        socks5_proxy.clone(),
-                        sasl_config.clone(),
-                        max_message_size,
-                    )
-                    .await;
-
-                let connection = match conn {
-                    Ok(transport) => transport,
-                    Err(e) => {
-                        warn!(%e, "Failed to connect to broker");
-                        errors.push(Box::new(e));
-                        continue;
-                    }
-                };
-
-                return ControlFlow::Break(connection);
-            }
+            let errors = match connect_to_first_broker(
+                brokers.iter().chain(&fallback_brokers).collect(),
+                connection_config,
+            )
+            .await
+            {
+                Ok(connection) => return ControlFlow::Break(connection),
+                Err(errors) => errors,
+            };
+            let errors = errors
+                .into_iter()
+                .map(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+                .collect();
             let err = Box::<dyn std::error::Error + Send + Sync>::from(MultiError(errors));
             let err: Arc<dyn std::error::Error + Send + Sync> = err.into();
             ControlFlow::Continue(ErrorOrThrottle::Error(err))
@@ -521,6 +982,52 @@ where
         .map_err(Error::RetryFailed)
 }
 
+/// Connect to the first of `brokers` that can be reached, or return the errors of all of them.
+///
+/// Like "Happy Eyeballs" ([RFC 8305]), the next broker is dialed once the
+/// [`connection_attempt_delay`](ConnectionConfig::connection_attempt_delay) elapsed or the previous attempt failed,
+/// while the attempts that are in progress continue. This way an unreachable broker does not hold up the connection to
+/// the next one. The remaining attempts are dropped once a connection is established.
+///
+/// [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+async fn connect_to_first_broker<B>(
+    brokers: Vec<&B>,
+    connection_config: &ConnectionConfig,
+) -> Result<Arc<B::R>, Vec<Error>>
+where
+    B: ConnectionHandler + Sync,
+{
+    let mut brokers = brokers.into_iter().peekable();
+    let mut attempts = FuturesUnordered::new();
+    let mut errors = vec![];
+    loop {
+        // dial the next broker initially, after a failure and once the delay elapsed
+        if let Some(broker) = brokers.next() {
+            attempts.push(broker.connect(connection_config));
+        }
+        if attempts.is_empty() {
+            return Err(errors);
+        }
+
+        let attempt_delay = match connection_config.connection_attempt_delay {
+            Some(delay) if brokers.peek().is_some() => runtime::sleep(delay),
+            _ => Box::pin(futures::future::pending()),
+        };
+        tokio::select! {
+            res = attempts.next() => match res.expect("attempts are not empty") {
+                Ok(connection) => return Ok(connection),
+                Err(e) => {
+                    warn!(%e, "Failed to connect to broker");
+                    errors.push(e);
+                }
+            },
+            _ = attempt_delay => {
+                debug!("Broker did not respond in time, dialing the next one");
+            }
+        }
+    }
+}
+
 async fn metadata_request_with_retry<A>(
     metadata_mode: &MetadataLookupMode<Arc<A::R>>,
     request_params: &MetadataRequest,
@@ -761,6 +1268,9 @@ mod tests {
         MetadataRequest {
             topics: Default::default(),
             allow_auto_topic_creation: Default::default(),
+            include_cluster_authorized_operations: Default::default(),
+            include_topic_authorized_operations: Default::default(),
+            tagged_fields: Default::default(),
         }
     }
 
@@ -771,6 +1281,8 @@ mod tests {
             cluster_id: Default::default(),
             controller_id: Default::default(),
             topics: Default::default(),
+            cluster_authorized_operations: Default::default(),
+            tagged_fields: Default::default(),
         }
     }
 
@@ -803,14 +1315,7 @@ mod tests {
     impl ConnectionHandler for FakeBrokerRepresentation {
         type R = FakeConn;
 
-        async fn connect(
-            &self,
-            _client_id: Arc<str>,
-            _tls_config: TlsConfig,
-            _socks5_proxy: Option<String>,
-            _sasl_config: Option<SaslConfig>,
-            _max_message_size: usize,
-        ) -> Result<Arc<Self::R>> {
+        async fn connect(&self, _config: &ConnectionConfig) -> Result<Arc<Self::R>> {
             (self.conn)()
         }
     }
@@ -832,16 +1337,141 @@ mod tests {
         // connects successfully.
         let conn = connect_to_a_broker_with_retry(
             brokers,
-            Arc::from(DEFAULT_CLIENT_ID),
+            vec![],
+            &Default::default(),
+            &connection_config(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*conn, FakeConn);
+    }
+
+    #[tokio::test]
+    async fn connect_uses_fallback_brokers() {
+        let brokers = vec![FakeBrokerRepresentation {
+            conn: Box::new(|| Err(Error::Metadata(arbitrary_recoverable_error()))),
+        }];
+        let fallback_brokers = vec![FakeBrokerRepresentation {
+            conn: Box::new(|| Ok(Arc::new(FakeConn))),
+        }];
+
+        let conn = connect_to_a_broker_with_retry(
+            brokers,
+            fallback_brokers,
             &Default::default(),
-            Default::default(),
-            Default::default(),
-            Default::default(),
-            Default::default(),
+            &connection_config(),
         )
         .await
         .unwrap();
 
         assert_eq!(*conn, FakeConn);
     }
+
+    /// Broker that never answers unless it is `reachable`.
+    struct SilentBroker {
+        reachable: bool,
+    }
+
+    impl ConnectionHandler for SilentBroker {
+        type R = FakeConn;
+
+        async fn connect(&self, _config: &ConnectionConfig) -> Result<Arc<Self::R>> {
+            if !self.reachable {
+                futures::future::pending::<()>().await;
+            }
+            Ok(Arc::new(FakeConn))
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_dials_next_broker_after_delay() {
+        let brokers = [
+            SilentBroker { reachable: false },
+            SilentBroker { reachable: true },
+        ];
+        let brokers = &brokers;
+        let connect = |connection_attempt_delay| {
+            let config = ConnectionConfig {
+                connection_attempt_delay,
+                ..connection_config()
+            };
+            async move {
+                tokio::time::timeout(
+                    Duration::from_millis(100),
+                    connect_to_first_broker(brokers.iter().collect(), &config),
+                )
+                .await
+            }
+        };
+
+        let conn = connect(Some(Duration::from_millis(10)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(*conn, FakeConn);
+
+        // sequential attempts wait for the first broker forever
+        connect(None).await.unwrap_err();
+    }
+
+    fn connection_config() -> ConnectionConfig {
+        ConnectionConfig {
+            client_id: Arc::from(DEFAULT_CLIENT_ID),
+            tcp_config: Default::default(),
+            tls_config: Default::default(),
+            tls_server_name: Default::default(),
+            socks5_proxy: Default::default(),
+            sasl_config: Default::default(),
+            max_message_size: Default::default(),
+            connect_timeout: Default::default(),
+            connection_attempt_delay: Default::default(),
+            request_timeout: Default::default(),
+            operation_timeout: Default::default(),
+            health_check_interval: Default::default(),
+            max_in_flight_requests: Default::default(),
+            broker_address_rewrite: Default::default(),
+            frame_capture: Default::default(),
+            metrics: Default::default(),
+            event_handler: Default::default(),
+            throttle_callback: Default::default(),
+            #[cfg(feature = "chaos")]
+            fault_policy: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_reports_events() {
+        // find a port that nothing listens on
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let events = Arc::new(parking_lot::Mutex::new(vec![]));
+        let events_captured = Arc::clone(&events);
+        let config = ConnectionConfig {
+            event_handler: Some(Arc::new(move |broker, event| {
+                let event = match event {
+                    ConnectionEvent::Connecting => "connecting",
+                    ConnectionEvent::ConnectFailed { .. } => "connect failed",
+                    _ => "other",
+                };
+                events_captured.lock().push(format!("{broker}: {event}"));
+            })),
+            ..connection_config()
+        };
+
+        BrokerRepresentation::Bootstrap(url.clone())
+            .connect(&config)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            *events.lock(),
+            [
+                format!("{url}: connecting"),
+                format!("{url}: connect failed")
+            ]
+        );
+    }
 }
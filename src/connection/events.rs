@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+/// Lifecycle event of a broker connection, see [`ConnectionEventHandler`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum ConnectionEvent<'a> {
+    /// A new connection is being established.
+    ///
+    /// This happens for the first connection to a broker as well as for every reconnect after a connection was
+    /// [invalidated](Self::Invalidated) or a previous attempt [failed](Self::ConnectFailed).
+    Connecting,
+
+    /// The connection could not be established, including failed API version negotiation and SASL authentication.
+    ConnectFailed {
+        error: &'a (dyn std::error::Error + Send + Sync),
+    },
+
+    /// The connection was established and the API versions were negotiated.
+    Established,
+
+    /// SASL authentication succeeded.
+    Authenticated {
+        /// Whether an already authenticated connection renewed its session ([KIP-368]).
+        ///
+        /// [KIP-368]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-368%3A+Allow+SASL+Connections+to+Periodically+Re-Authenticate
+        reauthentication: bool,
+    },
+
+    /// A client stopped using the connection, e.g. because it is broken or the broker is no longer the partition
+    /// leader. The next request establishes a new connection.
+    Invalidated { reason: &'a str },
+}
+
+/// Callback that is invoked with the `host:port` of the broker for every [`ConnectionEvent`].
+///
+/// The broker address is the advertised one, even if it is [rewritten] before dialing. The callback runs on the
+/// request path, so it should not block.
+///
+/// [rewritten]: crate::client::ClientBuilder::broker_address_rewrite
+pub type ConnectionEventHandler = Arc<dyn Fn(&str, &ConnectionEvent<'_>) + Send + Sync>;
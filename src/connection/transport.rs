@@ -5,13 +5,25 @@ use std::sync::Arc;
 use std::task::{Context, Poll};
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tokio::net::TcpStream;
+
+use crate::runtime::{self, Stream};
 
 #[cfg(feature = "transport-tls")]
 use tokio_rustls::{client::TlsStream, TlsConnector};
 
 mod sasl;
-pub use sasl::{Credentials, SaslConfig};
+mod tcp;
+#[cfg(feature = "transport-tls")]
+mod tls;
+pub use sasl::{
+    Credentials, GssapiClientContext, GssapiConfig, GssapiError, GssapiProvider, OauthBearerConfig,
+    OauthBearerToken, OauthBearerTokenError, OauthBearerTokenProvider, SaslConfig, SaslProvider,
+    SaslProviderError, SaslSession,
+};
+pub use tcp::{TcpConfig, TcpKeepalive};
+
+#[cfg(feature = "transport-tls")]
+pub use tls::{ReloadableClientCert, SkipServerNameVerification};
 
 #[cfg(feature = "transport-tls")]
 pub type TlsConfig = Option<Arc<rustls::ClientConfig>>;
@@ -33,6 +45,9 @@ pub enum Error {
     #[error("Invalid port: {0}")]
     InvalidPort(#[from] std::num::ParseIntError),
 
+    #[error("Connection timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
     #[cfg(feature = "transport-tls")]
     #[error("Invalid Hostname: {0}")]
     BadHostname(#[from] rustls::pki_types::InvalidDnsNameError),
@@ -48,18 +63,18 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[derive(Debug)]
 pub enum Transport {
     Plain {
-        inner: TcpStream,
+        inner: Box<dyn Stream>,
     },
 
     Tls {
-        inner: Pin<Box<TlsStream<TcpStream>>>,
+        inner: Pin<Box<TlsStream<Box<dyn Stream>>>>,
     },
 }
 
 #[cfg(not(feature = "transport-tls"))]
 #[derive(Debug)]
 pub enum Transport {
-    Plain { inner: TcpStream },
+    Plain { inner: Box<dyn Stream> },
 }
 
 impl AsyncRead for Transport {
@@ -111,22 +126,31 @@ impl AsyncWrite for Transport {
 }
 
 impl Transport {
+    /// Connect to `dial_addr`.
+    ///
+    /// The TLS server name is taken from `server_name`, which may contain a port.
     pub async fn connect(
-        broker: &str,
+        server_name: &str,
+        dial_addr: &str,
+        tcp_config: &TcpConfig,
         tls_config: TlsConfig,
         socks5_proxy: Option<String>,
     ) -> Result<Self> {
-        let tcp_stream = Self::connect_tcp(broker, socks5_proxy).await?;
-        Self::wrap_tls(tcp_stream, broker, tls_config).await
+        let tcp_stream = Self::connect_tcp(dial_addr, tcp_config, socks5_proxy).await?;
+        Self::wrap_tls(tcp_stream, server_name, tls_config).await
     }
 
     #[cfg(feature = "transport-socks5")]
-    async fn connect_tcp(broker: &str, socks5_proxy: Option<String>) -> Result<TcpStream> {
+    async fn connect_tcp(
+        broker: &str,
+        tcp_config: &TcpConfig,
+        socks5_proxy: Option<String>,
+    ) -> Result<Box<dyn Stream>> {
         use async_socks5::connect;
 
         match socks5_proxy {
             Some(proxy) => {
-                let mut stream = TcpStream::connect(proxy).await?;
+                let mut stream = runtime::global().connect_tcp(&proxy, tcp_config).await?;
 
                 let mut broker_iter = broker.split(':');
                 let broker_host = broker_iter
@@ -141,17 +165,25 @@ impl Transport {
 
                 Ok(stream)
             }
-            None => Ok(TcpStream::connect(broker).await?),
+            None => Ok(runtime::global().connect_tcp(broker, tcp_config).await?),
         }
     }
 
     #[cfg(not(feature = "transport-socks5"))]
-    async fn connect_tcp(broker: &str, _socks5_proxy: Option<String>) -> Result<TcpStream> {
-        Ok(TcpStream::connect(broker).await?)
+    async fn connect_tcp(
+        broker: &str,
+        tcp_config: &TcpConfig,
+        _socks5_proxy: Option<String>,
+    ) -> Result<Box<dyn Stream>> {
+        Ok(runtime::global().connect_tcp(broker, tcp_config).await?)
     }
 
     #[cfg(feature = "transport-tls")]
-    async fn wrap_tls(tcp_stream: TcpStream, broker: &str, tls_config: TlsConfig) -> Result<Self> {
+    async fn wrap_tls(
+        tcp_stream: Box<dyn Stream>,
+        broker: &str,
+        tls_config: TlsConfig,
+    ) -> Result<Self> {
         match tls_config {
             Some(config) => {
                 // Strip port if any
@@ -174,7 +206,7 @@ impl Transport {
 
     #[cfg(not(feature = "transport-tls"))]
     async fn wrap_tls(
-        tcp_stream: TcpStream,
+        tcp_stream: Box<dyn Stream>,
         _broker: &str,
         _tls_config: TlsConfig,
     ) -> Result<Self> {
@@ -1,3 +1,11 @@
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use tokio::sync::Mutex;
+
+use crate::record::utc_now;
+
 #[derive(Debug, Clone)]
 pub enum SaslConfig {
     /// SASL - PLAIN
@@ -15,6 +23,19 @@ pub enum SaslConfig {
     /// # References
     /// - <https://datatracker.ietf.org/doc/html/draft-melnikov-scram-sha-512-04>
     ScramSha512(Credentials),
+    /// SASL - OAUTHBEARER
+    ///
+    /// # References
+    /// - <https://datatracker.ietf.org/doc/html/rfc7628>
+    /// - <https://cwiki.apache.org/confluence/display/KAFKA/KIP-255%3A+OAuth+Authentication+via+SASL%2FOAUTHBEARER>
+    OauthBearer(OauthBearerConfig),
+    /// SASL - GSSAPI (Kerberos V5)
+    ///
+    /// # References
+    /// - <https://datatracker.ietf.org/doc/html/rfc4752>
+    Gssapi(GssapiConfig),
+    /// Custom SASL mechanism.
+    Custom(Arc<dyn SaslProvider>),
 }
 
 #[derive(Debug, Clone)]
@@ -30,19 +51,280 @@ impl Credentials {
 }
 
 impl SaslConfig {
-    pub(crate) fn credentials(&self) -> Credentials {
-        match self {
-            Self::Plain(credentials) => credentials.clone(),
-            Self::ScramSha256(credentials) => credentials.clone(),
-            Self::ScramSha512(credentials) => credentials.clone(),
-        }
-    }
-
     pub(crate) fn mechanism(&self) -> &str {
         match self {
             Self::Plain { .. } => "PLAIN",
             Self::ScramSha256 { .. } => "SCRAM-SHA-256",
             Self::ScramSha512 { .. } => "SCRAM-SHA-512",
+            Self::OauthBearer { .. } => "OAUTHBEARER",
+            Self::Gssapi { .. } => "GSSAPI",
+            Self::Custom(provider) => provider.mechanism(),
+        }
+    }
+}
+
+/// Error returned by an [`OauthBearerTokenProvider`].
+pub type OauthBearerTokenError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A token for [`SaslConfig::OauthBearer`].
+#[derive(Clone)]
+pub struct OauthBearerToken {
+    /// The bearer token, e.g. a JWT.
+    pub token: String,
+
+    /// Expiry time of the token.
+    ///
+    /// Tokens without expiry time are used until the broker rejects them.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// SASL extensions ([KIP-342]), e.g. `logicalCluster` and `identityPoolId` for Confluent Cloud.
+    ///
+    /// [KIP-342]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-342%3A+Add+support+for+Custom+SASL+extensions+in+OAuthBearer+authentication
+    pub extensions: BTreeMap<String, String>,
+}
+
+impl OauthBearerToken {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            expires_at: None,
+            extensions: BTreeMap::default(),
         }
     }
+
+    /// Initial client response as defined in [RFC 7628 section 3.1](https://datatracker.ietf.org/doc/html/rfc7628#section-3.1).
+    pub(crate) fn client_response(&self) -> Vec<u8> {
+        let mut msg = format!("n,,\x01auth=Bearer {}\x01", self.token);
+        for (k, v) in &self.extensions {
+            msg.push_str(&format!("{k}={v}\x01"));
+        }
+        msg.push('\x01');
+        msg.into_bytes()
+    }
+}
+
+impl Debug for OauthBearerToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OauthBearerToken")
+            .field("token", &"<redacted>")
+            .field("expires_at", &self.expires_at)
+            .field("extensions", &self.extensions)
+            .finish()
+    }
+}
+
+/// Provides tokens for [`SaslConfig::OauthBearer`], e.g. by talking to an OIDC identity provider.
+pub trait OauthBearerTokenProvider: Debug + Send + Sync {
+    /// Get a fresh token.
+    fn token(&self) -> BoxFuture<'_, Result<OauthBearerToken, OauthBearerTokenError>>;
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    token: OauthBearerToken,
+    fetched_at: DateTime<Utc>,
+}
+
+impl CachedToken {
+    /// Tokens are refreshed after 80% of their lifetime, like the Java client does by default.
+    fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        match self.token.expires_at {
+            Some(expires_at) => now >= self.fetched_at + (expires_at - self.fetched_at) * 4 / 5,
+            None => false,
+        }
+    }
+}
+
+/// Configuration for [`SaslConfig::OauthBearer`].
+///
+/// Tokens are cached and shared by all connections that use clones of this config. The provider is invoked again when
+/// the cached token approaches its expiry time or after the broker rejected it.
+#[derive(Debug, Clone)]
+pub struct OauthBearerConfig {
+    provider: Arc<dyn OauthBearerTokenProvider>,
+    cache: Arc<Mutex<Option<CachedToken>>>,
+}
+
+impl OauthBearerConfig {
+    pub fn new(provider: Arc<dyn OauthBearerTokenProvider>) -> Self {
+        Self {
+            provider,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Get the cached token or fetch a new one if required.
+    pub(crate) async fn token(&self) -> Result<OauthBearerToken, OauthBearerTokenError> {
+        let mut cache = self.cache.lock().await;
+        let now = utc_now();
+        if let Some(cached) = cache.as_ref() {
+            if !cached.needs_refresh(now) {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = self.provider.token().await?;
+        *cache = Some(CachedToken {
+            token: token.clone(),
+            fetched_at: now,
+        });
+        Ok(token)
+    }
+
+    /// Drop the cached token, e.g. after the broker rejected it.
+    pub(crate) async fn invalidate(&self) {
+        *self.cache.lock().await = None;
+    }
+}
+
+/// Error returned by a [`GssapiProvider`] or [`GssapiClientContext`].
+pub type GssapiError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Client side of a GSSAPI security context.
+///
+/// This is usually backed by a binding to the system's GSSAPI library (e.g. the `libgssapi` or `cross-krb5` crates),
+/// using the Kerberos V5 mechanism.
+pub trait GssapiClientContext: Send {
+    /// Process the `token` received from the server (`None` for the first call) and return the token that shall be
+    /// sent to the server, if any.
+    fn step(&mut self, token: Option<&[u8]>) -> Result<Option<Vec<u8>>, GssapiError>;
+
+    /// Returns `true` once the security context is established.
+    fn is_complete(&self) -> bool;
+
+    /// Verify and unwrap a message that the server protected with `GSS_Wrap`.
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, GssapiError>;
+
+    /// Protect a message for the server via `GSS_Wrap`, without confidentiality.
+    fn wrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, GssapiError>;
+}
+
+/// Creates [`GssapiClientContext`]s for [`SaslConfig::Gssapi`].
+pub trait GssapiProvider: Debug + Send + Sync {
+    /// Create a context for the host-based service principal `service@hostname`.
+    fn client_context(
+        &self,
+        service: &str,
+        hostname: &str,
+    ) -> Result<Box<dyn GssapiClientContext>, GssapiError>;
+}
+
+/// Configuration for [`SaslConfig::Gssapi`].
+#[derive(Debug, Clone)]
+pub struct GssapiConfig {
+    pub(crate) provider: Arc<dyn GssapiProvider>,
+    pub(crate) service_name: String,
+}
+
+impl GssapiConfig {
+    pub fn new(provider: Arc<dyn GssapiProvider>) -> Self {
+        Self {
+            provider,
+            service_name: "kafka".to_owned(),
+        }
+    }
+
+    /// Set the service name of the broker principals, i.e. the `sasl.kerberos.service.name` setting of the brokers.
+    ///
+    /// Defaults to `kafka`.
+    pub fn with_service_name(self, service_name: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            ..self
+        }
+    }
+}
+
+/// Error returned by a [`SaslProvider`] or [`SaslSession`].
+pub type SaslProviderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Client side of a single authentication exchange of a [`SaslProvider`].
+pub trait SaslSession: Send {
+    /// Process the `challenge` received from the server (`None` for the first step) and return the response that
+    /// shall be sent to the server.
+    ///
+    /// Returns `None` once the authentication is complete.
+    fn step<'a>(
+        &'a mut self,
+        challenge: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, SaslProviderError>>;
+}
+
+/// Custom SASL mechanism for [`SaslConfig::Custom`].
+///
+/// The mechanism is negotiated via `SaslHandshake` and the messages returned by the [`SaslSession`] are exchanged via
+/// `SaslAuthenticate` requests ([KIP-152]). Authentication fails as soon as the broker returns an error.
+///
+/// [KIP-152]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-152+-+Improve+diagnostics+for+SASL+authentication+failures
+pub trait SaslProvider: Debug + Send + Sync {
+    /// Name of the mechanism, e.g. `AWS_MSK_IAM`.
+    fn mechanism(&self) -> &str;
+
+    /// Start a new authentication exchange with the broker at `host`.
+    fn start(&self, host: &str) -> Result<Box<dyn SaslSession>, SaslProviderError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::Duration;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct Provider {
+        calls: AtomicUsize,
+        lifetime: Option<Duration>,
+    }
+
+    impl OauthBearerTokenProvider for Provider {
+        fn token(&self) -> BoxFuture<'_, Result<OauthBearerToken, OauthBearerTokenError>> {
+            Box::pin(async move {
+                let n = self.calls.fetch_add(1, Ordering::SeqCst);
+                Ok(OauthBearerToken {
+                    expires_at: self.lifetime.map(|l| utc_now() + l),
+                    ..OauthBearerToken::new(format!("token{n}"))
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn test_client_response() {
+        let token = OauthBearerToken {
+            extensions: BTreeMap::from([("logicalCluster".to_owned(), "lkc-1".to_owned())]),
+            ..OauthBearerToken::new("foo".to_owned())
+        };
+        assert_eq!(
+            token.client_response(),
+            b"n,,\x01auth=Bearer foo\x01logicalCluster=lkc-1\x01\x01"
+        );
+        assert!(!format!("{token:?}").contains("foo"));
+    }
+
+    #[tokio::test]
+    async fn test_token_cache() {
+        let provider = Arc::new(Provider::default());
+        let config = OauthBearerConfig::new(Arc::<Provider>::clone(&provider) as _);
+
+        assert_eq!(config.token().await.unwrap().token, "token0");
+        assert_eq!(config.clone().token().await.unwrap().token, "token0");
+
+        config.invalidate().await;
+        assert_eq!(config.token().await.unwrap().token, "token1");
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_token_refresh() {
+        let provider = Arc::new(Provider {
+            calls: Default::default(),
+            lifetime: Some(Duration::zero()),
+        });
+        let config = OauthBearerConfig::new(Arc::<Provider>::clone(&provider) as _);
+
+        assert_eq!(config.token().await.unwrap().token, "token0");
+        assert_eq!(config.token().await.unwrap().token, "token1");
+    }
 }
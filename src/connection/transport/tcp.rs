@@ -0,0 +1,198 @@
+use std::{io, net::SocketAddr, time::Duration};
+
+use socket2::SockRef;
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+
+/// TCP socket options for broker connections.
+///
+/// The default leaves all options at the OS defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpConfig {
+    /// Set `TCP_NODELAY`, i.e. disable Nagle's algorithm.
+    pub nodelay: bool,
+
+    /// Enable TCP keepalive.
+    pub keepalive: Option<TcpKeepalive>,
+
+    /// Size of the socket send buffer (`SO_SNDBUF`).
+    pub send_buffer_size: Option<u32>,
+
+    /// Size of the socket receive buffer (`SO_RCVBUF`).
+    pub recv_buffer_size: Option<u32>,
+}
+
+impl TcpConfig {
+    pub fn with_nodelay(self, nodelay: bool) -> Self {
+        Self { nodelay, ..self }
+    }
+
+    pub fn with_keepalive(self, keepalive: TcpKeepalive) -> Self {
+        Self {
+            keepalive: Some(keepalive),
+            ..self
+        }
+    }
+
+    pub fn with_send_buffer_size(self, size: u32) -> Self {
+        Self {
+            send_buffer_size: Some(size),
+            ..self
+        }
+    }
+
+    pub fn with_recv_buffer_size(self, size: u32) -> Self {
+        Self {
+            recv_buffer_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Resolve `addr` and connect to the first address that accepts the connection.
+    pub(crate) async fn connect(&self, addr: &str) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for addr in lookup_host(addr).await? {
+            match self.connect_addr(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            )
+        }))
+    }
+
+    async fn connect_addr(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+
+        // buffer sizes must be set before connecting, otherwise they do not affect the TCP window scaling
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        let stream = socket.connect(addr).await?;
+        stream.set_nodelay(self.nodelay)?;
+        self.set_keepalive(&SockRef::from(&stream))?;
+
+        Ok(stream)
+    }
+
+    /// Apply the options other than `nodelay` to a connected socket, for runtimes that do not allow to configure the
+    /// socket before connecting.
+    ///
+    /// Buffer sizes that are set after connecting might not affect the TCP window scaling.
+    #[cfg(any(feature = "runtime-async-std", feature = "runtime-smol"))]
+    pub(crate) fn apply_after_connect(&self, socket: &SockRef<'_>) -> io::Result<()> {
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size as usize)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size as usize)?;
+        }
+        self.set_keepalive(socket)
+    }
+
+    fn set_keepalive(&self, socket: &SockRef<'_>) -> io::Result<()> {
+        match &self.keepalive {
+            Some(keepalive) => socket.set_tcp_keepalive(&keepalive.to_socket2()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// TCP keepalive settings.
+///
+/// Unset values use the OS defaults. The probe interval and count are ignored on platforms that do not support
+/// configuring them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// Idle time before the first keepalive probe is sent (`TCP_KEEPIDLE`).
+    pub time: Option<Duration>,
+
+    /// Time between keepalive probes (`TCP_KEEPINTVL`).
+    pub interval: Option<Duration>,
+
+    /// Number of unanswered probes after which the connection is dropped (`TCP_KEEPCNT`).
+    pub retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    pub fn with_time(self, time: Duration) -> Self {
+        Self {
+            time: Some(time),
+            ..self
+        }
+    }
+
+    pub fn with_interval(self, interval: Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            ..self
+        }
+    }
+
+    pub fn with_retries(self, retries: u32) -> Self {
+        Self {
+            retries: Some(retries),
+            ..self
+        }
+    }
+
+    fn to_socket2(self) -> socket2::TcpKeepalive {
+        let mut keepalive = socket2::TcpKeepalive::new();
+        if let Some(time) = self.time {
+            keepalive = keepalive.with_time(time);
+        }
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "windows",
+        ))]
+        {
+            if let Some(interval) = self.interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+            if let Some(retries) = self.retries {
+                keepalive = keepalive.with_retries(retries);
+            }
+        }
+
+        keepalive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_applies_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let config = TcpConfig::default()
+            .with_nodelay(true)
+            .with_keepalive(TcpKeepalive::default().with_time(Duration::from_secs(30)));
+        let stream = config.connect(&addr).await.unwrap();
+
+        assert!(stream.nodelay().unwrap());
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+}
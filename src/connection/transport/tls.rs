@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        ResolvesClientCert,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    sign::CertifiedKey,
+    CertificateError, DigitallySignedStruct, SignatureScheme,
+};
+
+/// Client certificate that can be replaced at runtime, e.g. to rotate mTLS certificates.
+///
+/// Pass it to [`rustls::ConfigBuilder::with_client_cert_resolver`] when building the TLS config for the
+/// [`ClientBuilder`](crate::client::ClientBuilder). New connections use the current certificate, established
+/// connections are not affected and keep working until they are closed for other reasons.
+#[derive(Debug, Default)]
+pub struct ReloadableClientCert {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl ReloadableClientCert {
+    pub fn new(cert: Option<Arc<CertifiedKey>>) -> Self {
+        Self {
+            current: RwLock::new(cert),
+        }
+    }
+
+    /// Current certificate.
+    pub fn get(&self) -> Option<Arc<CertifiedKey>> {
+        self.current.read().clone()
+    }
+
+    /// Replace the certificate that is used for new connections.
+    pub fn set(&self, cert: Option<Arc<CertifiedKey>>) {
+        *self.current.write() = cert;
+    }
+}
+
+impl ResolvesClientCert for ReloadableClientCert {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        self.get()
+    }
+
+    fn has_certs(&self) -> bool {
+        self.current.read().is_some()
+    }
+}
+
+/// Certificate verifier that accepts server certificates that are not valid for the server name.
+///
+/// All other checks (chain of trust, expiry, signatures) are delegated to the inner verifier. This is an escape hatch
+/// for brokers behind load balancers whose certificates do not match any name the client can use. Prefer overriding
+/// the server name via [`ClientBuilder::tls_server_name`](crate::client::ClientBuilder::tls_server_name) where
+/// possible, since skipping the name check allows any holder of a trusted certificate to impersonate the brokers.
+///
+/// Install it via [`rustls::ClientConfig::dangerous`].
+#[derive(Debug)]
+pub struct SkipServerNameVerification {
+    inner: Arc<dyn ServerCertVerifier>,
+}
+
+impl SkipServerNameVerification {
+    pub fn new(inner: Arc<dyn ServerCertVerifier>) -> Self {
+        Self { inner }
+    }
+}
+
+impl ServerCertVerifier for SkipServerNameVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) {
+            // the name is checked last, so all other checks passed
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            res => res,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+
+    fn requires_raw_public_keys(&self) -> bool {
+        self.inner.requires_raw_public_keys()
+    }
+
+    fn root_hint_subjects(&self) -> Option<&[rustls::DistinguishedName]> {
+        self.inner.root_hint_subjects()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::{
+        sign::{Signer, SigningKey},
+        SignatureAlgorithm,
+    };
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct DummyKey;
+
+    impl SigningKey for DummyKey {
+        fn choose_scheme(&self, _offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+            None
+        }
+
+        fn algorithm(&self) -> SignatureAlgorithm {
+            SignatureAlgorithm::ED25519
+        }
+    }
+
+    fn cert(data: &[u8]) -> Arc<CertifiedKey> {
+        Arc::new(CertifiedKey::new(
+            vec![CertificateDer::from(data.to_vec())],
+            Arc::new(DummyKey),
+        ))
+    }
+
+    #[derive(Debug)]
+    struct FailingVerifier(CertificateError);
+
+    impl ServerCertVerifier for FailingVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Err(rustls::Error::InvalidCertificate(self.0.clone()))
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            unimplemented!()
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            unimplemented!()
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            vec![]
+        }
+    }
+
+    fn verify(inner: FailingVerifier) -> Result<ServerCertVerified, rustls::Error> {
+        SkipServerNameVerification::new(Arc::new(inner)).verify_server_cert(
+            &CertificateDer::from(vec![]),
+            &[],
+            &ServerName::try_from("broker").unwrap(),
+            &[],
+            UnixTime::now(),
+        )
+    }
+
+    #[test]
+    fn test_skip_server_name_verification() {
+        verify(FailingVerifier(CertificateError::NotValidForName)).unwrap();
+        verify(FailingVerifier(CertificateError::Expired)).unwrap_err();
+    }
+
+    #[test]
+    fn test_reload() {
+        let resolver = ReloadableClientCert::default();
+        assert!(!resolver.has_certs());
+        assert!(resolver.resolve(&[], &[]).is_none());
+
+        resolver.set(Some(cert(b"foo")));
+        assert!(resolver.has_certs());
+        assert_eq!(resolver.resolve(&[], &[]).unwrap().cert[0].as_ref(), b"foo");
+
+        resolver.set(Some(cert(b"bar")));
+        assert_eq!(resolver.resolve(&[], &[]).unwrap().cert[0].as_ref(), b"bar");
+    }
+}
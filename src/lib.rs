@@ -23,8 +23,20 @@ pub use backoff::{BackoffConfig, BackoffError};
 
 pub mod build_info;
 
+pub mod capture;
+
+#[cfg(feature = "chaos")]
+pub mod chaos;
+
 pub mod client;
 
+pub mod codec;
+
+pub mod metrics;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_broker;
+
 mod connection;
 
 pub use connection::Error as ConnectionError;
@@ -34,17 +46,25 @@ pub mod messenger;
 #[cfg(not(feature = "unstable-fuzzing"))]
 mod messenger;
 
-#[cfg(feature = "unstable-fuzzing")]
+#[cfg(feature = "protocol")]
 pub mod protocol;
-#[cfg(not(feature = "unstable-fuzzing"))]
+#[cfg(not(feature = "protocol"))]
 mod protocol;
 
 pub mod record;
 
+pub mod runtime;
+
+#[cfg(feature = "test-container")]
+pub mod test_container;
+
 mod throttle;
 
 pub mod topic;
 
+#[cfg(feature = "otel")]
+pub mod trace_context;
+
 // re-exports
 pub use chrono;
 
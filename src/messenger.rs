@@ -8,6 +8,7 @@ use std::{
         Arc,
     },
     task::Poll,
+    time::{Duration, Instant},
 };
 
 use futures::future::BoxFuture;
@@ -22,17 +23,21 @@ use tokio::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt, WriteHalf},
     sync::{
         oneshot::{channel, Sender},
-        Mutex as AsyncMutex,
+        Mutex as AsyncMutex, RwLock as AsyncRwLock, Semaphore,
     },
-    task::JoinHandle,
 };
-use tracing::{debug, info, warn};
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
 
+#[cfg(feature = "chaos")]
+use crate::chaos::{Fault, FaultContext, FaultPolicy};
 use crate::{
     backoff::ErrorOrThrottle,
+    capture::{Frame, FrameCapture, FrameDirection},
+    metrics::{Metrics, RequestEnd, RequestErrorClass, RequestStart},
     protocol::{
         api_key::ApiKey,
         api_version::ApiVersion,
+        buffer_pool::{self, PooledBuffer},
         error::Error as ApiError,
         frame::{AsyncMessageRead, AsyncMessageWrite},
         messages::{
@@ -42,6 +47,7 @@ use crate::{
         },
         primitives::{Int16, Int32, NullableString, TaggedFields},
     },
+    runtime::{self, JoinHandle},
     throttle::maybe_throttle,
 };
 use crate::{
@@ -49,7 +55,10 @@ use crate::{
     protocol::{api_version::ApiVersionRange, primitives::CompactString},
 };
 use crate::{
-    connection::Credentials,
+    connection::{
+        ConnectionEvent, ConnectionEventHandler, Credentials, GssapiConfig, GssapiError,
+        OauthBearerConfig, OauthBearerTokenError, SaslProvider, SaslProviderError,
+    },
     protocol::{messages::ApiVersionsRequest, traits::ReadType},
 };
 
@@ -105,9 +114,10 @@ impl MessengerState {
 
 /// A connection to a single broker
 ///
-/// Note: Requests to the same [`Messenger`] will be pipelined by Kafka
+/// Note: Requests to the same [`Messenger`] will be pipelined by Kafka, i.e. multiple requests can be in flight and
+/// responses are matched to their requests via the correlation ID. The pipelining depth can be limited via
+/// [`set_max_in_flight`](Self::set_max_in_flight).
 ///
-#[derive(Debug)]
 pub struct Messenger<RW> {
     /// The half of the stream that we use to send data TO the broker.
     ///
@@ -134,6 +144,154 @@ pub struct Messenger<RW> {
 
     /// Join handle for the background worker that fetches responses.
     join_handle: JoinHandle<()>,
+
+    /// Regular requests hold a read lock while being sent, SASL re-authentication holds the write lock.
+    ///
+    /// Brokers reject other requests in between the messages of a SASL exchange.
+    auth_gate: AsyncRwLock<()>,
+
+    /// Session lifetime reported by the last successful SASL authentication step.
+    sasl_session_lifetime: Mutex<Option<Duration>>,
+
+    /// Time after which a request without response poisons the connection.
+    request_timeout: Option<Duration>,
+
+    /// Set once the connection was closed via [`close`](Self::close).
+    ///
+    /// Requests hold a read lock for their whole duration, so closing waits for in-flight requests.
+    closed: AsyncRwLock<bool>,
+
+    /// Time of the last response, or of the creation if there was none yet.
+    last_response: Mutex<Instant>,
+
+    /// Limits the number of requests that are in flight concurrently, if configured.
+    in_flight: Option<Semaphore>,
+
+    /// Receives all frames except for the ones of the SASL exchange, if configured.
+    frame_capture: Option<FrameCapture>,
+
+    /// Receives request and connection events, if configured.
+    metrics: Option<ConnectionMetrics>,
+
+    /// Receives lifecycle events together with the broker address, if configured.
+    event_handler: Option<(ConnectionEventHandler, String)>,
+
+    /// ID of the broker, if known.
+    broker_id: Option<i32>,
+
+    /// `host:port` of the broker, if known.
+    broker_address: Option<Arc<str>>,
+
+    /// Decides which faults to inject into requests, if configured.
+    #[cfg(feature = "chaos")]
+    fault_policy: Option<FaultPolicy>,
+}
+
+#[cfg(feature = "chaos")]
+fn inject_fault_into_response<R>(
+    mut response: R::ResponseBody,
+    fault: Option<Fault>,
+) -> R::ResponseBody
+where
+    R: RequestBody,
+{
+    match fault {
+        Some(Fault::Throttle(throttle)) => {
+            let throttle_time_ms = i32::try_from(throttle.as_millis()).unwrap_or(i32::MAX);
+            R::inject_throttle(&mut response, throttle_time_ms);
+        }
+        Some(Fault::Error(error)) => R::inject_error(&mut response, error),
+        _ => {}
+    }
+    response
+}
+
+/// Reports the connection as closed when dropped.
+struct ConnectionMetrics {
+    metrics: Arc<dyn Metrics>,
+    broker: String,
+}
+
+impl Drop for ConnectionMetrics {
+    fn drop(&mut self) {
+        self.metrics.connection_closed(&self.broker);
+    }
+}
+
+/// Reports the end of a request when dropped.
+struct RequestReport<'a> {
+    metrics: &'a dyn Metrics,
+    started: Instant,
+    end: RequestEnd,
+}
+
+impl<'a> RequestReport<'a> {
+    fn start(metrics: &'a dyn Metrics, start: RequestStart) -> Self {
+        metrics.request_started(&start);
+        Self {
+            metrics,
+            started: Instant::now(),
+            end: RequestEnd {
+                api_key: start.api_key,
+                api_version: start.api_version,
+                duration: Duration::ZERO,
+                bytes_sent: start.bytes_sent,
+                bytes_received: 0,
+                error: Some(RequestErrorClass::Cancelled),
+            },
+        }
+    }
+
+    fn finish(mut self, bytes_received: usize, error: Option<&RequestError>) {
+        self.end.bytes_received = bytes_received;
+        self.end.error = error.map(RequestErrorClass::of);
+    }
+}
+
+impl Drop for RequestReport<'_> {
+    fn drop(&mut self) {
+        self.end.duration = self.started.elapsed();
+        self.metrics.request_completed(&self.end);
+    }
+}
+
+/// Broker and request that a response or [`RequestError`] belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequestInfo {
+    /// ID of the broker, if known.
+    pub broker_id: Option<i32>,
+
+    /// `host:port` of the broker, if known.
+    pub broker_address: Option<Arc<str>>,
+
+    /// API of the request.
+    pub api_key: ApiKey,
+
+    /// API version of the request, unless the request failed before a version was chosen.
+    pub api_version: Option<i16>,
+
+    /// Correlation ID of the request, unless the request failed before it was assigned.
+    pub correlation_id: Option<i32>,
+}
+
+impl std::fmt::Display for RequestInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.broker_id, &self.broker_address) {
+            (Some(id), Some(address)) => write!(f, "broker {id} ({address}), ")?,
+            (Some(id), None) => write!(f, "broker {id}, ")?,
+            (None, Some(address)) => write!(f, "broker {address}, ")?,
+            (None, None) => {}
+        }
+        write!(f, "{:?}", self.api_key)?;
+        if let Some(api_version) = self.api_version {
+            write!(f, " v{api_version}")?;
+        }
+        if let Some(correlation_id) = self.correlation_id {
+            write!(f, ", correlation ID {correlation_id}")?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -172,6 +330,15 @@ pub enum RequestError {
 
     #[error("Connection is poisoned: {0}")]
     Poisoned(Arc<RequestError>),
+
+    #[error("Shared request failed: {0}")]
+    Shared(Arc<RequestError>),
+
+    #[error("Request timed out after {timeout:?}: api_key={api_key:?}")]
+    Timeout { api_key: ApiKey, timeout: Duration },
+
+    #[error("Connection is closed")]
+    Closed,
 }
 
 #[derive(Error, Debug)]
@@ -207,6 +374,47 @@ pub enum SaslError {
 
     #[error("unsupported sasl mechanism")]
     UnsupportedSaslMechanism,
+
+    #[error("Cannot get OAUTHBEARER token: {0}")]
+    OauthBearerToken(OauthBearerTokenError),
+
+    #[error("GSSAPI error: {0}")]
+    Gssapi(GssapiError),
+
+    #[error("SASL provider error: {0}")]
+    Provider(SaslProviderError),
+}
+
+impl<RW> std::fmt::Debug for Messenger<RW>
+where
+    RW: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = f.debug_struct("Messenger");
+        f.field("stream_write", &self.stream_write)
+            .field("client_id", &self.client_id)
+            .field("correlation_id", &self.correlation_id)
+            .field("version_ranges", &self.version_ranges)
+            .field("state", &self.state)
+            .field("join_handle", &self.join_handle)
+            .field("auth_gate", &self.auth_gate)
+            .field("sasl_session_lifetime", &self.sasl_session_lifetime)
+            .field("request_timeout", &self.request_timeout)
+            .field("closed", &self.closed)
+            .field("last_response", &self.last_response)
+            .field("in_flight", &self.in_flight)
+            .field("frame_capture", &self.frame_capture.as_ref().map(|_| "..."))
+            .field("metrics", &self.metrics.as_ref().map(|_| "..."))
+            .field(
+                "event_handler",
+                &self.event_handler.as_ref().map(|(_, broker)| broker),
+            )
+            .field("broker_id", &self.broker_id)
+            .field("broker_address", &self.broker_address);
+        #[cfg(feature = "chaos")]
+        f.field("fault_policy", &self.fault_policy.as_ref().map(|_| "..."));
+        f.finish()
+    }
 }
 
 impl<RW> Messenger<RW>
@@ -218,7 +426,7 @@ where
         let state = Arc::new(Mutex::new(MessengerState::RequestMap(HashMap::default())));
         let state_captured = Arc::clone(&state);
 
-        let join_handle = tokio::spawn(async move {
+        let join_handle = runtime::spawn(async move {
             let mut stream_read = stream_read;
 
             loop {
@@ -298,9 +506,97 @@ where
             version_ranges: HashMap::new(),
             state,
             join_handle,
+            auth_gate: AsyncRwLock::new(()),
+            sasl_session_lifetime: Mutex::new(None),
+            request_timeout: None,
+            closed: AsyncRwLock::new(false),
+            last_response: Mutex::new(Instant::now()),
+            in_flight: None,
+            frame_capture: None,
+            metrics: None,
+            event_handler: None,
+            broker_id: None,
+            broker_address: None,
+            #[cfg(feature = "chaos")]
+            fault_policy: None,
+        }
+    }
+
+    /// Pass all request and response frames to `capture`.
+    ///
+    /// Frames of the SASL exchange are skipped since they contain credentials.
+    pub fn set_frame_capture(&mut self, capture: Option<FrameCapture>) {
+        self.frame_capture = capture;
+    }
+
+    /// Report requests to `metrics` and the connection to `broker` as opened now and as closed once the messenger is
+    /// dropped.
+    pub fn set_metrics(&mut self, metrics: Arc<dyn Metrics>, broker: &str) {
+        metrics.connection_opened(broker);
+        self.metrics = Some(ConnectionMetrics {
+            metrics,
+            broker: broker.to_owned(),
+        });
+    }
+
+    /// Inject the faults that `policy` returns into regular requests, i.e. not into the ones that set up the
+    /// connection.
+    #[cfg(feature = "chaos")]
+    pub fn set_fault_policy(&mut self, policy: Option<FaultPolicy>) {
+        self.fault_policy = policy;
+    }
+
+    /// Identify the broker in the spans of requests.
+    pub fn set_broker(&mut self, id: Option<i32>, address: &str) {
+        self.broker_id = id;
+        self.broker_address = Some(Arc::from(address));
+    }
+
+    /// Report lifecycle events of the connection to `broker` to `handler`.
+    pub fn set_event_handler(&mut self, handler: ConnectionEventHandler, broker: &str) {
+        self.event_handler = Some((handler, broker.to_owned()));
+    }
+
+    /// Pass `event` to the [event handler](Self::set_event_handler), if any.
+    pub fn report_event(&self, event: &ConnectionEvent<'_>) {
+        if let Some((handler, broker)) = &self.event_handler {
+            handler(broker, event);
         }
     }
 
+    /// Limit the number of requests that are in flight concurrently.
+    ///
+    /// Further requests wait until a response for an earlier one was received. `None` means no limit.
+    ///
+    /// # Panics
+    /// Panics if `max_in_flight` is `Some(0)`.
+    pub fn set_max_in_flight(&mut self, max_in_flight: Option<usize>) {
+        assert_ne!(
+            max_in_flight,
+            Some(0),
+            "max in-flight requests must be positive"
+        );
+        self.in_flight = max_in_flight.map(Semaphore::new);
+    }
+
+    /// Set the time after which a request without response fails and poisons the connection.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.request_timeout = timeout;
+    }
+
+    /// Version ranges that the broker advertised.
+    pub fn version_ranges(&self) -> &HashMap<ApiKey, ApiVersionRange> {
+        &self.version_ranges
+    }
+
+    /// Version that requests of type `R` are sent with, or `None` if the broker does not support any version we
+    /// implement.
+    pub fn api_version<R: RequestBody>(&self) -> Option<ApiVersion> {
+        self.version_ranges
+            .get(&R::API_KEY)
+            .and_then(|range_server| match_versions(*range_server, R::API_VERSION_RANGE))
+    }
+
     #[cfg(feature = "unstable-fuzzing")]
     pub fn override_version_ranges(&mut self, ranges: HashMap<ApiKey, ApiVersionRange>) {
         self.set_version_ranges(ranges);
@@ -316,19 +612,92 @@ where
         R: RequestBody + Send + WriteVersionedType<Vec<u8>>,
         R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>>,
     {
-        self.request_with_version_ranges(msg, &self.version_ranges)
+        self.request_with_info(msg).await.0
+    }
+
+    /// Same as [`request`](Self::request), but also returns the broker and request that the result belongs to.
+    pub async fn request_with_info<R>(
+        &self,
+        msg: R,
+    ) -> (Result<R::ResponseBody, RequestError>, RequestInfo)
+    where
+        R: RequestBody + Send + WriteVersionedType<Vec<u8>>,
+        R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>>,
+    {
+        self.request_with_version_ranges(msg, &self.version_ranges, true)
             .await
     }
 
+    /// Send a request within a span that identifies the request and the broker and records the error, if any.
     async fn request_with_version_ranges<R>(
         &self,
         msg: R,
         version_ranges: &HashMap<ApiKey, ApiVersionRange>,
+        gated: bool,
+    ) -> (Result<R::ResponseBody, RequestError>, RequestInfo)
+    where
+        R: RequestBody + Send + WriteVersionedType<Vec<u8>>,
+        R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>>,
+    {
+        let mut info = RequestInfo {
+            broker_id: self.broker_id,
+            broker_address: self.broker_address.clone(),
+            api_key: R::API_KEY,
+            api_version: None,
+            correlation_id: None,
+        };
+        let span = info_span!(
+            "kafka_request",
+            api_key = ?R::API_KEY,
+            api_version = field::Empty,
+            correlation_id = field::Empty,
+            broker_id = self.broker_id,
+            broker = self.broker_address.as_deref(),
+            error = field::Empty,
+        );
+        let res = self
+            .request_in_span(msg, version_ranges, gated, &span, &mut info)
+            .instrument(span.clone())
+            .await;
+        if let Err(e) = &res {
+            span.record("error", field::display(e));
+        }
+        (res, info)
+    }
+
+    async fn request_in_span<R>(
+        &self,
+        msg: R,
+        version_ranges: &HashMap<ApiKey, ApiVersionRange>,
+        gated: bool,
+        span: &Span,
+        info: &mut RequestInfo,
     ) -> Result<R::ResponseBody, RequestError>
     where
         R: RequestBody + Send + WriteVersionedType<Vec<u8>>,
         R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>>,
     {
+        // ungated requests are either sent before the messenger is shared or by `reauthenticate`, which holds the
+        // lock already
+        let _closed_guard = if gated {
+            let guard = self.closed.read().await;
+            if *guard {
+                return Err(RequestError::Closed);
+            }
+            Some(guard)
+        } else {
+            None
+        };
+        let _in_flight_permit = match (&self.in_flight, gated) {
+            (Some(in_flight), true) => Some(
+                in_flight
+                    .acquire()
+                    .await
+                    .expect("semaphore is never closed"),
+            ),
+            _ => None,
+        };
+
         let body_api_version = version_ranges
             .get(&R::API_KEY)
             .and_then(|range_server| match_versions(*range_server, R::API_VERSION_RANGE))
@@ -347,6 +716,10 @@ where
 
         // Correlation ID so that we can de-multiplex the responses.
         let correlation_id = self.correlation_id.fetch_add(1, Ordering::SeqCst);
+        span.record("api_version", body_api_version.0 .0);
+        span.record("correlation_id", correlation_id);
+        info.api_version = Some(body_api_version.0 .0);
+        info.correlation_id = Some(correlation_id);
 
         let header = RequestHeader {
             request_api_key: R::API_KEY,
@@ -363,12 +736,70 @@ where
             ApiVersion(Int16(1))
         };
 
-        let mut buf = Vec::new();
+        let mut buf = buffer_pool::take();
         header
-            .write_versioned(&mut buf, header_version)
+            .write_versioned(&mut *buf, header_version)
             .expect("Writing header to buffer should always work");
-        msg.write_versioned(&mut buf, body_api_version)?;
+        msg.write_versioned(&mut *buf, body_api_version)?;
+        self.capture_frame::<R>(FrameDirection::Request, body_api_version, &buf);
+
+        // reports the request as cancelled if this future is dropped
+        let report = self.metrics.as_ref().map(|m| {
+            RequestReport::start(
+                m.metrics.as_ref(),
+                RequestStart {
+                    api_key: Int16::from(R::API_KEY).0,
+                    api_version: body_api_version.0 .0,
+                    bytes_sent: buf.len(),
+                },
+            )
+        });
+        let mut bytes_received = 0;
+
+        #[cfg(feature = "chaos")]
+        let fault = self.fault::<R>(body_api_version, gated);
+        #[cfg(feature = "chaos")]
+        let injected = self.inject_fault_before_send(fault).await;
+        #[cfg(not(feature = "chaos"))]
+        let injected = Ok(());
+
+        let res = match injected {
+            Ok(()) => {
+                self.send_request::<R>(
+                    buf,
+                    correlation_id,
+                    use_tagged_fields_in_response,
+                    body_api_version,
+                    gated,
+                    &mut bytes_received,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        #[cfg(feature = "chaos")]
+        let res = res.map(|body| inject_fault_into_response::<R>(body, fault));
+
+        if let Some(report) = report {
+            report.finish(bytes_received, res.as_ref().err());
+        }
+        res
+    }
 
+    /// Send a request that was encoded into `buf` and decode the response.
+    async fn send_request<R>(
+        &self,
+        buf: PooledBuffer<'static>,
+        correlation_id: i32,
+        use_tagged_fields_in_response: bool,
+        body_api_version: ApiVersion,
+        gated: bool,
+        bytes_received: &mut usize,
+    ) -> Result<R::ResponseBody, RequestError>
+    where
+        R: RequestBody,
+        R::ResponseBody: ReadVersionedType<Cursor<Vec<u8>>>,
+    {
         let (tx, rx) = channel();
 
         // to prevent stale data in inner state, ensure that we would remove the request again if we are cancelled while
@@ -391,10 +822,65 @@ where
             }
         }
 
-        self.send_message(buf).await?;
-        cleanup_on_cancel.message_sent();
-
-        let mut response = rx.await.expect("Who closed this channel?!")?;
+        let send_and_receive = async {
+            let auth_guard = if gated {
+                Some(self.auth_gate.read().await)
+            } else {
+                None
+            };
+            debug!(
+                client_id = self.client_id.as_ref(),
+                correlation_id,
+                api_key = ?R::API_KEY,
+                api_version = body_api_version.0 .0,
+                "Sending request",
+            );
+            self.send_message(buf).await?;
+            cleanup_on_cancel.message_sent();
+            drop(auth_guard);
+
+            let response = rx.await.expect("Who closed this channel?!");
+            *self.last_response.lock() = Instant::now();
+            debug!(
+                client_id = self.client_id.as_ref(),
+                correlation_id,
+                api_key = ?R::API_KEY,
+                ok = response.is_ok(),
+                "Received response",
+            );
+            response
+        };
+        let mut response = match self.request_timeout {
+            Some(timeout) => match runtime::timeout(timeout, send_and_receive).await {
+                Ok(res) => res?,
+                Err(_) => {
+                    warn!(
+                        client_id = self.client_id.as_ref(),
+                        correlation_id,
+                        api_key = ?R::API_KEY,
+                        ?timeout,
+                        "Request timed out",
+                    );
+
+                    // the response might still arrive, but there is no way to tell if the broker is just slow or if
+                    // the connection is dead, so treat it like an IO error
+                    let mut state = self.state.lock();
+                    return Err(RequestError::Poisoned(state.poison(
+                        RequestError::Timeout {
+                            api_key: R::API_KEY,
+                            timeout,
+                        },
+                    )));
+                }
+            },
+            None => send_and_receive.await?,
+        };
+        *bytes_received = response.data.get_ref().len();
+        self.capture_frame::<R>(
+            FrameDirection::Response,
+            body_api_version,
+            response.data.get_ref(),
+        );
         let body = R::ResponseBody::read_versioned(&mut response.data, body_api_version)?;
 
         // check if we fully consumed the message, otherwise there might be a bug in our protocol code
@@ -412,7 +898,60 @@ where
         Ok(body)
     }
 
-    async fn send_message(&self, msg: Vec<u8>) -> Result<(), RequestError> {
+    /// Ask the fault policy, if any, which fault to inject into a regular request.
+    #[cfg(feature = "chaos")]
+    fn fault<R>(&self, api_version: ApiVersion, gated: bool) -> Option<Fault>
+    where
+        R: RequestBody,
+    {
+        let policy = self.fault_policy.as_ref().filter(|_| gated)?;
+        let fault = policy(&FaultContext {
+            broker_id: self.broker_id,
+            broker: self.broker_address.as_deref(),
+            api_key: Int16::from(R::API_KEY).0,
+            api_version: api_version.0 .0,
+        })?;
+        debug!(?fault, api_key = ?R::API_KEY, "Injecting fault");
+        Some(fault)
+    }
+
+    #[cfg(feature = "chaos")]
+    async fn inject_fault_before_send(&self, fault: Option<Fault>) -> Result<(), RequestError> {
+        match fault {
+            Some(Fault::Latency(latency)) => {
+                runtime::sleep(latency).await;
+                Ok(())
+            }
+            Some(Fault::Disconnect) => {
+                let mut state = self.state.lock();
+                Err(RequestError::Poisoned(state.poison(RequestError::IO(
+                    std::io::Error::new(std::io::ErrorKind::ConnectionReset, "injected fault"),
+                ))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn capture_frame<R>(&self, direction: FrameDirection, api_version: ApiVersion, data: &[u8])
+    where
+        R: RequestBody,
+    {
+        let Some(capture) = &self.frame_capture else {
+            return;
+        };
+        if matches!(R::API_KEY, ApiKey::SaslHandshake | ApiKey::SaslAuthenticate) {
+            return;
+        }
+
+        capture(&Frame {
+            direction,
+            api_key: Int16::from(R::API_KEY).0,
+            api_version: api_version.0 .0,
+            data: data.to_vec(),
+        });
+    }
+
+    async fn send_message(&self, msg: PooledBuffer<'static>) -> Result<(), RequestError> {
         match self.send_message_inner(msg).await {
             Ok(()) => Ok(()),
             Err(e) => {
@@ -423,7 +962,7 @@ where
         }
     }
 
-    async fn send_message_inner(&self, msg: Vec<u8>) -> Result<(), RequestError> {
+    async fn send_message_inner(&self, msg: PooledBuffer<'static>) -> Result<(), RequestError> {
         let mut stream_write = Arc::clone(&self.stream_write).lock_owned().await;
 
         // use a wrapper so that cancelation doesn't cancel the send operation and leaves half-send messages on the wire
@@ -462,8 +1001,9 @@ where
 
             'throttle: loop {
                 match self
-                    .request_with_version_ranges(&body, &version_ranges)
+                    .request_with_version_ranges(&body, &version_ranges, true)
                     .await
+                    .0
                 {
                     Ok(response) => {
                         if let Err(ErrorOrThrottle::Throttle(throttle)) =
@@ -474,7 +1014,7 @@ where
                                 request_name = "version sync",
                                 "broker asked us to throttle"
                             );
-                            tokio::time::sleep(throttle).await;
+                            runtime::sleep(throttle).await;
                             continue 'throttle;
                         }
 
@@ -557,7 +1097,10 @@ where
         auth_bytes: Vec<u8>,
     ) -> Result<SaslAuthenticateResponse, SaslError> {
         let req = SaslAuthenticateRequest::new(auth_bytes);
-        let resp = self.request(req).await?;
+        let resp = self
+            .request_with_version_ranges(req, &self.version_ranges, false)
+            .await
+            .0?;
         if let Some(err) = resp.error_code {
             if let Some(s) = resp.error_message.0 {
                 debug!("Sasl auth error message: {s}");
@@ -565,25 +1108,119 @@ where
             return Err(SaslError::ApiError(err));
         }
 
+        *self.sasl_session_lifetime.lock() = resp
+            .session_lifetime_ms
+            .and_then(|ms| u64::try_from(ms.0).ok())
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis);
+
         Ok(resp)
     }
 
     async fn sasl_handshake(&self, mechanism: &str) -> Result<SaslHandshakeResponse, SaslError> {
         let req = SaslHandshakeRequest::new(mechanism);
-        let resp = self.request(req).await?;
+        let resp = self
+            .request_with_version_ranges(req, &self.version_ranges, false)
+            .await
+            .0?;
         if let Some(err) = resp.error_code {
             return Err(SaslError::ApiError(err));
         }
         Ok(resp)
     }
 
-    pub async fn do_sasl(&self, config: SaslConfig) -> Result<(), SaslError> {
-        let mechanism = config.mechanism();
-        let resp = self.sasl_handshake(mechanism).await?;
+    /// Authenticate via SASL. `host` is the name of the broker that we are connected to.
+    ///
+    /// Returns the session lifetime if the broker requires re-authentication ([KIP-368]).
+    ///
+    /// [KIP-368]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-368%3A+Allow+SASL+Connections+to+Periodically+Re-Authenticate
+    pub async fn do_sasl(
+        &self,
+        config: SaslConfig,
+        host: &str,
+    ) -> Result<Option<Duration>, SaslError> {
+        *self.sasl_session_lifetime.lock() = None;
+        self.do_sasl_mechanism(config, host).await?;
+        Ok(*self.sasl_session_lifetime.lock())
+    }
+
+    /// Re-authenticate an already authenticated connection, see [`do_sasl`](Self::do_sasl).
+    ///
+    /// Other requests are held back until the SASL exchange is done.
+    pub async fn reauthenticate(
+        &self,
+        config: SaslConfig,
+        host: &str,
+    ) -> Result<Option<Duration>, SaslError> {
+        let closed = self.closed.read().await;
+        if *closed {
+            return Ok(None);
+        }
+
+        let _auth_guard = self.auth_gate.write().await;
+        self.do_sasl(config, host).await
+    }
+
+    /// Returns `true` if the connection is broken, e.g. because a [health check](Self::ping) failed, so that all
+    /// further requests fail.
+    pub fn is_poisoned(&self) -> bool {
+        matches!(*self.state.lock(), MessengerState::Poison(_))
+    }
+
+    /// Time since the last response was received.
+    pub fn idle_time(&self) -> Duration {
+        self.last_response.lock().elapsed()
+    }
+
+    /// Check that the broker still responds, using a cheap `ApiVersions` request.
+    ///
+    /// The connection is poisoned if there is no response within `timeout`.
+    pub async fn ping(&self, timeout: Duration) -> Result<(), RequestError> {
+        let request = self.request(ApiVersionsRequest {
+            client_software_name: Some(CompactString(String::from(env!("CARGO_PKG_NAME")))),
+            client_software_version: Some(CompactString(String::from(env!("CARGO_PKG_VERSION")))),
+            tagged_fields: Some(TaggedFields::default()),
+        });
+
+        match runtime::timeout(timeout, request).await {
+            Ok(res) => res.map(|_| ()),
+            Err(_) => {
+                let mut state = self.state.lock();
+                Err(RequestError::Poisoned(state.poison(
+                    RequestError::Timeout {
+                        api_key: ApiKey::ApiVersions,
+                        timeout,
+                    },
+                )))
+            }
+        }
+    }
+
+    /// Close the connection.
+    ///
+    /// Waits for in-flight requests to finish and then shuts the stream down. Later requests fail with
+    /// [`RequestError::Closed`].
+    pub async fn close(&self) {
+        let mut closed = self.closed.write().await;
+        if *closed {
+            return;
+        }
+        *closed = true;
+
+        let mut stream_write = self.stream_write.lock().await;
+        if let Err(e) = stream_write.shutdown().await {
+            debug!(%e, "Cannot shut down connection");
+        }
+        drop(stream_write);
+
+        self.state.lock().poison(RequestError::Closed);
+        self.join_handle.abort();
+    }
+
+    async fn do_sasl_mechanism(&self, config: SaslConfig, host: &str) -> Result<(), SaslError> {
+        let mechanism = config.mechanism().to_owned();
+        let resp = self.sasl_handshake(&mechanism).await?;
 
-        let Credentials { username, password } = config.credentials();
-        let config = SASLConfig::with_credentials(None, username, password).unwrap();
-        let sasl = rsasl::prelude::SASLClient::new(config);
         let raw_mechanisms = resp.mechanisms.0.unwrap_or_default();
         let mechanisms = raw_mechanisms
             .iter()
@@ -595,10 +1232,31 @@ where
         if !mechanisms.contains(&prefer_mechanism) {
             return Err(SaslError::UnsupportedSaslMechanism);
         }
+        debug!(?mechanism, "Using SASL Mechanism");
+
+        match config {
+            SaslConfig::Plain(credentials)
+            | SaslConfig::ScramSha256(credentials)
+            | SaslConfig::ScramSha512(credentials) => {
+                self.do_sasl_rsasl(prefer_mechanism, credentials).await
+            }
+            SaslConfig::OauthBearer(config) => self.do_sasl_oauthbearer(config).await,
+            SaslConfig::Gssapi(config) => self.do_sasl_gssapi(config, host).await,
+            SaslConfig::Custom(provider) => self.do_sasl_custom(provider.as_ref(), host).await,
+        }
+    }
+
+    async fn do_sasl_rsasl(
+        &self,
+        mechanism: &Mechname,
+        credentials: Credentials,
+    ) -> Result<(), SaslError> {
+        let Credentials { username, password } = credentials;
+        let config = SASLConfig::with_credentials(None, username, password).unwrap();
+        let sasl = rsasl::prelude::SASLClient::new(config);
         let mut session = sasl
-            .start_suggested(&[prefer_mechanism])
+            .start_suggested(&[mechanism])
             .map_err(|_| SaslError::UnsupportedSaslMechanism)?;
-        debug!(?mechanism, "Using SASL Mechanism");
         // we step through the auth process, starting on our side with NO data received so far
         let mut data_received: Option<Vec<u8>> = None;
         loop {
@@ -614,6 +1272,83 @@ where
 
         Ok(())
     }
+
+    async fn do_sasl_oauthbearer(&self, config: OauthBearerConfig) -> Result<(), SaslError> {
+        let token = config.token().await.map_err(SaslError::OauthBearerToken)?;
+
+        match self.sasl_authentication(token.client_response()).await {
+            Ok(_) => Ok(()),
+            Err(e @ SaslError::ApiError(_)) => {
+                // the broker closes the connection after a failed authentication, so we cannot retry here. Make sure
+                // that the next connection attempt uses a fresh token though.
+                config.invalidate().await;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn do_sasl_custom(
+        &self,
+        provider: &dyn SaslProvider,
+        host: &str,
+    ) -> Result<(), SaslError> {
+        let mut session = provider.start(host).map_err(SaslError::Provider)?;
+
+        let mut data_received: Option<Vec<u8>> = None;
+        while let Some(to_sent) = session
+            .step(data_received.as_deref())
+            .await
+            .map_err(SaslError::Provider)?
+        {
+            let authentication_response = self.sasl_authentication(to_sent).await?;
+            data_received = Some(authentication_response.auth_bytes.0);
+        }
+
+        Ok(())
+    }
+
+    /// GSSAPI authentication as described in [RFC 4752 section 3.1](https://datatracker.ietf.org/doc/html/rfc4752#section-3.1).
+    async fn do_sasl_gssapi(&self, config: GssapiConfig, host: &str) -> Result<(), SaslError> {
+        /// Kafka does not support any other security layer.
+        const NO_SECURITY_LAYER: u8 = 0x01;
+
+        let mut ctx = config
+            .provider
+            .client_context(&config.service_name, host)
+            .map_err(SaslError::Gssapi)?;
+
+        // establish security context, the server answers the last context token with the security layer offer
+        let mut data_received: Option<Vec<u8>> = None;
+        let offer = loop {
+            let to_sent = ctx
+                .step(data_received.as_deref())
+                .map_err(SaslError::Gssapi)?
+                .unwrap_or_default();
+            let complete = ctx.is_complete();
+
+            let authentication_response = self.sasl_authentication(to_sent).await?;
+            if complete {
+                break authentication_response.auth_bytes.0;
+            }
+            data_received = Some(authentication_response.auth_bytes.0);
+        };
+
+        let offer = ctx.unwrap(&offer).map_err(SaslError::Gssapi)?;
+        if offer.len() != 4 || offer[0] & NO_SECURITY_LAYER == 0 {
+            return Err(SaslError::Gssapi(
+                format!("unsupported security layer offer: {offer:?}").into(),
+            ));
+        }
+
+        // no security layer, no max message size, no authorization identity
+        let reply = ctx
+            .wrap(&[NO_SECURITY_LAYER, 0, 0, 0])
+            .map_err(SaslError::Gssapi)?;
+        self.sasl_authentication(reply).await?;
+
+        Ok(())
+    }
 }
 
 impl<RW> Drop for Messenger<RW> {
@@ -701,7 +1436,7 @@ where
     fn drop(&mut self) {
         if !self.done {
             let inner = self.inner.take().expect("Double-drop?");
-            tokio::task::spawn(async move {
+            runtime::spawn(async move {
                 inner.await;
             });
         }
@@ -755,11 +1490,13 @@ mod tests {
 
     use crate::{
         build_info::DEFAULT_CLIENT_ID,
+        connection::{GssapiClientContext, GssapiProvider, SaslSession},
         protocol::{
             error::Error as ApiError,
             messages::{
                 ApiVersionsResponse, ApiVersionsResponseApiKey, ListOffsetsRequest, NORMAL_CONSUMER,
             },
+            primitives::{Array, Bytes, String_},
             traits::WriteType,
         },
     };
@@ -837,38 +1574,221 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_sync_versions_ignores_error_code() {
+    async fn test_frame_capture() {
         let (sim, rx) = MessageSimulator::new();
         let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        let frames: Arc<Mutex<Vec<Frame>>> = Default::default();
+        let frames_captured = Arc::clone(&frames);
+        messenger.set_frame_capture(Some(Arc::new(move |frame: &Frame| {
+            frames_captured.lock().push(frame.clone())
+        })));
 
-        // construct error response
         let mut msg = vec![];
         ResponseHeader {
             correlation_id: Int32(0),
-            tagged_fields: Default::default(), // NOT serialized for ApiVersion!
+            tagged_fields: Default::default(),
         }
         .write_versioned(&mut msg, ApiVersion(Int16(0)))
         .unwrap();
         ApiVersionsResponse {
-            error_code: Some(ApiError::CorruptMessage),
-            api_keys: vec![ApiVersionsResponseApiKey {
-                api_key: ApiKey::Produce,
-                min_version: ApiVersion(Int16(2)),
-                max_version: ApiVersion(Int16(3)),
-                tagged_fields: Default::default(),
-            }],
+            error_code: None,
+            api_keys: vec![],
             throttle_time_ms: None,
             tagged_fields: None,
         }
         .write_versioned(&mut msg, ApiVersionsRequest::API_VERSION_RANGE.max())
         .unwrap();
-        sim.push(msg);
+        sim.push(msg.clone());
 
-        // construct good response
-        let mut msg = vec![];
-        ResponseHeader {
-            correlation_id: Int32(1),
-            tagged_fields: Default::default(),
+        messenger.sync_versions().await.unwrap();
+
+        let frames = frames.lock();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].direction, FrameDirection::Request);
+        assert_eq!(frames[0].api_key, Int16::from(ApiKey::ApiVersions).0);
+        assert_eq!(frames[1].direction, FrameDirection::Response);
+        assert_eq!(frames[1].data, msg);
+        crate::capture::decode_response(&frames[1]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        #[derive(Debug, Default)]
+        struct TestMetrics {
+            events: Mutex<Vec<String>>,
+            completed: Mutex<Vec<RequestEnd>>,
+        }
+
+        impl Metrics for TestMetrics {
+            fn request_started(&self, request: &RequestStart) {
+                self.events
+                    .lock()
+                    .push(format!("started {}", request.api_key));
+            }
+
+            fn request_completed(&self, request: &RequestEnd) {
+                self.events
+                    .lock()
+                    .push(format!("completed {}", request.api_key));
+                self.completed.lock().push(*request);
+            }
+
+            fn connection_opened(&self, broker: &str) {
+                self.events.lock().push(format!("opened {broker}"));
+            }
+
+            fn connection_closed(&self, broker: &str) {
+                self.events.lock().push(format!("closed {broker}"));
+            }
+        }
+
+        let (sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        let metrics = Arc::new(TestMetrics::default());
+        messenger.set_metrics(Arc::clone(&metrics) as _, "broker:9092");
+
+        let mut msg = vec![];
+        ResponseHeader {
+            correlation_id: Int32(0),
+            tagged_fields: Default::default(),
+        }
+        .write_versioned(&mut msg, ApiVersion(Int16(0)))
+        .unwrap();
+        ApiVersionsResponse {
+            error_code: None,
+            api_keys: vec![],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        }
+        .write_versioned(&mut msg, ApiVersionsRequest::API_VERSION_RANGE.max())
+        .unwrap();
+        sim.push(msg.clone());
+
+        messenger.sync_versions().await.unwrap();
+        drop(messenger);
+
+        assert_eq!(
+            *metrics.events.lock(),
+            [
+                "opened broker:9092",
+                "started 18",
+                "completed 18",
+                "closed broker:9092"
+            ]
+        );
+        let completed = metrics.completed.lock();
+        assert_eq!(completed[0].bytes_received, msg.len());
+        assert!(completed[0].bytes_sent > 0);
+        assert_eq!(completed[0].error, None);
+    }
+
+    #[tokio::test]
+    async fn test_request_span() {
+        let output = Arc::new(Mutex::new(vec![]));
+        let output_captured = Arc::clone(&output);
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || CaptureWriter(Arc::clone(&output_captured)))
+            .with_ansi(false)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let (sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_broker(Some(1), "broker:9092");
+
+        let mut msg = vec![];
+        ResponseHeader {
+            correlation_id: Int32(0),
+            tagged_fields: Default::default(),
+        }
+        .write_versioned(&mut msg, ApiVersion(Int16(0)))
+        .unwrap();
+        ApiVersionsResponse {
+            error_code: None,
+            api_keys: vec![],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        }
+        .write_versioned(&mut msg, ApiVersionsRequest::API_VERSION_RANGE.max())
+        .unwrap();
+        sim.push(msg);
+        messenger.sync_versions().await.unwrap();
+
+        // the broker did not advertise any API
+        messenger
+            .request(ListOffsetsRequest {
+                replica_id: NORMAL_CONSUMER,
+                isolation_level: None,
+                topics: vec![],
+            })
+            .await
+            .unwrap_err();
+
+        let output = String::from_utf8(output.lock().clone()).unwrap();
+        let lines = output
+            .lines()
+            .filter(|l| l.contains("kafka_request{"))
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2, "{output}");
+        // fields that are recorded later on are printed last
+        assert!(lines[0].contains(&format!(
+            "kafka_request{{api_key=ApiVersions broker_id=1 broker=\"broker:9092\" api_version={} \
+             correlation_id=0}}",
+            ApiVersionsRequest::API_VERSION_RANGE.max().0 .0,
+        )));
+        assert!(lines[1].contains(
+            "kafka_request{api_key=ListOffsets broker_id=1 broker=\"broker:9092\" \
+             error=Cannot find matching version for: ListOffsets}"
+        ));
+    }
+
+    struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CaptureWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_versions_ignores_error_code() {
+        let (sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+
+        // construct error response
+        let mut msg = vec![];
+        ResponseHeader {
+            correlation_id: Int32(0),
+            tagged_fields: Default::default(), // NOT serialized for ApiVersion!
+        }
+        .write_versioned(&mut msg, ApiVersion(Int16(0)))
+        .unwrap();
+        ApiVersionsResponse {
+            error_code: Some(ApiError::CorruptMessage),
+            api_keys: vec![ApiVersionsResponseApiKey {
+                api_key: ApiKey::Produce,
+                min_version: ApiVersion(Int16(2)),
+                max_version: ApiVersion(Int16(3)),
+                tagged_fields: Default::default(),
+            }],
+            throttle_time_ms: None,
+            tagged_fields: None,
+        }
+        .write_versioned(&mut msg, ApiVersionsRequest::API_VERSION_RANGE.max())
+        .unwrap();
+        sim.push(msg);
+
+        // construct good response
+        let mut msg = vec![];
+        ResponseHeader {
+            correlation_id: Int32(1),
+            tagged_fields: Default::default(),
         }
         .write_versioned(&mut msg, ApiVersion(Int16(0)))
         .unwrap();
@@ -1108,6 +2028,70 @@ mod tests {
         assert_matches!(err, RequestError::Poisoned(_));
     }
 
+    #[tokio::test]
+    async fn test_request_timeout_poisons() {
+        let (_sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ListOffsets,
+            ListOffsetsRequest::API_VERSION_RANGE,
+        )]));
+        messenger.set_request_timeout(Some(Duration::from_millis(10)));
+
+        let err = messenger
+            .request(ListOffsetsRequest {
+                replica_id: NORMAL_CONSUMER,
+                isolation_level: None,
+                topics: vec![],
+            })
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            RequestError::Poisoned(e) if matches!(e.as_ref(), RequestError::Timeout { api_key: ApiKey::ListOffsets, .. })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_info() {
+        let (_sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_broker(Some(1), "broker:9092");
+        messenger.set_request_timeout(Some(Duration::from_millis(10)));
+        let request = || ListOffsetsRequest {
+            replica_id: NORMAL_CONSUMER,
+            isolation_level: None,
+            topics: vec![],
+        };
+
+        // fails before a version is chosen
+        let (res, info) = messenger.request_with_info(request()).await;
+        assert_matches!(res, Err(RequestError::NoVersionMatch { .. }));
+        assert_eq!(info.to_string(), "broker 1 (broker:9092), ListOffsets");
+
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ListOffsets,
+            ListOffsetsRequest::API_VERSION_RANGE,
+        )]));
+        let (res, info) = messenger.request_with_info(request()).await;
+        res.unwrap_err();
+        let api_version = ListOffsetsRequest::API_VERSION_RANGE.max().0 .0;
+        assert_eq!(
+            info,
+            RequestInfo {
+                broker_id: Some(1),
+                broker_address: Some(Arc::from("broker:9092")),
+                api_key: ApiKey::ListOffsets,
+                api_version: Some(api_version),
+                correlation_id: Some(0),
+            }
+        );
+        assert_eq!(
+            info.to_string(),
+            format!("broker 1 (broker:9092), ListOffsets v{api_version}, correlation ID 0")
+        );
+    }
+
     #[tokio::test]
     async fn test_poison_negative_message_size() {
         let (sim, rx) = MessageSimulator::new();
@@ -1182,6 +2166,214 @@ mod tests {
         assert_eq!(actual, resp);
     }
 
+    #[tokio::test]
+    async fn test_auth_gate_holds_back_requests() {
+        let (tx, mut rx) = tokio::io::duplex(1_000);
+        let mut messenger = Messenger::new(tx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ApiVersions,
+            ApiVersionsRequest::API_VERSION_RANGE,
+        )]));
+        let messenger = Arc::new(messenger);
+
+        // simulate an ongoing re-authentication
+        let auth_guard = messenger.auth_gate.write().await;
+
+        let messenger_captured = Arc::clone(&messenger);
+        let request = tokio::spawn(async move {
+            messenger_captured
+                .request(ApiVersionsRequest {
+                    client_software_name: Some(CompactString(String::from("foo"))),
+                    client_software_version: Some(CompactString(String::from("1.0"))),
+                    tagged_fields: Some(TaggedFields::default()),
+                })
+                .await
+        });
+
+        tokio::time::timeout(Duration::from_millis(100), rx.read_message(1_000))
+            .await
+            .unwrap_err();
+
+        drop(auth_guard);
+        rx.read_message(1_000).await.unwrap();
+
+        request.abort();
+    }
+
+    #[tokio::test]
+    async fn test_ping_timeout_poisons() {
+        let (_sim, rx) = MessageSimulator::new();
+        let mut messenger = Messenger::new(rx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ApiVersions,
+            ApiVersionsRequest::API_VERSION_RANGE,
+        )]));
+
+        assert!(!messenger.is_poisoned());
+        let err = messenger.ping(Duration::from_millis(10)).await.unwrap_err();
+        assert_matches!(err, RequestError::Poisoned(_));
+        assert!(messenger.is_poisoned());
+
+        let err = messenger.ping(Duration::from_millis(10)).await.unwrap_err();
+        assert_matches!(err, RequestError::Poisoned(_));
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        let (tx, mut rx) = tokio::io::duplex(1_000);
+        let mut messenger = Messenger::new(tx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ApiVersions,
+            ApiVersionsRequest::API_VERSION_RANGE,
+        )]));
+        messenger.set_max_in_flight(Some(1));
+        let messenger = Arc::new(messenger);
+
+        let requests: Vec<_> = (0..2)
+            .map(|_| {
+                let messenger = Arc::clone(&messenger);
+                tokio::spawn(async move {
+                    messenger
+                        .request(ApiVersionsRequest {
+                            client_software_name: Some(CompactString(String::from("foo"))),
+                            client_software_version: Some(CompactString(String::from("1.0"))),
+                            tagged_fields: Some(TaggedFields::default()),
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        // only one request is sent until the first one gets a response
+        rx.read_message(1_000).await.unwrap();
+        tokio::time::timeout(Duration::from_millis(100), rx.read_message(1_000))
+            .await
+            .unwrap_err();
+
+        for request in requests {
+            request.abort();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sasl_gssapi() {
+        let (messenger, rx) = sasl_messenger();
+        let broker = sasl_broker(
+            rx,
+            "GSSAPI",
+            vec![
+                b"challenge".to_vec(),
+                // security layer offer: no security layer, max message size of 64KiB
+                [FakeGssapiContext::WRAP_PREFIX, &[0x01, 0x01, 0x00, 0x00]].concat(),
+                vec![],
+            ],
+        );
+
+        let provider = Arc::new(FakeGssapiProvider::default());
+        let config = GssapiConfig::new(Arc::clone(&provider) as _);
+        messenger
+            .do_sasl(SaslConfig::Gssapi(config), "broker-1")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            broker.await.unwrap(),
+            vec![
+                b"token1".to_vec(),
+                b"token2".to_vec(),
+                [FakeGssapiContext::WRAP_PREFIX, &[0x01, 0x00, 0x00, 0x00]].concat(),
+            ],
+        );
+        assert_eq!(*provider.principals.lock(), vec!["kafka@broker-1"]);
+    }
+
+    #[tokio::test]
+    async fn test_sasl_gssapi_unsupported_security_layer() {
+        let (messenger, rx) = sasl_messenger();
+        let broker = sasl_broker(
+            rx,
+            "GSSAPI",
+            vec![
+                b"challenge".to_vec(),
+                // integrity protection only
+                [FakeGssapiContext::WRAP_PREFIX, &[0x02, 0x01, 0x00, 0x00]].concat(),
+            ],
+        );
+
+        let config = GssapiConfig::new(Arc::new(FakeGssapiProvider::default()))
+            .with_service_name("redpanda");
+        let err = messenger
+            .do_sasl(SaslConfig::Gssapi(config), "broker-1")
+            .await
+            .unwrap_err();
+        assert_matches!(err, SaslError::Gssapi(_));
+
+        // the reply to the offer is never sent
+        assert_eq!(broker.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sasl_custom() {
+        let (messenger, rx) = sasl_messenger();
+        let broker = sasl_broker(
+            rx,
+            "FAKE",
+            vec![b"server-first".to_vec(), b"server-final".to_vec()],
+        );
+
+        messenger
+            .do_sasl(SaslConfig::Custom(Arc::new(FakeSaslProvider)), "broker-1")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            broker.await.unwrap(),
+            vec![b"client-first".to_vec(), b"client-final".to_vec()],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sasl_custom_provider_error() {
+        let (messenger, rx) = sasl_messenger();
+        let broker = sasl_broker(rx, "FAKE", vec![b"unexpected".to_vec()]);
+
+        let err = messenger
+            .do_sasl(SaslConfig::Custom(Arc::new(FakeSaslProvider)), "broker-1")
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            SaslError::Provider(e) if e.to_string() == "unexpected challenge"
+        );
+
+        assert_eq!(broker.await.unwrap(), vec![b"client-first".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let (tx, mut rx) = tokio::io::duplex(1_000);
+        let mut messenger = Messenger::new(tx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([(
+            ApiKey::ApiVersions,
+            ApiVersionsRequest::API_VERSION_RANGE,
+        )]));
+
+        messenger.close().await;
+
+        // broker sees EOF
+        rx.read_message(1_000).await.unwrap_err();
+
+        let err = messenger
+            .request(ApiVersionsRequest {
+                client_software_name: Some(CompactString(String::from("foo"))),
+                client_software_version: Some(CompactString(String::from("1.0"))),
+                tagged_fields: Some(TaggedFields::default()),
+            })
+            .await
+            .unwrap_err();
+        assert_matches!(err, RequestError::Closed);
+    }
+
     #[tokio::test]
     async fn test_cancel_request() {
         // Use a "virtual" network between a simulated broker and a client. The network is intercepted in the middle to
@@ -1335,6 +2527,179 @@ mod tests {
         handle_network.abort();
     }
 
+    /// Messenger that talks to a simulated broker, see [`sasl_broker`].
+    fn sasl_messenger() -> (Messenger<DuplexStream>, DuplexStream) {
+        let (tx, rx) = tokio::io::duplex(1_000);
+        let mut messenger = Messenger::new(tx, 1_000, Arc::from(DEFAULT_CLIENT_ID));
+        messenger.set_version_ranges(HashMap::from([
+            (
+                ApiKey::SaslHandshake,
+                SaslHandshakeRequest::API_VERSION_RANGE,
+            ),
+            (
+                ApiKey::SaslAuthenticate,
+                ApiVersionRange::new(ApiVersion(Int16(1)), ApiVersion(Int16(1))),
+            ),
+        ]));
+        (messenger, rx)
+    }
+
+    /// Simulated broker that supports the SASL `mechanism`.
+    ///
+    /// Answers `SaslAuthenticate` requests with `responses` in order and returns the auth bytes of the requests once
+    /// all responses are sent.
+    fn sasl_broker(
+        mut stream: DuplexStream,
+        mechanism: &'static str,
+        responses: Vec<Vec<u8>>,
+    ) -> tokio::task::JoinHandle<Vec<Vec<u8>>> {
+        tokio::spawn(async move {
+            let mut responses = responses.into_iter();
+            let mut received = vec![];
+            while responses.len() > 0 {
+                let msg = stream.read_message(1_000).await.unwrap();
+                let mut reader = Cursor::new(msg);
+                let header =
+                    RequestHeader::read_versioned(&mut reader, ApiVersion(Int16(1))).unwrap();
+
+                let mut response = vec![];
+                ResponseHeader {
+                    correlation_id: header.correlation_id,
+                    tagged_fields: None,
+                }
+                .write_versioned(&mut response, ApiVersion(Int16(0)))
+                .unwrap();
+                match header.request_api_key {
+                    ApiKey::SaslHandshake => {
+                        SaslHandshakeRequest::read_versioned(
+                            &mut reader,
+                            header.request_api_version,
+                        )
+                        .unwrap();
+                        SaslHandshakeResponse {
+                            error_code: None,
+                            mechanisms: Array(Some(vec![String_(mechanism.to_owned())])),
+                        }
+                        .write_versioned(&mut response, header.request_api_version)
+                        .unwrap();
+                    }
+                    ApiKey::SaslAuthenticate => {
+                        let req = SaslAuthenticateRequest::read_versioned(
+                            &mut reader,
+                            header.request_api_version,
+                        )
+                        .unwrap();
+                        received.push(req.auth_bytes.0);
+                        SaslAuthenticateResponse {
+                            error_code: None,
+                            error_message: NullableString(None),
+                            auth_bytes: Bytes(responses.next().unwrap()),
+                            session_lifetime_ms: None,
+                            tagged_fields: None,
+                        }
+                        .write_versioned(&mut response, header.request_api_version)
+                        .unwrap();
+                    }
+                    api_key => panic!("unexpected request: {api_key:?}"),
+                }
+                stream.write_message(&response).await.unwrap();
+            }
+            received
+        })
+    }
+
+    /// GSSAPI context that "wraps" messages by prefixing them.
+    #[derive(Debug)]
+    struct FakeGssapiContext {
+        steps: usize,
+    }
+
+    impl FakeGssapiContext {
+        const WRAP_PREFIX: &'static [u8] = b"wrapped:";
+    }
+
+    impl GssapiClientContext for FakeGssapiContext {
+        fn step(&mut self, token: Option<&[u8]>) -> Result<Option<Vec<u8>>, GssapiError> {
+            let expected: Option<&[u8]> = match self.steps {
+                0 => None,
+                1 => Some(b"challenge"),
+                _ => panic!("context already established"),
+            };
+            assert_eq!(token, expected);
+            self.steps += 1;
+            Ok(Some(format!("token{}", self.steps).into_bytes()))
+        }
+
+        fn is_complete(&self) -> bool {
+            self.steps == 2
+        }
+
+        fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, GssapiError> {
+            assert!(self.is_complete());
+            msg.strip_prefix(Self::WRAP_PREFIX)
+                .map(|msg| msg.to_vec())
+                .ok_or_else(|| "not wrapped".into())
+        }
+
+        fn wrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, GssapiError> {
+            assert!(self.is_complete());
+            Ok([Self::WRAP_PREFIX, msg].concat())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct FakeGssapiProvider {
+        principals: Mutex<Vec<String>>,
+    }
+
+    impl GssapiProvider for FakeGssapiProvider {
+        fn client_context(
+            &self,
+            service: &str,
+            hostname: &str,
+        ) -> Result<Box<dyn GssapiClientContext>, GssapiError> {
+            self.principals.lock().push(format!("{service}@{hostname}"));
+            Ok(Box::new(FakeGssapiContext { steps: 0 }))
+        }
+    }
+
+    /// SASL mechanism that sends `client-first` and `client-final` and fails on any other server message than
+    /// `server-first`.
+    #[derive(Debug)]
+    struct FakeSaslProvider;
+
+    struct FakeSaslSession {
+        steps: usize,
+    }
+
+    impl SaslSession for FakeSaslSession {
+        fn step<'a>(
+            &'a mut self,
+            challenge: Option<&'a [u8]>,
+        ) -> BoxFuture<'a, Result<Option<Vec<u8>>, SaslProviderError>> {
+            Box::pin(async move {
+                self.steps += 1;
+                match (self.steps, challenge) {
+                    (1, None) => Ok(Some(b"client-first".to_vec())),
+                    (2, Some(b"server-first")) => Ok(Some(b"client-final".to_vec())),
+                    (3, Some(_)) => Ok(None),
+                    _ => Err("unexpected challenge".into()),
+                }
+            })
+        }
+    }
+
+    impl SaslProvider for FakeSaslProvider {
+        fn mechanism(&self) -> &str {
+            "FAKE"
+        }
+
+        fn start(&self, host: &str) -> Result<Box<dyn SaslSession>, SaslProviderError> {
+            assert_eq!(host, "broker-1");
+            Ok(Box::new(FakeSaslSession { steps: 0 }))
+        }
+    }
+
     #[derive(Debug)]
     enum Message {
         Send(Vec<u8>),
@@ -1345,7 +2710,7 @@ mod tests {
 
     struct MessageSimulator {
         messages: UnboundedSender<Message>,
-        join_handle: JoinHandle<()>,
+        join_handle: tokio::task::JoinHandle<()>,
     }
 
     impl MessageSimulator {
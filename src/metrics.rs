@@ -0,0 +1,303 @@
+//! Instrumentation hooks.
+//!
+//! Implement [`Metrics`] and register it via [`ClientBuilder::metrics`](crate::client::ClientBuilder::metrics) to feed
+//! client events into an existing telemetry system.
+//!
+//! With the `metrics-rs` feature, `MetricsFacade` emits counters, gauges and histograms via the
+//! [metrics](https://docs.rs/metrics) facade.
+use std::{sync::Arc, time::Duration};
+
+use crate::messenger::RequestError;
+
+#[cfg(feature = "metrics-rs")]
+mod facade;
+#[cfg(feature = "metrics-rs")]
+pub use facade::MetricsFacade;
+
+/// Receiver of client events.
+///
+/// All methods do nothing by default, so implementations only need to override the events they are interested in.
+/// Methods are called on the request path and should therefore not block.
+pub trait Metrics: Send + Sync {
+    /// A request is about to be sent to a broker.
+    fn request_started(&self, _request: &RequestStart) {}
+
+    /// A request finished, either with a response or with an error.
+    ///
+    /// This is called exactly once for every [started](Self::request_started) request, including cancelled ones.
+    fn request_completed(&self, _request: &RequestEnd) {}
+
+    /// A connection to the given broker was established.
+    fn connection_opened(&self, _broker: &str) {}
+
+    /// A connection to the given broker was dropped.
+    fn connection_closed(&self, _broker: &str) {}
+
+    /// A batch of records is about to be produced.
+    fn produce_batch(&self, _batch: &ProduceBatch<'_>) {}
+
+    /// Records were fetched and decoded.
+    fn fetch_batch(&self, _batch: &FetchBatch<'_>) {}
+
+    /// An operation failed or was throttled and is retried.
+    fn retry(&self, _retry: &Retry<'_>) {}
+
+    /// A broker throttled a request because the client exceeded a quota.
+    ///
+    /// This is reported in addition to the [retry](Self::retry).
+    fn throttled(&self, _throttle: &Throttle<'_>) {}
+
+    /// A [`BatchProducer`](crate::client::producer::BatchProducer) flushed the data that its aggregator buffered.
+    ///
+    /// Empty batches are not reported.
+    fn aggregator_flushed(&self, _flush: &AggregatorFlush) {}
+}
+
+/// Passes every event to all of the contained receivers, in order.
+pub(crate) struct MetricsList(pub(crate) Vec<Arc<dyn Metrics>>);
+
+impl Metrics for MetricsList {
+    fn request_started(&self, request: &RequestStart) {
+        self.0.iter().for_each(|m| m.request_started(request));
+    }
+
+    fn request_completed(&self, request: &RequestEnd) {
+        self.0.iter().for_each(|m| m.request_completed(request));
+    }
+
+    fn connection_opened(&self, broker: &str) {
+        self.0.iter().for_each(|m| m.connection_opened(broker));
+    }
+
+    fn connection_closed(&self, broker: &str) {
+        self.0.iter().for_each(|m| m.connection_closed(broker));
+    }
+
+    fn produce_batch(&self, batch: &ProduceBatch<'_>) {
+        self.0.iter().for_each(|m| m.produce_batch(batch));
+    }
+
+    fn fetch_batch(&self, batch: &FetchBatch<'_>) {
+        self.0.iter().for_each(|m| m.fetch_batch(batch));
+    }
+
+    fn retry(&self, retry: &Retry<'_>) {
+        self.0.iter().for_each(|m| m.retry(retry));
+    }
+
+    fn throttled(&self, throttle: &Throttle<'_>) {
+        self.0.iter().for_each(|m| m.throttled(throttle));
+    }
+
+    fn aggregator_flushed(&self, flush: &AggregatorFlush) {
+        self.0.iter().for_each(|m| m.aggregator_flushed(flush));
+    }
+}
+
+/// Callback that is invoked whenever a broker throttles a request, see [`Metrics::throttled`].
+pub type ThrottleCallback = Arc<dyn Fn(&Throttle<'_>) + Send + Sync>;
+
+/// See [`Metrics::request_started`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequestStart {
+    /// API key, see <https://kafka.apache.org/protocol#protocol_api_keys>.
+    pub api_key: i16,
+
+    pub api_version: i16,
+
+    /// Size of the request, excluding the length prefix.
+    pub bytes_sent: usize,
+}
+
+/// See [`Metrics::request_completed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequestEnd {
+    /// API key, see <https://kafka.apache.org/protocol#protocol_api_keys>.
+    pub api_key: i16,
+
+    pub api_version: i16,
+
+    /// Time from sending the request until the response was decoded or the request failed.
+    pub duration: Duration,
+
+    /// Size of the request, excluding the length prefix.
+    pub bytes_sent: usize,
+
+    /// Size of the response, excluding the length prefix. `0` if no response was received.
+    pub bytes_received: usize,
+
+    /// Class of the error if the request failed.
+    ///
+    /// Error codes that the broker reports within a response do not count as failure here.
+    pub error: Option<RequestErrorClass>,
+}
+
+/// Coarse classification of request errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RequestErrorClass {
+    /// No API version is supported by both the client and the broker.
+    Unsupported,
+
+    /// The request could not be encoded.
+    Encode,
+
+    /// The response could not be decoded.
+    Decode,
+
+    /// The connection failed.
+    Io,
+
+    /// No response was received in time.
+    Timeout,
+
+    /// The connection was closed.
+    Closed,
+
+    /// The caller stopped waiting for the request.
+    Cancelled,
+}
+
+impl RequestErrorClass {
+    pub(crate) fn of(e: &RequestError) -> Self {
+        match e {
+            RequestError::NoVersionMatch { .. } => Self::Unsupported,
+            RequestError::WriteError(_) | RequestError::WriteMessageError(_) => Self::Encode,
+            RequestError::ReadError(_)
+            | RequestError::ReadVersionedError(_)
+            | RequestError::TooMuchData { .. }
+            | RequestError::ReadFramedMessageError(_) => Self::Decode,
+            RequestError::IO(_) => Self::Io,
+            // timeouts poison the connection as well
+            RequestError::Poisoned(e) => match e.as_ref() {
+                RequestError::Timeout { .. } => Self::Timeout,
+                _ => Self::Io,
+            },
+            RequestError::Shared(e) => Self::of(e),
+            RequestError::Timeout { .. } => Self::Timeout,
+            RequestError::Closed => Self::Closed,
+        }
+    }
+}
+
+/// See [`Metrics::produce_batch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ProduceBatch<'a> {
+    pub topic: &'a str,
+
+    pub partition: i32,
+
+    /// Number of records.
+    pub records: usize,
+
+    /// Approximate size of the encoded records, before compression.
+    pub bytes: usize,
+}
+
+/// See [`Metrics::fetch_batch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FetchBatch<'a> {
+    pub topic: &'a str,
+
+    pub partition: i32,
+
+    /// Number of records, including control records.
+    pub records: usize,
+
+    /// Size of the record data that the broker returned, i.e. after compression.
+    pub bytes: usize,
+}
+
+/// See [`Metrics::retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Retry<'a> {
+    /// Name of the operation, e.g. `produce` or `fetch_records`.
+    pub request_name: &'a str,
+
+    /// Number of the retry, starting at `1`.
+    pub attempt: usize,
+
+    /// Time to wait before the retry.
+    pub backoff: Duration,
+
+    /// Whether the retry was requested by the broker via throttling instead of caused by an error.
+    pub throttled: bool,
+}
+
+/// See [`Metrics::throttled`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Throttle<'a> {
+    /// Name of the operation, e.g. `produce` or `fetch_records`.
+    pub request_name: &'a str,
+
+    /// Time that the broker asked the client to wait, i.e. `throttle_time_ms` of the response.
+    pub duration: Duration,
+}
+
+/// See [`Metrics::aggregator_flushed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AggregatorFlush {
+    pub reason: FlushReason,
+
+    /// Number of inputs that the aggregator accepted for the batch.
+    pub inputs: usize,
+
+    /// Approximate size of the batch, see
+    /// [`Aggregator::buffered_bytes`](crate::client::producer::aggregator::Aggregator::buffered_bytes).
+    pub bytes: Option<usize>,
+
+    /// Time from the first input until the flush.
+    pub age: Duration,
+}
+
+/// Cause of an [`AggregatorFlush`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FlushReason {
+    /// The linger time elapsed.
+    Linger,
+
+    /// The aggregator had no capacity left for the next input.
+    Full,
+
+    /// The batch was flushed via [`BatchProducer::flush`](crate::client::producer::BatchProducer::flush).
+    Manual,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::api_key::ApiKey;
+
+    use super::*;
+
+    #[test]
+    fn test_request_error_class() {
+        let timeout = RequestError::Timeout {
+            api_key: ApiKey::Produce,
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(
+            RequestErrorClass::of(&RequestError::Poisoned(Arc::new(timeout))),
+            RequestErrorClass::Timeout
+        );
+
+        let io = RequestError::IO(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert_eq!(
+            RequestErrorClass::of(&RequestError::Shared(Arc::new(RequestError::Poisoned(
+                Arc::new(io)
+            )))),
+            RequestErrorClass::Io
+        );
+        assert_eq!(
+            RequestErrorClass::of(&RequestError::Closed),
+            RequestErrorClass::Closed
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit,
+};
+
+use crate::protocol::{api_key::ApiKey, primitives::Int16};
+
+use super::{
+    AggregatorFlush, FetchBatch, FlushReason, Metrics, ProduceBatch, RequestEnd, RequestErrorClass,
+    RequestStart, Retry, Throttle,
+};
+
+/// [`Metrics`] that are emitted via the [`metrics`] facade, e.g. to a Prometheus exporter.
+///
+/// Requests are labeled with the API name (`api`), connections with the broker address (`broker`) and records with
+/// the `topic`. The following metrics are emitted:
+///
+/// | Name                                   | Type      | Labels              |
+/// | -------------------------------------- | --------- | ------------------- |
+/// | `rskafka_requests_total`               | counter   | `api`, `result`     |
+/// | `rskafka_requests_in_flight`           | gauge     | `api`               |
+/// | `rskafka_request_duration_seconds`     | histogram | `api`               |
+/// | `rskafka_request_sent_bytes_total`     | counter   | `api`               |
+/// | `rskafka_request_received_bytes_total` | counter   | `api`               |
+/// | `rskafka_connections`                  | gauge     | `broker`            |
+/// | `rskafka_connections_opened_total`     | counter   | `broker`            |
+/// | `rskafka_produced_records_total`       | counter   | `topic`             |
+/// | `rskafka_produced_bytes_total`         | counter   | `topic`             |
+/// | `rskafka_fetched_records_total`        | counter   | `topic`             |
+/// | `rskafka_fetched_bytes_total`          | counter   | `topic`             |
+/// | `rskafka_retries_total`                | counter   | `request`, `reason` |
+/// | `rskafka_throttle_duration_seconds`    | histogram | `request`           |
+/// | `rskafka_producer_flushes_total`       | counter   | `reason`            |
+/// | `rskafka_producer_batch_inputs`        | histogram |                     |
+/// | `rskafka_producer_batch_bytes`         | histogram |                     |
+/// | `rskafka_producer_batch_age_seconds`   | histogram |                     |
+///
+/// `result` is either `ok` or the [error class](RequestErrorClass) in snake case. `reason` is either `error` or
+/// `throttle` for retries and the [flush reason](FlushReason) in snake case for flushes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsFacade;
+
+impl MetricsFacade {
+    /// Create a new instance and describe the metrics to the installed recorder.
+    pub fn new() -> Self {
+        describe_counter!("rskafka_requests_total", "Finished requests");
+        describe_gauge!("rskafka_requests_in_flight", "Requests in flight");
+        describe_histogram!(
+            "rskafka_request_duration_seconds",
+            Unit::Seconds,
+            "Request latency"
+        );
+        describe_counter!(
+            "rskafka_request_sent_bytes_total",
+            Unit::Bytes,
+            "Size of sent requests"
+        );
+        describe_counter!(
+            "rskafka_request_received_bytes_total",
+            Unit::Bytes,
+            "Size of received responses"
+        );
+        describe_gauge!("rskafka_connections", "Open broker connections");
+        describe_counter!(
+            "rskafka_connections_opened_total",
+            "Established broker connections"
+        );
+        describe_counter!("rskafka_produced_records_total", "Produced records");
+        describe_counter!(
+            "rskafka_produced_bytes_total",
+            Unit::Bytes,
+            "Approximate size of produced records"
+        );
+        describe_counter!("rskafka_fetched_records_total", "Fetched records");
+        describe_counter!(
+            "rskafka_fetched_bytes_total",
+            Unit::Bytes,
+            "Size of fetched record data"
+        );
+        describe_counter!("rskafka_retries_total", "Retried operations");
+        describe_histogram!(
+            "rskafka_throttle_duration_seconds",
+            Unit::Seconds,
+            "Time that brokers throttled requests for"
+        );
+        describe_counter!(
+            "rskafka_producer_flushes_total",
+            "Batches flushed by producers"
+        );
+        describe_histogram!(
+            "rskafka_producer_batch_inputs",
+            "Inputs per batch flushed by producers"
+        );
+        describe_histogram!(
+            "rskafka_producer_batch_bytes",
+            Unit::Bytes,
+            "Approximate size of batches flushed by producers"
+        );
+        describe_histogram!(
+            "rskafka_producer_batch_age_seconds",
+            Unit::Seconds,
+            "Time from the first input of a batch until it was flushed"
+        );
+
+        Self
+    }
+}
+
+impl Metrics for MetricsFacade {
+    fn request_started(&self, request: &RequestStart) {
+        gauge!("rskafka_requests_in_flight", "api" => api_name(request.api_key)).increment(1);
+    }
+
+    fn request_completed(&self, request: &RequestEnd) {
+        let api = api_name(request.api_key);
+        let result = request.error.map(error_class_name).unwrap_or("ok");
+
+        gauge!("rskafka_requests_in_flight", "api" => api.clone()).decrement(1);
+        counter!("rskafka_requests_total", "api" => api.clone(), "result" => result).increment(1);
+        histogram!("rskafka_request_duration_seconds", "api" => api.clone())
+            .record(request.duration);
+        counter!("rskafka_request_sent_bytes_total", "api" => api.clone())
+            .increment(request.bytes_sent as u64);
+        counter!("rskafka_request_received_bytes_total", "api" => api)
+            .increment(request.bytes_received as u64);
+    }
+
+    fn connection_opened(&self, broker: &str) {
+        gauge!("rskafka_connections", "broker" => broker.to_owned()).increment(1);
+        counter!("rskafka_connections_opened_total", "broker" => broker.to_owned()).increment(1);
+    }
+
+    fn connection_closed(&self, broker: &str) {
+        gauge!("rskafka_connections", "broker" => broker.to_owned()).decrement(1);
+    }
+
+    fn produce_batch(&self, batch: &ProduceBatch<'_>) {
+        counter!("rskafka_produced_records_total", "topic" => batch.topic.to_owned())
+            .increment(batch.records as u64);
+        counter!("rskafka_produced_bytes_total", "topic" => batch.topic.to_owned())
+            .increment(batch.bytes as u64);
+    }
+
+    fn fetch_batch(&self, batch: &FetchBatch<'_>) {
+        counter!("rskafka_fetched_records_total", "topic" => batch.topic.to_owned())
+            .increment(batch.records as u64);
+        counter!("rskafka_fetched_bytes_total", "topic" => batch.topic.to_owned())
+            .increment(batch.bytes as u64);
+    }
+
+    fn retry(&self, retry: &Retry<'_>) {
+        let reason = if retry.throttled { "throttle" } else { "error" };
+        counter!(
+            "rskafka_retries_total",
+            "request" => retry.request_name.to_owned(),
+            "reason" => reason
+        )
+        .increment(1);
+    }
+
+    fn throttled(&self, throttle: &Throttle<'_>) {
+        histogram!(
+            "rskafka_throttle_duration_seconds",
+            "request" => throttle.request_name.to_owned()
+        )
+        .record(throttle.duration);
+    }
+
+    fn aggregator_flushed(&self, flush: &AggregatorFlush) {
+        counter!(
+            "rskafka_producer_flushes_total",
+            "reason" => flush_reason_name(flush.reason)
+        )
+        .increment(1);
+        histogram!("rskafka_producer_batch_inputs").record(flush.inputs as f64);
+        if let Some(bytes) = flush.bytes {
+            histogram!("rskafka_producer_batch_bytes").record(bytes as f64);
+        }
+        histogram!("rskafka_producer_batch_age_seconds").record(flush.age);
+    }
+}
+
+fn api_name(api_key: i16) -> String {
+    match ApiKey::from(Int16(api_key)) {
+        ApiKey::Unknown(_) => api_key.to_string(),
+        api_key => format!("{api_key:?}"),
+    }
+}
+
+fn error_class_name(class: RequestErrorClass) -> &'static str {
+    match class {
+        RequestErrorClass::Unsupported => "unsupported",
+        RequestErrorClass::Encode => "encode",
+        RequestErrorClass::Decode => "decode",
+        RequestErrorClass::Io => "io",
+        RequestErrorClass::Timeout => "timeout",
+        RequestErrorClass::Closed => "closed",
+        RequestErrorClass::Cancelled => "cancelled",
+    }
+}
+
+fn flush_reason_name(reason: FlushReason) -> &'static str {
+    match reason {
+        FlushReason::Linger => "linger",
+        FlushReason::Full => "full",
+        FlushReason::Manual => "manual",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_name() {
+        assert_eq!(api_name(0), "Produce");
+        assert_eq!(api_name(18), "ApiVersions");
+        assert_eq!(api_name(-1), "-1");
+    }
+}
@@ -0,0 +1,876 @@
+//! In-memory broker to test code that uses rskafka without a Kafka cluster, see [`MockBroker`].
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read, Write},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Notify,
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{debug, warn};
+
+use crate::protocol::{
+    api_key::ApiKey,
+    api_version::ApiVersion,
+    error::Error as ProtocolError,
+    frame::{AsyncMessageRead, AsyncMessageWrite},
+    messages::{
+        ApiVersionsRequest, ApiVersionsResponse, ApiVersionsResponseApiKey, ReadVersionedError,
+        ReadVersionedType, RequestHeader, ResponseHeader, WriteVersionedError, WriteVersionedType,
+    },
+    primitives::{
+        Boolean, Int16, Int32, Int64, Int8, NullableBytes, NullableString, RawRecords, String_,
+    },
+    traits::{ReadError, ReadType, WriteError, WriteType},
+};
+
+/// ID of the mock broker.
+const BROKER_ID: i32 = 0;
+
+/// Limit for the size of requests.
+const MAX_MESSAGE_SIZE: usize = 100 * 1024 * 1024;
+
+/// Supported APIs with their minimum and maximum version.
+///
+/// Apart from `ApiVersions` only a single version of each API is implemented, the client negotiates it.
+const API_VERSIONS: &[(ApiKey, i16, i16)] = &[
+    (ApiKey::ApiVersions, 0, 3),
+    (ApiKey::Metadata, 2, 2),
+    (ApiKey::Produce, 3, 3),
+    (ApiKey::Fetch, 4, 4),
+    (ApiKey::ListOffsets, 2, 2),
+    (ApiKey::CreateTopics, 1, 1),
+];
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("Cannot read request: {0}")]
+    Read(#[from] ReadError),
+
+    #[error("Cannot read request: {0}")]
+    ReadVersioned(#[from] ReadVersionedError),
+
+    #[error("Cannot write response: {0}")]
+    Write(#[from] WriteError),
+
+    #[error("Cannot write response: {0}")]
+    WriteVersioned(#[from] WriteVersionedError),
+
+    #[error("Unsupported API {api_key:?} version {version}")]
+    Unsupported { api_key: ApiKey, version: i16 },
+}
+
+/// A single-node Kafka broker that keeps topics in memory.
+///
+/// The broker listens on a random local port and implements just enough of the protocol for [`Client`] to create
+/// topics, produce, fetch and list offsets. It has no replication, retention, transactions, authentication or
+/// consumer groups. Topics are not created automatically.
+///
+/// The broker stops when it is dropped.
+///
+/// # Example
+/// ```
+/// # async fn test() {
+/// use rskafka::{
+///     client::{partition::UnknownTopicHandling, ClientBuilder},
+///     mock_broker::MockBroker,
+/// };
+///
+/// let broker = MockBroker::start().await.unwrap();
+/// broker.create_topic("my_topic", 1);
+///
+/// let client = ClientBuilder::new(broker.bootstrap_brokers())
+///     .build()
+///     .await
+///     .unwrap();
+/// let partition_client = client
+///     .partition_client("my_topic", 0, UnknownTopicHandling::Error)
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+///
+/// [`Client`]: crate::client::Client
+pub struct MockBroker {
+    addr: SocketAddr,
+    state: Arc<State>,
+    accept: JoinHandle<()>,
+}
+
+impl MockBroker {
+    /// Start a broker that listens on a random port of `127.0.0.1`.
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State {
+            addr,
+            topics: Default::default(),
+            appended: Notify::new(),
+            connections: Default::default(),
+        });
+
+        let state_captured = Arc::clone(&state);
+        let accept = tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _peer)) => stream,
+                    Err(e) => {
+                        warn!(%e, "mock broker cannot accept connection");
+                        continue;
+                    }
+                };
+                let stalled = Arc::new(AtomicBool::new(false));
+                let connection = tokio::spawn(serve(
+                    stream,
+                    Arc::clone(&state_captured),
+                    Arc::clone(&stalled),
+                ));
+
+                let mut connections = state_captured.connections.lock();
+                connections.retain(|(c, _)| !c.is_finished());
+                connections.push((connection, stalled));
+            }
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            accept,
+        })
+    }
+
+    /// Bootstrap brokers to pass to [`ClientBuilder::new`](crate::client::ClientBuilder::new).
+    pub fn bootstrap_brokers(&self) -> Vec<String> {
+        vec![self.addr.to_string()]
+    }
+
+    /// Create a topic with `num_partitions` empty partitions.
+    ///
+    /// Returns `false` if the topic already exists.
+    pub fn create_topic(&self, name: impl Into<String>, num_partitions: i32) -> bool {
+        self.state
+            .create_topic(name.into(), num_partitions)
+            .is_none()
+    }
+
+    /// Close all connections of clients, e.g. to test how they recover from broken connections.
+    ///
+    /// The broker keeps accepting new connections.
+    pub fn close_connections(&self) {
+        for (connection, _) in self.state.connections.lock().drain(..) {
+            connection.abort();
+        }
+    }
+
+    /// Stop answering requests on all connections of clients without closing them, like half-open connections after
+    /// a network failure.
+    ///
+    /// The broker keeps answering on new connections.
+    pub fn stall_connections(&self) {
+        for (_, stalled) in self.state.connections.lock().iter() {
+            stalled.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl std::fmt::Debug for MockBroker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockBroker")
+            .field("addr", &self.addr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for MockBroker {
+    fn drop(&mut self) {
+        self.accept.abort();
+        self.close_connections();
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    addr: SocketAddr,
+    topics: Mutex<BTreeMap<String, Vec<PartitionLog>>>,
+
+    /// Notified when records are appended, to answer fetch requests that wait for data.
+    appended: Notify,
+
+    /// Connection tasks together with a flag to stop answering requests, see [`MockBroker::stall_connections`].
+    connections: Mutex<Vec<(JoinHandle<()>, Arc<AtomicBool>)>>,
+}
+
+#[derive(Debug, Default)]
+struct PartitionLog {
+    /// Record batches with their offsets patched in.
+    batches: Vec<Batch>,
+
+    /// Offset of the next record, i.e. the high watermark.
+    next_offset: i64,
+}
+
+#[derive(Debug)]
+struct Batch {
+    last_offset: i64,
+    data: Bytes,
+}
+
+impl State {
+    /// Create a topic, returning the error if that is not possible.
+    fn create_topic(&self, name: String, num_partitions: i32) -> Option<ProtocolError> {
+        if name.is_empty() {
+            return Some(ProtocolError::InvalidTopicException);
+        }
+        if num_partitions <= 0 {
+            return Some(ProtocolError::InvalidPartitions);
+        }
+
+        let mut topics = self.topics.lock();
+        if topics.contains_key(&name) {
+            return Some(ProtocolError::TopicAlreadyExists);
+        }
+        topics.insert(
+            name,
+            (0..num_partitions)
+                .map(|_| PartitionLog::default())
+                .collect(),
+        );
+        None
+    }
+
+    fn metadata(&self, reader: &mut impl Read, writer: &mut impl Write) -> Result<(), Error> {
+        let requested = read_array(reader, String_::read)?;
+        let topics = self.topics.lock();
+        let names: Vec<String> = match requested {
+            Some(requested) => requested.into_iter().map(|t| t.0).collect(),
+            None => topics.keys().cloned().collect(),
+        };
+
+        // brokers
+        write_array(writer, &[()], |writer, ()| {
+            Int32(BROKER_ID).write(writer)?;
+            String_(self.addr.ip().to_string()).write(writer)?;
+            Int32(i32::from(self.addr.port())).write(writer)?;
+            NullableString(None).write(writer)
+        })?;
+        // cluster ID and controller
+        NullableString(Some("mock".to_owned())).write(writer)?;
+        Int32(BROKER_ID).write(writer)?;
+
+        write_array(writer, &names, |writer, name| {
+            let partitions = topics.get(name).map(Vec::len);
+            let error = partitions
+                .is_none()
+                .then_some(ProtocolError::UnknownTopicOrPartition);
+            Int16::from(error).write(writer)?;
+            String_(name.clone()).write(writer)?;
+            Boolean(false).write(writer)?;
+            let partitions: Vec<_> = (0..partitions.unwrap_or_default() as i32).collect();
+            write_array(writer, &partitions, |writer, partition| {
+                Int16(0).write(writer)?;
+                Int32(*partition).write(writer)?;
+                Int32(BROKER_ID).write(writer)?;
+                write_array(writer, &[BROKER_ID], |w, id| Int32(*id).write(w))?;
+                write_array(writer, &[BROKER_ID], |w, id| Int32(*id).write(w))
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Handle a produce request, returning `false` if no response is expected.
+    fn produce(&self, reader: &mut impl Read, writer: &mut impl Write) -> Result<bool, Error> {
+        let _transactional_id = NullableString::read(reader)?;
+        let acks = Int16::read(reader)?.0;
+        let _timeout_ms = Int32::read(reader)?;
+        let topic_data = read_array(reader, |reader| {
+            let name = String_::read(reader)?.0;
+            let partitions = read_array(reader, |reader| {
+                let index = Int32::read(reader)?.0;
+                let records = NullableBytes::read(reader)?.0.unwrap_or_default();
+                Ok((index, records))
+            })?;
+            Ok((name, partitions.unwrap_or_default()))
+        })?;
+
+        let mut responses = vec![];
+        {
+            let mut topics = self.topics.lock();
+            for (name, partitions) in topic_data.unwrap_or_default() {
+                let partition_responses: Vec<_> = partitions
+                    .into_iter()
+                    .map(|(index, records)| {
+                        let log = topics
+                            .get_mut(&name)
+                            .and_then(|t| t.get_mut(usize::try_from(index).ok()?));
+                        let res = match log {
+                            Some(log) => log.append(records),
+                            None => Err(ProtocolError::UnknownTopicOrPartition),
+                        };
+                        (index, res)
+                    })
+                    .collect();
+                responses.push((name, partition_responses));
+            }
+        }
+        self.appended.notify_waiters();
+
+        if acks == 0 {
+            return Ok(false);
+        }
+        write_array(writer, &responses, |writer, (name, partitions)| {
+            String_(name.clone()).write(writer)?;
+            write_array(writer, partitions, |writer, (index, res)| {
+                Int32(*index).write(writer)?;
+                Int16::from(res.err()).write(writer)?;
+                Int64(*res.as_ref().unwrap_or(&-1)).write(writer)?;
+                // log append time
+                Int64(-1).write(writer)
+            })
+        })?;
+        // throttle time
+        Int32(0).write(writer)?;
+        Ok(true)
+    }
+
+    async fn fetch(
+        &self,
+        reader: &mut (impl Read + Send),
+        writer: &mut (impl Write + Send),
+    ) -> Result<(), Error> {
+        let _replica_id = Int32::read(reader)?;
+        let max_wait = Duration::from_millis(Int32::read(reader)?.0.max(0) as u64);
+        let min_bytes = Int32::read(reader)?.0;
+        let _max_bytes = Int32::read(reader)?;
+        let _isolation_level = Int8::read(reader)?;
+        let requested = read_array(reader, |reader| {
+            let topic = String_::read(reader)?.0;
+            let partitions = read_array(reader, |reader| {
+                let partition = Int32::read(reader)?.0;
+                let fetch_offset = Int64::read(reader)?.0;
+                let max_bytes = Int32::read(reader)?.0;
+                Ok((partition, fetch_offset, max_bytes))
+            })?;
+            Ok((topic, partitions.unwrap_or_default()))
+        })?
+        .unwrap_or_default();
+
+        // wait until there is any data or `max_wait` elapsed
+        let deadline = Instant::now() + max_wait;
+        let responses = loop {
+            let appended = self.appended.notified();
+            let responses = self.fetch_once(&requested);
+            let bytes: usize = responses
+                .iter()
+                .flat_map(|(_, partitions)| partitions)
+                .map(|p| p.records.len())
+                .sum();
+            if bytes > 0 || min_bytes <= 0 || Instant::now() >= deadline {
+                break responses;
+            }
+            let _ = tokio::time::timeout_at(deadline, appended).await;
+        };
+
+        // throttle time
+        Int32(0).write(writer)?;
+        write_array(writer, &responses, |writer, (topic, partitions)| {
+            String_(topic.clone()).write(writer)?;
+            write_array(writer, partitions, |writer, p| {
+                Int32(p.partition).write(writer)?;
+                Int16::from(p.error).write(writer)?;
+                Int64(p.high_watermark).write(writer)?;
+                // last stable offset
+                Int64(p.high_watermark).write(writer)?;
+                // aborted transactions
+                write_array(writer, &[], |_, ()| Ok(()))?;
+                NullableBytes(Some(p.records.clone())).write(writer)
+            })
+        })?;
+        Ok(())
+    }
+
+    fn fetch_once(&self, requested: &[FetchTopic]) -> Vec<(String, Vec<FetchedPartition>)> {
+        let topics = self.topics.lock();
+        requested
+            .iter()
+            .map(|(topic, partitions)| {
+                let partitions = partitions
+                    .iter()
+                    .map(|(partition, fetch_offset, max_bytes)| {
+                        let log = topics
+                            .get(topic)
+                            .and_then(|t| t.get(usize::try_from(*partition).ok()?));
+                        let (records, error, high_watermark) = match log {
+                            Some(log) => match log.read(*fetch_offset, *max_bytes) {
+                                Some(records) => (records, None, log.next_offset),
+                                None => (
+                                    vec![],
+                                    Some(ProtocolError::OffsetOutOfRange),
+                                    log.next_offset,
+                                ),
+                            },
+                            None => (vec![], Some(ProtocolError::UnknownTopicOrPartition), -1),
+                        };
+                        FetchedPartition {
+                            partition: *partition,
+                            error,
+                            high_watermark,
+                            records,
+                        }
+                    })
+                    .collect();
+                (topic.clone(), partitions)
+            })
+            .collect()
+    }
+
+    fn list_offsets(&self, reader: &mut impl Read, writer: &mut impl Write) -> Result<(), Error> {
+        let _replica_id = Int32::read(reader)?;
+        let _isolation_level = Int8::read(reader)?;
+        let requested = read_array(reader, |reader| {
+            let name = String_::read(reader)?.0;
+            let partitions = read_array(reader, |reader| {
+                let partition = Int32::read(reader)?.0;
+                let timestamp = Int64::read(reader)?.0;
+                Ok((partition, timestamp))
+            })?;
+            Ok((name, partitions.unwrap_or_default()))
+        })?
+        .unwrap_or_default();
+
+        let topics = self.topics.lock();
+        // throttle time
+        Int32(0).write(writer)?;
+        write_array(writer, &requested, |writer, (name, partitions)| {
+            String_(name.clone()).write(writer)?;
+            write_array(writer, partitions, |writer, (partition, timestamp)| {
+                let log = topics
+                    .get(name)
+                    .and_then(|t| t.get(usize::try_from(*partition).ok()?));
+                let (error, offset) = match log {
+                    Some(log) => (None, log.offset_for(*timestamp)),
+                    None => (Some(ProtocolError::UnknownTopicOrPartition), -1),
+                };
+                Int32(*partition).write(writer)?;
+                Int16::from(error).write(writer)?;
+                Int64(-1).write(writer)?;
+                Int64(offset).write(writer)
+            })
+        })?;
+        Ok(())
+    }
+
+    fn create_topics(&self, reader: &mut impl Read, writer: &mut impl Write) -> Result<(), Error> {
+        let requested = read_array(reader, |reader| {
+            let name = String_::read(reader)?.0;
+            let num_partitions = Int32::read(reader)?.0;
+            let _replication_factor = Int16::read(reader)?;
+            let _assignments = read_array(reader, |reader| {
+                let _partition_index = Int32::read(reader)?;
+                read_array(reader, Int32::read)
+            })?;
+            let _configs = read_array(reader, |reader| {
+                let _name = String_::read(reader)?;
+                NullableString::read(reader)
+            })?;
+            Ok((name, num_partitions))
+        })?
+        .unwrap_or_default();
+        let _timeout_ms = Int32::read(reader)?;
+        let validate_only = Boolean::read(reader)?.0;
+
+        write_array(writer, &requested, |writer, (name, num_partitions)| {
+            let error = if validate_only {
+                None
+            } else {
+                self.create_topic(name.clone(), *num_partitions)
+            };
+            String_(name.clone()).write(writer)?;
+            Int16::from(error).write(writer)?;
+            NullableString(None).write(writer)
+        })?;
+        Ok(())
+    }
+}
+
+/// Topic of a fetch request with the partition, fetch offset and max bytes of each partition.
+type FetchTopic = (String, Vec<(i32, i64, i32)>);
+
+struct FetchedPartition {
+    partition: i32,
+    error: Option<ProtocolError>,
+    high_watermark: i64,
+    records: Vec<u8>,
+}
+
+impl PartitionLog {
+    /// Append the record batches in `records` and return the offset of the first record.
+    fn append(&mut self, records: Vec<u8>) -> Result<i64, ProtocolError> {
+        let batches = RawRecords(records.into())
+            .batches()
+            .map_err(|_| ProtocolError::CorruptMessage)?;
+        if batches.is_empty() {
+            return Err(ProtocolError::InvalidRecord);
+        }
+
+        let base_offset = self.next_offset;
+        for batch in batches {
+            let mut data = batch.data.to_vec();
+            // the base offset is the first field and not covered by the CRC
+            data[..8].copy_from_slice(&self.next_offset.to_be_bytes());
+            let last_offset = self.next_offset + i64::from(batch.last_offset_delta);
+            self.batches.push(Batch {
+                last_offset,
+                data: data.into(),
+            });
+            self.next_offset = last_offset + 1;
+        }
+        Ok(base_offset)
+    }
+
+    /// Read the batches that contain `offset` or later records, or `None` if `offset` is out of range.
+    ///
+    /// Like Kafka, this returns at least one batch even if it is larger than `max_bytes`.
+    fn read(&self, offset: i64, max_bytes: i32) -> Option<Vec<u8>> {
+        if !(0..=self.next_offset).contains(&offset) {
+            return None;
+        }
+
+        let max_bytes = usize::try_from(max_bytes).unwrap_or_default();
+        let mut records = vec![];
+        for batch in self.batches.iter().filter(|b| b.last_offset >= offset) {
+            if !records.is_empty() && records.len() + batch.data.len() > max_bytes {
+                break;
+            }
+            records.extend_from_slice(&batch.data);
+        }
+        Some(records)
+    }
+
+    /// Offset for a list offsets request, where `-1` means latest and `-2` earliest.
+    ///
+    /// Timestamp-based queries are not supported (the client does not expose them) and return `-1`, like Kafka does
+    /// if there is no matching record.
+    fn offset_for(&self, timestamp: i64) -> i64 {
+        match timestamp {
+            -1 => self.next_offset,
+            -2 => 0,
+            _ => -1,
+        }
+    }
+}
+
+/// Serve requests of a single connection one after another, like Kafka does.
+async fn serve(mut stream: TcpStream, state: Arc<State>, stalled: Arc<AtomicBool>) {
+    loop {
+        let msg = match stream.read_message(MAX_MESSAGE_SIZE).await {
+            Ok(msg) => msg,
+            Err(e) => {
+                debug!(%e, "mock broker connection closed");
+                return;
+            }
+        };
+        if stalled.load(Ordering::SeqCst) {
+            continue;
+        }
+
+        match handle(&state, msg).await {
+            Ok(Some(response)) => {
+                if let Err(e) = stream.write_message(&response).await {
+                    debug!(%e, "mock broker connection closed");
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!(%e, "mock broker cannot handle request, closing connection");
+                return;
+            }
+        }
+    }
+}
+
+/// Handle a request, returning `None` if no response is expected.
+async fn handle(state: &State, msg: Vec<u8>) -> Result<Option<Vec<u8>>, Error> {
+    let mut reader = Cursor::new(msg);
+    let api_key = ApiKey::from(Int16::read(&mut reader)?);
+    let version = Int16::read(&mut reader)?.0;
+    if !API_VERSIONS
+        .iter()
+        .any(|(key, min, max)| *key == api_key && (*min..=*max).contains(&version))
+    {
+        return Err(Error::Unsupported { api_key, version });
+    }
+
+    // only ApiVersions v3 uses a flexible request header
+    let header_version = if api_key == ApiKey::ApiVersions && version >= 3 {
+        2
+    } else {
+        1
+    };
+    reader.set_position(0);
+    let header = RequestHeader::read_versioned(&mut reader, ApiVersion(Int16(header_version)))?;
+
+    let mut body = vec![];
+    match api_key {
+        ApiKey::ApiVersions => {
+            let version = ApiVersion(Int16(version));
+            ApiVersionsRequest::read_versioned(&mut reader, version)?;
+            ApiVersionsResponse {
+                error_code: None,
+                api_keys: API_VERSIONS
+                    .iter()
+                    .map(|(api_key, min, max)| ApiVersionsResponseApiKey {
+                        api_key: *api_key,
+                        min_version: ApiVersion(Int16(*min)),
+                        max_version: ApiVersion(Int16(*max)),
+                        tagged_fields: None,
+                    })
+                    .collect(),
+                throttle_time_ms: None,
+                tagged_fields: None,
+            }
+            .write_versioned(&mut body, version)?;
+        }
+        ApiKey::Metadata => state.metadata(&mut reader, &mut body)?,
+        ApiKey::Produce => {
+            if !state.produce(&mut reader, &mut body)? {
+                return Ok(None);
+            }
+        }
+        ApiKey::Fetch => state.fetch(&mut reader, &mut body).await?,
+        ApiKey::ListOffsets => state.list_offsets(&mut reader, &mut body)?,
+        ApiKey::CreateTopics => state.create_topics(&mut reader, &mut body)?,
+        _ => unreachable!("API is not advertised"),
+    }
+
+    let mut response = vec![];
+    ResponseHeader {
+        correlation_id: header.correlation_id,
+        tagged_fields: None,
+    }
+    .write_versioned(&mut response, ApiVersion(Int16(0)))?;
+    response.extend(body);
+    Ok(Some(response))
+}
+
+/// Read a nullable array.
+fn read_array<R, T, F>(reader: &mut R, mut f: F) -> Result<Option<Vec<T>>, ReadError>
+where
+    R: Read,
+    F: FnMut(&mut R) -> Result<T, ReadError>,
+{
+    let len = Int32::read(reader)?.0;
+    if len < 0 {
+        return Ok(None);
+    }
+    (0..len)
+        .map(|_| f(reader))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn write_array<W, T, F>(writer: &mut W, items: &[T], mut f: F) -> Result<(), WriteError>
+where
+    W: Write,
+    F: FnMut(&mut W, &T) -> Result<(), WriteError>,
+{
+    let len = i32::try_from(items.len()).map_err(|e| WriteError::Malformed(Box::new(e)))?;
+    Int32(len).write(writer)?;
+    items.iter().try_for_each(|item| f(writer, item))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        client::{
+            error::{Error as ClientError, ProtocolError},
+            partition::{Compression, OffsetAt, UnknownTopicHandling},
+            ClientBuilder,
+        },
+        record::Record,
+    };
+
+    fn record(ts: i64) -> Record {
+        Record {
+            key: Some(b"key".to_vec().into()),
+            value: Some(b"value".to_vec().into()),
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(ts).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_broker() {
+        let broker = MockBroker::start().await.unwrap();
+        assert!(broker.create_topic("foo", 2));
+        assert!(!broker.create_topic("foo", 2));
+
+        let client = ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+
+        let controller_client = client.controller_client().unwrap();
+        controller_client
+            .create_topic("bar", 1, 1, 5_000)
+            .await
+            .unwrap();
+        let err = controller_client
+            .create_topic("bar", 1, 1, 5_000)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            ClientError::ServerError {
+                protocol_error: ProtocolError::TopicAlreadyExists,
+                ..
+            }
+        );
+
+        let topics = client.list_topics().await.unwrap();
+        assert_eq!(
+            topics
+                .iter()
+                .map(|t| (t.name.as_str(), t.partitions.len()))
+                .collect::<Vec<_>>(),
+            vec![("bar", 1), ("foo", 2)]
+        );
+
+        let partition_client = client
+            .partition_client("foo", 1, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+        let offsets = partition_client
+            .produce(
+                vec![record(1_000), record(2_000)],
+                Compression::NoCompression,
+            )
+            .await
+            .unwrap();
+        assert_eq!(offsets, vec![0, 1]);
+        let offsets = partition_client
+            .produce(vec![record(3_000)], Compression::NoCompression)
+            .await
+            .unwrap();
+        assert_eq!(offsets, vec![2]);
+
+        let (records, high_watermark) = partition_client
+            .fetch_records(1, 1..1_000_000, 1_000)
+            .await
+            .unwrap();
+        assert_eq!(high_watermark, 3);
+        assert_eq!(
+            records.iter().map(|r| r.offset).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(records[0].record, record(2_000));
+
+        assert_eq!(
+            partition_client
+                .get_offset(OffsetAt::Earliest)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            partition_client.get_offset(OffsetAt::Latest).await.unwrap(),
+            3
+        );
+        assert_eq!(partition_client.watermarks().await.unwrap(), (0, 3));
+
+        let err = partition_client
+            .fetch_records(4, 1..1_000_000, 1_000)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            ClientError::ServerError {
+                protocol_error: ProtocolError::OffsetOutOfRange,
+                ..
+            }
+        );
+
+        let err = client
+            .partition_client("baz", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            err,
+            ClientError::ServerError {
+                protocol_error: ProtocolError::UnknownTopicOrPartition,
+                ..
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_waits_for_records() {
+        let broker = MockBroker::start().await.unwrap();
+        broker.create_topic("foo", 1);
+
+        let client = ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        // no records
+        let (records, high_watermark) = partition_client
+            .fetch_records(0, 1..1_000_000, 10)
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+        assert_eq!(high_watermark, 0);
+
+        // records are produced while the fetch request waits, using a separate connection
+        let fetch = {
+            let partition_client = Arc::clone(&partition_client);
+            tokio::spawn(async move {
+                partition_client
+                    .fetch_records(0, 1..1_000_000, 10_000)
+                    .await
+                    .unwrap()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let producer = ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap()
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+        producer
+            .produce(vec![record(1_000)], Compression::NoCompression)
+            .await
+            .unwrap();
+
+        let (records, high_watermark) = tokio::time::timeout(Duration::from_secs(5), fetch)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(high_watermark, 1);
+    }
+}
@@ -67,6 +67,8 @@ pub enum ApiKey {
     DescribeTransactions,
     ListTransactions,
     AllocateProducerIds,
+    GetTelemetrySubscriptions,
+    PushTelemetry,
     Unknown(Int16),
 }
 
@@ -132,6 +134,8 @@ impl From<Int16> for ApiKey {
             65 => Self::DescribeTransactions,
             66 => Self::ListTransactions,
             67 => Self::AllocateProducerIds,
+            71 => Self::GetTelemetrySubscriptions,
+            72 => Self::PushTelemetry,
             _ => Self::Unknown(key),
         }
     }
@@ -199,6 +203,8 @@ impl From<ApiKey> for Int16 {
             ApiKey::DescribeTransactions => Self(65),
             ApiKey::ListTransactions => Self(66),
             ApiKey::AllocateProducerIds => Self(67),
+            ApiKey::GetTelemetrySubscriptions => Self(71),
+            ApiKey::PushTelemetry => Self(72),
             ApiKey::Unknown(code) => code,
         }
     }
@@ -115,6 +115,8 @@ pub enum Error {
     InconsistentTopicId,
     InconsistentClusterId,
     TransactionalIdNotFound,
+    UnknownSubscriptionId,
+    TelemetryTooLarge,
     Unknown(i16),
 }
 
@@ -231,9 +233,47 @@ impl Error {
             103 => Some(Self::InconsistentTopicId),
             104 => Some(Self::InconsistentClusterId),
             105 => Some(Self::TransactionalIdNotFound),
+            117 => Some(Self::UnknownSubscriptionId),
+            118 => Some(Self::TelemetryTooLarge),
             _ => Some(Self::Unknown(code)),
         }
     }
+
+    /// Whether the error is transient, i.e. retrying the same request may succeed.
+    ///
+    /// This follows the "retriable" column of the protocol documentation.
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            Self::CorruptMessage
+                | Self::UnknownTopicOrPartition
+                | Self::LeaderNotAvailable
+                | Self::NotLeaderOrFollower
+                | Self::RequestTimedOut
+                | Self::ReplicaNotAvailable
+                | Self::NetworkException
+                | Self::CoordinatorLoadInProgress
+                | Self::CoordinatorNotAvailable
+                | Self::NotCoordinator
+                | Self::NotEnoughReplicas
+                | Self::NotEnoughReplicasAfterAppend
+                | Self::NotController
+                | Self::ConcurrentTransactions
+                | Self::KafkaStorageError
+                | Self::FetchSessionIdNotFound
+                | Self::InvalidFetchSessionEpoch
+                | Self::ListenerNotFound
+                | Self::FencedLeaderEpoch
+                | Self::UnknownLeaderEpoch
+                | Self::OffsetNotAvailable
+                | Self::PreferredLeaderNotAvailable
+                | Self::EligibleLeadersNotAvailable
+                | Self::UnstableOffsetCommit
+                | Self::ThrottlingQuotaExceeded
+                | Self::UnknownTopicId
+                | Self::InconsistentTopicId
+        )
+    }
 }
 
 impl From<Option<Error>> for Int16 {
@@ -350,6 +390,8 @@ impl From<Option<Error>> for Int16 {
             Error::InconsistentTopicId => Self(103),
             Error::InconsistentClusterId => Self(104),
             Error::TransactionalIdNotFound => Self(105),
+            Error::UnknownSubscriptionId => Self(117),
+            Error::TelemetryTooLarge => Self(118),
             Error::Unknown(code) => Self(code),
         }
     }
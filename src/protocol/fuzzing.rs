@@ -0,0 +1,146 @@
+//! Entry points for fuzz targets that cover responses whose content is largely controlled by other clients.
+//!
+//! In contrast to reading a message via the messenger, these also decode nested data like record batches and skip
+//! checksums, which a fuzzer is unlikely to get right.
+use std::io::Cursor;
+
+use thiserror::Error;
+
+use super::{
+    api_version::ApiVersion,
+    messages::{FetchResponse, MetadataResponse, ReadVersionedError, ReadVersionedType},
+    primitives::{Int16, RawRecords},
+    record::RecordBatch,
+    traits::ReadError,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot read response: {0}")]
+    ReadVersioned(#[from] ReadVersionedError),
+
+    #[error("Cannot read records: {0}")]
+    Read(#[from] ReadError),
+
+    #[error("Found {0} trailing bytes after the response")]
+    TrailingData(usize),
+}
+
+/// Decode a fetch response body and all record batches in it, like a consumer does.
+///
+/// The CRCs of the record batches are fixed up before decoding, so that inputs reach the decompression and record
+/// decoding. For compressed legacy messages this only applies to the outer message.
+pub fn decode_fetch_response(
+    data: &[u8],
+    version: i16,
+) -> Result<(FetchResponse, Vec<RecordBatch>), Error> {
+    let response = read_response::<FetchResponse>(data, version)?;
+
+    let mut batches = vec![];
+    for partition in response.responses.iter().flat_map(|t| &t.partitions) {
+        let records = RawRecords(fix_crcs(&partition.records.0).into());
+        batches.extend(records.decode()?.0);
+    }
+    Ok((response, batches))
+}
+
+/// Decode a metadata response body.
+pub fn decode_metadata_response(data: &[u8], version: i16) -> Result<MetadataResponse, Error> {
+    read_response(data, version)
+}
+
+fn read_response<T>(data: &[u8], version: i16) -> Result<T, Error>
+where
+    T: for<'a> ReadVersionedType<Cursor<&'a [u8]>>,
+{
+    let mut cursor = Cursor::new(data);
+    let response = T::read_versioned(&mut cursor, ApiVersion(Int16(version)))?;
+
+    let trailing = data.len() - cursor.position() as usize;
+    if trailing != 0 {
+        return Err(Error::TrailingData(trailing));
+    }
+    Ok(response)
+}
+
+/// Replace the CRC of every record batch and legacy message in `records` with the correct one.
+///
+/// Stops at the first entry that is truncated, decoding will report it.
+fn fix_crcs(records: &[u8]) -> Vec<u8> {
+    let mut records = records.to_vec();
+    let mut pos = 0;
+
+    // every entry starts with offset (INT64) and length (INT32), followed by the data
+    while let Some(len) = records.get(pos + 8..pos + 12) {
+        let len = i32::from_be_bytes(len.try_into().expect("4 bytes")) as usize;
+        let start = pos + 12;
+        let Some(end) = start.checked_add(len).filter(|end| *end <= records.len()) else {
+            break;
+        };
+
+        match records.get(start + 4) {
+            // record batch: partition leader epoch (INT32), magic (INT8), CRC-32C of the rest
+            Some(2) if len >= 9 => {
+                let crc = crc32c::crc32c(&records[start + 9..end]);
+                records[start + 5..start + 9].copy_from_slice(&crc.to_be_bytes());
+            }
+            // legacy message: CRC-32 of the rest, magic (INT8)
+            Some(0 | 1) if len >= 4 => {
+                let crc = crc32fast::hash(&records[start + 4..end]);
+                records[start..start + 4].copy_from_slice(&crc.to_be_bytes());
+            }
+            _ => {}
+        }
+
+        pos = end;
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{
+        record::{ControlBatchOrRecords, Record, RecordBatchCompression, RecordBatchTimestampType},
+        traits::WriteType,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_fix_crcs() {
+        let batch = RecordBatch {
+            base_offset: 0,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: ControlBatchOrRecords::Records(vec![Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: Some(b"foo".to_vec().into()),
+                value: Some(b"bar".to_vec().into()),
+                headers: vec![],
+            }]),
+            compression: RecordBatchCompression::NoCompression,
+            is_transactional: false,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+        let mut data = vec![];
+        batch.write(&mut data).unwrap();
+        data.extend(data.clone());
+
+        // corrupt the CRC of the second batch
+        let second = data.len() / 2;
+        data[second + 17] ^= 0xff;
+        RawRecords(data.clone().into()).decode().unwrap_err();
+
+        let fixed = fix_crcs(&data);
+        assert_eq!(RawRecords(fixed.into()).decode().unwrap().0.len(), 2);
+
+        // truncated entries are kept as they are
+        assert_eq!(fix_crcs(&data[..second + 5]), &data[..second + 5]);
+    }
+}
@@ -3,6 +3,9 @@ use crate::protocol::primitives::{Int32, Int8};
 /// The `replica_id` to use to signify the request is being made by a normal consumer.
 pub const NORMAL_CONSUMER: Int32 = Int32(-1);
 
+/// The `resource_type` of topics in config requests like `DescribeConfigs`.
+pub const RESOURCE_TYPE_TOPIC: Int8 = Int8(2);
+
 /// Using `READ_UNCOMMITTED` (`isolation_level = 0`) makes all records visible. With `READ_COMMITTED`
 /// (`isolation_level = 1`), non-transactional and `COMMITTED` transactional records are visible. To be more
 /// concrete, `READ_COMMITTED` returns all data from offsets smaller than the current LSO (last stable offset), and
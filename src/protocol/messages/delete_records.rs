@@ -171,6 +171,18 @@ impl RequestBody for DeleteRecordsRequest {
         ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(2)));
 
     const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(2));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Int32(throttle_time_ms);
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: Error) {
+        for partition in response.topics.iter_mut().flat_map(|t| &mut t.partitions) {
+            partition.error = Some(error);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -39,6 +39,18 @@ impl RequestBody for DeleteTopicsRequest {
         ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(5)));
 
     const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(4));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Some(Int32(throttle_time_ms));
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: Error) {
+        for topic in &mut response.responses {
+            topic.error = Some(error);
+        }
+    }
 }
 
 impl<W> WriteVersionedType<W> for DeleteTopicsRequest
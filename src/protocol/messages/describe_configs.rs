@@ -0,0 +1,467 @@
+use std::io::{Read, Write};
+
+use crate::protocol::{
+    api_key::ApiKey,
+    api_version::{ApiVersion, ApiVersionRange},
+    error::Error as ApiError,
+    primitives::{Array, Boolean, Int16, Int32, Int8, NullableString, String_},
+    traits::{ReadType, WriteType},
+};
+
+use super::{
+    read_versioned_array, write_versioned_array, ReadVersionedError, ReadVersionedType,
+    RequestBody, WriteVersionedError, WriteVersionedType,
+};
+
+#[cfg(test)]
+use proptest::prelude::*;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsRequest {
+    /// The resources whose configurations we want to describe.
+    #[cfg_attr(
+        test,
+        proptest(
+            strategy = "prop::collection::vec(any::<DescribeConfigsRequestResource>(), 0..2)"
+        )
+    )]
+    pub resources: Vec<DescribeConfigsRequestResource>,
+
+    /// True if we should include all synonyms.
+    ///
+    /// Added in version 1.
+    pub include_synonyms: Option<Boolean>,
+
+    /// True if we should include configuration documentation.
+    ///
+    /// Added in version 3.
+    pub include_documentation: Option<Boolean>,
+}
+
+impl RequestBody for DescribeConfigsRequest {
+    type ResponseBody = DescribeConfigsResponse;
+
+    const API_KEY: ApiKey = ApiKey::DescribeConfigs;
+
+    /// Enough for now.
+    const API_VERSION_RANGE: ApiVersionRange =
+        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(3)));
+
+    const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(4));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Int32(throttle_time_ms);
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: ApiError) {
+        for result in &mut response.results {
+            result.error = Some(error);
+        }
+    }
+}
+
+impl<W> WriteVersionedType<W> for DescribeConfigsRequest
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        write_versioned_array(writer, version, Some(&self.resources))?;
+
+        if v >= 1 {
+            self.include_synonyms
+                .unwrap_or(Boolean(false))
+                .write(writer)?;
+        }
+
+        if v >= 3 {
+            self.include_documentation
+                .unwrap_or(Boolean(false))
+                .write(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<R> ReadVersionedType<R> for DescribeConfigsRequest
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        Ok(Self {
+            resources: read_versioned_array(reader, version)?.unwrap_or_default(),
+            include_synonyms: (v >= 1).then(|| Boolean::read(reader)).transpose()?,
+            include_documentation: (v >= 3).then(|| Boolean::read(reader)).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsRequestResource {
+    /// The resource type.
+    pub resource_type: Int8,
+
+    /// The resource name.
+    pub resource_name: String_,
+
+    /// The configuration keys to list, or null to list all configuration keys.
+    pub configuration_keys: Array<String_>,
+}
+
+impl<W> WriteVersionedType<W> for DescribeConfigsRequestResource
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        self.resource_type.write(writer)?;
+        self.resource_name.write(writer)?;
+        self.configuration_keys.write(writer)?;
+
+        Ok(())
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<R> ReadVersionedType<R> for DescribeConfigsRequestResource
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        Ok(Self {
+            resource_type: Int8::read(reader)?,
+            resource_name: String_::read(reader)?,
+            configuration_keys: Array::read(reader)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsResponse {
+    /// The duration in milliseconds for which the request was throttled due to a quota violation, or zero if the
+    /// request did not violate any quota.
+    pub throttle_time_ms: Int32,
+
+    /// The results for each resource.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "prop::collection::vec(any::<DescribeConfigsResult>(), 0..2)")
+    )]
+    pub results: Vec<DescribeConfigsResult>,
+}
+
+impl<R> ReadVersionedType<R> for DescribeConfigsResponse
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        Ok(Self {
+            throttle_time_ms: Int32::read(reader)?,
+            results: read_versioned_array(reader, version)?.unwrap_or_default(),
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for DescribeConfigsResponse
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        self.throttle_time_ms.write(writer)?;
+        write_versioned_array(writer, version, Some(&self.results))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsResult {
+    /// The error code, or 0 if we were able to successfully describe the configurations.
+    #[cfg_attr(test, proptest(strategy = "any::<i16>().prop_map(ApiError::new)"))]
+    pub error: Option<ApiError>,
+
+    /// The error message, or null if we were able to successfully describe the configurations.
+    pub error_message: NullableString,
+
+    /// The resource type.
+    pub resource_type: Int8,
+
+    /// The resource name.
+    pub resource_name: String_,
+
+    /// Each listed configuration.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "prop::collection::vec(any::<DescribeConfigsResourceResult>(), 0..2)")
+    )]
+    pub configs: Vec<DescribeConfigsResourceResult>,
+}
+
+impl<R> ReadVersionedType<R> for DescribeConfigsResult
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        Ok(Self {
+            error: ApiError::new(Int16::read(reader)?.0),
+            error_message: NullableString::read(reader)?,
+            resource_type: Int8::read(reader)?,
+            resource_name: String_::read(reader)?,
+            configs: read_versioned_array(reader, version)?.unwrap_or_default(),
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for DescribeConfigsResult
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        let error: Int16 = self.error.into();
+        error.write(writer)?;
+        self.error_message.write(writer)?;
+        self.resource_type.write(writer)?;
+        self.resource_name.write(writer)?;
+        write_versioned_array(writer, version, Some(&self.configs))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsResourceResult {
+    /// The configuration name.
+    pub name: String_,
+
+    /// The configuration value.
+    pub value: NullableString,
+
+    /// True if the configuration is read-only.
+    pub read_only: Boolean,
+
+    /// True if the configuration is not set.
+    ///
+    /// Removed in version 1.
+    pub is_default: Option<Boolean>,
+
+    /// The configuration source.
+    ///
+    /// Added in version 1.
+    pub config_source: Option<Int8>,
+
+    /// True if this configuration is sensitive.
+    pub is_sensitive: Boolean,
+
+    /// The synonyms for this configuration key.
+    ///
+    /// Added in version 1.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "prop::collection::vec(any::<DescribeConfigsSynonym>(), 0..2)")
+    )]
+    pub synonyms: Vec<DescribeConfigsSynonym>,
+
+    /// The configuration data type.
+    ///
+    /// Added in version 3.
+    pub config_type: Option<Int8>,
+
+    /// The configuration documentation.
+    ///
+    /// Added in version 3.
+    pub documentation: Option<NullableString>,
+}
+
+impl<R> ReadVersionedType<R> for DescribeConfigsResourceResult
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        let name = String_::read(reader)?;
+        let value = NullableString::read(reader)?;
+        let read_only = Boolean::read(reader)?;
+        let is_default = (v == 0).then(|| Boolean::read(reader)).transpose()?;
+        let config_source = (v >= 1).then(|| Int8::read(reader)).transpose()?;
+        let is_sensitive = Boolean::read(reader)?;
+        let synonyms = if v >= 1 {
+            read_versioned_array(reader, version)?.unwrap_or_default()
+        } else {
+            vec![]
+        };
+        let config_type = (v >= 3).then(|| Int8::read(reader)).transpose()?;
+        let documentation = (v >= 3).then(|| NullableString::read(reader)).transpose()?;
+
+        Ok(Self {
+            name,
+            value,
+            read_only,
+            is_default,
+            config_source,
+            is_sensitive,
+            synonyms,
+            config_type,
+            documentation,
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for DescribeConfigsResourceResult
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 3);
+
+        self.name.write(writer)?;
+        self.value.write(writer)?;
+        self.read_only.write(writer)?;
+        if v == 0 {
+            self.is_default.unwrap_or(Boolean(false)).write(writer)?;
+        } else {
+            // defaults to "unknown"
+            self.config_source.unwrap_or(Int8(0)).write(writer)?;
+        }
+        self.is_sensitive.write(writer)?;
+        if v >= 1 {
+            write_versioned_array(writer, version, Some(&self.synonyms))?;
+        }
+        if v >= 3 {
+            self.config_type.unwrap_or(Int8(0)).write(writer)?;
+            match &self.documentation {
+                Some(documentation) => documentation.write(writer)?,
+                None => NullableString(None).write(writer)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct DescribeConfigsSynonym {
+    /// The synonym name.
+    pub name: String_,
+
+    /// The synonym value.
+    pub value: NullableString,
+
+    /// The synonym source.
+    pub source: Int8,
+}
+
+impl<R> ReadVersionedType<R> for DescribeConfigsSynonym
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!((1..=3).contains(&v));
+
+        Ok(Self {
+            name: String_::read(reader)?,
+            value: NullableString::read(reader)?,
+            source: Int8::read(reader)?,
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for DescribeConfigsSynonym
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!((1..=3).contains(&v));
+
+        self.name.write(writer)?;
+        self.value.write(writer)?;
+        self.source.write(writer)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::messages::test_utils::test_roundtrip_versioned;
+
+    use super::*;
+
+    test_roundtrip_versioned!(
+        DescribeConfigsRequest,
+        DescribeConfigsRequest::API_VERSION_RANGE.min(),
+        DescribeConfigsRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_describe_configs_request
+    );
+
+    test_roundtrip_versioned!(
+        DescribeConfigsResponse,
+        DescribeConfigsRequest::API_VERSION_RANGE.min(),
+        DescribeConfigsRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_describe_configs_response
+    );
+}
@@ -4,8 +4,14 @@ use crate::protocol::{
     api_key::ApiKey,
     api_version::{ApiVersion, ApiVersionRange},
     error::Error as ApiError,
-    messages::{read_versioned_array, write_versioned_array, IsolationLevel},
-    primitives::{Int16, Int32, Int64, Int8, Records, String_},
+    messages::{
+        read_compact_versioned_array, read_versioned_array, write_compact_versioned_array,
+        write_tagged_fields, write_versioned_array, IsolationLevel,
+    },
+    primitives::{
+        ArrayRef, CompactArrayRef, CompactRawRecords, CompactString, CompactStringRef, Int16,
+        Int32, Int64, Int8, RawRecords, String_, TaggedFields,
+    },
     traits::{ReadType, WriteType},
 };
 
@@ -19,13 +25,37 @@ pub struct FetchRequestPartition {
     /// The partition index.
     pub partition: Int32,
 
+    /// The current leader epoch of the partition.
+    ///
+    /// Defaults to -1, i.e. the leader epoch is not checked.
+    ///
+    /// Added in version 9.
+    pub current_leader_epoch: Option<Int32>,
+
     /// The message offset.
     pub fetch_offset: Int64,
 
+    /// The epoch of the last fetched record or -1 if there is none.
+    ///
+    /// Added in version 12.
+    pub last_fetched_epoch: Option<Int32>,
+
+    /// The earliest available offset of the follower replica.
+    ///
+    /// The field is only used when the request is sent by the follower, defaults to -1.
+    ///
+    /// Added in version 5.
+    pub log_start_offset: Option<Int64>,
+
     /// The maximum bytes to fetch from this partition.
     ///
     /// See KIP-74 for cases where this limit may not be honored.
     pub partition_max_bytes: Int32,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for FetchRequestPartition
@@ -38,11 +68,25 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 12);
 
         self.partition.write(writer)?;
+        if v >= 9 {
+            self.current_leader_epoch
+                .unwrap_or(Int32(-1))
+                .write(writer)?;
+        }
         self.fetch_offset.write(writer)?;
+        if v >= 12 {
+            self.last_fetched_epoch.unwrap_or(Int32(-1)).write(writer)?;
+        }
+        if v >= 5 {
+            self.log_start_offset.unwrap_or(Int64(-1)).write(writer)?;
+        }
         self.partition_max_bytes.write(writer)?;
+        if v >= 12 {
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        }
 
         Ok(())
     }
@@ -55,6 +99,11 @@ pub struct FetchRequestTopic {
 
     /// The partitions to fetch.
     pub partitions: Vec<FetchRequestPartition>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for FetchRequestTopic
@@ -67,10 +116,55 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 12);
+
+        if v >= 12 {
+            CompactStringRef(&self.topic.0).write(writer)?;
+            write_compact_versioned_array(writer, version, Some(&self.partitions))?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            self.topic.write(writer)?;
+            write_versioned_array(writer, version, Some(&self.partitions))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchRequestForgottenTopic {
+    /// The topic name.
+    pub topic: String_,
 
-        self.topic.write(writer)?;
-        write_versioned_array(writer, version, Some(&self.partitions))?;
+    /// The partitions indexes to forget.
+    pub partitions: Vec<Int32>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl<W> WriteVersionedType<W> for FetchRequestForgottenTopic
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!((7..=12).contains(&v));
+
+        if v >= 12 {
+            CompactStringRef(&self.topic.0).write(writer)?;
+            CompactArrayRef(Some(&self.partitions)).write(writer)?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            self.topic.write(writer)?;
+            ArrayRef(Some(&self.partitions)).write(writer)?;
+        }
 
         Ok(())
     }
@@ -109,8 +203,37 @@ pub struct FetchRequest {
     /// [KIP-98]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-98+-+Exactly+Once+Delivery+and+Transactional+Messaging
     pub isolation_level: Option<IsolationLevel>,
 
+    /// The fetch session ID.
+    ///
+    /// Defaults to 0, i.e. no fetch session.
+    ///
+    /// Added in version 7.
+    pub session_id: Option<Int32>,
+
+    /// The fetch session epoch, which is used for ordering requests in a session.
+    ///
+    /// Defaults to -1, i.e. a full fetch request without a fetch session.
+    ///
+    /// Added in version 7.
+    pub session_epoch: Option<Int32>,
+
     /// The topics to fetch.
     pub topics: Vec<FetchRequestTopic>,
+
+    /// In an incremental fetch request, the partitions to remove.
+    ///
+    /// Added in version 7.
+    pub forgotten_topics_data: Vec<FetchRequestForgottenTopic>,
+
+    /// Rack ID of the consumer making this request.
+    ///
+    /// Added in version 11.
+    pub rack_id: Option<String_>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for FetchRequest
@@ -123,7 +246,14 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 12);
+
+        if v < 7 && !self.forgotten_topics_data.is_empty() {
+            return Err(WriteVersionedError::FieldNotAvailable {
+                version,
+                field: "forgotten_topics_data".to_string(),
+            });
+        }
 
         self.replica_id.write(writer)?;
         self.max_wait_ms.write(writer)?;
@@ -140,7 +270,37 @@ where
             level.write(writer)?;
         }
 
-        write_versioned_array(writer, version, Some(&self.topics))?;
+        if v >= 7 {
+            self.session_id.unwrap_or(Int32(0)).write(writer)?;
+            self.session_epoch.unwrap_or(Int32(-1)).write(writer)?;
+        }
+
+        if v >= 12 {
+            write_compact_versioned_array(writer, version, Some(&self.topics))?;
+            write_compact_versioned_array(writer, version, Some(&self.forgotten_topics_data))?;
+        } else {
+            write_versioned_array(writer, version, Some(&self.topics))?;
+            if v >= 7 {
+                write_versioned_array(writer, version, Some(&self.forgotten_topics_data))?;
+            }
+        }
+
+        if v >= 12 {
+            let rack_id = self
+                .rack_id
+                .as_ref()
+                .map(|r| r.0.as_str())
+                .unwrap_or_default();
+            CompactStringRef(rack_id).write(writer)?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else if v >= 11 {
+            let rack_id = self
+                .rack_id
+                .as_ref()
+                .map(|r| r.0.as_str())
+                .unwrap_or_default();
+            String_(rack_id.to_owned()).write(writer)?;
+        }
 
         Ok(())
     }
@@ -151,16 +311,30 @@ impl RequestBody for FetchRequest {
 
     const API_KEY: ApiKey = ApiKey::Fetch;
 
-    /// That's enough for now.
-    ///
-    /// Note that we do not support fetch request prior to version 4, since this is the version when message version 2
-    /// was introduced ([KIP-98]).
+    /// Version 13 replaces the topic names by topic IDs, which we do not support.
     ///
-    /// [KIP-98]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-98+-+Exactly+Once+Delivery+and+Transactional+Messaging
+    /// Versions prior to 4 return legacy message sets (message versions 0 and 1), which are converted into record
+    /// batches when reading. These versions ignore the [isolation level](Self::isolation_level).
     const API_VERSION_RANGE: ApiVersionRange =
-        ApiVersionRange::new(ApiVersion(Int16(4)), ApiVersion(Int16(4)));
+        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(12)));
 
     const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(12));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Some(Int32(throttle_time_ms));
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: ApiError) {
+        for partition in response
+            .responses
+            .iter_mut()
+            .flat_map(|t| &mut t.partitions)
+        {
+            partition.error_code = Some(error);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -171,6 +345,11 @@ pub struct FetchResponseAbortedTransaction {
 
     /// The first offset in the aborted transaction.
     pub first_offset: Int64,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for FetchResponseAbortedTransaction
@@ -179,11 +358,12 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(4 <= v && v <= 4);
+        assert!((4..=12).contains(&v));
 
         Ok(Self {
             producer_id: Int64::read(reader)?,
             first_offset: Int64::read(reader)?,
+            tagged_fields: (v >= 12).then(|| TaggedFields::read(reader)).transpose()?,
         })
     }
 }
@@ -207,13 +387,30 @@ pub struct FetchResponsePartition {
     /// Added in version 4.
     pub last_stable_offset: Option<Int64>,
 
+    /// The current log start offset.
+    ///
+    /// Added in version 5.
+    pub log_start_offset: Option<Int64>,
+
     /// The aborted transactions.
     ///
     /// Added in version 4.
     pub aborted_transactions: Vec<FetchResponseAbortedTransaction>,
 
+    /// The preferred read replica for the consumer to use on its next fetch request.
+    ///
+    /// Added in version 11.
+    pub preferred_read_replica: Option<Int32>,
+
     /// The record data.
-    pub records: Records,
+    ///
+    /// The record batches are only decoded on demand, so they can also be passed on as they are.
+    pub records: RawRecords,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for FetchResponsePartition
@@ -222,19 +419,29 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 12);
 
         Ok(Self {
             partition_index: Int32::read(reader)?,
             error_code: ApiError::new(Int16::read(reader)?.0),
             high_watermark: Int64::read(reader)?,
             last_stable_offset: (v >= 4).then(|| Int64::read(reader)).transpose()?,
-            aborted_transactions: (v >= 4)
-                .then(|| read_versioned_array(reader, version))
-                .transpose()?
-                .flatten()
-                .unwrap_or_default(),
-            records: Records::read(reader)?,
+            log_start_offset: (v >= 5).then(|| Int64::read(reader)).transpose()?,
+            aborted_transactions: if v >= 12 {
+                read_compact_versioned_array(reader, version)?
+            } else if v >= 4 {
+                read_versioned_array(reader, version)?
+            } else {
+                None
+            }
+            .unwrap_or_default(),
+            preferred_read_replica: (v >= 11).then(|| Int32::read(reader)).transpose()?,
+            records: if v >= 12 {
+                CompactRawRecords::read(reader)?.0
+            } else {
+                RawRecords::read(reader)?
+            },
+            tagged_fields: (v >= 12).then(|| TaggedFields::read(reader)).transpose()?,
         })
     }
 }
@@ -246,6 +453,11 @@ pub struct FetchResponseTopic {
 
     /// The topic partitions.
     pub partitions: Vec<FetchResponsePartition>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for FetchResponseTopic
@@ -254,12 +466,21 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
-
-        Ok(Self {
-            topic: String_::read(reader)?,
-            partitions: read_versioned_array(reader, version)?.unwrap_or_default(),
-        })
+        assert!(v <= 12);
+
+        if v >= 12 {
+            Ok(Self {
+                topic: String_(CompactString::read(reader)?.0),
+                partitions: read_compact_versioned_array(reader, version)?.unwrap_or_default(),
+                tagged_fields: Some(TaggedFields::read(reader)?),
+            })
+        } else {
+            Ok(Self {
+                topic: String_::read(reader)?,
+                partitions: read_versioned_array(reader, version)?.unwrap_or_default(),
+                tagged_fields: None,
+            })
+        }
     }
 }
 
@@ -270,8 +491,23 @@ pub struct FetchResponse {
     /// Added in version 1.
     pub throttle_time_ms: Option<Int32>,
 
+    /// The top level response error code.
+    ///
+    /// Added in version 7.
+    pub error_code: Option<ApiError>,
+
+    /// The fetch session ID, or 0 if this is not part of a fetch session.
+    ///
+    /// Added in version 7.
+    pub session_id: Option<Int32>,
+
     /// The response topics.
     pub responses: Vec<FetchResponseTopic>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 12.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for FetchResponse
@@ -280,11 +516,150 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 12);
+
+        let throttle_time_ms = (v >= 1).then(|| Int32::read(reader)).transpose()?;
+        let error_code = if v >= 7 {
+            ApiError::new(Int16::read(reader)?.0)
+        } else {
+            None
+        };
+        let session_id = (v >= 7).then(|| Int32::read(reader)).transpose()?;
+        let responses = if v >= 12 {
+            read_compact_versioned_array(reader, version)?
+        } else {
+            read_versioned_array(reader, version)?
+        }
+        .unwrap_or_default();
+        let tagged_fields = (v >= 12).then(|| TaggedFields::read(reader)).transpose()?;
 
         Ok(Self {
-            throttle_time_ms: (v >= 1).then(|| Int32::read(reader)).transpose()?,
-            responses: read_versioned_array(reader, version)?.unwrap_or_default(),
+            throttle_time_ms,
+            error_code,
+            session_id,
+            responses,
+            tagged_fields,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::protocol::primitives::UnsignedVarint;
+
+    use super::*;
+
+    fn request() -> FetchRequest {
+        FetchRequest {
+            replica_id: Int32(-1),
+            max_wait_ms: Int32(500),
+            min_bytes: Int32(1),
+            max_bytes: None,
+            isolation_level: None,
+            session_id: None,
+            session_epoch: None,
+            topics: vec![FetchRequestTopic {
+                topic: String_("foo".to_owned()),
+                partitions: vec![FetchRequestPartition {
+                    partition: Int32(1),
+                    current_leader_epoch: None,
+                    fetch_offset: Int64(10),
+                    last_fetched_epoch: None,
+                    log_start_offset: None,
+                    partition_max_bytes: Int32(100),
+                    tagged_fields: None,
+                }],
+                tagged_fields: None,
+            }],
+            forgotten_topics_data: vec![],
+            rack_id: None,
+            tagged_fields: None,
+        }
+    }
+
+    #[test]
+    fn test_request_flexible() {
+        let mut expected = vec![];
+        Int32(-1).write(&mut expected).unwrap();
+        Int32(500).write(&mut expected).unwrap();
+        Int32(1).write(&mut expected).unwrap();
+        Int32(i32::MAX).write(&mut expected).unwrap();
+        Int8(0).write(&mut expected).unwrap();
+        Int32(0).write(&mut expected).unwrap(); // session_id
+        Int32(-1).write(&mut expected).unwrap(); // session_epoch
+        UnsignedVarint(2).write(&mut expected).unwrap(); // topics
+        CompactStringRef("foo").write(&mut expected).unwrap();
+        UnsignedVarint(2).write(&mut expected).unwrap(); // partitions
+        Int32(1).write(&mut expected).unwrap();
+        Int32(-1).write(&mut expected).unwrap(); // current_leader_epoch
+        Int64(10).write(&mut expected).unwrap();
+        Int32(-1).write(&mut expected).unwrap(); // last_fetched_epoch
+        Int64(-1).write(&mut expected).unwrap(); // log_start_offset
+        Int32(100).write(&mut expected).unwrap();
+        expected.extend([0, 0]); // partition and topic tagged fields
+        UnsignedVarint(1).write(&mut expected).unwrap(); // forgotten_topics_data
+        CompactStringRef("").write(&mut expected).unwrap(); // rack_id
+        expected.push(0); // tagged fields
+
+        let mut actual = vec![];
+        request()
+            .write_versioned(&mut actual, ApiVersion(Int16(12)))
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_request_forgotten_topics_not_available() {
+        let request = FetchRequest {
+            forgotten_topics_data: vec![FetchRequestForgottenTopic {
+                topic: String_("foo".to_owned()),
+                partitions: vec![Int32(0)],
+                tagged_fields: None,
+            }],
+            ..request()
+        };
+
+        let err = request
+            .write_versioned(&mut vec![], ApiVersion(Int16(6)))
+            .unwrap_err();
+        assert!(matches!(err, WriteVersionedError::FieldNotAvailable { .. }));
+        request
+            .write_versioned(&mut vec![], ApiVersion(Int16(7)))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_response_flexible() {
+        let mut buf = vec![];
+        Int32(0).write(&mut buf).unwrap(); // throttle_time_ms
+        Int16(0).write(&mut buf).unwrap(); // error_code
+        Int32(0).write(&mut buf).unwrap(); // session_id
+        UnsignedVarint(2).write(&mut buf).unwrap(); // responses
+        CompactStringRef("foo").write(&mut buf).unwrap();
+        UnsignedVarint(2).write(&mut buf).unwrap(); // partitions
+        Int32(1).write(&mut buf).unwrap();
+        Int16(0).write(&mut buf).unwrap();
+        Int64(12).write(&mut buf).unwrap(); // high_watermark
+        Int64(12).write(&mut buf).unwrap(); // last_stable_offset
+        Int64(3).write(&mut buf).unwrap(); // log_start_offset
+        UnsignedVarint(0).write(&mut buf).unwrap(); // aborted_transactions
+        Int32(-1).write(&mut buf).unwrap(); // preferred_read_replica
+        UnsignedVarint(0).write(&mut buf).unwrap(); // records
+        buf.extend([0, 0, 0]); // tagged fields
+
+        let mut reader = Cursor::new(buf);
+        let response = FetchResponse::read_versioned(&mut reader, ApiVersion(Int16(12))).unwrap();
+        assert_eq!(reader.position(), reader.get_ref().len() as u64);
+
+        assert_eq!(response.error_code, None);
+        assert_eq!(response.session_id, Some(Int32(0)));
+        let partition = &response.responses[0].partitions[0];
+        assert_eq!(partition.high_watermark, Int64(12));
+        assert_eq!(partition.log_start_offset, Some(Int64(3)));
+        assert_eq!(partition.preferred_read_replica, Some(Int32(-1)));
+        assert!(partition.aborted_transactions.is_empty());
+        assert!(partition.records.0.is_empty());
+    }
+}
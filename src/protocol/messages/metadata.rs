@@ -4,7 +4,10 @@ use super::{
     ReadVersionedError, ReadVersionedType, RequestBody, WriteVersionedError, WriteVersionedType,
 };
 use crate::protocol::api_version::ApiVersionRange;
-use crate::protocol::messages::{read_versioned_array, write_versioned_array};
+use crate::protocol::messages::{
+    read_compact_versioned_array, read_versioned_array, write_compact_versioned_array,
+    write_tagged_fields, write_versioned_array,
+};
 use crate::protocol::{
     api_key::ApiKey,
     api_version::ApiVersion,
@@ -25,6 +28,21 @@ pub struct MetadataRequest {
     ///
     /// Added in version 4
     pub allow_auto_topic_creation: Option<Boolean>,
+
+    /// Whether to include cluster authorized operations.
+    ///
+    /// Added in version 8
+    pub include_cluster_authorized_operations: Option<Boolean>,
+
+    /// Whether to include topic authorized operations.
+    ///
+    /// Added in version 8
+    pub include_topic_authorized_operations: Option<Boolean>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl RequestBody for MetadataRequest {
@@ -32,11 +50,23 @@ impl RequestBody for MetadataRequest {
 
     const API_KEY: ApiKey = ApiKey::Metadata;
 
-    /// At the time of writing this is the same subset supported by rdkafka
+    /// Version 10 replaces the topic names by topic IDs, which we do not support.
     const API_VERSION_RANGE: ApiVersionRange =
-        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(4)));
+        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(9)));
 
     const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(9));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Some(Int32(throttle_time_ms));
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: Error) {
+        for topic in &mut response.topics {
+            topic.error = Some(error);
+        }
+    }
 }
 
 impl<W> WriteVersionedType<W> for MetadataRequest
@@ -49,7 +79,7 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 9);
 
         if v < 4 && self.allow_auto_topic_creation.is_some() {
             return Err(WriteVersionedError::FieldNotAvailable {
@@ -57,8 +87,24 @@ where
                 field: "allow_auto_topic_creation".to_string(),
             });
         }
+        if v < 8 && self.include_cluster_authorized_operations.is_some() {
+            return Err(WriteVersionedError::FieldNotAvailable {
+                version,
+                field: "include_cluster_authorized_operations".to_string(),
+            });
+        }
+        if v < 8 && self.include_topic_authorized_operations.is_some() {
+            return Err(WriteVersionedError::FieldNotAvailable {
+                version,
+                field: "include_topic_authorized_operations".to_string(),
+            });
+        }
 
-        write_versioned_array(writer, version, self.topics.as_deref())?;
+        if v >= 9 {
+            write_compact_versioned_array(writer, version, self.topics.as_deref())?;
+        } else {
+            write_versioned_array(writer, version, self.topics.as_deref())?;
+        }
         if v >= 4 {
             match self.allow_auto_topic_creation {
                 // The default behaviour is to allow topic creation
@@ -66,6 +112,17 @@ where
                 Some(b) => b.write(writer)?,
             }
         }
+        if v >= 8 {
+            self.include_cluster_authorized_operations
+                .unwrap_or(Boolean(false))
+                .write(writer)?;
+            self.include_topic_authorized_operations
+                .unwrap_or(Boolean(false))
+                .write(writer)?;
+        }
+        if v >= 9 {
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        }
         Ok(())
     }
 }
@@ -74,6 +131,11 @@ where
 pub struct MetadataRequestTopic {
     /// The topic name
     pub name: String_,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for MetadataRequestTopic
@@ -85,8 +147,16 @@ where
         writer: &mut W,
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
-        assert!(version.0 .0 <= 4);
-        Ok(self.name.write(writer)?)
+        let v = version.0 .0;
+        assert!(v <= 9);
+
+        if v >= 9 {
+            CompactStringRef(&self.name.0).write(writer)?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            self.name.write(writer)?;
+        }
+        Ok(())
     }
 }
 
@@ -113,6 +183,16 @@ pub struct MetadataResponse {
 
     /// Each topic in the response
     pub topics: Vec<MetadataResponseTopic>,
+
+    /// 32-bit bitfield to represent authorized operations for this cluster.
+    ///
+    /// Added in version 8
+    pub cluster_authorized_operations: Option<Int32>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for MetadataResponse
@@ -121,13 +201,29 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 9);
 
         let throttle_time_ms = (v >= 3).then(|| Int32::read(reader)).transpose()?;
-        let brokers = read_versioned_array(reader, version)?.unwrap_or_default();
-        let cluster_id = (v >= 2).then(|| NullableString::read(reader)).transpose()?;
+        let brokers = if v >= 9 {
+            read_compact_versioned_array(reader, version)?
+        } else {
+            read_versioned_array(reader, version)?
+        }
+        .unwrap_or_default();
+        let cluster_id = if v >= 9 {
+            Some(NullableString(CompactNullableString::read(reader)?.0))
+        } else {
+            (v >= 2).then(|| NullableString::read(reader)).transpose()?
+        };
         let controller_id = (v >= 1).then(|| Int32::read(reader)).transpose()?;
-        let topics = read_versioned_array(reader, version)?.unwrap_or_default();
+        let topics = if v >= 9 {
+            read_compact_versioned_array(reader, version)?
+        } else {
+            read_versioned_array(reader, version)?
+        }
+        .unwrap_or_default();
+        let cluster_authorized_operations = (v >= 8).then(|| Int32::read(reader)).transpose()?;
+        let tagged_fields = (v >= 9).then(|| TaggedFields::read(reader)).transpose()?;
 
         Ok(Self {
             throttle_time_ms,
@@ -135,6 +231,8 @@ where
             topics,
             cluster_id,
             controller_id,
+            cluster_authorized_operations,
+            tagged_fields,
         })
     }
 }
@@ -149,6 +247,8 @@ pub struct MetadataResponseBroker {
     pub port: Int32,
     /// Added in version 1
     pub rack: Option<NullableString>,
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for MetadataResponseBroker
@@ -157,18 +257,28 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 9);
 
         let node_id = Int32::read(reader)?;
-        let host = String_::read(reader)?;
+        let host = if v >= 9 {
+            String_(CompactString::read(reader)?.0)
+        } else {
+            String_::read(reader)?
+        };
         let port = Int32::read(reader)?;
-        let rack = (v >= 1).then(|| NullableString::read(reader)).transpose()?;
+        let rack = if v >= 9 {
+            Some(NullableString(CompactNullableString::read(reader)?.0))
+        } else {
+            (v >= 1).then(|| NullableString::read(reader)).transpose()?
+        };
+        let tagged_fields = (v >= 9).then(|| TaggedFields::read(reader)).transpose()?;
 
         Ok(Self {
             node_id,
             host,
             port,
             rack,
+            tagged_fields,
         })
     }
 }
@@ -183,6 +293,12 @@ pub struct MetadataResponseTopic {
     pub is_internal: Option<Boolean>,
     /// Each partition in the topic
     pub partitions: Vec<MetadataResponsePartition>,
+    /// 32-bit bitfield to represent authorized operations for this topic.
+    ///
+    /// Added in version 8
+    pub topic_authorized_operations: Option<Int32>,
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for MetadataResponseTopic
@@ -191,18 +307,31 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 9);
 
         let error = Error::new(Int16::read(reader)?.0);
-        let name = String_::read(reader)?;
+        let name = if v >= 9 {
+            String_(CompactString::read(reader)?.0)
+        } else {
+            String_::read(reader)?
+        };
         let is_internal = (v >= 1).then(|| Boolean::read(reader)).transpose()?;
-        let partitions = read_versioned_array(reader, version)?.unwrap_or_default();
+        let partitions = if v >= 9 {
+            read_compact_versioned_array(reader, version)?
+        } else {
+            read_versioned_array(reader, version)?
+        }
+        .unwrap_or_default();
+        let topic_authorized_operations = (v >= 8).then(|| Int32::read(reader)).transpose()?;
+        let tagged_fields = (v >= 9).then(|| TaggedFields::read(reader)).transpose()?;
 
         Ok(Self {
             error,
             name,
             is_internal,
             partitions,
+            topic_authorized_operations,
+            tagged_fields,
         })
     }
 }
@@ -215,10 +344,20 @@ pub struct MetadataResponsePartition {
     pub partition_index: Int32,
     /// The ID of the leader broker
     pub leader_id: Int32,
+    /// The leader epoch of this partition.
+    ///
+    /// Added in version 7
+    pub leader_epoch: Option<Int32>,
     /// The set of all nodes that host this partition
     pub replica_nodes: Array<Int32>,
     /// The set of all nodes that are in sync with the leader for this partition
     pub isr_nodes: Array<Int32>,
+    /// The set of offline replicas of this partition.
+    ///
+    /// Added in version 5
+    pub offline_replicas: Option<Array<Int32>>,
+    /// Added in version 9
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for MetadataResponsePartition
@@ -227,14 +366,123 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 4);
+        assert!(v <= 9);
+
+        let read_nodes = |reader: &mut R| -> Result<Array<Int32>, ReadVersionedError> {
+            if v >= 9 {
+                Ok(Array(CompactArray::read(reader)?.0))
+            } else {
+                Ok(Array::read(reader)?)
+            }
+        };
 
         Ok(Self {
             error: Error::new(Int16::read(reader)?.0),
             partition_index: Int32::read(reader)?,
             leader_id: Int32::read(reader)?,
-            replica_nodes: Array::read(reader)?,
-            isr_nodes: Array::read(reader)?,
+            leader_epoch: (v >= 7).then(|| Int32::read(reader)).transpose()?,
+            replica_nodes: read_nodes(reader)?,
+            isr_nodes: read_nodes(reader)?,
+            offline_replicas: (v >= 5).then(|| read_nodes(reader)).transpose()?,
+            tagged_fields: (v >= 9).then(|| TaggedFields::read(reader)).transpose()?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_request_flexible() {
+        let request = MetadataRequest {
+            topics: Some(vec![MetadataRequestTopic {
+                name: String_("foo".to_owned()),
+                tagged_fields: None,
+            }]),
+            allow_auto_topic_creation: None,
+            include_cluster_authorized_operations: None,
+            include_topic_authorized_operations: None,
+            tagged_fields: None,
+        };
+
+        let mut buf = vec![];
+        request
+            .write_versioned(&mut buf, ApiVersion(Int16(9)))
+            .unwrap();
+        assert_eq!(
+            buf,
+            [
+                2, // topics
+                4, b'f', b'o', b'o', // name
+                0,    // topic tagged fields
+                1,    // allow_auto_topic_creation
+                0,    // include_cluster_authorized_operations
+                0,    // include_topic_authorized_operations
+                0,    // tagged fields
+            ]
+        );
+
+        let request = MetadataRequest {
+            include_topic_authorized_operations: Some(Boolean(true)),
+            ..request
+        };
+        assert!(matches!(
+            request.write_versioned(&mut vec![], ApiVersion(Int16(7))),
+            Err(WriteVersionedError::FieldNotAvailable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_response_flexible() {
+        let mut buf = vec![];
+        Int32(0).write(&mut buf).unwrap(); // throttle_time_ms
+        UnsignedVarint(2).write(&mut buf).unwrap(); // brokers
+        Int32(1).write(&mut buf).unwrap();
+        CompactStringRef("host").write(&mut buf).unwrap();
+        Int32(9092).write(&mut buf).unwrap();
+        CompactNullableStringRef(None).write(&mut buf).unwrap();
+        TaggedFields::default().write(&mut buf).unwrap();
+        CompactNullableStringRef(Some("cluster"))
+            .write(&mut buf)
+            .unwrap();
+        Int32(1).write(&mut buf).unwrap(); // controller_id
+        UnsignedVarint(2).write(&mut buf).unwrap(); // topics
+        Int16(0).write(&mut buf).unwrap();
+        CompactStringRef("foo").write(&mut buf).unwrap();
+        Boolean(false).write(&mut buf).unwrap();
+        UnsignedVarint(2).write(&mut buf).unwrap(); // partitions
+        Int16(0).write(&mut buf).unwrap();
+        Int32(0).write(&mut buf).unwrap(); // partition_index
+        Int32(1).write(&mut buf).unwrap(); // leader_id
+        Int32(3).write(&mut buf).unwrap(); // leader_epoch
+        CompactArray(Some(vec![Int32(1)])).write(&mut buf).unwrap();
+        CompactArray(Some(vec![Int32(1)])).write(&mut buf).unwrap();
+        CompactArray::<Int32>(Some(vec![])).write(&mut buf).unwrap();
+        TaggedFields::default().write(&mut buf).unwrap();
+        Int32(i32::MIN).write(&mut buf).unwrap(); // topic_authorized_operations
+        TaggedFields::default().write(&mut buf).unwrap();
+        Int32(i32::MIN).write(&mut buf).unwrap(); // cluster_authorized_operations
+        TaggedFields::default().write(&mut buf).unwrap();
+
+        let mut reader = Cursor::new(buf);
+        let response = MetadataResponse::read_versioned(&mut reader, ApiVersion(Int16(9))).unwrap();
+        assert_eq!(reader.position(), reader.get_ref().len() as u64);
+
+        assert_eq!(response.brokers[0].host.0, "host");
+        assert_eq!(response.brokers[0].rack, Some(NullableString(None)));
+        assert_eq!(
+            response.cluster_id,
+            Some(NullableString(Some("cluster".to_owned())))
+        );
+        let topic = &response.topics[0];
+        assert_eq!(topic.name.0, "foo");
+        let partition = &topic.partitions[0];
+        assert_eq!(partition.leader_id, Int32(1));
+        assert_eq!(partition.leader_epoch, Some(Int32(3)));
+        assert_eq!(partition.replica_nodes, Array(Some(vec![Int32(1)])));
+        assert_eq!(partition.offline_replicas, Some(Array(Some(vec![]))));
+    }
+}
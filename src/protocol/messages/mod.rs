@@ -11,7 +11,7 @@ use thiserror::Error;
 use super::{
     api_key::ApiKey,
     api_version::{ApiVersion, ApiVersionRange},
-    primitives::{Int32, UnsignedVarint},
+    primitives::{Int32, TaggedFields, UnsignedVarint},
     traits::{ReadError, ReadType, WriteError, WriteType},
     vec_builder::VecBuilder,
 };
@@ -26,6 +26,8 @@ mod delete_records;
 pub use delete_records::*;
 mod delete_topics;
 pub use delete_topics::*;
+mod describe_configs;
+pub use describe_configs::*;
 mod fetch;
 pub use fetch::*;
 mod header;
@@ -38,6 +40,8 @@ mod produce;
 pub use produce::*;
 mod sasl_msg;
 pub use sasl_msg::*;
+mod telemetry;
+pub use telemetry::*;
 #[cfg(test)]
 mod test_utils;
 
@@ -114,6 +118,15 @@ pub trait RequestBody {
     /// there are some special snowflakes.
     const FIRST_TAGGED_FIELD_IN_RESPONSE_VERSION: ApiVersion =
         Self::FIRST_TAGGED_FIELD_IN_REQUEST_VERSION;
+
+    /// Let `response` report that the broker throttled the request, see [`Fault`](crate::chaos::Fault).
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(_response: &mut Self::ResponseBody, _throttle_time_ms: i32) {}
+
+    /// Let `response` report `error` for the request or all its topics or partitions, see
+    /// [`Fault`](crate::chaos::Fault).
+    #[cfg(feature = "chaos")]
+    fn inject_error(_response: &mut Self::ResponseBody, _error: crate::protocol::error::Error) {}
 }
 
 impl<T: RequestBody> RequestBody for &T {
@@ -124,6 +137,16 @@ impl<T: RequestBody> RequestBody for &T {
         T::FIRST_TAGGED_FIELD_IN_REQUEST_VERSION;
     const FIRST_TAGGED_FIELD_IN_RESPONSE_VERSION: ApiVersion =
         T::FIRST_TAGGED_FIELD_IN_RESPONSE_VERSION;
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        T::inject_throttle(response, throttle_time_ms)
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: crate::protocol::error::Error) {
+        T::inject_error(response, error)
+    }
 }
 
 /// Read an array of versioned objects.
@@ -229,6 +252,18 @@ fn write_compact_versioned_array<W: Write, T: WriteVersionedType<W>>(
     }
 }
 
+/// Write the tagged fields of a flexible message version, or an empty set of tagged fields if there are none.
+fn write_tagged_fields<W: Write>(
+    writer: &mut W,
+    tagged_fields: Option<&TaggedFields>,
+) -> Result<(), WriteVersionedError> {
+    match tagged_fields {
+        Some(tagged_fields) => tagged_fields.write(writer)?,
+        None => TaggedFields::default().write(writer)?,
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
@@ -1,12 +1,22 @@
 use std::io::{Read, Write};
 
+use bytes::Bytes;
+
 use crate::protocol::{
     api_key::ApiKey,
     api_version::{ApiVersion, ApiVersionRange},
+    buffer_pool,
     error::Error,
-    messages::{read_versioned_array, write_versioned_array},
-    primitives::{Int16, Int32, Int64, NullableString, Records, String_},
-    traits::{ReadType, WriteType},
+    messages::{
+        read_compact_versioned_array, read_versioned_array, write_compact_versioned_array,
+        write_tagged_fields, write_versioned_array,
+    },
+    primitives::{
+        CompactBytesRef, CompactNullableString, CompactNullableStringRef, CompactString,
+        CompactStringRef, Int16, Int32, Int64, NullableString, Records, String_, TaggedFields,
+    },
+    record::CompressionSettings,
+    traits::{ReadType, WriteError, WriteType},
 };
 
 use super::{
@@ -19,7 +29,79 @@ pub struct ProduceRequestPartitionData {
     pub index: Int32,
 
     /// The record data to be produced.
-    pub records: Records,
+    pub records: ProduceRecords,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+/// Record data of a [`ProduceRequestPartitionData`].
+#[derive(Debug)]
+pub enum ProduceRecords {
+    /// Record batches that are encoded (and compressed with the given settings) when the request is written.
+    Batches(Records, CompressionSettings),
+
+    /// Already encoded record batches, without the length prefix.
+    Encoded(Bytes),
+}
+
+impl ProduceRecords {
+    /// Encode the record batches so that writing the request later on is a plain copy.
+    ///
+    /// This is the expensive part of writing a produce request, esp. when compression is involved.
+    pub fn encode(self) -> Result<Self, WriteError> {
+        match self {
+            Self::Batches(..) => Ok(Self::Encoded(self.encoded()?)),
+            Self::Encoded(_) => Ok(self),
+        }
+    }
+
+    /// Get the encoded record batches, without the length prefix.
+    pub fn encoded(&self) -> Result<Bytes, WriteError> {
+        match self {
+            Self::Batches(records, settings) => {
+                let mut buf = vec![];
+                for batch in &records.0 {
+                    batch.write_with_settings(&mut buf, *settings)?;
+                }
+                Ok(buf.into())
+            }
+            Self::Encoded(buf) => Ok(buf.clone()),
+        }
+    }
+
+    /// Write the record batches as COMPACT_BYTES, as used by flexible message versions.
+    fn write_compact<W: Write>(&self, writer: &mut W) -> Result<(), WriteError> {
+        CompactBytesRef(&self.encoded()?).write(writer)
+    }
+}
+
+impl<W> WriteType<W> for ProduceRecords
+where
+    W: Write,
+{
+    fn write(&self, writer: &mut W) -> Result<(), WriteError> {
+        match self {
+            Self::Batches(records, settings) => {
+                let mut buf = buffer_pool::take();
+                for batch in &records.0 {
+                    batch.write_with_settings(&mut *buf, *settings)?;
+                }
+                write_nullable_bytes_ref(writer, &buf)
+            }
+            Self::Encoded(buf) => write_nullable_bytes_ref(writer, buf),
+        }
+    }
+}
+
+/// Same as `NullableBytes` but w/o taking ownership of the buffer.
+fn write_nullable_bytes_ref<W: Write>(writer: &mut W, buf: &[u8]) -> Result<(), WriteError> {
+    let l = i32::try_from(buf.len()).map_err(|e| WriteError::Malformed(Box::new(e)))?;
+    Int32(l).write(writer)?;
+    writer.write_all(buf)?;
+    Ok(())
 }
 
 impl<W> WriteVersionedType<W> for ProduceRequestPartitionData
@@ -32,10 +114,15 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
+        assert!(v <= 9);
 
         self.index.write(writer)?;
-        self.records.write(writer)?;
+        if v >= 9 {
+            self.records.write_compact(writer)?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            self.records.write(writer)?;
+        }
         Ok(())
     }
 }
@@ -47,6 +134,11 @@ pub struct ProduceRequestTopicData {
 
     /// Each partition to produce to.
     pub partition_data: Vec<ProduceRequestPartitionData>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for ProduceRequestTopicData
@@ -59,10 +151,16 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
-
-        self.name.write(writer)?;
-        write_versioned_array(writer, version, Some(&self.partition_data))?;
+        assert!(v <= 9);
+
+        if v >= 9 {
+            CompactStringRef(&self.name.0).write(writer)?;
+            write_compact_versioned_array(writer, version, Some(&self.partition_data))?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            self.name.write(writer)?;
+            write_versioned_array(writer, version, Some(&self.partition_data))?;
+        }
 
         Ok(())
     }
@@ -85,6 +183,11 @@ pub struct ProduceRequest {
 
     /// Each topic to produce to.
     pub topic_data: Vec<ProduceRequestTopicData>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<W> WriteVersionedType<W> for ProduceRequest
@@ -97,14 +200,21 @@ where
         version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
+        assert!(v <= 9);
 
-        if v >= 3 {
+        if v >= 9 {
+            CompactNullableStringRef(self.transactional_id.0.as_deref()).write(writer)?;
+        } else if v >= 3 {
             self.transactional_id.write(writer)?;
         }
         self.acks.write(writer)?;
         self.timeout_ms.write(writer)?;
-        write_versioned_array(writer, version, Some(&self.topic_data))?;
+        if v >= 9 {
+            write_compact_versioned_array(writer, version, Some(&self.topic_data))?;
+            write_tagged_fields(writer, self.tagged_fields.as_ref())?;
+        } else {
+            write_versioned_array(writer, version, Some(&self.topic_data))?;
+        }
 
         Ok(())
     }
@@ -122,9 +232,25 @@ impl RequestBody for ProduceRequest {
     ///
     /// [KIP-98]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-98+-+Exactly+Once+Delivery+and+Transactional+Messaging
     const API_VERSION_RANGE: ApiVersionRange =
-        ApiVersionRange::new(ApiVersion(Int16(3)), ApiVersion(Int16(7)));
+        ApiVersionRange::new(ApiVersion(Int16(3)), ApiVersion(Int16(9)));
 
     const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(9));
+
+    #[cfg(feature = "chaos")]
+    fn inject_throttle(response: &mut Self::ResponseBody, throttle_time_ms: i32) {
+        response.throttle_time_ms = Some(Int32(throttle_time_ms));
+    }
+
+    #[cfg(feature = "chaos")]
+    fn inject_error(response: &mut Self::ResponseBody, error: Error) {
+        for partition in response
+            .responses
+            .iter_mut()
+            .flat_map(|r| &mut r.partition_responses)
+        {
+            partition.error = Some(error);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -151,6 +277,21 @@ pub struct ProduceResponsePartitionResponse {
     ///
     /// Added in version 5.
     pub log_start_offset: Option<Int64>,
+
+    /// The batch indices of records that caused the batch to be dropped.
+    ///
+    /// Added in version 8.
+    pub record_errors: Vec<ProduceResponseBatchIndexAndErrorMessage>,
+
+    /// The global error message summarizing the common root cause of the records that caused the batch to be dropped.
+    ///
+    /// Added in version 8.
+    pub error_message: Option<NullableString>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for ProduceResponsePartitionResponse
@@ -159,7 +300,7 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
+        assert!(v <= 9);
 
         Ok(Self {
             index: Int32::read(reader)?,
@@ -167,6 +308,57 @@ where
             base_offset: Int64::read(reader)?,
             log_append_time_ms: (v >= 2).then(|| Int64::read(reader)).transpose()?,
             log_start_offset: (v >= 5).then(|| Int64::read(reader)).transpose()?,
+            record_errors: if v >= 9 {
+                read_compact_versioned_array(reader, version)?.unwrap_or_default()
+            } else if v >= 8 {
+                read_versioned_array(reader, version)?.unwrap_or_default()
+            } else {
+                vec![]
+            },
+            error_message: if v >= 9 {
+                Some(NullableString(CompactNullableString::read(reader)?.0))
+            } else {
+                (v >= 8).then(|| NullableString::read(reader)).transpose()?
+            },
+            tagged_fields: (v >= 9).then(|| TaggedFields::read(reader)).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ProduceResponseBatchIndexAndErrorMessage {
+    /// The batch index of the record that caused the batch to be dropped.
+    pub batch_index: Int32,
+
+    /// The error message of the record that caused the batch to be dropped.
+    pub batch_index_error_message: NullableString,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl<R> ReadVersionedType<R> for ProduceResponseBatchIndexAndErrorMessage
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!((8..=9).contains(&v));
+
+        let batch_index = Int32::read(reader)?;
+        let batch_index_error_message = if v >= 9 {
+            NullableString(CompactNullableString::read(reader)?.0)
+        } else {
+            NullableString::read(reader)?
+        };
+        let tagged_fields = (v >= 9).then(|| TaggedFields::read(reader)).transpose()?;
+
+        Ok(Self {
+            batch_index,
+            batch_index_error_message,
+            tagged_fields,
         })
     }
 }
@@ -178,6 +370,11 @@ pub struct ProduceResponseResponse {
 
     /// Each partition that we produced to within the topic.
     pub partition_responses: Vec<ProduceResponsePartitionResponse>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for ProduceResponseResponse
@@ -186,12 +383,22 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
-
-        Ok(Self {
-            name: String_::read(reader)?,
-            partition_responses: read_versioned_array(reader, version)?.unwrap_or_default(),
-        })
+        assert!(v <= 9);
+
+        if v >= 9 {
+            Ok(Self {
+                name: String_(CompactString::read(reader)?.0),
+                partition_responses: read_compact_versioned_array(reader, version)?
+                    .unwrap_or_default(),
+                tagged_fields: Some(TaggedFields::read(reader)?),
+            })
+        } else {
+            Ok(Self {
+                name: String_::read(reader)?,
+                partition_responses: read_versioned_array(reader, version)?.unwrap_or_default(),
+                tagged_fields: None,
+            })
+        }
     }
 }
 
@@ -204,6 +411,11 @@ pub struct ProduceResponse {
     ///
     /// Added in version 1.
     pub throttle_time_ms: Option<Int32>,
+
+    /// The tagged fields.
+    ///
+    /// Added in version 9.
+    pub tagged_fields: Option<TaggedFields>,
 }
 
 impl<R> ReadVersionedType<R> for ProduceResponse
@@ -212,11 +424,149 @@ where
 {
     fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
         let v = version.0 .0;
-        assert!(v <= 7);
+        assert!(v <= 9);
+
+        let responses = if v >= 9 {
+            read_compact_versioned_array(reader, version)?
+        } else {
+            read_versioned_array(reader, version)?
+        }
+        .unwrap_or_default();
 
         Ok(Self {
-            responses: read_versioned_array(reader, version)?.unwrap_or_default(),
+            responses,
             throttle_time_ms: (v >= 1).then(|| Int32::read(reader)).transpose()?,
+            tagged_fields: (v >= 9).then(|| TaggedFields::read(reader)).transpose()?,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::record::{
+        ControlBatchOrRecords, Record, RecordBatch, RecordBatchCompression,
+        RecordBatchTimestampType,
+    };
+
+    use super::*;
+
+    fn records() -> Records {
+        Records(vec![RecordBatch {
+            base_offset: 0,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            is_transactional: false,
+            base_sequence: -1,
+            compression: RecordBatchCompression::NoCompression,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+            producer_id: -1,
+            producer_epoch: -1,
+            first_timestamp: 42,
+            max_timestamp: 42,
+            records: ControlBatchOrRecords::Records(vec![Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: Some(b"foo".to_vec().into()),
+                value: Some(b"bar".to_vec().into()),
+                headers: vec![],
+            }]),
+        }])
+    }
+
+    #[test]
+    fn test_encoded_records() {
+        let mut expected = vec![];
+        ProduceRecords::Batches(records(), Default::default())
+            .write(&mut expected)
+            .unwrap();
+
+        let encoded = ProduceRecords::Batches(records(), Default::default())
+            .encode()
+            .unwrap();
+        assert!(matches!(encoded, ProduceRecords::Encoded(_)));
+        let mut actual = vec![];
+        encoded.write(&mut actual).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_request_flexible() {
+        let request = ProduceRequest {
+            transactional_id: NullableString(None),
+            acks: Int16(-1),
+            timeout_ms: Int32(1_000),
+            topic_data: vec![ProduceRequestTopicData {
+                name: String_("foo".to_owned()),
+                partition_data: vec![ProduceRequestPartitionData {
+                    index: Int32(1),
+                    records: ProduceRecords::Batches(records(), Default::default()),
+                    tagged_fields: None,
+                }],
+                tagged_fields: None,
+            }],
+            tagged_fields: None,
+        };
+
+        let encoded = ProduceRecords::Batches(records(), Default::default())
+            .encoded()
+            .unwrap();
+        let mut expected = vec![];
+        CompactNullableStringRef(None).write(&mut expected).unwrap();
+        Int16(-1).write(&mut expected).unwrap();
+        Int32(1_000).write(&mut expected).unwrap();
+        expected.push(2); // topic_data
+        CompactStringRef("foo").write(&mut expected).unwrap();
+        expected.push(2); // partition_data
+        Int32(1).write(&mut expected).unwrap();
+        CompactBytesRef(&encoded).write(&mut expected).unwrap();
+        expected.extend([0, 0, 0]); // tagged fields
+
+        let mut actual = vec![];
+        request
+            .write_versioned(&mut actual, ApiVersion(Int16(9)))
+            .unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_response_record_errors() {
+        let mut buf = vec![];
+        buf.push(2); // responses
+        CompactStringRef("foo").write(&mut buf).unwrap();
+        buf.push(2); // partition_responses
+        Int32(1).write(&mut buf).unwrap();
+        Int16(87).write(&mut buf).unwrap(); // INVALID_RECORD
+        Int64(-1).write(&mut buf).unwrap();
+        Int64(-1).write(&mut buf).unwrap();
+        Int64(0).write(&mut buf).unwrap();
+        buf.push(2); // record_errors
+        Int32(0).write(&mut buf).unwrap();
+        CompactNullableStringRef(Some("bad record"))
+            .write(&mut buf)
+            .unwrap();
+        buf.push(0); // record error tagged fields
+        CompactNullableStringRef(Some("batch dropped"))
+            .write(&mut buf)
+            .unwrap();
+        buf.extend([0, 0]); // partition and topic tagged fields
+        Int32(0).write(&mut buf).unwrap(); // throttle_time_ms
+        buf.push(0); // tagged fields
+
+        let mut reader = std::io::Cursor::new(buf);
+        let response = ProduceResponse::read_versioned(&mut reader, ApiVersion(Int16(9))).unwrap();
+        assert_eq!(reader.position(), reader.get_ref().len() as u64);
+
+        let partition = &response.responses[0].partition_responses[0];
+        assert_eq!(partition.error, Some(Error::InvalidRecord));
+        assert_eq!(partition.record_errors[0].batch_index, Int32(0));
+        assert_eq!(
+            partition.record_errors[0].batch_index_error_message,
+            NullableString(Some("bad record".to_owned()))
+        );
+        assert_eq!(
+            partition.error_message,
+            Some(NullableString(Some("batch dropped".to_owned())))
+        );
+    }
+}
@@ -7,8 +7,8 @@ use crate::protocol::{
     api_version::{ApiVersion, ApiVersionRange},
     error::Error as ApiError,
     primitives::{
-        Array, Bytes, CompactBytes, CompactBytesRef, CompactNullableString, Int16, Int64,
-        NullableString, String_, TaggedFields,
+        Array, Bytes, CompactBytes, CompactBytesRef, CompactNullableString,
+        CompactNullableStringRef, Int16, Int64, NullableString, String_, TaggedFields,
     },
     traits::{ReadType, WriteType},
 };
@@ -88,15 +88,21 @@ where
     }
 }
 
+// this is not technically required for production but helpful for testing
 impl<W> WriteVersionedType<W> for SaslHandshakeResponse
 where
     W: Write,
 {
     fn write_versioned(
         &self,
-        _writer: &mut W,
-        _version: ApiVersion,
+        writer: &mut W,
+        version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v == 1);
+        let error_code: Int16 = self.error_code.into();
+        error_code.write(writer)?;
+        self.mechanisms.write(writer)?;
         Ok(())
     }
 }
@@ -238,15 +244,40 @@ where
     }
 }
 
+// this is not technically required for production but helpful for testing
 impl<W> WriteVersionedType<W> for SaslAuthenticateResponse
 where
     W: Write,
 {
     fn write_versioned(
         &self,
-        _writer: &mut W,
-        _version: ApiVersion,
+        writer: &mut W,
+        version: ApiVersion,
     ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 2);
+        let error_code: Int16 = self.error_code.into();
+        error_code.write(writer)?;
+        if v < 2 {
+            self.error_message.write(writer)?;
+            self.auth_bytes.write(writer)?;
+        } else {
+            CompactNullableStringRef(self.error_message.0.as_deref()).write(writer)?;
+            CompactBytesRef(&self.auth_bytes.0[..]).write(writer)?;
+        }
+        if v >= 1 {
+            self.session_lifetime_ms.unwrap_or(Int64(0)).write(writer)?;
+        }
+        if v >= 2 {
+            match self.tagged_fields.as_ref() {
+                Some(tagged_fields) => {
+                    tagged_fields.write(writer)?;
+                }
+                None => {
+                    TaggedFields::default().write(writer)?;
+                }
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,360 @@
+//! Client telemetry, see [KIP-714].
+//!
+//! [KIP-714]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-714%3A+Client+metrics+and+observability
+use std::io::{Read, Write};
+
+use crate::protocol::{
+    api_key::ApiKey,
+    api_version::{ApiVersion, ApiVersionRange},
+    error::Error as ApiError,
+    primitives::{
+        Boolean, CompactArray, CompactArrayRef, CompactBytes, CompactString, Int16, Int32, Int8,
+        TaggedFields, Uuid,
+    },
+    traits::{ReadType, WriteType},
+};
+
+use super::{
+    ReadVersionedError, ReadVersionedType, RequestBody, WriteVersionedError, WriteVersionedType,
+};
+
+#[cfg(test)]
+use proptest::prelude::*;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct GetTelemetrySubscriptionsRequest {
+    /// Unique id for this client instance, must be set to all zeros on the first request.
+    pub client_instance_id: Uuid,
+
+    /// The tagged fields.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl RequestBody for GetTelemetrySubscriptionsRequest {
+    type ResponseBody = GetTelemetrySubscriptionsResponse;
+
+    const API_KEY: ApiKey = ApiKey::GetTelemetrySubscriptions;
+
+    const API_VERSION_RANGE: ApiVersionRange =
+        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(0)));
+
+    const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(0));
+}
+
+impl<W> WriteVersionedType<W> for GetTelemetrySubscriptionsRequest
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        self.client_instance_id.write(writer)?;
+        match self.tagged_fields.as_ref() {
+            Some(tagged_fields) => {
+                tagged_fields.write(writer)?;
+            }
+            None => {
+                TaggedFields::default().write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<R> ReadVersionedType<R> for GetTelemetrySubscriptionsRequest
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        Ok(Self {
+            client_instance_id: Uuid::read(reader)?,
+            tagged_fields: Some(TaggedFields::read(reader)?),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct GetTelemetrySubscriptionsResponse {
+    /// The duration in milliseconds for which the request was throttled due to a quota violation, or zero if the
+    /// request did not violate any quota.
+    pub throttle_time_ms: Int32,
+
+    /// The error code, or 0 if there was no error.
+    #[cfg_attr(test, proptest(strategy = "any::<i16>().prop_map(ApiError::new)"))]
+    pub error_code: Option<ApiError>,
+
+    /// Assigned client instance id if the request contained all zeros, otherwise the id of the request.
+    pub client_instance_id: Uuid,
+
+    /// Unique identifier for the current subscription set for this client instance.
+    pub subscription_id: Int32,
+
+    /// Compression types that the broker accepts for pushed metrics, in order of preference.
+    pub accepted_compression_types: Vec<Int8>,
+
+    /// Configured push interval, which is the lowest configured interval in the current subscription set.
+    pub push_interval_ms: Int32,
+
+    /// The maximum bytes of binary data the broker accepts in a push request.
+    pub telemetry_max_bytes: Int32,
+
+    /// Whether sums shall be pushed as deltas instead of cumulative values.
+    pub delta_temporality: Boolean,
+
+    /// Requested metrics prefix string match.
+    ///
+    /// No metrics are requested if this is empty, all metrics are requested if it contains an empty string or `*`.
+    pub requested_metrics: Vec<CompactString>,
+
+    /// The tagged fields.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl<R> ReadVersionedType<R> for GetTelemetrySubscriptionsResponse
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        Ok(Self {
+            throttle_time_ms: Int32::read(reader)?,
+            error_code: ApiError::new(Int16::read(reader)?.0),
+            client_instance_id: Uuid::read(reader)?,
+            subscription_id: Int32::read(reader)?,
+            accepted_compression_types: CompactArray::read(reader)?.0.unwrap_or_default(),
+            push_interval_ms: Int32::read(reader)?,
+            telemetry_max_bytes: Int32::read(reader)?,
+            delta_temporality: Boolean::read(reader)?,
+            requested_metrics: CompactArray::read(reader)?.0.unwrap_or_default(),
+            tagged_fields: Some(TaggedFields::read(reader)?),
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for GetTelemetrySubscriptionsResponse
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        self.throttle_time_ms.write(writer)?;
+        let error_code: Int16 = self.error_code.into();
+        error_code.write(writer)?;
+        self.client_instance_id.write(writer)?;
+        self.subscription_id.write(writer)?;
+        CompactArrayRef(Some(&self.accepted_compression_types)).write(writer)?;
+        self.push_interval_ms.write(writer)?;
+        self.telemetry_max_bytes.write(writer)?;
+        self.delta_temporality.write(writer)?;
+        CompactArrayRef(Some(&self.requested_metrics)).write(writer)?;
+        match self.tagged_fields.as_ref() {
+            Some(tagged_fields) => {
+                tagged_fields.write(writer)?;
+            }
+            None => {
+                TaggedFields::default().write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct PushTelemetryRequest {
+    /// Unique id for this client instance.
+    pub client_instance_id: Uuid,
+
+    /// Unique identifier for the current subscription.
+    pub subscription_id: Int32,
+
+    /// Client is terminating the connection.
+    pub terminating: Boolean,
+
+    /// Compression codec used to compress the metrics.
+    pub compression_type: Int8,
+
+    /// Metrics encoded in OpenTelemetry `MetricsData` v1 protobuf format.
+    pub metrics: CompactBytes,
+
+    /// The tagged fields.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl RequestBody for PushTelemetryRequest {
+    type ResponseBody = PushTelemetryResponse;
+
+    const API_KEY: ApiKey = ApiKey::PushTelemetry;
+
+    const API_VERSION_RANGE: ApiVersionRange =
+        ApiVersionRange::new(ApiVersion(Int16(0)), ApiVersion(Int16(0)));
+
+    const FIRST_TAGGED_FIELD_IN_REQUEST_VERSION: ApiVersion = ApiVersion(Int16(0));
+}
+
+impl<W> WriteVersionedType<W> for PushTelemetryRequest
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        self.client_instance_id.write(writer)?;
+        self.subscription_id.write(writer)?;
+        self.terminating.write(writer)?;
+        self.compression_type.write(writer)?;
+        self.metrics.write(writer)?;
+        match self.tagged_fields.as_ref() {
+            Some(tagged_fields) => {
+                tagged_fields.write(writer)?;
+            }
+            None => {
+                TaggedFields::default().write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<R> ReadVersionedType<R> for PushTelemetryRequest
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        Ok(Self {
+            client_instance_id: Uuid::read(reader)?,
+            subscription_id: Int32::read(reader)?,
+            terminating: Boolean::read(reader)?,
+            compression_type: Int8::read(reader)?,
+            metrics: CompactBytes::read(reader)?,
+            tagged_fields: Some(TaggedFields::read(reader)?),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct PushTelemetryResponse {
+    /// The duration in milliseconds for which the request was throttled due to a quota violation, or zero if the
+    /// request did not violate any quota.
+    pub throttle_time_ms: Int32,
+
+    /// The error code, or 0 if there was no error.
+    #[cfg_attr(test, proptest(strategy = "any::<i16>().prop_map(ApiError::new)"))]
+    pub error_code: Option<ApiError>,
+
+    /// The tagged fields.
+    pub tagged_fields: Option<TaggedFields>,
+}
+
+impl<R> ReadVersionedType<R> for PushTelemetryResponse
+where
+    R: Read,
+{
+    fn read_versioned(reader: &mut R, version: ApiVersion) -> Result<Self, ReadVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        Ok(Self {
+            throttle_time_ms: Int32::read(reader)?,
+            error_code: ApiError::new(Int16::read(reader)?.0),
+            tagged_fields: Some(TaggedFields::read(reader)?),
+        })
+    }
+}
+
+// this is not technically required for production but helpful for testing
+impl<W> WriteVersionedType<W> for PushTelemetryResponse
+where
+    W: Write,
+{
+    fn write_versioned(
+        &self,
+        writer: &mut W,
+        version: ApiVersion,
+    ) -> Result<(), WriteVersionedError> {
+        let v = version.0 .0;
+        assert!(v <= 0);
+
+        self.throttle_time_ms.write(writer)?;
+        let error_code: Int16 = self.error_code.into();
+        error_code.write(writer)?;
+        match self.tagged_fields.as_ref() {
+            Some(tagged_fields) => {
+                tagged_fields.write(writer)?;
+            }
+            None => {
+                TaggedFields::default().write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::messages::test_utils::test_roundtrip_versioned;
+
+    use super::*;
+
+    test_roundtrip_versioned!(
+        GetTelemetrySubscriptionsRequest,
+        GetTelemetrySubscriptionsRequest::API_VERSION_RANGE.min(),
+        GetTelemetrySubscriptionsRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_get_telemetry_subscriptions_request
+    );
+
+    test_roundtrip_versioned!(
+        GetTelemetrySubscriptionsResponse,
+        GetTelemetrySubscriptionsRequest::API_VERSION_RANGE.min(),
+        GetTelemetrySubscriptionsRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_get_telemetry_subscriptions_response
+    );
+
+    test_roundtrip_versioned!(
+        PushTelemetryRequest,
+        PushTelemetryRequest::API_VERSION_RANGE.min(),
+        PushTelemetryRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_push_telemetry_request
+    );
+
+    test_roundtrip_versioned!(
+        PushTelemetryResponse,
+        PushTelemetryRequest::API_VERSION_RANGE.min(),
+        PushTelemetryRequest::API_VERSION_RANGE.max(),
+        test_roundtrip_push_telemetry_response
+    );
+}
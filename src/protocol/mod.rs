@@ -1,5 +1,16 @@
 //! The Apache Kafka protocol.
 //!
+//! Only public with the `protocol` feature, for tooling that speaks the wire protocol directly, e.g. proxies or traffic
+//! inspectors.
+//!
+//! Requests and responses live in [`messages`], one module per API. They are encoded and decoded via
+//! [`WriteVersionedType`](messages::WriteVersionedType) and [`ReadVersionedType`](messages::ReadVersionedType) with the
+//! API version that was negotiated with the broker, see [`RequestBody`](messages::RequestBody) for the versions that
+//! each request supports. Primitive types implement [`ReadType`](traits::ReadType) and
+//! [`WriteType`](traits::WriteType), record batches live in [`record`] and message framing in [`frame`].
+//!
+//! Messages gain fields when support for newer API versions is added, which may happen in minor releases.
+//!
 //! # References
 //! - <https://github.com/edenhill/librdkafka/blob/2b76b65212e5efda213961d5f84e565038036270/src/rdkafka_feature.c#L52-L212>
 //! - <https://kafka.apache.org/protocol>
@@ -7,12 +18,15 @@
 //! - <https://github.com/twmb/franz-go/tree/858592494064d5a6bef4b622a567183a39932712/generate/definitions>
 pub mod api_key;
 pub mod api_version;
+pub(crate) mod buffer_pool;
 pub mod error;
 pub mod frame;
+#[cfg(feature = "unstable-fuzzing")]
+pub mod fuzzing;
 pub mod messages;
 pub mod primitives;
 pub mod record;
 #[cfg(test)]
 pub mod test_utils;
 pub mod traits;
-pub mod vec_builder;
+pub(crate) mod vec_builder;
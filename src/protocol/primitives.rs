@@ -12,7 +12,8 @@ use integer_encoding::{VarIntReader, VarIntWriter};
 use proptest::prelude::*;
 
 use super::{
-    record::RecordBatch,
+    buffer_pool,
+    record::{AllowedCompressions, EncodedRecordBatch, RecordBatch},
     traits::{ReadError, ReadType, WriteError, WriteType},
     vec_builder::VecBuilder,
 };
@@ -162,6 +163,34 @@ where
     }
 }
 
+/// Represents a type 4 immutable universally unique identifier.
+///
+/// The values are encoded using sixteen bytes in network byte order (big-endian).
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct Uuid(pub [u8; 16]);
+
+impl<R> ReadType<R> for Uuid
+where
+    R: Read,
+{
+    fn read(reader: &mut R) -> Result<Self, ReadError> {
+        let mut buf = [0u8; 16];
+        reader.read_exact(&mut buf)?;
+        Ok(Self(buf))
+    }
+}
+
+impl<W> WriteType<W> for Uuid
+where
+    W: Write,
+{
+    fn write(&self, writer: &mut W) -> Result<(), WriteError> {
+        writer.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
 /// Represents an integer between `-2^31` and `2^31-1` inclusive.
 ///
 /// Encoding follows the variable-length zig-zag encoding from Google Protocol Buffers.
@@ -603,7 +632,7 @@ where
 }
 
 /// Represents a section containing optional tagged fields.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Clone)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 pub struct TaggedFields(pub Vec<(UnsignedVarint, Vec<u8>)>);
 
@@ -785,8 +814,8 @@ where
 
 /// Represents a sequence of Kafka records as NULLABLE_BYTES.
 ///
-/// This primitive actually depends on the message version and evolved twice in [KIP-32] and [KIP-98]. We only support
-/// the latest generation (message version 2).
+/// This primitive actually depends on the message version and evolved twice in [KIP-32] and [KIP-98]. We only write
+/// the latest generation (message version 2), older message sets are converted into record batches when reading.
 ///
 /// It seems that during `Produce` this must contain exactly one batch, but during `Fetch` this can contain zero, one or
 /// more batches -- however I could not find any documentation stating this behavior. [KIP-74] at least documents the
@@ -808,17 +837,106 @@ pub struct Records(
 );
 
 impl<R> ReadType<R> for Records
+where
+    R: Read,
+{
+    fn read(reader: &mut R) -> Result<Self, ReadError> {
+        RawRecords::read(reader)?.decode()
+    }
+}
+
+impl<W> WriteType<W> for Records
+where
+    W: Write,
+{
+    fn write(&self, writer: &mut W) -> Result<(), WriteError> {
+        // TODO: it would be nice if we could avoid the copy here by writing the records and then seeking back.
+        let mut buf = buffer_pool::take();
+        for record in &self.0 {
+            record.write(&mut *buf)?;
+        }
+
+        // same as `NullableBytes` but w/o taking ownership of the buffer
+        let l = i32::try_from(buf.len()).map_err(|e| WriteError::Malformed(Box::new(e)))?;
+        Int32(l).write(writer)?;
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// Same as [`Records`] but the record batches are not decoded when reading.
+///
+/// This allows to pass fetched record batches on as they are, without decompressing them. A null value is read as no
+/// records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct RawRecords(
+    #[cfg_attr(
+        test,
+        proptest(strategy = "any::<Vec<u8>>().prop_map(bytes::Bytes::from)")
+    )]
+    pub bytes::Bytes,
+);
+
+impl<R> ReadType<R> for RawRecords
 where
     R: Read,
 {
     fn read(reader: &mut R) -> Result<Self, ReadError> {
         let buf = NullableBytes::read(reader)?.0.unwrap_or_default();
-        let len = u64::try_from(buf.len())?;
-        let mut buf = Cursor::new(buf);
+        Ok(Self(buf.into()))
+    }
+}
+
+impl<W> WriteType<W> for RawRecords
+where
+    W: Write,
+{
+    fn write(&self, writer: &mut W) -> Result<(), WriteError> {
+        let l = i32::try_from(self.0.len()).map_err(|e| WriteError::Malformed(Box::new(e)))?;
+        Int32(l).write(writer)?;
+        writer.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
+impl RawRecords {
+    /// Decode the record batches.
+    ///
+    /// Keys and values of uncompressed records are slices of the buffer, so it is kept alive as long as any of them is.
+    pub fn decode(&self) -> Result<Records, ReadError> {
+        self.split(RecordBatch::read_bytes).map(Records)
+    }
+
+    /// Same as [`decode`](Self::decode), but only checks the CRC of the record batches for which `check_crc` returns
+    /// `true` and only decompresses `allowed` compressions, see [`RecordBatch::read_bytes_checked`].
+    pub(crate) fn decode_checked<F>(
+        &self,
+        mut check_crc: F,
+        allowed: AllowedCompressions,
+    ) -> Result<Records, ReadError>
+    where
+        F: FnMut() -> bool,
+    {
+        self.split(|reader| RecordBatch::read_bytes_checked(reader, check_crc(), allowed))
+            .map(Records)
+    }
+
+    /// Split the buffer into record batches without decoding them.
+    pub fn batches(&self) -> Result<Vec<EncodedRecordBatch>, ReadError> {
+        self.split(EncodedRecordBatch::read_bytes)
+    }
+
+    fn split<F, T>(&self, mut f: F) -> Result<Vec<T>, ReadError>
+    where
+        F: FnMut(&mut Cursor<bytes::Bytes>) -> Result<T, ReadError>,
+    {
+        let len = u64::try_from(self.0.len())?;
+        let mut buf = Cursor::new(self.0.clone());
 
         let mut batches = vec![];
         while buf.position() < len {
-            let batch = match RecordBatch::read(&mut buf) {
+            let batch = match f(&mut buf) {
                 Ok(batch) => batch,
                 Err(ReadError::IO(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                     // Record batch got cut off, likely due to `FetchRequest::max_bytes`.
@@ -831,22 +949,41 @@ where
             batches.push(batch);
         }
 
-        Ok(Self(batches))
+        Ok(batches)
     }
 }
 
-impl<W> WriteType<W> for Records
+/// Same as [`RawRecords`] but encoded as COMPACT_NULLABLE_BYTES, as used by flexible message versions.
+///
+/// First the length N+1 is given as an UNSIGNED_VARINT, then N bytes follow. A null value is encoded with a length of 0
+/// and read as no records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(proptest_derive::Arbitrary))]
+pub struct CompactRawRecords(pub RawRecords);
+
+impl<R> ReadType<R> for CompactRawRecords
+where
+    R: Read,
+{
+    fn read(reader: &mut R) -> Result<Self, ReadError> {
+        let len = UnsignedVarint::read(reader)?.0;
+        let buf = match len {
+            0 => vec![],
+            n => {
+                let len = usize::try_from(n - 1)?;
+                VecBuilder::new(len).read_exact(reader)?.into()
+            }
+        };
+        Ok(Self(RawRecords(buf.into())))
+    }
+}
+
+impl<W> WriteType<W> for CompactRawRecords
 where
     W: Write,
 {
     fn write(&self, writer: &mut W) -> Result<(), WriteError> {
-        // TODO: it would be nice if we could avoid the copy here by writing the records and then seeking back.
-        let mut buf = vec![];
-        for record in &self.0 {
-            record.write(&mut buf)?;
-        }
-        NullableBytes(Some(buf)).write(writer)?;
-        Ok(())
+        CompactBytesRef(&self.0 .0).write(writer)
     }
 }
 
@@ -883,6 +1020,8 @@ mod tests {
 
     test_roundtrip!(Int64, test_int64_roundtrip);
 
+    test_roundtrip!(Uuid, test_uuid_roundtrip);
+
     test_roundtrip!(Varint, test_varint_roundtrip);
 
     #[test]
@@ -1101,6 +1240,10 @@ mod tests {
 
     test_roundtrip!(Records, test_records_roundtrip);
 
+    test_roundtrip!(RawRecords, test_raw_records_roundtrip);
+
+    test_roundtrip!(CompactRawRecords, test_compact_raw_records_roundtrip);
+
     #[test]
     fn test_records_partial() {
         // Records might be partially returned when fetch requests are issued w/ size limits
@@ -1119,6 +1262,30 @@ mod tests {
         assert_eq!(records.0, vec![batch_1]);
     }
 
+    #[test]
+    fn test_raw_records_batches() {
+        let batch_1 = record_batch(1);
+        let batch_2 = record_batch(2);
+
+        let mut encoded_1 = vec![];
+        batch_1.write(&mut encoded_1).unwrap();
+
+        let mut buf = encoded_1.clone();
+        batch_2.write(&mut buf).unwrap();
+        buf.pop();
+
+        let batches = RawRecords(buf.into()).batches().unwrap();
+        assert_eq!(
+            batches,
+            vec![EncodedRecordBatch {
+                base_offset: 1,
+                last_offset_delta: 0,
+                is_control: false,
+                data: encoded_1.into(),
+            }]
+        );
+    }
+
     fn record_batch(base_offset: i64) -> RecordBatch {
         RecordBatch {
             base_offset,
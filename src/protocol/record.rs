@@ -1,6 +1,7 @@
 //! The actual payload message which is also the on-disk format for Kafka.
 //!
-//! The format evolved twice in [KIP-32] and [KIP-98]. We only support the latest generation (message version 2).
+//! The format evolved twice in [KIP-32] and [KIP-98]. We read and write the latest generation (message version 2).
+//! Older message sets (message versions 0 and 1) can be read and are converted into record batches.
 //!
 //!
 //! # CRC
@@ -21,15 +22,20 @@
 //! [KIP-98]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-98+-+Exactly+Once+Delivery+and+Transactional+Messaging
 use std::io::{Cursor, Read, Write};
 
+use bytes::Bytes;
 #[cfg(test)]
 use proptest::prelude::*;
+use thiserror::Error;
 
 use super::{
+    buffer_pool,
     primitives::{Int16, Int32, Int64, Int8, Varint, Varlong},
     traits::{ReadError, ReadType, WriteError, WriteType},
     vec_builder::VecBuilder,
 };
 
+mod legacy;
+
 /// Record Header
 ///
 /// # References
@@ -38,7 +44,8 @@ use super::{
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 pub struct RecordHeader {
     pub key: String,
-    pub value: Vec<u8>,
+    #[cfg_attr(test, proptest(strategy = "arbitrary_bytes()"))]
+    pub value: Bytes,
 }
 
 impl<R> ReadType<R> for RecordHeader
@@ -58,7 +65,25 @@ where
         let len = usize::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
         let mut value = VecBuilder::new(len);
         value = value.read_exact(reader)?;
-        let value = value.into();
+        let value = Vec::from(value).into();
+
+        Ok(Self { key, value })
+    }
+}
+
+impl RecordHeader {
+    /// Read a header from a buffer, referencing the value instead of copying it.
+    fn read_bytes(reader: &mut Cursor<Bytes>) -> Result<Self, ReadError> {
+        // key
+        let len = Varint::read(reader)?;
+        let len = usize::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+        let key = take_bytes(reader, len)?;
+        let key = String::from_utf8(key.to_vec()).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+
+        // value
+        let len = Varint::read(reader)?;
+        let len = usize::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+        let value = take_bytes(reader, len)?;
 
         Ok(Self { key, value })
     }
@@ -85,6 +110,9 @@ where
 
 /// Record
 ///
+/// Keys, values and header values that are read from a buffer reference that buffer instead of being copied, see
+/// [`Records`](super::primitives::Records).
+///
 /// # References
 /// - <https://kafka.apache.org/documentation/#record>
 #[derive(Debug, PartialEq, Eq)]
@@ -92,11 +120,23 @@ where
 pub struct Record {
     pub timestamp_delta: i64,
     pub offset_delta: i32,
-    pub key: Option<Vec<u8>>,
-    pub value: Option<Vec<u8>>,
+    #[cfg_attr(test, proptest(strategy = "arbitrary_nullable_bytes()"))]
+    pub key: Option<Bytes>,
+    #[cfg_attr(test, proptest(strategy = "arbitrary_nullable_bytes()"))]
+    pub value: Option<Bytes>,
     pub headers: Vec<RecordHeader>,
 }
 
+#[cfg(test)]
+fn arbitrary_bytes() -> impl Strategy<Value = Bytes> {
+    any::<Vec<u8>>().prop_map(Bytes::from)
+}
+
+#[cfg(test)]
+fn arbitrary_nullable_bytes() -> impl Strategy<Value = Option<Bytes>> {
+    prop::option::of(arbitrary_bytes())
+}
+
 impl<R> ReadType<R> for Record
 where
     R: Read,
@@ -104,8 +144,28 @@ where
     fn read(reader: &mut R) -> Result<Self, ReadError> {
         // length
         let len = Varint::read(reader)?;
-        let len = u64::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
-        let reader = &mut reader.take(len);
+        let len = usize::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+        let mut data = VecBuilder::new(len);
+        data = data.read_exact(reader)?;
+
+        Self::decode(Vec::from(data).into())
+    }
+}
+
+impl Record {
+    /// Read a record from a buffer, referencing key and value instead of copying them.
+    fn read_bytes(reader: &mut Cursor<Bytes>) -> Result<Self, ReadError> {
+        // length
+        let len = Varint::read(reader)?;
+        let len = usize::try_from(len.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+        let data = take_bytes(reader, len)?;
+
+        Self::decode(data)
+    }
+
+    /// Decode the record data that follows the length prefix.
+    fn decode(data: Bytes) -> Result<Self, ReadError> {
+        let reader = &mut Cursor::new(data);
 
         // attributes
         Int8::read(reader)?;
@@ -122,9 +182,7 @@ where
             None
         } else {
             let len = usize::try_from(len).map_err(|e| ReadError::Malformed(Box::new(e)))?;
-            let mut key = VecBuilder::new(len);
-            key = key.read_exact(reader)?;
-            Some(key.into())
+            Some(take_bytes(reader, len)?)
         };
 
         // value
@@ -133,9 +191,7 @@ where
             None
         } else {
             let len = usize::try_from(len).map_err(|e| ReadError::Malformed(Box::new(e)))?;
-            let mut value = VecBuilder::new(len);
-            value = value.read_exact(reader)?;
-            Some(value.into())
+            Some(take_bytes(reader, len)?)
         };
 
         // headers
@@ -145,13 +201,14 @@ where
             usize::try_from(n_headers.0).map_err(|e| ReadError::Malformed(Box::new(e)))?;
         let mut headers = VecBuilder::new(n_headers);
         for _ in 0..n_headers {
-            headers.push(RecordHeader::read(reader)?);
+            headers.push(RecordHeader::read_bytes(reader)?);
         }
 
         // check if there is any trailing data because this is likely a bug
-        if reader.limit() != 0 {
+        let bytes_left = bytes_left(reader);
+        if bytes_left != 0 {
             return Err(ReadError::Malformed(
-                format!("Found {} trailing bytes after Record", reader.limit()).into(),
+                format!("Found {} trailing bytes after Record", bytes_left).into(),
             ));
         }
 
@@ -310,6 +367,82 @@ pub enum RecordBatchCompression {
     Zstd,
 }
 
+impl RecordBatchCompression {
+    /// Code of the compression in the attributes of record batches and legacy messages.
+    fn code(self) -> u8 {
+        match self {
+            Self::NoCompression => 0,
+            Self::Gzip => 1,
+            Self::Snappy => 2,
+            Self::Lz4 => 3,
+            Self::Zstd => 4,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::NoCompression => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Snappy => "Snappy",
+            Self::Lz4 => "LZ4",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Compressions that may be decompressed when reading record batches, uncompressed data is always allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AllowedCompressions(u8);
+
+impl AllowedCompressions {
+    pub(crate) const ALL: Self = Self(u8::MAX);
+
+    pub(crate) fn new(compressions: impl IntoIterator<Item = RecordBatchCompression>) -> Self {
+        let uncompressed = 1 << RecordBatchCompression::NoCompression.code();
+        Self(
+            compressions
+                .into_iter()
+                .fold(uncompressed, |set, c| set | 1 << c.code()),
+        )
+    }
+
+    /// Fail with a [`CompressionNotAllowed`] if `compression` is not allowed.
+    fn check(self, offset: i64, compression: RecordBatchCompression) -> Result<(), ReadError> {
+        if self.0 & 1 << compression.code() != 0 {
+            return Ok(());
+        }
+        Err(ReadError::Malformed(Box::new(CompressionNotAllowed {
+            offset,
+            compression,
+        })))
+    }
+}
+
+impl Default for AllowedCompressions {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// A record batch or legacy message uses a compression that is not in the [`AllowedCompressions`].
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("{} compression at offset {offset} is not allowed", compression.name())]
+pub(crate) struct CompressionNotAllowed {
+    /// Base offset of the record batch, or offset of the legacy message.
+    pub(crate) offset: i64,
+    pub(crate) compression: RecordBatchCompression,
+}
+
+/// Settings for compressing a [`RecordBatch`] that are not part of the wire format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionSettings {
+    /// zstd compression level, `0` selects the zstd default.
+    pub zstd_level: i32,
+
+    /// Write the batch uncompressed if compression does not make it smaller.
+    pub uncompressed_fallback: bool,
+}
+
 /// Record batch timestamp type.
 ///
 /// # References
@@ -343,6 +476,143 @@ pub struct RecordBatch {
 }
 
 impl<R> ReadType<R> for RecordBatch
+where
+    R: Read,
+{
+    fn read(reader: &mut R) -> Result<Self, ReadError> {
+        let header = RecordBatchHeader::read(reader)?;
+
+        // data
+        let mut data = VecBuilder::new(header.len);
+        data = data.read_exact(reader)?;
+
+        Self::decode(
+            header,
+            Vec::from(data).into(),
+            true,
+            AllowedCompressions::ALL,
+        )
+    }
+}
+
+impl RecordBatch {
+    /// Read a record batch from a buffer, referencing the record data instead of copying it.
+    ///
+    /// Uncompressed record keys and values are slices of `reader`, compressed ones are slices of the decompressed data.
+    ///
+    /// Legacy message set entries (message versions 0 and 1) are converted into a record batch.
+    pub(crate) fn read_bytes(reader: &mut Cursor<Bytes>) -> Result<Self, ReadError> {
+        Self::read_bytes_checked(reader, true, AllowedCompressions::ALL)
+    }
+
+    /// Same as [`read_bytes`](Self::read_bytes), but only checks the CRC if `check_crc` is set and only decompresses
+    /// `allowed` compressions.
+    ///
+    /// A mismatch is reported as [`ReadError::Malformed`] containing a [`CrcMismatch`], a compression that is not
+    /// allowed as one containing a [`CompressionNotAllowed`].
+    pub(crate) fn read_bytes_checked(
+        reader: &mut Cursor<Bytes>,
+        check_crc: bool,
+        allowed: AllowedCompressions,
+    ) -> Result<Self, ReadError> {
+        // Both formats start with a 64-bit offset, a 32-bit length and another 32-bit field followed by the magic byte.
+        let magic_pos = reader.position() + 16;
+        if let Some(magic) = usize::try_from(magic_pos)
+            .ok()
+            .and_then(|pos| reader.get_ref().get(pos))
+        {
+            if *magic < 2 {
+                return legacy::read_message_set_entry(reader, check_crc, allowed);
+            }
+        }
+
+        let header = RecordBatchHeader::read(reader)?;
+        let data = take_bytes(reader, header.len)?;
+
+        Self::decode(header, data, check_crc, allowed)
+    }
+
+    /// Decode the CRC-checked data that follows the header.
+    fn decode(
+        header: RecordBatchHeader,
+        data: Bytes,
+        check_crc: bool,
+        allowed: AllowedCompressions,
+    ) -> Result<Self, ReadError> {
+        if check_crc {
+            CrcMismatch::check(header.base_offset, header.crc, crc32c::crc32c(&data))?;
+        }
+
+        // ==========================================================================================
+        // ======================================== CRC data ========================================
+        let mut data = Cursor::new(data);
+        let body = RecordBatchBody::read_bytes(&mut data, |compression| {
+            allowed.check(header.base_offset, compression)
+        })?;
+
+        // check if there is any trailing data because this is likely a bug
+        let bytes_left = bytes_left(&data);
+        if bytes_left != 0 {
+            return Err(ReadError::Malformed(
+                format!("Found {} trailing bytes after RecordBatch", bytes_left).into(),
+            ));
+        }
+
+        // ==========================================================================================
+        // ==========================================================================================
+
+        Ok(Self {
+            base_offset: header.base_offset,
+            partition_leader_epoch: header.partition_leader_epoch,
+            last_offset_delta: body.last_offset_delta,
+            first_timestamp: body.first_timestamp,
+            max_timestamp: body.max_timestamp,
+            producer_id: body.producer_id,
+            producer_epoch: body.producer_epoch,
+            base_sequence: body.base_sequence,
+            compression: body.compression,
+            timestamp_type: body.timestamp_type,
+            is_transactional: body.is_transactional,
+            records: body.records,
+        })
+    }
+}
+
+/// The CRC of a record batch or legacy message does not match its data.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("CRC error at offset {offset}, got 0x{actual:x}, expected 0x{expected:x}")]
+pub(crate) struct CrcMismatch {
+    /// Base offset of the record batch or offset of the legacy message.
+    pub(crate) offset: i64,
+    pub(crate) expected: u32,
+    pub(crate) actual: u32,
+}
+
+impl CrcMismatch {
+    fn check(offset: i64, expected: u32, actual: u32) -> Result<(), ReadError> {
+        if expected == actual {
+            return Ok(());
+        }
+        Err(ReadError::Malformed(Box::new(Self {
+            offset,
+            expected,
+            actual,
+        })))
+    }
+}
+
+/// Fields of a [`RecordBatch`] that precede the CRC-checked data.
+#[derive(Debug)]
+struct RecordBatchHeader {
+    base_offset: i64,
+    partition_leader_epoch: i32,
+    crc: u32,
+
+    /// Length of the CRC-checked data.
+    len: usize,
+}
+
+impl<R> ReadType<R> for RecordBatchHeader
 where
     R: Read,
 {
@@ -381,48 +651,51 @@ where
         let crc = Int32::read(reader)?.0;
         let crc = u32::from_be_bytes(crc.to_be_bytes());
 
-        // data
-        let mut data = VecBuilder::new(len);
-        data = data.read_exact(reader)?;
-        let data: Vec<u8> = data.into();
-        let actual_crc = crc32c::crc32c(&data);
-        if crc != actual_crc {
-            return Err(ReadError::Malformed(
-                format!("CRC error, got 0x{:x}, expected 0x{:x}", actual_crc, crc).into(),
-            ));
-        }
+        Ok(Self {
+            base_offset,
+            partition_leader_epoch,
+            crc,
+            len,
+        })
+    }
+}
 
-        // ==========================================================================================
-        // ======================================== CRC data ========================================
-        let mut data = Cursor::new(data);
-        let body = RecordBatchBody::read(&mut data)?;
+/// A [`RecordBatch`] that was split off a record set without decoding it.
+///
+/// The CRC is not checked, so this is only useful to pass record batches on as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedRecordBatch {
+    pub base_offset: i64,
+    pub last_offset_delta: i32,
+    pub is_control: bool,
 
-        // check if there is any trailing data because this is likely a bug
-        let bytes_read = data.position();
-        let bytes_total = u64::try_from(data.into_inner().len()).map_err(ReadError::Overflow)?;
-        let bytes_left = bytes_total - bytes_read;
-        if bytes_left != 0 {
-            return Err(ReadError::Malformed(
-                format!("Found {} trailing bytes after RecordBatch", bytes_left).into(),
-            ));
-        }
+    /// The entire encoded batch, including the fields that precede the CRC-checked data.
+    pub data: Bytes,
+}
 
-        // ==========================================================================================
-        // ==========================================================================================
+impl EncodedRecordBatch {
+    /// Split the next record batch off `reader` without copying it.
+    ///
+    /// Legacy message sets (message versions 0 and 1) are rejected since they cannot be passed on as record batches.
+    pub(crate) fn read_bytes(reader: &mut Cursor<Bytes>) -> Result<Self, ReadError> {
+        let start = reader.position() as usize;
+        let header = RecordBatchHeader::read(reader)?;
+        let body = take_bytes(reader, header.len)?;
+
+        // attributes
+        let mut body = Cursor::new(body);
+        let attributes = Int16::read(&mut body)?.0;
+        let is_control = ((attributes >> 5) & 0x1) == 1;
+
+        // lastOffsetDelta
+        let last_offset_delta = Int32::read(&mut body)?.0;
 
+        let end = reader.position() as usize;
         Ok(Self {
-            base_offset,
-            partition_leader_epoch,
-            last_offset_delta: body.last_offset_delta,
-            first_timestamp: body.first_timestamp,
-            max_timestamp: body.max_timestamp,
-            producer_id: body.producer_id,
-            producer_epoch: body.producer_epoch,
-            base_sequence: body.base_sequence,
-            compression: body.compression,
-            timestamp_type: body.timestamp_type,
-            is_transactional: body.is_transactional,
-            records: body.records,
+            base_offset: header.base_offset,
+            last_offset_delta,
+            is_control,
+            data: reader.get_ref().slice(start..end),
         })
     }
 }
@@ -432,10 +705,24 @@ where
     W: Write,
 {
     fn write(&self, writer: &mut W) -> Result<(), WriteError> {
+        self.write_with_settings(writer, CompressionSettings::default())
+    }
+}
+
+impl RecordBatch {
+    /// Write the record batch, compressing it with the given settings.
+    pub fn write_with_settings<W>(
+        &self,
+        writer: &mut W,
+        settings: CompressionSettings,
+    ) -> Result<(), WriteError>
+    where
+        W: Write,
+    {
         // ==========================================================================================
         // ======================================== CRC data ========================================
         // collect everything that should be part of the CRC calculation
-        let mut data = vec![];
+        let mut data = buffer_pool::take();
         let body_ref = RecordBatchBodyRef {
             last_offset_delta: self.last_offset_delta,
             first_timestamp: self.first_timestamp,
@@ -445,10 +732,25 @@ where
             base_sequence: self.base_sequence,
             records: &self.records,
             compression: self.compression,
+            settings,
             is_transactional: self.is_transactional,
             timestamp_type: self.timestamp_type,
         };
-        body_ref.write(&mut data)?;
+        body_ref.write(&mut *data)?;
+
+        if settings.uncompressed_fallback
+            && self.compression != RecordBatchCompression::NoCompression
+        {
+            let mut uncompressed = buffer_pool::take();
+            RecordBatchBodyRef {
+                compression: RecordBatchCompression::NoCompression,
+                ..body_ref
+            }
+            .write(&mut *uncompressed)?;
+            if uncompressed.len() <= data.len() {
+                data = uncompressed;
+            }
+        }
 
         // ==========================================================================================
         // ==========================================================================================
@@ -510,14 +812,11 @@ pub struct RecordBatchBody {
 }
 
 impl RecordBatchBody {
-    fn read_records<R>(
-        reader: &mut R,
+    fn read_records(
+        reader: &mut Cursor<Bytes>,
         is_control: bool,
         n_records: usize,
-    ) -> Result<ControlBatchOrRecords, ReadError>
-    where
-        R: Read,
-    {
+    ) -> Result<ControlBatchOrRecords, ReadError> {
         if is_control {
             if n_records != 1 {
                 return Err(ReadError::Malformed(
@@ -530,18 +829,38 @@ impl RecordBatchBody {
         } else {
             let mut records = VecBuilder::new(n_records);
             for _ in 0..n_records {
-                records.push(Record::read(reader)?);
+                records.push(Record::read_bytes(reader)?);
             }
             Ok(ControlBatchOrRecords::Records(records.into()))
         }
     }
-}
 
-impl<R> ReadType<R> for RecordBatchBody
-where
-    R: Read,
-{
-    fn read(reader: &mut R) -> Result<Self, ReadError> {
+    /// Read records from decompressed data, which must not contain anything else.
+    fn read_decompressed_records(
+        data: Vec<u8>,
+        is_control: bool,
+        n_records: usize,
+        compression: RecordBatchCompression,
+    ) -> Result<ControlBatchOrRecords, ReadError> {
+        let mut reader = Cursor::new(Bytes::from(data));
+        let records = Self::read_records(&mut reader, is_control, n_records)?;
+
+        if bytes_left(&reader) != 0 {
+            return Err(ReadError::Malformed(
+                format!("Data left in {} block", compression.name()).into(),
+            ));
+        }
+
+        Ok(records)
+    }
+
+    /// Read the body from a buffer, see [`RecordBatch::read_bytes`].
+    ///
+    /// `check_compression` is called before the records are decompressed.
+    fn read_bytes<F>(reader: &mut Cursor<Bytes>, check_compression: F) -> Result<Self, ReadError>
+    where
+        F: FnOnce(RecordBatchCompression) -> Result<(), ReadError>,
+    {
         // attributes
         let attributes = Int16::read(reader)?.0;
         let compression = match attributes & 0x7 {
@@ -587,116 +906,14 @@ where
             -1 => 0,
             n => usize::try_from(n)?,
         };
+        check_compression(compression)?;
         let records = match compression {
             RecordBatchCompression::NoCompression => {
                 Self::read_records(reader, is_control, n_records)?
             }
-            #[cfg(feature = "compression-gzip")]
-            RecordBatchCompression::Gzip => {
-                use flate2::read::GzDecoder;
-
-                let mut decoder = GzDecoder::new(reader);
-                let records = Self::read_records(&mut decoder, is_control, n_records)?;
-
-                ensure_eof(&mut decoder, "Data left in gzip block")?;
-
-                records
-            }
-            #[cfg(feature = "compression-lz4")]
-            RecordBatchCompression::Lz4 => {
-                use lz4::Decoder;
-
-                let mut decoder = Decoder::new(reader)?;
-                let records = Self::read_records(&mut decoder, is_control, n_records)?;
-
-                // the lz4 decoder requires us to consume the whole inner stream until we reach EOF
-                ensure_eof(&mut decoder, "Data left in LZ4 block")?;
-
-                let (_reader, res) = decoder.finish();
-                res?;
-
-                records
-            }
-            #[cfg(feature = "compression-snappy")]
-            RecordBatchCompression::Snappy => {
-                use crate::protocol::vec_builder::DEFAULT_BLOCK_SIZE;
-
-                // Construct the input for the raw decoder.
-                let mut input = vec![];
-                reader.read_to_end(&mut input)?;
-
-                const JAVA_MAGIC: &[u8] = &[0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
-
-                // There are "normal" compression libs, and there is Java
-                // See https://github.com/edenhill/librdkafka/blob/2b76b65212e5efda213961d5f84e565038036270/src/rdkafka_msgset_reader.c#L307-L318
-                let output = if input.starts_with(JAVA_MAGIC) {
-                    let cursor_content = &input[JAVA_MAGIC.len()..];
-                    let mut cursor = Cursor::new(cursor_content);
-
-                    let mut buf_version = [0u8; 4];
-                    cursor.read_exact(&mut buf_version)?;
-                    if buf_version != [0, 0, 0, 1] {
-                        return Err(ReadError::Malformed(
-                            format!("Detected Java-specific Snappy compression, but got unknown version: {buf_version:?}").into(),
-                        ));
-                    }
-
-                    let mut buf_compatible = [0u8; 4];
-                    cursor.read_exact(&mut buf_compatible)?;
-                    if buf_compatible != [0, 0, 0, 1] {
-                        return Err(ReadError::Malformed(
-                            format!("Detected Java-specific Snappy compression, but got unknown compat flags: {buf_compatible:?}").into(),
-                        ));
-                    }
-
-                    let mut output = vec![];
-                    while cursor.position() < cursor.get_ref().len() as u64 {
-                        let mut buf_chunk_length = [0u8; 4];
-                        cursor.read_exact(&mut buf_chunk_length)?;
-                        let chunk_length = u32::from_be_bytes(buf_chunk_length) as usize;
-                        let bytes_left = cursor_content.len() - (cursor.position() as usize);
-                        if chunk_length > bytes_left {
-                            // do NOT try to allocate massive buffer for `chunk_data` but instead fail early
-                            return Err(ReadError::Malformed(format!("Java-specific Snappy-compressed data has illegal chunk length, got {chunk_length} bytes but only {bytes_left} bytes are left.").into()));
-                        }
-
-                        let mut chunk_data = vec![0u8; chunk_length];
-                        cursor.read_exact(&mut chunk_data)?;
-
-                        let mut buf = carefully_decompress_snappy(&chunk_data, DEFAULT_BLOCK_SIZE)?;
-                        output.append(&mut buf);
-                    }
-
-                    output
-                } else {
-                    carefully_decompress_snappy(&input, DEFAULT_BLOCK_SIZE)?
-                };
-
-                // Read uncompressed records.
-                let mut decoder = Cursor::new(output);
-                let records = Self::read_records(&mut decoder, is_control, n_records)?;
-
-                // Check that there's no data left within the uncompressed block.
-                ensure_eof(&mut decoder, "Data left in Snappy block")?;
-
-                records
-            }
-            #[cfg(feature = "compression-zstd")]
-            RecordBatchCompression::Zstd => {
-                use zstd::Decoder;
-
-                let mut decoder = Decoder::new(reader)?;
-                let records = Self::read_records(&mut decoder, is_control, n_records)?;
-
-                ensure_eof(&mut decoder, "Data left in zstd block")?;
-
-                records
-            }
-            #[allow(unreachable_patterns)]
-            _ => {
-                return Err(ReadError::Malformed(
-                    format!("Unimplemented compression: {:?}", compression).into(),
-                ));
+            compression => {
+                let data = decompress(compression, reader)?;
+                Self::read_decompressed_records(data, is_control, n_records, compression)?
             }
         };
 
@@ -715,6 +932,18 @@ where
     }
 }
 
+impl<R> ReadType<R> for RecordBatchBody
+where
+    R: Read,
+{
+    fn read(reader: &mut R) -> Result<Self, ReadError> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        Self::read_bytes(&mut Cursor::new(data.into()), |_| Ok(()))
+    }
+}
+
 impl<W> WriteType<W> for RecordBatchBody
 where
     W: Write,
@@ -729,6 +958,7 @@ where
             base_sequence: self.base_sequence,
             records: &self.records,
             compression: self.compression,
+            settings: CompressionSettings::default(),
             is_transactional: self.is_transactional,
             timestamp_type: self.timestamp_type,
         };
@@ -749,6 +979,7 @@ struct RecordBatchBodyRef<'a> {
     pub base_sequence: i32,
     pub records: &'a ControlBatchOrRecords,
     pub compression: RecordBatchCompression,
+    pub settings: CompressionSettings,
     pub is_transactional: bool,
     pub timestamp_type: RecordBatchTimestampType,
 }
@@ -776,13 +1007,7 @@ where
 {
     fn write(&self, writer: &mut W) -> Result<(), WriteError> {
         // attributes
-        let mut attributes: i16 = match self.compression {
-            RecordBatchCompression::NoCompression => 0,
-            RecordBatchCompression::Gzip => 1,
-            RecordBatchCompression::Snappy => 2,
-            RecordBatchCompression::Lz4 => 3,
-            RecordBatchCompression::Zstd => 4,
-        };
+        let mut attributes = i16::from(self.compression.code());
         match self.timestamp_type {
             RecordBatchTimestampType::CreateTime => (),
             RecordBatchTimestampType::LogAppendTime => {
@@ -851,11 +1076,12 @@ where
             RecordBatchCompression::Snappy => {
                 use snap::raw::{max_compress_len, Encoder};
 
-                let mut input = vec![];
-                Self::write_records(&mut input, self.records)?;
+                let mut input = buffer_pool::take();
+                Self::write_records(&mut *input, self.records)?;
 
                 let mut encoder = Encoder::new();
-                let mut output = vec![0; max_compress_len(input.len())];
+                let mut output = buffer_pool::take();
+                output.resize(max_compress_len(input.len()), 0);
                 let len = encoder
                     .compress(&input, &mut output)
                     .map_err(|e| WriteError::Malformed(Box::new(e)))?;
@@ -866,7 +1092,7 @@ where
             RecordBatchCompression::Zstd => {
                 use zstd::Encoder;
 
-                let mut encoder = Encoder::new(writer, 0)?;
+                let mut encoder = Encoder::new(writer, self.settings.zstd_level)?;
                 Self::write_records(&mut encoder, self.records)?;
                 encoder.finish()?;
             }
@@ -882,19 +1108,131 @@ where
     }
 }
 
-/// Ensure that given reader is at EOF.
-#[allow(dead_code)] // only use by some features
-fn ensure_eof<R>(reader: &mut R, msg: &str) -> Result<(), ReadError>
+/// Decompress the remaining data of `reader`.
+fn decompress<R>(compression: RecordBatchCompression, mut reader: R) -> Result<Vec<u8>, ReadError>
 where
     R: Read,
 {
-    let mut buf = [0u8; 1];
-    match reader.read(&mut buf) {
-        Ok(0) => Ok(()),
-        Ok(_) => Err(ReadError::Malformed(msg.to_string().into())),
-        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
-        Err(e) => Err(ReadError::IO(e)),
+    match compression {
+        RecordBatchCompression::NoCompression => {
+            let mut data = vec![];
+            reader.read_to_end(&mut data)?;
+            Ok(data)
+        }
+        #[cfg(feature = "compression-gzip")]
+        RecordBatchCompression::Gzip => {
+            use flate2::read::GzDecoder;
+
+            let mut decoder = GzDecoder::new(reader);
+            let mut data = vec![];
+            decoder.read_to_end(&mut data)?;
+
+            Ok(data)
+        }
+        #[cfg(feature = "compression-lz4")]
+        RecordBatchCompression::Lz4 => {
+            use lz4::Decoder;
+
+            // the lz4 decoder requires us to consume the whole inner stream until we reach EOF
+            let mut decoder = Decoder::new(reader)?;
+            let mut data = vec![];
+            decoder.read_to_end(&mut data)?;
+
+            let (_reader, res) = decoder.finish();
+            res?;
+
+            Ok(data)
+        }
+        #[cfg(feature = "compression-snappy")]
+        RecordBatchCompression::Snappy => {
+            use crate::protocol::vec_builder::DEFAULT_BLOCK_SIZE;
+
+            // Construct the input for the raw decoder.
+            let mut input = vec![];
+            reader.read_to_end(&mut input)?;
+
+            const JAVA_MAGIC: &[u8] = &[0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+
+            // There are "normal" compression libs, and there is Java
+            // See https://github.com/edenhill/librdkafka/blob/2b76b65212e5efda213961d5f84e565038036270/src/rdkafka_msgset_reader.c#L307-L318
+            let output = if input.starts_with(JAVA_MAGIC) {
+                let cursor_content = &input[JAVA_MAGIC.len()..];
+                let mut cursor = Cursor::new(cursor_content);
+
+                let mut buf_version = [0u8; 4];
+                cursor.read_exact(&mut buf_version)?;
+                if buf_version != [0, 0, 0, 1] {
+                    return Err(ReadError::Malformed(
+                        format!("Detected Java-specific Snappy compression, but got unknown version: {buf_version:?}").into(),
+                    ));
+                }
+
+                let mut buf_compatible = [0u8; 4];
+                cursor.read_exact(&mut buf_compatible)?;
+                if buf_compatible != [0, 0, 0, 1] {
+                    return Err(ReadError::Malformed(
+                        format!("Detected Java-specific Snappy compression, but got unknown compat flags: {buf_compatible:?}").into(),
+                    ));
+                }
+
+                let mut output = vec![];
+                while cursor.position() < cursor.get_ref().len() as u64 {
+                    let mut buf_chunk_length = [0u8; 4];
+                    cursor.read_exact(&mut buf_chunk_length)?;
+                    let chunk_length = u32::from_be_bytes(buf_chunk_length) as usize;
+                    let bytes_left = cursor_content.len() - (cursor.position() as usize);
+                    if chunk_length > bytes_left {
+                        // do NOT try to allocate massive buffer for `chunk_data` but instead fail early
+                        return Err(ReadError::Malformed(format!("Java-specific Snappy-compressed data has illegal chunk length, got {chunk_length} bytes but only {bytes_left} bytes are left.").into()));
+                    }
+
+                    let mut chunk_data = vec![0u8; chunk_length];
+                    cursor.read_exact(&mut chunk_data)?;
+
+                    let mut buf = carefully_decompress_snappy(&chunk_data, DEFAULT_BLOCK_SIZE)?;
+                    output.append(&mut buf);
+                }
+
+                output
+            } else {
+                carefully_decompress_snappy(&input, DEFAULT_BLOCK_SIZE)?
+            };
+
+            Ok(output)
+        }
+        #[cfg(feature = "compression-zstd")]
+        RecordBatchCompression::Zstd => {
+            use zstd::Decoder;
+
+            let mut decoder = Decoder::new(reader)?;
+            let mut data = vec![];
+            decoder.read_to_end(&mut data)?;
+
+            Ok(data)
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(ReadError::Malformed(
+            format!("Unimplemented compression: {:?}", compression).into(),
+        )),
+    }
+}
+
+/// Number of bytes that have not been read from `reader` yet.
+fn bytes_left(reader: &Cursor<Bytes>) -> u64 {
+    (reader.get_ref().len() as u64).saturating_sub(reader.position())
+}
+
+/// Take the next `len` bytes from `reader` without copying them.
+fn take_bytes(reader: &mut Cursor<Bytes>, len: usize) -> Result<Bytes, ReadError> {
+    if (len as u64) > bytes_left(reader) {
+        return Err(ReadError::IO(std::io::Error::from(
+            std::io::ErrorKind::UnexpectedEof,
+        )));
     }
+
+    let start = reader.position() as usize;
+    reader.set_position((start + len) as u64);
+    Ok(reader.get_ref().slice(start..start + len))
 }
 
 /// Try to decompress a snappy message without blindly believing the uncompressed size encoded at the start of the
@@ -1082,11 +1420,11 @@ mod tests {
             records: ControlBatchOrRecords::Records(vec![Record {
                 timestamp_delta: 0,
                 offset_delta: 0,
-                key: Some(vec![]),
-                value: Some(b"hello kafka".to_vec()),
+                key: Some(vec![].into()),
+                value: Some(b"hello kafka".to_vec().into()),
                 headers: vec![RecordHeader {
                     key: "foo".to_owned(),
-                    value: b"bar".to_vec(),
+                    value: b"bar".to_vec().into(),
                 }],
             }]),
             compression: RecordBatchCompression::NoCompression,
@@ -1128,11 +1466,11 @@ mod tests {
             records: ControlBatchOrRecords::Records(vec![Record {
                 timestamp_delta: 0,
                 offset_delta: 0,
-                key: Some(vec![b'x'; 100]),
-                value: Some(b"hello kafka".to_vec()),
+                key: Some(vec![b'x'; 100].into()),
+                value: Some(b"hello kafka".to_vec().into()),
                 headers: vec![RecordHeader {
                     key: "foo".to_owned(),
-                    value: b"bar".to_vec(),
+                    value: b"bar".to_vec().into(),
                 }],
             }]),
             compression: RecordBatchCompression::Gzip,
@@ -1178,11 +1516,11 @@ mod tests {
             records: ControlBatchOrRecords::Records(vec![Record {
                 timestamp_delta: 0,
                 offset_delta: 0,
-                key: Some(vec![b'x'; 100]),
-                value: Some(b"hello kafka".to_vec()),
+                key: Some(vec![b'x'; 100].into()),
+                value: Some(b"hello kafka".to_vec().into()),
                 headers: vec![RecordHeader {
                     key: "foo".to_owned(),
-                    value: b"bar".to_vec(),
+                    value: b"bar".to_vec().into(),
                 }],
             }]),
             compression: RecordBatchCompression::Lz4,
@@ -1231,11 +1569,11 @@ mod tests {
                 records: ControlBatchOrRecords::Records(vec![Record {
                     timestamp_delta: 0,
                     offset_delta: 0,
-                    key: Some(vec![b'x'; 100]),
-                    value: Some(b"hello kafka".to_vec()),
+                    key: Some(vec![b'x'; 100].into()),
+                    value: Some(b"hello kafka".to_vec().into()),
                     headers: vec![RecordHeader {
                         key: "foo".to_owned(),
-                        value: b"bar".to_vec(),
+                        value: b"bar".to_vec().into(),
                     }],
                 }]),
                 compression: RecordBatchCompression::Snappy,
@@ -1285,21 +1623,21 @@ mod tests {
                     Record {
                         timestamp_delta: 0,
                         offset_delta: 0,
-                        key: Some(vec![b'x'; 100]),
-                        value: Some(b"hello kafka".to_vec()),
+                        key: Some(vec![b'x'; 100].into()),
+                        value: Some(b"hello kafka".to_vec().into()),
                         headers: vec![RecordHeader {
                             key: "foo".to_owned(),
-                            value: b"bar".to_vec(),
+                            value: b"bar".to_vec().into(),
                         }],
                     },
                     Record {
                         timestamp_delta: 0,
                         offset_delta: 1,
-                        key: Some(vec![b'x'; 100]),
-                        value: Some(b"some value".to_vec()),
+                        key: Some(vec![b'x'; 100].into()),
+                        value: Some(b"some value".to_vec().into()),
                         headers: vec![RecordHeader {
                             key: "foo".to_owned(),
-                            value: b"bar".to_vec(),
+                            value: b"bar".to_vec().into(),
                         }],
                     },
                 ]),
@@ -1400,11 +1738,11 @@ mod tests {
             records: ControlBatchOrRecords::Records(vec![Record {
                 timestamp_delta: 0,
                 offset_delta: 0,
-                key: Some(vec![b'x'; 100]),
-                value: Some(b"hello kafka".to_vec()),
+                key: Some(vec![b'x'; 100].into()),
+                value: Some(b"hello kafka".to_vec().into()),
                 headers: vec![RecordHeader {
                     key: "foo".to_owned(),
-                    value: b"bar".to_vec(),
+                    value: b"bar".to_vec().into(),
                 }],
             }]),
             compression: RecordBatchCompression::Zstd,
@@ -1422,6 +1760,145 @@ mod tests {
         assert_eq!(actual2, expected);
     }
 
+    #[cfg(feature = "compression-zstd")]
+    #[test]
+    fn test_write_zstd_level() {
+        let batch = RecordBatch {
+            base_offset: 0,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: ControlBatchOrRecords::Records(vec![Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: None,
+                value: Some(b"hello kafka ".repeat(100).into()),
+                headers: vec![],
+            }]),
+            compression: RecordBatchCompression::Zstd,
+            is_transactional: false,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+
+        let mut data_default = vec![];
+        batch.write(&mut data_default).unwrap();
+
+        let mut data_fast = vec![];
+        batch
+            .write_with_settings(
+                &mut data_fast,
+                CompressionSettings {
+                    zstd_level: -100,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_ne!(data_default, data_fast);
+
+        let actual = RecordBatch::read(&mut Cursor::new(data_fast)).unwrap();
+        assert_eq!(actual, batch);
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_write_uncompressed_fallback() {
+        let batch = |value: Vec<u8>| RecordBatch {
+            base_offset: 0,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: ControlBatchOrRecords::Records(vec![Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: None,
+                value: Some(value.into()),
+                headers: vec![],
+            }]),
+            compression: RecordBatchCompression::Gzip,
+            is_transactional: false,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+        let settings = CompressionSettings {
+            uncompressed_fallback: true,
+            ..Default::default()
+        };
+
+        // gzip framing makes tiny batches larger
+        let mut data = vec![];
+        batch(b"x".to_vec())
+            .write_with_settings(&mut data, settings)
+            .unwrap();
+        let actual = RecordBatch::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(actual.compression, RecordBatchCompression::NoCompression);
+
+        let mut data = vec![];
+        batch(vec![b'x'; 1_000])
+            .write_with_settings(&mut data, settings)
+            .unwrap();
+        let actual = RecordBatch::read(&mut Cursor::new(data)).unwrap();
+        assert_eq!(actual, batch(vec![b'x'; 1_000]));
+    }
+
+    #[test]
+    fn test_read_bytes_references_buffer() {
+        let batch = RecordBatch {
+            base_offset: 0,
+            partition_leader_epoch: 0,
+            last_offset_delta: 0,
+            first_timestamp: 0,
+            max_timestamp: 0,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: ControlBatchOrRecords::Records(vec![Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: Some(Bytes::from_static(b"foo")),
+                value: Some(Bytes::from_static(b"hello kafka")),
+                headers: vec![RecordHeader {
+                    key: "foo".to_owned(),
+                    value: Bytes::from_static(b"bar"),
+                }],
+            }]),
+            compression: RecordBatchCompression::NoCompression,
+            is_transactional: false,
+            timestamp_type: RecordBatchTimestampType::CreateTime,
+        };
+        let mut data = vec![];
+        batch.write(&mut data).unwrap();
+        let data = Bytes::from(data);
+
+        let mut reader = Cursor::new(data.clone());
+        let actual = RecordBatch::read_bytes(&mut reader).unwrap();
+        assert_eq!(actual, batch);
+        assert_eq!(reader.position(), data.len() as u64);
+
+        let ControlBatchOrRecords::Records(records) = actual.records else {
+            panic!("expected records");
+        };
+        let buffer = data.as_ptr_range();
+        for b in [
+            records[0].key.as_ref(),
+            records[0].value.as_ref(),
+            Some(&records[0].headers[0].value),
+        ] {
+            assert!(buffer.contains(&b.unwrap().as_ptr()));
+        }
+
+        // cut-off batches are reported as EOF
+        let mut reader = Cursor::new(data.slice(..data.len() - 1));
+        let err = RecordBatch::read_bytes(&mut reader).unwrap_err();
+        assert_matches!(err, ReadError::IO(e) if e.kind() == std::io::ErrorKind::UnexpectedEof);
+    }
+
     #[test]
     fn test_decode_fixture_null_key() {
         // This data was obtained by watching rdkafka driven by IOx.
@@ -1469,15 +1946,15 @@ mod tests {
                     3, 117, 112, 99, 18, 23, 10, 4, 117, 115, 101, 114, 16, 3, 26, 10, 18, 8, 0, 0,
                     0, 0, 0, 0, 240, 63, 34, 1, 0, 18, 16, 10, 4, 116, 105, 109, 101, 16, 4, 26, 3,
                     10, 1, 100, 34, 1, 0, 24, 1,
-                ]),
+                ].into()),
                 headers: vec![
                     RecordHeader {
                         key: "content-type".to_owned(),
-                        value: br#"application/x-protobuf; schema="influxdata.iox.write_buffer.v1.WriteBufferPayload""#.to_vec(),
+                        value: br#"application/x-protobuf; schema="influxdata.iox.write_buffer.v1.WriteBufferPayload""#.to_vec().into(),
                     },
                     RecordHeader {
                         key: "iox-namespace".to_owned(),
-                        value: b"namespace".to_vec(),
+                        value: b"namespace".to_vec().into(),
                     },
                 ],
             }]),
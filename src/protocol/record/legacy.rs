@@ -0,0 +1,409 @@
+//! Legacy message sets (message versions 0 and 1) that predate [KIP-98].
+//!
+//! We only support reading them. Every message set entry is converted into a [`RecordBatch`], so consumers do not need
+//! to care about the message version.
+//!
+//! # References
+//! - <https://kafka.apache.org/documentation/#messageset>
+//! - [KIP-31]
+//! - [KIP-32]
+//!
+//! [KIP-31]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-31+-+Move+to+relative+offsets+in+compressed+message+sets
+//! [KIP-32]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-32+-+Add+timestamps+to+Kafka+message
+//! [KIP-98]: https://cwiki.apache.org/confluence/display/KAFKA/KIP-98+-+Exactly+Once+Delivery+and+Transactional+Messaging
+use std::io::Cursor;
+
+use bytes::Bytes;
+
+use super::{
+    bytes_left, decompress, take_bytes, AllowedCompressions, ControlBatchOrRecords, CrcMismatch,
+    Record, RecordBatch, RecordBatchCompression, RecordBatchTimestampType,
+};
+use crate::protocol::{
+    primitives::{Int32, Int64, Int8},
+    traits::{ReadError, ReadType},
+};
+
+/// A single message of a message set.
+#[derive(Debug)]
+struct Message {
+    offset: i64,
+    magic: i8,
+    compression: RecordBatchCompression,
+    timestamp_type: RecordBatchTimestampType,
+
+    /// Timestamp, `-1` for message version 0.
+    timestamp: i64,
+    key: Option<Bytes>,
+    value: Option<Bytes>,
+}
+
+impl Message {
+    fn read(reader: &mut Cursor<Bytes>, check_crc: bool) -> Result<Self, ReadError> {
+        // offset
+        let offset = Int64::read(reader)?.0;
+
+        // message_size
+        let len = Int32::read(reader)?.0;
+        let len = usize::try_from(len).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+        let data = take_bytes(reader, len)?;
+        let reader = &mut Cursor::new(data);
+
+        // crc
+        let crc = Int32::read(reader)?.0;
+        let crc = u32::from_be_bytes(crc.to_be_bytes());
+        if check_crc {
+            CrcMismatch::check(offset, crc, crc32fast::hash(&reader.get_ref()[4..]))?;
+        }
+
+        // magic
+        let magic = Int8::read(reader)?.0;
+        if !(0..=1).contains(&magic) {
+            return Err(ReadError::Malformed(
+                format!("Invalid magic number in legacy message: {}", magic).into(),
+            ));
+        }
+
+        // attributes
+        let attributes = Int8::read(reader)?.0;
+        let compression = match attributes & 0x7 {
+            0 => RecordBatchCompression::NoCompression,
+            1 => RecordBatchCompression::Gzip,
+            2 => RecordBatchCompression::Snappy,
+            3 => RecordBatchCompression::Lz4,
+            other => {
+                return Err(ReadError::Malformed(
+                    format!("Invalid compression type in legacy message: {}", other).into(),
+                ));
+            }
+        };
+        let timestamp_type = if magic == 1 && ((attributes >> 3) & 0x1) == 1 {
+            RecordBatchTimestampType::LogAppendTime
+        } else {
+            RecordBatchTimestampType::CreateTime
+        };
+
+        // timestamp
+        let timestamp = if magic == 1 {
+            Int64::read(reader)?.0
+        } else {
+            -1
+        };
+
+        // key and value
+        let key = read_nullable_bytes(reader)?;
+        let value = read_nullable_bytes(reader)?;
+
+        let bytes_left = bytes_left(reader);
+        if bytes_left != 0 {
+            return Err(ReadError::Malformed(
+                format!("Found {} trailing bytes after legacy message", bytes_left).into(),
+            ));
+        }
+
+        Ok(Self {
+            offset,
+            magic,
+            compression,
+            timestamp_type,
+            timestamp,
+            key,
+            value,
+        })
+    }
+}
+
+fn read_nullable_bytes(reader: &mut Cursor<Bytes>) -> Result<Option<Bytes>, ReadError> {
+    let len = Int32::read(reader)?.0;
+    match len {
+        -1 => Ok(None),
+        l => {
+            let len = usize::try_from(l).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+            Ok(Some(take_bytes(reader, len)?))
+        }
+    }
+}
+
+/// Read a legacy message set entry and convert it into a [`RecordBatch`].
+///
+/// Compressed entries are wrapper messages that contain an entire message set, which becomes a single batch.
+pub(super) fn read_message_set_entry(
+    reader: &mut Cursor<Bytes>,
+    check_crc: bool,
+    allowed: AllowedCompressions,
+) -> Result<RecordBatch, ReadError> {
+    let wrapper = Message::read(reader, check_crc)?;
+    allowed.check(wrapper.offset, wrapper.compression)?;
+
+    let messages = match wrapper.compression {
+        RecordBatchCompression::NoCompression => vec![(
+            wrapper.offset,
+            wrapper.timestamp,
+            wrapper.key,
+            wrapper.value,
+        )],
+        compression => {
+            let value = wrapper.value.ok_or_else(|| {
+                ReadError::Malformed("Compressed legacy message without value".into())
+            })?;
+            let data = decompress(compression, &value[..])?;
+            let inner_reader = &mut Cursor::new(Bytes::from(data));
+
+            let mut inner = vec![];
+            while bytes_left(inner_reader) > 0 {
+                let message = Message::read(inner_reader, check_crc)?;
+                if message.compression != RecordBatchCompression::NoCompression {
+                    return Err(ReadError::Malformed(
+                        "Nested compression in legacy message set".into(),
+                    ));
+                }
+                inner.push(message);
+            }
+
+            // Version 1 uses relative offsets for inner messages, and the wrapper carries the absolute offset of the
+            // last one, see KIP-31.
+            let offset_base = match (wrapper.magic, inner.last()) {
+                (1, Some(last)) => wrapper
+                    .offset
+                    .checked_sub(last.offset)
+                    .ok_or_else(|| overflow("offset", wrapper.offset, last.offset))?,
+                _ => 0,
+            };
+
+            inner
+                .into_iter()
+                .map(|message| {
+                    let timestamp = match wrapper.timestamp_type {
+                        RecordBatchTimestampType::CreateTime => message.timestamp,
+                        RecordBatchTimestampType::LogAppendTime => wrapper.timestamp,
+                    };
+                    let offset = offset_base
+                        .checked_add(message.offset)
+                        .ok_or_else(|| overflow("offset", offset_base, message.offset))?;
+                    Ok((offset, timestamp, message.key, message.value))
+                })
+                .collect::<Result<_, ReadError>>()?
+        }
+    };
+
+    let base_offset = messages
+        .first()
+        .map(|(offset, ..)| *offset)
+        .unwrap_or(wrapper.offset);
+    let first_timestamp = messages
+        .first()
+        .map(|(_, timestamp, ..)| *timestamp)
+        .unwrap_or(wrapper.timestamp);
+    let max_timestamp = messages
+        .iter()
+        .map(|(_, timestamp, ..)| *timestamp)
+        .max()
+        .unwrap_or(wrapper.timestamp);
+
+    let mut last_offset_delta = 0;
+    let records = messages
+        .into_iter()
+        .map(|(offset, timestamp, key, value)| {
+            let offset_delta = offset
+                .checked_sub(base_offset)
+                .ok_or_else(|| overflow("offset", offset, base_offset))?;
+            let offset_delta =
+                i32::try_from(offset_delta).map_err(|e| ReadError::Malformed(Box::new(e)))?;
+            last_offset_delta = offset_delta;
+            let timestamp_delta = timestamp
+                .checked_sub(first_timestamp)
+                .ok_or_else(|| overflow("timestamp", timestamp, first_timestamp))?;
+            Ok(Record {
+                timestamp_delta,
+                offset_delta,
+                key,
+                value,
+                headers: vec![],
+            })
+        })
+        .collect::<Result<Vec<_>, ReadError>>()?;
+
+    Ok(RecordBatch {
+        base_offset,
+        partition_leader_epoch: -1,
+        last_offset_delta,
+        first_timestamp,
+        max_timestamp,
+        producer_id: -1,
+        producer_epoch: -1,
+        base_sequence: -1,
+        records: ControlBatchOrRecords::Records(records),
+        compression: wrapper.compression,
+        is_transactional: false,
+        timestamp_type: wrapper.timestamp_type,
+    })
+}
+
+/// Error for broker-supplied offsets or timestamps whose difference or sum does not fit into an `i64`.
+fn overflow(what: &str, a: i64, b: i64) -> ReadError {
+    ReadError::Malformed(format!("{what} overflow in legacy message set ({a}, {b})").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(
+        offset: i64,
+        magic: i8,
+        attributes: i8,
+        timestamp: i64,
+        key: Option<&[u8]>,
+        value: Option<&[u8]>,
+    ) -> Vec<u8> {
+        let mut body = vec![magic as u8, attributes as u8];
+        if magic == 1 {
+            body.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        for data in [key, value] {
+            match data {
+                Some(data) => {
+                    body.extend_from_slice(&(data.len() as i32).to_be_bytes());
+                    body.extend_from_slice(data);
+                }
+                None => body.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+
+        let mut out = offset.to_be_bytes().to_vec();
+        out.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        out.extend_from_slice(&crc32fast::hash(&body).to_be_bytes());
+        out.extend_from_slice(&body);
+        out
+    }
+
+    fn records(batch: &RecordBatch) -> &[Record] {
+        match &batch.records {
+            ControlBatchOrRecords::Records(records) => records,
+            ControlBatchOrRecords::ControlBatch(_) => panic!("unexpected control batch"),
+        }
+    }
+
+    #[test]
+    fn test_read_v0() {
+        let data = message(42, 0, 0, 0, None, Some(b"hello"));
+        let batch = RecordBatch::read_bytes(&mut Cursor::new(Bytes::from(data))).unwrap();
+
+        assert_eq!(batch.base_offset, 42);
+        assert_eq!(batch.last_offset_delta, 0);
+        assert_eq!(batch.first_timestamp, -1);
+        assert_eq!(batch.compression, RecordBatchCompression::NoCompression);
+        assert_eq!(
+            records(&batch),
+            [Record {
+                timestamp_delta: 0,
+                offset_delta: 0,
+                key: None,
+                value: Some(Bytes::from_static(b"hello")),
+                headers: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_v1() {
+        let mut data = message(7, 1, 0, 1_000, Some(b"k"), Some(b"v"));
+        data.extend(message(8, 1, 0, 1_010, None, None));
+        let reader = &mut Cursor::new(Bytes::from(data));
+
+        let batch = RecordBatch::read_bytes(reader).unwrap();
+        assert_eq!(batch.base_offset, 7);
+        assert_eq!(batch.first_timestamp, 1_000);
+        assert_eq!(batch.timestamp_type, RecordBatchTimestampType::CreateTime);
+        assert_eq!(records(&batch)[0].key, Some(Bytes::from_static(b"k")));
+
+        let batch = RecordBatch::read_bytes(reader).unwrap();
+        assert_eq!(batch.base_offset, 8);
+        assert_eq!(batch.max_timestamp, 1_010);
+        assert_eq!(records(&batch)[0].value, None);
+
+        assert_eq!(bytes_left(reader), 0);
+    }
+
+    #[test]
+    fn test_read_crc_mismatch() {
+        let mut data = message(0, 1, 0, 0, None, Some(b"hello"));
+        *data.last_mut().unwrap() ^= 1;
+
+        let err = RecordBatch::read_bytes(&mut Cursor::new(Bytes::from(data))).unwrap_err();
+        assert!(err.to_string().contains("CRC error"), "{err}");
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let mut data = message(0, 1, 0, 0, None, Some(b"hello"));
+        data.truncate(data.len() - 1);
+
+        let err = RecordBatch::read_bytes(&mut Cursor::new(Bytes::from(data))).unwrap_err();
+        assert!(matches!(err, ReadError::IO(e) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_read_v1_gzip_relative_offsets() {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let mut inner = message(0, 1, 0, 100, None, Some(b"a"));
+        inner.extend(message(1, 1, 0, 120, None, Some(b"b")));
+        inner.extend(message(2, 1, 0, 110, None, Some(b"c")));
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&inner).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        // wrapper offset is the absolute offset of the last inner message, LogAppendTime overrides inner timestamps
+        let data = message(12, 1, 1 | 0x8, 500, None, Some(&compressed));
+        let batch = RecordBatch::read_bytes(&mut Cursor::new(Bytes::from(data))).unwrap();
+
+        assert_eq!(batch.base_offset, 10);
+        assert_eq!(batch.last_offset_delta, 2);
+        assert_eq!(batch.first_timestamp, 500);
+        assert_eq!(batch.max_timestamp, 500);
+        assert_eq!(batch.compression, RecordBatchCompression::Gzip);
+        assert_eq!(
+            batch.timestamp_type,
+            RecordBatchTimestampType::LogAppendTime
+        );
+
+        let records = records(&batch);
+        let offset_deltas: Vec<_> = records.iter().map(|r| r.offset_delta).collect();
+        assert_eq!(offset_deltas, [0, 1, 2]);
+        assert_eq!(records[2].value, Some(Bytes::from_static(b"c")));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[test]
+    fn test_read_v1_gzip_overflow() {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let wrap = |wrapper_offset: i64, inner: &[(i64, i64)]| {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            for (offset, timestamp) in inner {
+                encoder
+                    .write_all(&message(*offset, 1, 0, *timestamp, None, None))
+                    .unwrap();
+            }
+            let compressed = encoder.finish().unwrap();
+            let data = message(wrapper_offset, 1, 1, 0, None, Some(&compressed));
+            RecordBatch::read_bytes(&mut Cursor::new(Bytes::from(data))).unwrap_err()
+        };
+
+        let err = wrap(i64::MIN, &[(0, 0), (1, 0)]);
+        assert!(err.to_string().contains("offset overflow"), "{err}");
+
+        let err = wrap(i64::MAX, &[(i64::MIN, 0), (0, 0)]);
+        assert!(err.to_string().contains("offset overflow"), "{err}");
+
+        let err = wrap(1, &[(0, i64::MAX), (1, i64::MIN)]);
+        assert!(err.to_string().contains("timestamp overflow"), "{err}");
+    }
+}
@@ -1,17 +1,34 @@
-use std::collections::BTreeMap;
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
 
-use chrono::{DateTime, Utc};
+mod header;
+mod headers;
+pub use header::{HeaderError, HeaderValue};
+pub use headers::Headers;
 
 /// High-level record.
+///
+/// Fetched keys, values and header values are slices of the fetch response, so cloning them is cheap and does not copy
+/// any data. Note that holding on to any of them keeps the whole response buffer alive.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Record {
-    pub key: Option<Vec<u8>>,
-    pub value: Option<Vec<u8>>,
-    pub headers: BTreeMap<String, Vec<u8>>,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+    pub headers: Headers,
     pub timestamp: DateTime<Utc>,
 }
 
 impl Record {
+    /// Start building a record, see [`RecordBuilder`].
+    pub fn builder() -> RecordBuilder {
+        RecordBuilder::default()
+    }
+
+    /// Timestamp in milliseconds since the Unix epoch, which is how Kafka stores it.
+    pub fn timestamp_millis(&self) -> i64 {
+        self.timestamp.timestamp_millis()
+    }
+
     /// Returns the approximate uncompressed size of this [`Record`]
     pub fn approximate_size(&self) -> usize {
         self.key.as_ref().map(|k| k.len()).unwrap_or_default()
@@ -24,6 +41,90 @@ impl Record {
     }
 }
 
+/// Builder for [`Record`].
+///
+/// Key and value are unset and there are no headers by default. The timestamp defaults to the time at which
+/// [`build`](Self::build) is called.
+#[derive(Debug, Clone, Default)]
+pub struct RecordBuilder {
+    key: Option<Bytes>,
+    value: Option<Bytes>,
+    headers: Headers,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl RecordBuilder {
+    /// Set the key.
+    ///
+    /// Passing [`Bytes`] hands over a shared buffer without copying it.
+    pub fn key(mut self, key: impl Into<Bytes>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Set the value.
+    ///
+    /// Passing [`Bytes`] hands over a shared buffer without copying it.
+    pub fn value(mut self, value: impl Into<Bytes>) -> Self {
+        self.value = Some(value.into());
+        self
+    }
+
+    /// Add a header, replacing any previous header with the same key.
+    pub fn header(mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Self {
+        self.headers.insert(key, value);
+        self
+    }
+
+    /// Add a header, keeping any previous header with the same key.
+    pub fn append_header(mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Self {
+        self.headers.append(key, value);
+        self
+    }
+
+    /// Set the timestamp.
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Set the timestamp in milliseconds since the Unix epoch.
+    ///
+    /// Values outside of the range of [`DateTime`], i.e. more than about 262,000 years away from the epoch, saturate
+    /// at [`DateTime::<Utc>::MIN_UTC`] and [`DateTime::<Utc>::MAX_UTC`].
+    pub fn timestamp_millis(self, millis: i64) -> Self {
+        let timestamp = Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .unwrap_or(if millis < 0 {
+                DateTime::<Utc>::MIN_UTC
+            } else {
+                DateTime::<Utc>::MAX_UTC
+            });
+        self.timestamp(timestamp)
+    }
+
+    pub fn build(self) -> Record {
+        Record {
+            key: self.key,
+            value: self.value,
+            headers: self.headers,
+            timestamp: self.timestamp.unwrap_or_else(utc_now),
+        }
+    }
+}
+
+/// Current wall clock time.
+///
+/// We do not enable the `clock` feature of [`chrono`], hence this helper.
+pub(crate) fn utc_now() -> DateTime<Utc> {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    Utc.timestamp_millis_opt(millis).unwrap()
+}
+
 /// Record that has offset information attached.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RecordAndOffset {
@@ -31,6 +132,54 @@ pub struct RecordAndOffset {
     pub offset: i64,
 }
 
+/// Type of a [`ControlRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRecordType {
+    /// The transaction was aborted.
+    Abort,
+
+    /// The transaction was committed.
+    Commit,
+}
+
+/// Transaction marker that the transaction coordinator wrote to the partition.
+///
+/// Marks the end of a transaction of the given producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlRecord {
+    pub control_type: ControlRecordType,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Record or control record that has offset information attached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordOrControl {
+    Record(RecordAndOffset),
+    Control { record: ControlRecord, offset: i64 },
+}
+
+/// Record batch as it is stored by the broker, i.e. encoded and possibly compressed.
+///
+/// This allows to move data between partitions or clusters without decoding and re-encoding it, see
+/// [`fetch_raw_batches`](crate::client::partition::PartitionClient::fetch_raw_batches) and
+/// [`produce_raw_batches`](crate::client::partition::PartitionClient::produce_raw_batches).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawRecordBatch {
+    /// Offset of the first record in the batch.
+    pub base_offset: i64,
+
+    /// Offset of the last record in the batch.
+    ///
+    /// Records might have been removed by log compaction, so the batch does not necessarily contain every offset in
+    /// between.
+    pub last_offset: i64,
+
+    /// The encoded batch in message format version 2.
+    pub data: Bytes,
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::TimeZone;
@@ -40,14 +189,65 @@ mod tests {
     #[test]
     fn test_approximate_size() {
         let record = Record {
-            key: Some(vec![0; 23]),
-            value: Some(vec![0; 45]),
-            headers: vec![("a".to_string(), vec![0; 5]), ("b".to_string(), vec![0; 7])]
-                .into_iter()
-                .collect(),
+            key: Some(vec![0; 23].into()),
+            value: Some(vec![0; 45].into()),
+            headers: Headers::from([("a", vec![0; 5]), ("b", vec![0; 7])]),
             timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
         };
 
         assert_eq!(record.approximate_size(), 23 + 45 + 1 + 5 + 1 + 7);
     }
+
+    #[test]
+    fn test_builder() {
+        let timestamp = Utc.timestamp_millis_opt(1337).unwrap();
+        let record = Record::builder()
+            .key("k")
+            .value(vec![1, 2])
+            .header("a", "x")
+            .header("a", b"y".as_slice())
+            .timestamp(timestamp)
+            .build();
+        assert_eq!(
+            record,
+            Record {
+                key: Some(Bytes::from_static(b"k")),
+                value: Some(Bytes::from_static(&[1, 2])),
+                headers: Headers::from([("a", "y")]),
+                timestamp,
+            }
+        );
+
+        let record = Record::builder()
+            .append_header("a", "x")
+            .append_header("b", "y")
+            .append_header("a", "z")
+            .build();
+        assert_eq!(
+            record.headers,
+            Headers::from([("a", "x"), ("b", "y"), ("a", "z")])
+        );
+
+        // shared buffers are handed over as they are
+        let value = Bytes::from(vec![1, 2, 3]);
+        let record = Record::builder().value(value.clone()).build();
+        assert_eq!(record.value.unwrap().as_ptr(), value.as_ptr());
+
+        let record = Record::builder().timestamp_millis(-1337).build();
+        assert_eq!(record.timestamp_millis(), -1337);
+        assert_eq!(record.timestamp, Utc.timestamp_millis_opt(-1337).unwrap());
+
+        // out of range values saturate instead of panicking
+        let record = Record::builder().timestamp_millis(i64::MAX).build();
+        assert_eq!(record.timestamp, DateTime::<Utc>::MAX_UTC);
+        let record = Record::builder().timestamp_millis(i64::MIN).build();
+        assert_eq!(record.timestamp, DateTime::<Utc>::MIN_UTC);
+
+        let before = utc_now();
+        let record = Record::builder().build();
+        assert_eq!(record.key, None);
+        assert_eq!(record.value, None);
+        assert!(record.headers.is_empty());
+        assert!(record.timestamp >= before);
+    }
 }
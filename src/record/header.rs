@@ -0,0 +1,197 @@
+//! Encoding of typed header values.
+//!
+//! The encodings match the serializers of the Java client, so headers can be exchanged with other Kafka clients:
+//! strings are UTF-8, integers are big-endian, booleans are a single `0` or `1` byte and UUIDs use their hyphenated
+//! string representation.
+use std::str::Utf8Error;
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use super::{Record, RecordBuilder};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HeaderError {
+    #[error("Header value is not valid UTF-8: {0}")]
+    Utf8(#[from] Utf8Error),
+
+    #[error("Expected header value of {expected} bytes, got {actual}")]
+    InvalidLength { expected: usize, actual: usize },
+
+    #[error("Invalid boolean header value: {0}")]
+    InvalidBool(u8),
+
+    #[cfg(feature = "uuid")]
+    #[error("Invalid UUID header value: {0}")]
+    Uuid(#[from] uuid::Error),
+}
+
+/// Type that can be stored in a [`Record`] header.
+pub trait HeaderValue: Sized {
+    fn encode(&self) -> Vec<u8>;
+
+    fn decode(data: &[u8]) -> Result<Self, HeaderError>;
+}
+
+impl HeaderValue for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, HeaderError> {
+        Ok(std::str::from_utf8(data)?.to_owned())
+    }
+}
+
+impl HeaderValue for i64 {
+    fn encode(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, HeaderError> {
+        let data = data.try_into().map_err(|_| HeaderError::InvalidLength {
+            expected: 8,
+            actual: data.len(),
+        })?;
+        Ok(Self::from_be_bytes(data))
+    }
+}
+
+impl HeaderValue for bool {
+    fn encode(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, HeaderError> {
+        match data {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [b] => Err(HeaderError::InvalidBool(*b)),
+            _ => Err(HeaderError::InvalidLength {
+                expected: 1,
+                actual: data.len(),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl HeaderValue for uuid::Uuid {
+    fn encode(&self) -> Vec<u8> {
+        self.hyphenated().to_string().into_bytes()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, HeaderError> {
+        Ok(Self::try_parse_ascii(data)?)
+    }
+}
+
+impl Record {
+    /// Raw value of the header `key`.
+    ///
+    /// If the key occurs multiple times, this is the last value.
+    pub fn header(&self, key: &str) -> Option<&[u8]> {
+        self.headers.get(key).map(Bytes::as_ref)
+    }
+
+    /// Decode the value of the header `key`.
+    ///
+    /// Returns `Ok(None)` if the header does not exist.
+    pub fn header_as<T: HeaderValue>(&self, key: &str) -> Result<Option<T>, HeaderError> {
+        self.header(key).map(T::decode).transpose()
+    }
+
+    /// Value of the header `key` as string, without copying it.
+    pub fn header_str(&self, key: &str) -> Result<Option<&str>, HeaderError> {
+        Ok(self.header(key).map(std::str::from_utf8).transpose()?)
+    }
+
+    /// See [`header_as`](Self::header_as).
+    pub fn header_i64(&self, key: &str) -> Result<Option<i64>, HeaderError> {
+        self.header_as(key)
+    }
+
+    /// See [`header_as`](Self::header_as).
+    pub fn header_bool(&self, key: &str) -> Result<Option<bool>, HeaderError> {
+        self.header_as(key)
+    }
+
+    /// See [`header_as`](Self::header_as).
+    #[cfg(feature = "uuid")]
+    pub fn header_uuid(&self, key: &str) -> Result<Option<uuid::Uuid>, HeaderError> {
+        self.header_as(key)
+    }
+
+    /// Encode `value` and store it in the header `key`, replacing any previous value.
+    pub fn set_header(&mut self, key: impl Into<String>, value: &impl HeaderValue) {
+        self.headers.insert(key, value.encode());
+    }
+}
+
+impl RecordBuilder {
+    /// Encode `value` and add it as header, replacing any previous header with the same key.
+    pub fn typed_header(self, key: impl Into<String>, value: &impl HeaderValue) -> Self {
+        self.header(key, value.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut record = Record::builder()
+            .header("s", "foo")
+            .typed_header("i", &-2i64)
+            .typed_header("b", &true)
+            .build();
+        record.set_header("s2", &"bar".to_owned());
+
+        assert_eq!(record.header_str("s").unwrap(), Some("foo"));
+        assert_eq!(
+            record.header_as::<String>("s2").unwrap().as_deref(),
+            Some("bar")
+        );
+        assert_eq!(
+            record.header("i"),
+            Some([0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe].as_slice())
+        );
+        assert_eq!(record.header_i64("i").unwrap(), Some(-2));
+        assert_eq!(record.header_bool("b").unwrap(), Some(true));
+        assert_eq!(record.header_bool("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let record = Record::builder()
+            .header("s", vec![0xff])
+            .header("b", vec![2])
+            .build();
+
+        assert_matches!(record.header_str("s"), Err(HeaderError::Utf8(_)));
+        assert_matches!(
+            record.header_i64("s"),
+            Err(HeaderError::InvalidLength {
+                expected: 8,
+                actual: 1
+            })
+        );
+        assert_matches!(record.header_bool("b"), Err(HeaderError::InvalidBool(2)));
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid() {
+        let id = uuid::Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+        let record = Record::builder().typed_header("id", &id).build();
+        assert_eq!(
+            record.header_str("id").unwrap(),
+            Some("67e55044-10b1-426f-9247-bb680e5fe0c8")
+        );
+        assert_eq!(record.header_uuid("id").unwrap(), Some(id));
+    }
+}
@@ -0,0 +1,176 @@
+//! Ordered record headers.
+use std::{fmt, ops::Index};
+
+use bytes::Bytes;
+
+/// Headers of a [`Record`](super::Record).
+///
+/// Kafka allows a key to occur multiple times and preserves the order of the headers, so this is a list of key-value
+/// pairs rather than a map. Lookups by key return the last value, like `Headers.lastHeader` of the Java client.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Headers(Vec<(String, Bytes)>);
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of headers, including repeated keys.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Last value of the header `key`.
+    pub fn get(&self, key: &str) -> Option<&Bytes> {
+        self.0.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// All values of the header `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl DoubleEndedIterator<Item = &'a Bytes> + 'a {
+        self.iter().filter(move |(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(k, _)| k == key)
+    }
+
+    /// Add a header after all existing ones, keeping previous values of `key`.
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<Bytes>) {
+        self.0.push((key.into(), value.into()));
+    }
+
+    /// Replace all values of the header `key` with `value`.
+    ///
+    /// Returns the last previous value, if any.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Option<Bytes> {
+        let key = key.into();
+        let previous = self.remove(&key);
+        self.0.push((key, value.into()));
+        previous
+    }
+
+    /// Remove all values of the header `key`.
+    ///
+    /// Returns the last removed value, if any.
+    pub fn remove(&mut self, key: &str) -> Option<Bytes> {
+        let mut removed = None;
+        self.0.retain_mut(|(k, v)| {
+            if k == key {
+                removed = Some(std::mem::take(v));
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    /// Iterate over all headers in order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, &Bytes)> + ExactSizeIterator {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+impl fmt::Debug for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter()).finish()
+    }
+}
+
+impl Index<&str> for Headers {
+    type Output = [u8];
+
+    /// Last value of the header `key`.
+    ///
+    /// # Panics
+    /// Panics if the header does not exist.
+    fn index(&self, key: &str) -> &[u8] {
+        self.get(key).expect("header not found")
+    }
+}
+
+impl<K: Into<String>, V: Into<Bytes>> FromIterator<(K, V)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+impl<K: Into<String>, V: Into<Bytes>> Extend<(K, V)> for Headers {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.0
+            .extend(iter.into_iter().map(|(k, v)| (k.into(), v.into())));
+    }
+}
+
+impl<K: Into<String>, V: Into<Bytes>, const N: usize> From<[(K, V); N]> for Headers {
+    fn from(headers: [(K, V); N]) -> Self {
+        headers.into_iter().collect()
+    }
+}
+
+impl From<Vec<(String, Bytes)>> for Headers {
+    fn from(headers: Vec<(String, Bytes)>) -> Self {
+        Self(headers)
+    }
+}
+
+impl From<Headers> for Vec<(String, Bytes)> {
+    fn from(headers: Headers) -> Self {
+        headers.0
+    }
+}
+
+impl IntoIterator for Headers {
+    type Item = (String, Bytes);
+    type IntoIter = std::vec::IntoIter<(String, Bytes)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_keys() {
+        let mut headers = Headers::from([("a", "1"), ("b", "2"), ("a", "3")]);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers.get("a"), Some(&Bytes::from_static(b"3")));
+        assert_eq!(&headers["b"], b"2");
+        assert_eq!(headers.get("c"), None);
+        assert_eq!(headers.get_all("a").collect::<Vec<_>>(), [&"1", &"3"]);
+
+        headers.append("b", "4");
+        assert_eq!(
+            headers.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            ["a", "b", "a", "b"]
+        );
+        assert_eq!(
+            headers.iter().map(|(_, v)| v).collect::<Vec<_>>(),
+            [&"1", &"2", &"3", &"4"]
+        );
+
+        assert_eq!(headers.insert("a", "5"), Some(Bytes::from_static(b"3")));
+        assert_eq!(
+            Vec::from(headers.clone()),
+            [
+                ("b".to_owned(), Bytes::from_static(b"2")),
+                ("b".to_owned(), Bytes::from_static(b"4")),
+                ("a".to_owned(), Bytes::from_static(b"5"))
+            ]
+        );
+
+        assert_eq!(headers.remove("b"), Some(Bytes::from_static(b"4")));
+        assert_eq!(headers.remove("b"), None);
+        assert!(!headers.contains_key("b"));
+        assert_eq!(headers, Headers::from([("a", "5")]));
+    }
+}
@@ -0,0 +1,407 @@
+//! Executor that runs background tasks, timers and broker connections, see [`Runtime`].
+//!
+//! By default rskafka uses [tokio](TokioRuntime) and must be used within a tokio runtime. Applications that use another
+//! executor select it once via [`set_global`] before creating any client:
+//!
+//! ```
+//! # #[cfg(feature = "runtime-smol")]
+//! # fn f() {
+//! rskafka::runtime::set_global(rskafka::runtime::SmolRuntime).unwrap();
+//! # }
+//! ```
+//!
+//! The synchronization primitives that rskafka uses work with any executor.
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    future::{AbortHandle, Abortable, BoxFuture},
+    FutureExt,
+};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::oneshot,
+};
+
+use crate::connection::TcpConfig;
+
+/// Byte stream to a broker.
+pub trait Stream: AsyncRead + AsyncWrite + Debug + Send + Unpin {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Debug + Send + Unpin {}
+
+/// Executor that runs background tasks, timers and broker connections.
+pub trait Runtime: Debug + Send + Sync + 'static {
+    /// Run `task` in the background.
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+
+    /// Run `f`, which may block, on a thread where blocking is acceptable.
+    ///
+    /// Defaults to a new thread.
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        std::thread::spawn(f);
+    }
+
+    /// Complete after `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Resolve `addr` (`host:port`) and connect to the first address that accepts the connection.
+    fn connect_tcp<'a>(
+        &'a self,
+        addr: &'a str,
+        config: &'a TcpConfig,
+    ) -> BoxFuture<'a, io::Result<Box<dyn Stream>>>;
+}
+
+/// [tokio](https://tokio.rs), the default runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        tokio::spawn(task);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(f);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        tokio::time::sleep(duration).boxed()
+    }
+
+    fn connect_tcp<'a>(
+        &'a self,
+        addr: &'a str,
+        config: &'a TcpConfig,
+    ) -> BoxFuture<'a, io::Result<Box<dyn Stream>>> {
+        async move {
+            let stream = config.connect(addr).await?;
+            Ok(Box::new(stream) as _)
+        }
+        .boxed()
+    }
+}
+
+/// [async-std](https://async.rs).
+#[cfg(feature = "runtime-async-std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "runtime-async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        async_std::task::spawn(task);
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(f);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        async_std::task::sleep(duration).boxed()
+    }
+
+    fn connect_tcp<'a>(
+        &'a self,
+        addr: &'a str,
+        config: &'a TcpConfig,
+    ) -> BoxFuture<'a, io::Result<Box<dyn Stream>>> {
+        async move {
+            let stream = async_std::net::TcpStream::connect(addr).await?;
+            stream.set_nodelay(config.nodelay)?;
+            config.apply_after_connect(&socket2::SockRef::from(&stream))?;
+            Ok(Box::new(FuturesIo(stream)) as _)
+        }
+        .boxed()
+    }
+}
+
+/// [smol](https://github.com/smol-rs/smol).
+#[cfg(feature = "runtime-smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "runtime-smol")]
+impl Runtime for SmolRuntime {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        smol::spawn(task).detach();
+    }
+
+    fn spawn_blocking(&self, f: Box<dyn FnOnce() + Send>) {
+        smol::unblock(f).detach();
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        async move {
+            smol::Timer::after(duration).await;
+        }
+        .boxed()
+    }
+
+    fn connect_tcp<'a>(
+        &'a self,
+        addr: &'a str,
+        config: &'a TcpConfig,
+    ) -> BoxFuture<'a, io::Result<Box<dyn Stream>>> {
+        async move {
+            let stream = smol::net::TcpStream::connect(addr).await?;
+            stream.set_nodelay(config.nodelay)?;
+            config.apply_after_connect(&socket2::SockRef::from(&stream))?;
+            Ok(Box::new(FuturesIo(stream)) as _)
+        }
+        .boxed()
+    }
+}
+
+/// Adapts a stream that implements the [`futures::io`] traits to the tokio ones.
+#[cfg(any(feature = "runtime-async-std", feature = "runtime-smol"))]
+#[derive(Debug)]
+struct FuturesIo<S>(S);
+
+#[cfg(any(feature = "runtime-async-std", feature = "runtime-smol"))]
+impl<S> AsyncRead for FuturesIo<S>
+where
+    S: futures::io::AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let n = futures::ready!(Pin::new(&mut self.0).poll_read(cx, buf.initialize_unfilled()))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(any(feature = "runtime-async-std", feature = "runtime-smol"))]
+impl<S> AsyncWrite for FuturesIo<S>
+where
+    S: futures::io::AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_close(cx)
+    }
+}
+
+static GLOBAL: OnceLock<Arc<dyn Runtime>> = OnceLock::new();
+
+/// Use `runtime` for all clients.
+///
+/// This must be called before the runtime is used for the first time, i.e. before any client is created. Returns the
+/// runtime back if a runtime is already in use.
+pub fn set_global(runtime: impl Runtime) -> Result<(), Arc<dyn Runtime>> {
+    let runtime: Arc<dyn Runtime> = Arc::new(runtime);
+    GLOBAL.set(Arc::clone(&runtime)).map_err(|_| runtime)
+}
+
+/// The runtime in use, see [`set_global`].
+pub fn global() -> &'static dyn Runtime {
+    GLOBAL.get_or_init(|| Arc::new(TokioRuntime)).as_ref()
+}
+
+/// Run `future` in the background.
+///
+/// Like for tokio, dropping the handle detaches the task.
+pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    let (abort, registration) = AbortHandle::new_pair();
+    let finished = Arc::new(AtomicBool::new(false));
+    let guard = FinishedGuard(Arc::clone(&finished));
+    let task = Abortable::new(
+        async move {
+            // the handle might be gone already
+            tx.send(future.await).ok();
+        },
+        registration,
+    );
+    global().spawn(
+        async move {
+            // also set if the task panics
+            let _guard = guard;
+            task.await.ok();
+        }
+        .boxed(),
+    );
+
+    JoinHandle {
+        rx,
+        abort,
+        finished,
+    }
+}
+
+/// Run `f`, which may block, on a thread where blocking is acceptable.
+///
+/// A panic in `f` is resumed in the caller. Fails if the runtime dropped `f` without running it.
+pub(crate) async fn spawn_blocking<F, T>(f: F) -> Result<T, JoinError>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    global().spawn_blocking(Box::new(move || {
+        tx.send(std::panic::catch_unwind(AssertUnwindSafe(f))).ok();
+    }));
+    match rx.await {
+        Ok(Ok(res)) => Ok(res),
+        Ok(Err(payload)) => std::panic::resume_unwind(payload),
+        Err(_) => Err(JoinError),
+    }
+}
+
+/// Complete after `duration`.
+pub(crate) fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+    global().sleep(duration)
+}
+
+/// Run `future` for at most `duration`.
+pub(crate) fn timeout<F>(duration: Duration, future: F) -> Timeout<F>
+where
+    F: Future,
+{
+    Timeout {
+        future: Box::pin(future),
+        sleep: sleep(duration),
+    }
+}
+
+/// Future returned by [`timeout`].
+///
+/// The inner future is boxed, which keeps the types of deeply nested request futures small.
+pub(crate) struct Timeout<F> {
+    future: Pin<Box<F>>,
+    sleep: BoxFuture<'static, ()>,
+}
+
+impl<F> Future for Timeout<F>
+where
+    F: Future,
+{
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(res) = self.future.as_mut().poll(cx) {
+            return Poll::Ready(Ok(res));
+        }
+        self.sleep.poll_unpin(cx).map(|_| Err(Elapsed))
+    }
+}
+
+impl<F> Debug for Timeout<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Timeout").finish_non_exhaustive()
+    }
+}
+
+/// The future did not complete in time, see [`timeout`].
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("deadline has elapsed")]
+pub(crate) struct Elapsed;
+
+/// The task panicked or was aborted, see [`JoinHandle`].
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("task panicked or was aborted")]
+pub(crate) struct JoinError;
+
+/// Handle of a task started via [`spawn`] that resolves to the output of the task.
+#[derive(Debug)]
+pub(crate) struct JoinHandle<T> {
+    rx: oneshot::Receiver<T>,
+    abort: AbortHandle,
+    finished: Arc<AtomicBool>,
+}
+
+/// Marks a task as finished when dropped.
+struct FinishedGuard(Arc<AtomicBool>);
+
+impl Drop for FinishedGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl<T> JoinHandle<T> {
+    /// Stop the task at its next await point.
+    pub(crate) fn abort(&self) {
+        self.abort.abort();
+    }
+
+    /// The task finished, panicked or was aborted.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.abort.is_aborted() || self.finished.load(Ordering::SeqCst)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.rx.poll_unpin(cx).map_err(|_| JoinError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spawn() {
+        assert_eq!(spawn(async { 1 }).await, Ok(1));
+
+        let handle = spawn(futures::future::pending::<()>());
+        assert!(!handle.is_finished());
+        handle.abort();
+        assert!(handle.is_finished());
+        assert_eq!(handle.await, Err(JoinError));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        assert_eq!(timeout(Duration::from_secs(1), async { 1 }).await, Ok(1));
+        assert_eq!(
+            timeout(Duration::from_millis(1), futures::future::pending::<()>()).await,
+            Err(Elapsed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_blocking() {
+        assert_eq!(spawn_blocking(|| 1).await, Ok(1));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "foo")]
+    async fn test_spawn_blocking_panic() {
+        spawn_blocking(|| panic!("foo")).await.ok();
+    }
+}
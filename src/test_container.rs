@@ -0,0 +1,323 @@
+//! Single-node brokers in Docker containers for integration tests.
+//!
+//! [`BrokerContainer`] starts a Kafka or Redpanda broker via [testcontainers], waits until it accepts requests and
+//! removes the container again when dropped. This allows tests to run against a real broker without setting up a
+//! cluster upfront.
+//!
+//! ```no_run
+//! # async fn test() {
+//! use rskafka::test_container::{BrokerContainer, BrokerImpl};
+//!
+//! let container = BrokerContainer::builder(BrokerImpl::Redpanda)
+//!     .start()
+//!     .await
+//!     .unwrap();
+//! let client = container.client().await.unwrap();
+//! let topics = client.list_topics().await.unwrap();
+//! # }
+//! ```
+//!
+//! Docker assigns the host port of the broker, which is advertised on `127.0.0.1`, so the tests need to run on the
+//! Docker host. Containers are managed via the Docker API and need a [tokio] runtime.
+//!
+//! [testcontainers]: https://docs.rs/testcontainers
+//! [tokio]: https://tokio.rs
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
+
+use testcontainers::{
+    core::{CmdWaitFor, ContainerPort, ContainerState, ExecCommand, WaitFor},
+    runners::AsyncRunner,
+    ContainerAsync, Image, ImageExt, TestcontainersError,
+};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    client::{error::Error as ClientError, Client, ClientBuilder},
+    runtime,
+};
+
+/// Port of the Kafka listener within the container.
+const KAFKA_PORT: ContainerPort = ContainerPort::Tcp(9092);
+
+/// Script that starts the broker once the host port is known, see [`BrokerImage`].
+const START_SCRIPT: &str = "/tmp/rskafka-start.sh";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ContainerError {
+    #[error("Cannot run container: {0}")]
+    Container(#[from] TestcontainersError),
+
+    #[error("Broker did not become ready within {0:?}: {1}")]
+    Startup(Duration, #[source] ClientError),
+}
+
+/// Broker implementation to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BrokerImpl {
+    /// Apache Kafka in KRaft mode.
+    Kafka,
+
+    /// Redpanda.
+    Redpanda,
+}
+
+impl BrokerImpl {
+    fn default_image(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Kafka => ("docker.io/apache/kafka", "3.7.0"),
+            Self::Redpanda => ("docker.io/vectorized/redpanda", "v22.2.1"),
+        }
+    }
+
+    fn env_vars(&self) -> Vec<(&'static str, &'static str)> {
+        match self {
+            Self::Kafka => vec![
+                ("KAFKA_NODE_ID", "1"),
+                ("KAFKA_PROCESS_ROLES", "broker,controller"),
+                ("KAFKA_LISTENERS", "PLAINTEXT://:9092,CONTROLLER://:9093"),
+                ("KAFKA_CONTROLLER_LISTENER_NAMES", "CONTROLLER"),
+                (
+                    "KAFKA_LISTENER_SECURITY_PROTOCOL_MAP",
+                    "CONTROLLER:PLAINTEXT,PLAINTEXT:PLAINTEXT",
+                ),
+                ("KAFKA_CONTROLLER_QUORUM_VOTERS", "1@localhost:9093"),
+                ("KAFKA_OFFSETS_TOPIC_REPLICATION_FACTOR", "1"),
+                ("KAFKA_TRANSACTION_STATE_LOG_REPLICATION_FACTOR", "1"),
+                ("KAFKA_TRANSACTION_STATE_LOG_MIN_ISR", "1"),
+                ("KAFKA_AUTO_CREATE_TOPICS_ENABLE", "false"),
+            ],
+            Self::Redpanda => vec![],
+        }
+    }
+
+    /// Shell script that starts a broker that is reachable via `host_port` on the host.
+    fn start_script(&self, host_port: u16) -> String {
+        let advertised = format!("PLAINTEXT://127.0.0.1:{host_port}");
+        match self {
+            Self::Kafka => format!(
+                "export KAFKA_ADVERTISED_LISTENERS={advertised}\nexec /etc/kafka/docker/run\n"
+            ),
+            Self::Redpanda => format!(
+                "exec /entrypoint.sh redpanda start --smp 1 --memory 1G --reserve-memory 0M --overprovisioned \
+                 --node-id 0 --check=false --set redpanda.auto_create_topics_enabled=false \
+                 --kafka-addr PLAINTEXT://0.0.0.0:{} --advertise-kafka-addr {advertised}\n",
+                KAFKA_PORT.as_u16(),
+            ),
+        }
+    }
+}
+
+/// Container image of a [`BrokerImpl`].
+///
+/// The broker has to advertise the host port that Docker maps to its listener, which is only known after the
+/// container is started. So the container waits for a start script that is written afterwards.
+#[derive(Debug, Clone)]
+struct BrokerImage {
+    broker_impl: BrokerImpl,
+    name: String,
+    tag: String,
+}
+
+impl Image for BrokerImage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn ready_conditions(&self) -> Vec<WaitFor> {
+        // the broker only starts after `exec_after_start`, readiness is checked with a client afterwards
+        vec![]
+    }
+
+    fn env_vars(
+        &self,
+    ) -> impl IntoIterator<Item = (impl Into<Cow<'_, str>>, impl Into<Cow<'_, str>>)> {
+        self.broker_impl.env_vars()
+    }
+
+    fn entrypoint(&self) -> Option<&str> {
+        Some("sh")
+    }
+
+    fn cmd(&self) -> impl IntoIterator<Item = impl Into<Cow<'_, str>>> {
+        [
+            "-c".to_owned(),
+            format!("while [ ! -f {START_SCRIPT} ]; do sleep 0.1; done; exec sh {START_SCRIPT}"),
+        ]
+    }
+
+    fn expose_ports(&self) -> &[ContainerPort] {
+        &[KAFKA_PORT]
+    }
+
+    fn exec_after_start(
+        &self,
+        cs: ContainerState,
+    ) -> Result<Vec<ExecCommand>, TestcontainersError> {
+        let script = self
+            .broker_impl
+            .start_script(cs.host_port_ipv4(KAFKA_PORT)?);
+        // rename the script into place, so that the container never runs a partially written one
+        let write = format!(
+            "printf '%s' \"$1\" > {START_SCRIPT}.tmp && mv {START_SCRIPT}.tmp {START_SCRIPT}"
+        );
+        let cmd = ExecCommand::new([
+            "sh".to_owned(),
+            "-c".to_owned(),
+            write,
+            "sh".to_owned(),
+            script,
+        ])
+        .with_cmd_ready_condition(CmdWaitFor::exit_code(0));
+        Ok(vec![cmd])
+    }
+}
+
+/// Builder for [`BrokerContainer`].
+#[derive(Debug, Clone)]
+pub struct BrokerContainerBuilder {
+    broker_impl: BrokerImpl,
+    image: Option<(String, String)>,
+    startup_timeout: Duration,
+}
+
+impl BrokerContainerBuilder {
+    /// Use a different Docker image, e.g. to test against another broker version.
+    ///
+    /// The image must accept the same configuration as the default one.
+    pub fn image(mut self, name: impl Into<String>, tag: impl Into<String>) -> Self {
+        self.image = Some((name.into(), tag.into()));
+        self
+    }
+
+    /// How long to wait for the container to start and the broker to accept requests.
+    ///
+    /// Defaults to 2 minutes.
+    pub fn startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Start the container and wait until the broker is ready.
+    pub async fn start(self) -> Result<BrokerContainer, ContainerError> {
+        let deadline = Instant::now() + self.startup_timeout;
+        let (name, tag) = match self.image {
+            Some(image) => image,
+            None => {
+                let (name, tag) = self.broker_impl.default_image();
+                (name.to_owned(), tag.to_owned())
+            }
+        };
+
+        let container = BrokerImage {
+            broker_impl: self.broker_impl,
+            name,
+            tag,
+        }
+        .with_startup_timeout(self.startup_timeout)
+        .start()
+        .await?;
+        let port = container.get_host_port_ipv4(KAFKA_PORT).await?;
+        debug!(id = container.id(), port, "started broker container");
+
+        let container = BrokerContainer {
+            container,
+            bootstrap_brokers: vec![format!("127.0.0.1:{port}")],
+        };
+        container.wait_ready(deadline, self.startup_timeout).await?;
+        Ok(container)
+    }
+}
+
+/// Broker running in a Docker container.
+///
+/// The container is removed when this is dropped.
+#[derive(Debug)]
+pub struct BrokerContainer {
+    container: ContainerAsync<BrokerImage>,
+    bootstrap_brokers: Vec<String>,
+}
+
+impl BrokerContainer {
+    /// Builder to start a container with the given broker implementation.
+    pub fn builder(broker_impl: BrokerImpl) -> BrokerContainerBuilder {
+        BrokerContainerBuilder {
+            broker_impl,
+            image: None,
+            startup_timeout: Duration::from_secs(120),
+        }
+    }
+
+    /// Docker ID of the container.
+    pub fn id(&self) -> &str {
+        self.container.id()
+    }
+
+    /// Bootstrap brokers to connect to the broker from the host.
+    pub fn bootstrap_brokers(&self) -> Vec<String> {
+        self.bootstrap_brokers.clone()
+    }
+
+    /// Client builder that connects to the broker.
+    pub fn client_builder(&self) -> ClientBuilder {
+        ClientBuilder::new(self.bootstrap_brokers())
+    }
+
+    /// Client connected to the broker.
+    pub async fn client(&self) -> Result<Client, ClientError> {
+        self.client_builder().build().await
+    }
+
+    async fn wait_ready(&self, deadline: Instant, timeout: Duration) -> Result<(), ContainerError> {
+        loop {
+            // the client retries connection errors with backoff, so limit each attempt
+            let attempt = runtime::timeout(Duration::from_secs(5), async {
+                self.client().await?.list_topics().await
+            })
+            .await;
+            let err = match attempt {
+                Ok(Ok(_)) => return Ok(()),
+                Ok(Err(e)) => e,
+                Err(_) => ClientError::Timeout,
+            };
+
+            if Instant::now() >= deadline {
+                return Err(ContainerError::Startup(timeout, err));
+            }
+            debug!(id = self.id(), %err, "broker container not ready yet");
+            runtime::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_script() {
+        assert_eq!(
+            BrokerImpl::Kafka.start_script(1234),
+            "export KAFKA_ADVERTISED_LISTENERS=PLAINTEXT://127.0.0.1:1234\nexec /etc/kafka/docker/run\n"
+        );
+        assert!(!BrokerImpl::Kafka
+            .env_vars()
+            .iter()
+            .any(|(key, _)| *key == "KAFKA_ADVERTISED_LISTENERS"));
+
+        let script = BrokerImpl::Redpanda.start_script(1234);
+        assert!(script.starts_with("exec /entrypoint.sh redpanda start "));
+        assert!(script.ends_with(
+            " --kafka-addr PLAINTEXT://0.0.0.0:9092 --advertise-kafka-addr PLAINTEXT://127.0.0.1:1234\n"
+        ));
+    }
+}
@@ -1,7 +1,346 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{btree_map, BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use crate::protocol::error::Error as ProtocolError;
 
 #[derive(Debug)]
 pub struct Topic {
     pub name: String,
     pub partitions: BTreeSet<i32>,
 }
+
+impl Topic {
+    /// All partitions of this topic.
+    pub fn topic_partitions(&self) -> impl Iterator<Item = TopicPartition> + '_ {
+        self.partitions
+            .iter()
+            .map(|partition| TopicPartition::new(self.name.clone(), *partition))
+    }
+}
+
+/// Topic as described by the cluster metadata, see [`Client::topics`](crate::client::Client::topics).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TopicMetadata {
+    pub name: String,
+
+    /// Whether this is an internal topic of the cluster, e.g. `__consumer_offsets`.
+    pub is_internal: bool,
+
+    /// Partitions, ordered by partition index.
+    pub partitions: Vec<PartitionMetadata>,
+
+    /// Error that the broker reported for this topic, e.g. because a leader election is in progress.
+    pub error: Option<ProtocolError>,
+}
+
+impl TopicMetadata {
+    /// All partitions of this topic.
+    pub fn topic_partitions(&self) -> impl Iterator<Item = TopicPartition> + '_ {
+        self.partitions
+            .iter()
+            .map(|p| TopicPartition::new(self.name.clone(), p.partition))
+    }
+}
+
+/// Placement of a partition, see [`TopicMetadata`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PartitionMetadata {
+    pub partition: i32,
+
+    /// Broker ID of the leader, `None` if the partition is currently without leader.
+    pub leader: Option<i32>,
+
+    /// Leader epoch, `None` if the broker does not report it.
+    pub leader_epoch: Option<i32>,
+
+    /// Broker IDs of all replicas, including the leader.
+    pub replicas: Vec<i32>,
+
+    /// Broker IDs of the replicas that are in sync with the leader.
+    pub isr: Vec<i32>,
+
+    /// Broker IDs of the replicas that are offline.
+    pub offline_replicas: Vec<i32>,
+
+    /// Error that the broker reported for this partition, e.g. [`ProtocolError::LeaderNotAvailable`].
+    pub error: Option<ProtocolError>,
+}
+
+/// Topic with its partitions and configuration, see
+/// [`Client::describe_topic`](crate::client::Client::describe_topic).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TopicDescription {
+    pub name: String,
+
+    /// Whether this is an internal topic of the cluster, e.g. `__consumer_offsets`.
+    pub is_internal: bool,
+
+    /// Partitions, ordered by partition index.
+    pub partitions: Vec<PartitionMetadata>,
+
+    /// Configuration entries by name, including the ones that are not set for this topic specifically.
+    pub configs: BTreeMap<String, ConfigEntry>,
+}
+
+/// Configuration entry of a [`TopicDescription`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConfigEntry {
+    /// The value, `None` if it is not set or [sensitive](Self::is_sensitive).
+    pub value: Option<String>,
+
+    /// Where the value comes from.
+    pub source: ConfigSource,
+
+    /// Whether the value cannot be changed.
+    pub is_read_only: bool,
+
+    /// Whether the value is sensitive, e.g. a password, and therefore not returned by the broker.
+    pub is_sensitive: bool,
+}
+
+/// Where the value of a [`ConfigEntry`] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigSource {
+    /// Set for the topic.
+    Topic,
+
+    /// Dynamic config of the broker.
+    DynamicBroker,
+
+    /// Dynamic config that is the default for all brokers.
+    DynamicDefaultBroker,
+
+    /// Static config of the broker, e.g. from `server.properties`.
+    StaticBroker,
+
+    /// Default value.
+    Default,
+
+    /// Unknown, e.g. because the broker is too old to report the source.
+    Unknown,
+}
+
+impl ConfigSource {
+    pub(crate) fn new(source: i8) -> Self {
+        match source {
+            1 => Self::Topic,
+            2 => Self::DynamicBroker,
+            3 => Self::DynamicDefaultBroker,
+            4 => Self::StaticBroker,
+            5 => Self::Default,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// A partition of a topic.
+///
+/// Formatted as `topic-partition`, like the other Kafka clients do.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TopicPartition {
+    pub topic: String,
+    pub partition: i32,
+}
+
+impl TopicPartition {
+    pub fn new(topic: impl Into<String>, partition: i32) -> Self {
+        Self {
+            topic: topic.into(),
+            partition,
+        }
+    }
+}
+
+impl Display for TopicPartition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.topic, self.partition)
+    }
+}
+
+impl<T> From<(T, i32)> for TopicPartition
+where
+    T: Into<String>,
+{
+    fn from((topic, partition): (T, i32)) -> Self {
+        Self::new(topic, partition)
+    }
+}
+
+/// An offset within a [`TopicPartition`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TopicPartitionOffset {
+    pub topic_partition: TopicPartition,
+    pub offset: i64,
+}
+
+impl TopicPartitionOffset {
+    pub fn new(topic: impl Into<String>, partition: i32, offset: i64) -> Self {
+        Self {
+            topic_partition: TopicPartition::new(topic, partition),
+            offset,
+        }
+    }
+}
+
+impl Display for TopicPartitionOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.topic_partition, self.offset)
+    }
+}
+
+/// Set of [`TopicPartition`]s, each with an optional offset.
+///
+/// Partitions are ordered by topic and then by partition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopicPartitionList {
+    partitions: BTreeMap<TopicPartition, Option<i64>>,
+}
+
+impl TopicPartitionList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a partition without offset.
+    ///
+    /// Keeps the offset if the partition is already part of the list.
+    pub fn add(&mut self, topic_partition: TopicPartition) {
+        self.partitions.entry(topic_partition).or_default();
+    }
+
+    /// Add a partition or replace its offset.
+    pub fn set_offset(&mut self, topic_partition: TopicPartition, offset: i64) {
+        self.partitions.insert(topic_partition, Some(offset));
+    }
+
+    /// Remove a partition, returning whether it was part of the list.
+    pub fn remove(&mut self, topic_partition: &TopicPartition) -> bool {
+        self.partitions.remove(topic_partition).is_some()
+    }
+
+    pub fn contains(&self, topic_partition: &TopicPartition) -> bool {
+        self.partitions.contains_key(topic_partition)
+    }
+
+    /// Offset of the given partition, `None` if the partition is unknown or has no offset.
+    pub fn offset(&self, topic_partition: &TopicPartition) -> Option<i64> {
+        self.partitions.get(topic_partition).copied().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.partitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// All partitions and their offsets.
+    pub fn iter(&self) -> impl Iterator<Item = (&TopicPartition, Option<i64>)> {
+        self.partitions.iter().map(|(tp, offset)| (tp, *offset))
+    }
+
+    /// All partitions that have an offset.
+    pub fn offsets(&self) -> impl Iterator<Item = TopicPartitionOffset> + '_ {
+        self.partitions.iter().filter_map(|(tp, offset)| {
+            offset.map(|offset| TopicPartitionOffset {
+                topic_partition: tp.clone(),
+                offset,
+            })
+        })
+    }
+
+    /// Distinct topics, in order.
+    pub fn topics(&self) -> BTreeSet<&str> {
+        self.partitions.keys().map(|tp| tp.topic.as_str()).collect()
+    }
+}
+
+impl FromIterator<TopicPartition> for TopicPartitionList {
+    fn from_iter<I: IntoIterator<Item = TopicPartition>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl FromIterator<TopicPartitionOffset> for TopicPartitionList {
+    fn from_iter<I: IntoIterator<Item = TopicPartitionOffset>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl Extend<TopicPartition> for TopicPartitionList {
+    fn extend<I: IntoIterator<Item = TopicPartition>>(&mut self, iter: I) {
+        for topic_partition in iter {
+            self.add(topic_partition);
+        }
+    }
+}
+
+impl Extend<TopicPartitionOffset> for TopicPartitionList {
+    fn extend<I: IntoIterator<Item = TopicPartitionOffset>>(&mut self, iter: I) {
+        for tpo in iter {
+            self.set_offset(tpo.topic_partition, tpo.offset);
+        }
+    }
+}
+
+impl IntoIterator for TopicPartitionList {
+    type Item = (TopicPartition, Option<i64>);
+    type IntoIter = btree_map::IntoIter<TopicPartition, Option<i64>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.partitions.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_partition_list() {
+        let mut list: TopicPartitionList = [
+            TopicPartitionOffset::new("b", 0, 10),
+            TopicPartitionOffset::new("a", 1, 20),
+        ]
+        .into_iter()
+        .collect();
+        list.add(TopicPartition::new("a", 0));
+        list.add(TopicPartition::new("b", 0));
+
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.offset(&TopicPartition::new("b", 0)), Some(10));
+        assert_eq!(list.offset(&TopicPartition::new("a", 0)), None);
+        assert_eq!(
+            list.iter()
+                .map(|(tp, offset)| (tp.to_string(), offset))
+                .collect::<Vec<_>>(),
+            [
+                ("a-0".to_owned(), None),
+                ("a-1".to_owned(), Some(20)),
+                ("b-0".to_owned(), Some(10)),
+            ]
+        );
+        assert_eq!(
+            list.offsets()
+                .map(|tpo| tpo.to_string())
+                .collect::<Vec<_>>(),
+            ["a-1@20", "b-0@10"]
+        );
+        assert_eq!(list.topics().into_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        assert!(list.remove(&("a", 0).into()));
+        assert!(!list.contains(&("a", 0).into()));
+    }
+}
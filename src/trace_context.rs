@@ -0,0 +1,200 @@
+//! Propagation of OpenTelemetry trace contexts via record headers.
+//!
+//! Trace contexts are stored in the `traceparent` and `tracestate` headers defined by
+//! [W3C Trace Context](https://www.w3.org/TR/trace-context/), so they interoperate with other Kafka clients. The
+//! context of the current [`tracing`] span is read via [`tracing_opentelemetry`], so its layer must be part of the
+//! subscriber.
+//!
+//! Producers can inject the context into every record via
+//! [`ClientBuilder::inject_trace_context`](crate::client::ClientBuilder::inject_trace_context) or into single records
+//! via [`inject`]. Consumers continue the trace by passing fetched records to [`set_parent`].
+use opentelemetry::{
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::record::Record;
+
+/// Header that contains trace ID, span ID and trace flags.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Header that contains vendor-specific trace data.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+/// Store the trace context of the current span in the headers of `record`.
+///
+/// Does nothing if there is no active trace.
+pub fn inject(record: &mut Record) {
+    inject_context(&Span::current().context(), record);
+}
+
+/// Store the trace context of `cx` in the headers of `record`.
+///
+/// Does nothing if `cx` has no active span.
+pub fn inject_context(cx: &Context, record: &mut Record) {
+    let span = cx.span();
+    let span_context = span.span_context();
+    if !span_context.is_valid() {
+        return;
+    }
+
+    record.headers.insert(
+        TRACEPARENT_HEADER.to_owned(),
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8(),
+        )
+        .into_bytes(),
+    );
+
+    let trace_state = span_context.trace_state().header();
+    if trace_state.is_empty() {
+        record.headers.remove(TRACESTATE_HEADER);
+    } else {
+        record
+            .headers
+            .insert(TRACESTATE_HEADER.to_owned(), trace_state.into_bytes());
+    }
+}
+
+/// Read the trace context from the headers of `record`.
+///
+/// Returns `None` if there is no valid `traceparent` header. An invalid `tracestate` header is ignored.
+pub fn extract(record: &Record) -> Option<SpanContext> {
+    let traceparent = std::str::from_utf8(record.headers.get(TRACEPARENT_HEADER)?).ok()?;
+    let (trace_id, span_id, trace_flags) = parse_traceparent(traceparent)?;
+
+    let trace_state = record
+        .headers
+        .get(TRACESTATE_HEADER)
+        .and_then(|v| std::str::from_utf8(v).ok())
+        .and_then(|s| s.parse::<TraceState>().ok())
+        .unwrap_or_default();
+
+    Some(SpanContext::new(
+        trace_id,
+        span_id,
+        trace_flags,
+        true,
+        trace_state,
+    ))
+}
+
+/// Make the producer span that is stored in the headers of `record` the parent of `span`.
+///
+/// Does nothing if `record` contains no trace context. This must be called before `span` is entered for the first
+/// time.
+pub fn set_parent(span: &Span, record: &Record) {
+    if let Some(span_context) = extract(record) {
+        // only fails if the span is disabled or not handled by the OpenTelemetry layer
+        span.set_parent(Context::new().with_remote_span_context(span_context))
+            .ok();
+    }
+}
+
+fn parse_traceparent(s: &str) -> Option<(TraceId, SpanId, TraceFlags)> {
+    let mut parts = s.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let trace_flags = parts.next()?;
+
+    // future versions may append fields, version 00 must not
+    if !is_lower_hex(version, 2) || version == "ff" || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    if !is_lower_hex(trace_id, 32) || !is_lower_hex(span_id, 16) || !is_lower_hex(trace_flags, 2) {
+        return None;
+    }
+
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    if trace_id == TraceId::INVALID || span_id == SpanId::INVALID {
+        return None;
+    }
+    let trace_flags = TraceFlags::new(u8::from_str_radix(trace_flags, 16).ok()?);
+
+    Some((trace_id, span_id, trace_flags))
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    fn record() -> Record {
+        Record {
+            key: None,
+            value: None,
+            headers: Default::default(),
+            timestamp: Utc.timestamp_millis_opt(0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_inject_extract_roundtrip() {
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            false,
+            "congo=t61rcWkgMzE".parse().unwrap(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context.clone());
+
+        let mut record = record();
+        inject_context(&cx, &mut record);
+        assert_eq!(
+            &record.headers[TRACEPARENT_HEADER],
+            b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(&record.headers[TRACESTATE_HEADER], b"congo=t61rcWkgMzE");
+
+        let extracted = extract(&record).unwrap();
+        assert_eq!(extracted.trace_id(), span_context.trace_id());
+        assert_eq!(extracted.span_id(), span_context.span_id());
+        assert_eq!(extracted.trace_flags(), span_context.trace_flags());
+        assert_eq!(extracted.trace_state(), span_context.trace_state());
+        assert!(extracted.is_remote());
+    }
+
+    #[test]
+    fn test_inject_without_span() {
+        let mut record = record();
+        inject_context(&Context::new(), &mut record);
+        assert!(record.headers.is_empty());
+        assert!(extract(&record).is_none());
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        assert!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").is_some()
+        );
+        assert!(
+            parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-foo")
+                .is_some()
+        );
+
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-foo",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-00",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-00",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-00",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-00",
+        ] {
+            assert!(parse_traceparent(invalid).is_none(), "{invalid}");
+        }
+    }
+}
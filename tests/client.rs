@@ -1,15 +1,18 @@
 use assert_matches::assert_matches;
 use chrono::{TimeZone, Utc};
+use futures::StreamExt;
 use rskafka::{
     client::{
         error::{Error as ClientError, ProtocolError, ServerErrorResponse},
         partition::{Compression, OffsetAt, UnknownTopicHandling},
-        ClientBuilder,
+        watch::TopicEvent,
+        with_timeout, ClientBuilder, ConnectionEvent,
     },
-    record::{Record, RecordAndOffset},
+    record::{Headers, Record, RecordAndOffset},
+    topic::ConfigSource,
     BackoffConfig,
 };
-use std::{collections::BTreeMap, env, str::FromStr, sync::Arc, time::Duration};
+use std::{env, str::FromStr, sync::Arc, time::Duration};
 
 mod test_helpers;
 use test_helpers::{maybe_start_logging, random_topic_name, record, BrokerImpl, TEST_TIMEOUT};
@@ -25,6 +28,142 @@ async fn test_plain() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_api_versions() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+
+    let versions = client.api_versions().await.unwrap();
+    // produce and fetch
+    assert!(versions.range(0).is_some());
+    assert!(versions.range(1).is_some());
+    assert!(versions.supports_zstd());
+
+    assert_eq!(client.broker_api_versions(i32::MAX).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_health_check() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+    client.health_check(TEST_TIMEOUT).await.unwrap();
+
+    client.close().await;
+    client.health_check(TEST_TIMEOUT).await.unwrap_err();
+}
+
+#[tokio::test]
+async fn test_operation_timeout() {
+    maybe_start_logging();
+
+    // nothing listens on this port, so connecting is retried until the timeout elapses
+    let bootstrap_brokers = vec!["localhost:1".to_owned()];
+    let err = tokio::time::timeout(
+        TEST_TIMEOUT,
+        ClientBuilder::new(bootstrap_brokers.clone())
+            .operation_timeout(Some(Duration::from_millis(100)))
+            .build(),
+    )
+    .await
+    .unwrap()
+    .unwrap_err();
+    assert!(err.to_string().contains("deadline"), "{err}");
+
+    let err = tokio::time::timeout(
+        TEST_TIMEOUT,
+        with_timeout(
+            Duration::from_millis(100),
+            ClientBuilder::new(bootstrap_brokers).build(),
+        ),
+    )
+    .await
+    .unwrap()
+    .unwrap_err();
+    assert!(err.to_string().contains("deadline"), "{err}");
+}
+
+#[tokio::test]
+async fn test_cluster_metadata() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+
+    let cluster = client.cluster().await.unwrap();
+    assert!(cluster.cluster_id.is_some());
+    assert!(!cluster.brokers.is_empty());
+    assert!(cluster.brokers.windows(2).all(|w| w[0].id < w[1].id));
+    if let Some(controller_id) = cluster.controller_id {
+        assert!(cluster.brokers.iter().any(|b| b.id == controller_id));
+    }
+}
+
+#[tokio::test]
+async fn test_watch_topic() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .metadata_refresh_interval(Some(Duration::from_millis(100)))
+        .build()
+        .await
+        .unwrap();
+
+    let topic = random_topic_name();
+    let mut watcher = client.watch_topic(&topic).await.unwrap();
+
+    client
+        .controller_client()
+        .unwrap()
+        .create_topic(&topic, 2, 1, 5_000)
+        .await
+        .unwrap();
+
+    let event = tokio::time::timeout(TEST_TIMEOUT, watcher.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(event, TopicEvent::PartitionCountChanged { old: 0, new: 2 });
+
+    client.close().await;
+    assert!(watcher.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_connection_events() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let events = Arc::new(std::sync::Mutex::new(vec![]));
+    let events_captured = Arc::clone(&events);
+    ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .connection_events(move |_broker, event| {
+            let established = matches!(event, ConnectionEvent::Established);
+            events_captured.lock().unwrap().push(established);
+        })
+        .build()
+        .await
+        .unwrap();
+
+    // every connection reports `Connecting` first
+    let events = events.lock().unwrap();
+    assert!(!events[0]);
+    assert!(events.contains(&true));
+}
+
 #[tokio::test]
 async fn test_sasl() {
     maybe_start_logging();
@@ -136,6 +275,109 @@ async fn test_topic_crud() {
     .unwrap();
 }
 
+#[tokio::test]
+async fn test_topics_metadata() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_name = random_topic_name();
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+
+    let controller_client = client.controller_client().unwrap();
+    controller_client
+        .create_topic(&topic_name, 2, 1, 5_000)
+        .await
+        .unwrap();
+
+    // might take a while to converge
+    let topic = tokio::time::timeout(TEST_TIMEOUT, async {
+        loop {
+            let topics = client.topics().await.unwrap();
+            if let Some(topic) = topics.into_iter().find(|t| t.name == topic_name) {
+                if topic.partitions.iter().all(|p| p.leader.is_some()) {
+                    return topic;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert!(!topic.is_internal);
+    assert_eq!(topic.error, None);
+    assert_eq!(
+        topic
+            .partitions
+            .iter()
+            .map(|p| p.partition)
+            .collect::<Vec<_>>(),
+        [0, 1]
+    );
+    for partition in &topic.partitions {
+        let leader = partition.leader.unwrap();
+        assert_eq!(partition.replicas, [leader]);
+        assert_eq!(partition.isr, [leader]);
+        assert!(partition.offline_replicas.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_describe_topic() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_name = random_topic_name();
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+
+    let err = client.describe_topic(&topic_name).await.unwrap_err();
+    assert_matches!(
+        err,
+        ClientError::ServerError {
+            protocol_error: ProtocolError::UnknownTopicOrPartition,
+            ..
+        }
+    );
+
+    let controller_client = client.controller_client().unwrap();
+    controller_client
+        .create_topic(&topic_name, 2, 1, 5_000)
+        .await
+        .unwrap();
+
+    // might take a while to converge
+    let topic = tokio::time::timeout(TEST_TIMEOUT, async {
+        loop {
+            if let Ok(topic) = client.describe_topic(&topic_name).await {
+                if topic.partitions.iter().all(|p| p.leader.is_some()) {
+                    return topic;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(topic.name, topic_name);
+    assert!(!topic.is_internal);
+    assert_eq!(topic.partitions.len(), 2);
+    let retention = &topic.configs["retention.ms"];
+    assert!(retention.value.is_some());
+    assert_ne!(retention.source, ConfigSource::Topic);
+    assert!(!retention.is_sensitive);
+}
+
 #[tokio::test]
 async fn test_partition_client() {
     maybe_start_logging();
@@ -160,6 +402,18 @@ async fn test_partition_client() {
         .unwrap();
     assert_eq!(partition_client.topic(), &topic_name);
     assert_eq!(partition_client.partition(), 0);
+
+    // clients are shared per topic-partition and unknown topic handling
+    let shared = client
+        .partition_client(topic_name.clone(), 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+    assert!(Arc::ptr_eq(&partition_client, &shared));
+    let other = client
+        .partition_client(topic_name.clone(), 0, UnknownTopicHandling::Error)
+        .await
+        .unwrap();
+    assert!(!Arc::ptr_eq(&partition_client, &other));
 }
 
 #[tokio::test]
@@ -451,6 +705,125 @@ async fn test_get_offset() {
         partition_client.get_offset(OffsetAt::Latest).await.unwrap(),
         2
     );
+    assert_eq!(partition_client.watermarks().await.unwrap(), (0, 2));
+}
+
+#[tokio::test]
+async fn test_produce_coalesced() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_name = random_topic_name();
+    let n_partitions = 4;
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .coalesce_produce_requests(true)
+        .build()
+        .await
+        .unwrap();
+    let controller_client = client.controller_client().unwrap();
+    controller_client
+        .create_topic(&topic_name, n_partitions, 1, 5_000)
+        .await
+        .unwrap();
+
+    let mut partition_clients = vec![];
+    for partition in 0..n_partitions {
+        partition_clients.push(
+            client
+                .partition_client(topic_name.clone(), partition, UnknownTopicHandling::Retry)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let record = record(b"");
+    for _ in 0..2 {
+        let offsets = futures::future::try_join_all(partition_clients.iter().flat_map(|c| {
+            [
+                c.produce(vec![record.clone()], Compression::NoCompression),
+                c.produce(vec![record.clone()], Compression::NoCompression),
+            ]
+        }))
+        .await
+        .unwrap();
+        assert_eq!(offsets.len(), 2 * n_partitions as usize);
+    }
+
+    for partition_client in partition_clients {
+        assert_eq!(
+            partition_client.get_offset(OffsetAt::Latest).await.unwrap(),
+            4
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_produce_max_in_flight() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_name = random_topic_name();
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .max_in_flight_produce_requests(Some(2))
+        .build()
+        .await
+        .unwrap();
+    let controller_client = client.controller_client().unwrap();
+    controller_client
+        .create_topic(&topic_name, 1, 1, 5_000)
+        .await
+        .unwrap();
+
+    let partition_client = client
+        .partition_client(topic_name.clone(), 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+
+    let record = record(b"");
+    let offsets = futures::future::try_join_all(
+        (0..10).map(|_| partition_client.produce(vec![record.clone()], Compression::NoCompression)),
+    )
+    .await
+    .unwrap();
+    let offsets: Vec<_> = offsets.into_iter().flatten().collect();
+    assert_eq!(offsets, (0..10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_close() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_name = random_topic_name();
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+    let controller_client = client.controller_client().unwrap();
+    controller_client
+        .create_topic(&topic_name, 1, 1, 5_000)
+        .await
+        .unwrap();
+
+    let partition_client = client
+        .partition_client(topic_name.clone(), 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+    partition_client
+        .produce(vec![record(b"")], Compression::NoCompression)
+        .await
+        .unwrap();
+
+    client.close().await;
+
+    partition_client
+        .produce(vec![record(b"")], Compression::NoCompression)
+        .await
+        .unwrap_err();
+    client.list_topics().await.unwrap_err();
 }
 
 #[tokio::test]
@@ -470,12 +843,10 @@ async fn test_produce_consume_size_cutoff() {
         .await
         .unwrap();
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic_name, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic_name, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     let record_1 = large_record();
     let record_2 = large_record();
@@ -585,6 +956,65 @@ async fn test_consume_midbatch() {
     );
 }
 
+#[cfg(feature = "compression-gzip")]
+#[tokio::test]
+async fn test_raw_batches_pass_through() {
+    maybe_start_logging();
+
+    let test_cfg = maybe_skip_kafka_integration!();
+    let topic_source = random_topic_name();
+    let topic_target = random_topic_name();
+
+    let client = ClientBuilder::new(test_cfg.bootstrap_brokers)
+        .build()
+        .await
+        .unwrap();
+    let controller_client = client.controller_client().unwrap();
+    for topic in [&topic_source, &topic_target] {
+        controller_client
+            .create_topic(topic, 1, 1, 5_000)
+            .await
+            .unwrap();
+    }
+
+    let source = client
+        .partition_client(&topic_source, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+    let target = client
+        .partition_client(&topic_target, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+
+    let record_1 = record(b"x");
+    let record_2 = record(b"y");
+    let record_3 = record(b"z");
+    source
+        .produce(vec![record_1.clone(), record_2.clone()], Compression::Gzip)
+        .await
+        .unwrap();
+    source
+        .produce(vec![record_3.clone()], Compression::NoCompression)
+        .await
+        .unwrap();
+
+    let (batches, high_watermark) = source
+        .fetch_raw_batches(0, 1..1_000_000, 1_000)
+        .await
+        .unwrap();
+    assert_eq!(high_watermark, 3);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].base_offset, 0);
+    assert_eq!(batches[0].last_offset, 1);
+
+    let res = target.produce_raw_batches(batches).await.unwrap();
+    assert_eq!(res.offsets, [0, 1, 2]);
+
+    let (records, _watermark) = target.fetch_records(0, 1..1_000_000, 1_000).await.unwrap();
+    let records: Vec<_> = records.into_iter().map(|r| r.record).collect();
+    assert_eq!(records, [record_1, record_2, record_3]);
+}
+
 #[tokio::test]
 async fn test_delete_records() {
     maybe_start_logging();
@@ -739,9 +1169,9 @@ async fn test_client_backoff_terminates() {
 
 pub fn large_record() -> Record {
     Record {
-        key: Some(b"".to_vec()),
-        value: Some(vec![b'x'; 1024]),
-        headers: BTreeMap::from([("foo".to_owned(), b"bar".to_vec())]),
+        key: Some(b"".to_vec().into()),
+        value: Some(vec![b'x'; 1024].into()),
+        headers: Headers::from([("foo", "bar")]),
         timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
     }
 }
@@ -37,12 +37,10 @@ async fn test_stream_consumer_start_at_0() {
 
     let record = record(b"x");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
     partition_client
         .produce(vec![record.clone()], Compression::NoCompression)
         .await
@@ -96,12 +94,10 @@ async fn test_stream_consumer_start_at_1() {
     let record_1 = record(b"x");
     let record_2 = record(b"y");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
     partition_client
         .produce(
             vec![record_1.clone(), record_2.clone()],
@@ -139,12 +135,10 @@ async fn test_stream_consumer_offset_out_of_range() {
         .await
         .unwrap();
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     let mut stream = StreamConsumerBuilder::new(partition_client, StartOffset::At(1)).build();
 
@@ -181,12 +175,10 @@ async fn test_stream_consumer_start_at_earliest() {
     let record_1 = record(b"x");
     let record_2 = record(b"y");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
     partition_client
         .produce(vec![record_1.clone()], Compression::NoCompression)
         .await
@@ -236,12 +228,10 @@ async fn test_stream_consumer_start_at_earliest_empty() {
 
     let record = record(b"x");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     let mut stream =
         StreamConsumerBuilder::new(Arc::clone(&partition_client), StartOffset::Earliest)
@@ -289,12 +279,10 @@ async fn test_stream_consumer_start_at_earliest_after_deletion() {
     let record_1 = record(b"x");
     let record_2 = record(b"y");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
     partition_client
         .produce(
             vec![record_1.clone(), record_2.clone()],
@@ -338,12 +326,10 @@ async fn test_stream_consumer_start_at_latest() {
     let record_1 = record(b"x");
     let record_2 = record(b"y");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
     partition_client
         .produce(vec![record_1.clone()], Compression::NoCompression)
         .await
@@ -388,12 +374,10 @@ async fn test_stream_consumer_start_at_latest_empty() {
 
     let record = record(b"x");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     let mut stream = StreamConsumerBuilder::new(Arc::clone(&partition_client), StartOffset::Latest)
         .with_max_wait_ms(50)
@@ -1,10 +1,10 @@
-use std::{collections::BTreeMap, sync::LazyLock};
+use std::sync::LazyLock;
 
 use chrono::{TimeZone, Utc};
 use j4rs::{Instance, InvocationArg, Jvm, JvmBuilder, MavenArtifact};
 use rskafka::{
     client::partition::Compression,
-    record::{Record, RecordAndOffset},
+    record::{Headers, Record, RecordAndOffset},
 };
 
 /// If `TEST_JAVA_INTEROPT` is not set, skip the calling test by returning early.
@@ -38,6 +38,9 @@ pub async fn produce(
         Compression::Snappy => "snappy",
         #[cfg(feature = "compression-zstd")]
         Compression::Zstd => "zstd",
+        // the level is only configurable in newer Java clients
+        #[cfg(feature = "compression-zstd")]
+        Compression::ZstdWithLevel(_) => "zstd",
     };
 
     let props = create_properties(
@@ -68,8 +71,8 @@ pub async fn produce(
     let mut futures = vec![];
     for (topic_name, partition_index, record) in records {
         let ts = record.timestamp.timestamp_millis();
-        let k = String::from_utf8(record.key.unwrap()).unwrap();
-        let v = String::from_utf8(record.value.unwrap()).unwrap();
+        let k = String::from_utf8(record.key.unwrap().to_vec()).unwrap();
+        let v = String::from_utf8(record.value.unwrap().to_vec()).unwrap();
 
         // We need something that implements `org.apache.kafka.common.header.Headers` without writing any Java code,
         // so we abuse the following internal data structure:
@@ -272,7 +275,7 @@ pub async fn consume(
             let headers_it = jvm
                 .invoke(&headers, "iterator", InvocationArg::empty())
                 .expect("iterator");
-            let mut headers = BTreeMap::new();
+            let mut headers = Headers::new();
             for header in JavaIterator::new(&jvm, headers_it) {
                 let header = jvm
                     .cast(&header, "org.apache.kafka.common.header.Header")
@@ -288,12 +291,12 @@ pub async fn consume(
                     .expect("value");
                 let value = from_java_bytes(&jvm, value);
 
-                headers.insert(key, value);
+                headers.append(key, value);
             }
 
             let record = Record {
-                key: Some(key.as_bytes().to_vec()),
-                value: Some(value.as_bytes().to_vec()),
+                key: Some(key.as_bytes().to_vec().into()),
+                value: Some(value.as_bytes().to_vec().into()),
                 headers,
                 timestamp: Utc.timestamp_millis_opt(timestamp).unwrap(),
             };
@@ -234,6 +234,17 @@ async fn test_produce_rskafka_consume_rskafka_zstd() {
     assert_produce_consume(produce_rskafka, consume_rskafka, Compression::Zstd).await;
 }
 
+#[cfg(feature = "compression-zstd")]
+#[tokio::test]
+async fn test_produce_rskafka_consume_rdkafka_zstd_level() {
+    assert_produce_consume(
+        produce_rskafka,
+        consume_rdkafka,
+        Compression::ZstdWithLevel(19),
+    )
+    .await;
+}
+
 async fn assert_produce_consume<F1, G1, F2, G2>(
     f_produce: F1,
     f_consume: F2,
@@ -261,12 +272,10 @@ async fn assert_produce_consume<F1, G1, F2, G2>(
         .await
         .unwrap();
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(topic_name.clone(), 1, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(topic_name.clone(), 1, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     // timestamps for records. We'll reorder the messages though to ts2, ts1, ts3
     let ts1 = Utc.timestamp_millis_opt(1337).unwrap();
@@ -285,19 +294,19 @@ async fn assert_produce_consume<F1, G1, F2, G2>(
                 // add a bit more data to encourage rdkafka to actually use compression, otherwise the compressed data
                 // is larger than the uncompressed version and rdkafka will not use compression at all
                 Record {
-                    key: Some(vec![b'x'; 100]),
+                    key: Some(vec![b'x'; 100].into()),
                     ..record
                 }
             }
         }
     };
     let record_2 = Record {
-        value: Some(b"some value".to_vec()),
+        value: Some(b"some value".to_vec().into()),
         timestamp: ts1,
         ..record_1.clone()
     };
     let record_3 = Record {
-        value: Some(b"more value".to_vec()),
+        value: Some(b"more value".to_vec().into()),
         timestamp: ts3,
         ..record_1.clone()
     };
@@ -7,7 +7,6 @@ use rskafka::client::{
 use std::time::Duration;
 
 mod test_helpers;
-use std::sync::Arc;
 use test_helpers::{maybe_start_logging, random_topic_name, record};
 
 #[tokio::test]
@@ -29,12 +28,10 @@ async fn test_batch_producer() {
 
     let record = record(b"");
 
-    let partition_client = Arc::new(
-        client
-            .partition_client(&topic, 0, UnknownTopicHandling::Retry)
-            .await
-            .unwrap(),
-    );
+    let partition_client = client
+        .partition_client(&topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
 
     let producer = BatchProducerBuilder::new(partition_client)
         .with_linger(Duration::from_secs(5))
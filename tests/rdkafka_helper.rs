@@ -41,6 +41,11 @@ pub async fn produce(
         Compression::Zstd => {
             cfg.set("compression.codec", "zstd");
         }
+        #[cfg(feature = "compression-zstd")]
+        Compression::ZstdWithLevel(level) => {
+            cfg.set("compression.codec", "zstd");
+            cfg.set("compression.level", level.to_string());
+        }
     }
     let client: FutureProducer<_> = cfg.create().unwrap();
 
@@ -51,7 +56,7 @@ pub async fn produce(
         for (k, v) in record.headers {
             headers = headers.insert(Header {
                 key: &k,
-                value: Some(&v),
+                value: Some(v.as_ref()),
             });
         }
 
@@ -59,8 +64,8 @@ pub async fn produce(
             .partition(partition_index)
             .headers(headers)
             .timestamp(record.timestamp.timestamp_millis());
-        let key_ref: Option<&Vec<u8>> = record.key.as_ref();
-        let value_ref: Option<&Vec<u8>> = record.value.as_ref();
+        let key_ref: Option<&[u8]> = record.key.as_deref();
+        let value_ref: Option<&[u8]> = record.value.as_deref();
         if let Some(key) = key_ref {
             f_record = f_record.key(key);
         }
@@ -100,8 +105,8 @@ pub async fn consume(
                 .take(n)
                 .map_ok(|msg| RecordAndOffset {
                     record: Record {
-                        key: msg.key().map(|k| k.to_vec()),
-                        value: msg.payload().map(|v| v.to_vec()),
+                        key: msg.key().map(|k| k.to_vec().into()),
+                        value: msg.payload().map(|v| v.to_vec().into()),
                         headers: msg
                             .headers()
                             .map(|headers| {
@@ -0,0 +1,53 @@
+//! Clients that run on an executor other than tokio.
+#![cfg(all(feature = "runtime-smol", feature = "test-util"))]
+
+use chrono::{TimeZone, Utc};
+use rskafka::{
+    client::{
+        partition::{Compression, UnknownTopicHandling},
+        ClientBuilder,
+    },
+    mock_broker::MockBroker,
+    record::{Headers, Record},
+    runtime::{self, SmolRuntime},
+};
+
+#[test]
+fn test_smol() {
+    runtime::set_global(SmolRuntime).unwrap();
+
+    // the mock broker is implemented with tokio, so give it a runtime of its own
+    let tokio = tokio::runtime::Runtime::new().unwrap();
+    let broker = tokio.block_on(MockBroker::start()).unwrap();
+    broker.create_topic("foo", 1);
+
+    smol::block_on(async {
+        let client = ClientBuilder::new(broker.bootstrap_brokers())
+            .build()
+            .await
+            .unwrap();
+        let partition_client = client
+            .partition_client("foo", 0, UnknownTopicHandling::Error)
+            .await
+            .unwrap();
+
+        let record = Record {
+            key: Some(b"foo".to_vec().into()),
+            value: Some(b"bar".to_vec().into()),
+            headers: Headers::default(),
+            timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+        };
+        partition_client
+            .produce(vec![record.clone()], Compression::NoCompression)
+            .await
+            .unwrap();
+
+        let (records, high_watermark) = partition_client
+            .fetch_records(0, 1..1_000_000, 1_000)
+            .await
+            .unwrap();
+        assert_eq!(high_watermark, 1);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].record, record);
+    });
+}
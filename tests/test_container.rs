@@ -0,0 +1,72 @@
+#![cfg(feature = "test-container")]
+
+use chrono::{TimeZone, Utc};
+use rskafka::{
+    client::partition::{Compression, UnknownTopicHandling},
+    record::{Headers, Record},
+    test_container::{BrokerContainer, BrokerImpl},
+};
+
+/// Environment variable to configure if tests that start Docker containers should be run.
+const ENV_TEST_DOCKER: &str = "TEST_DOCKER";
+
+/// If `TEST_DOCKER` is not set, skip the calling test by returning early.
+macro_rules! maybe_skip_docker {
+    () => {{
+        if std::env::var(ENV_TEST_DOCKER).is_err() {
+            eprintln!("skipping Docker tests - set {ENV_TEST_DOCKER} to run");
+            return;
+        }
+    }};
+}
+
+#[tokio::test]
+async fn test_produce_fetch_kafka() {
+    maybe_skip_docker!();
+
+    produce_fetch(BrokerImpl::Kafka).await;
+}
+
+#[tokio::test]
+async fn test_produce_fetch_redpanda() {
+    maybe_skip_docker!();
+
+    produce_fetch(BrokerImpl::Redpanda).await;
+}
+
+async fn produce_fetch(broker_impl: BrokerImpl) {
+    let container = BrokerContainer::builder(broker_impl).start().await.unwrap();
+    let topic_name = format!("test_topic_{}", uuid::Uuid::new_v4());
+
+    let client = container.client().await.unwrap();
+    client
+        .controller_client()
+        .unwrap()
+        .create_topic(&topic_name, 1, 1, 5_000)
+        .await
+        .unwrap();
+
+    let partition_client = client
+        .partition_client(topic_name, 0, UnknownTopicHandling::Retry)
+        .await
+        .unwrap();
+    let record = Record {
+        key: Some(b"key".to_vec().into()),
+        value: Some(b"hello kafka".to_vec().into()),
+        headers: Headers::new(),
+        timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
+    };
+    let offsets = partition_client
+        .produce(vec![record.clone()], Compression::NoCompression)
+        .await
+        .unwrap();
+    assert_eq!(offsets, vec![0]);
+
+    let (records, high_watermark) = partition_client
+        .fetch_records(0, 1..10_000, 1_000)
+        .await
+        .unwrap();
+    assert_eq!(high_watermark, 1);
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].record, record);
+}
@@ -1,7 +1,7 @@
 use chrono::{TimeZone, Utc};
 use parking_lot::Once;
-use rskafka::record::Record;
-use std::{collections::BTreeMap, time::Duration};
+use rskafka::record::{Headers, Record};
+use std::time::Duration;
 
 /// Sensible test timeout.
 #[allow(dead_code)]
@@ -159,9 +159,9 @@ pub fn random_topic_name() -> String {
 
 pub fn record(key: &[u8]) -> Record {
     Record {
-        key: Some(key.to_vec()),
-        value: Some(b"hello kafka".to_vec()),
-        headers: BTreeMap::from([("foo".to_owned(), b"bar".to_vec())]),
+        key: Some(key.to_vec().into()),
+        value: Some(b"hello kafka".to_vec().into()),
+        headers: Headers::from([("foo", "bar")]),
         timestamp: Utc.timestamp_millis_opt(1337).unwrap(),
     }
 }